@@ -7,17 +7,77 @@
 // except according to those terms.
 
 use crate::constants::{HASH_SIZE, PEER_ID_SIZE};
+use crate::dht::krpc::{Query, Response};
 use crate::messages::bitfield::Bitfield;
-use crate::TrackerResp;
+use crate::utp::UtpStream;
+use crate::{Error, HolepunchMsg, Mapping};
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use tokio::sync::oneshot;
 
+#[derive(Debug)]
+pub enum TrackerServerCmd {
+    HttpRequest {
+        target: String,
+        addr: SocketAddr,
+        resp_ch: oneshot::Sender<Option<Vec<u8>>>,
+    },
+}
+
+#[derive(Debug)]
+pub enum DhtCmd {
+    Query {
+        addr: SocketAddr,
+        query: Query,
+        resp_ch: oneshot::Sender<Result<Response, Error>>,
+    },
+    Closest {
+        target: [u8; HASH_SIZE],
+        count: usize,
+        resp_ch: oneshot::Sender<Vec<([u8; HASH_SIZE], SocketAddr)>>,
+    },
+    Save {
+        resp_ch: oneshot::Sender<Result<(), Error>>,
+    },
+}
+
+#[derive(Debug)]
+pub enum UtpCmd {
+    Connect {
+        addr: SocketAddr,
+        resp_ch: oneshot::Sender<Result<UtpStream, Error>>,
+    },
+    Punch {
+        addr: SocketAddr,
+    },
+}
+
 #[derive(Debug, Clone)]
-pub enum TrackerCmd {
-    TrackerResp(TrackerResp),
+pub enum AnnounceCmd {
+    Announce,
+    Completed,
+    Paused,
+    ExternalAddr(SocketAddr),
+    Stop,
+}
+
+#[derive(Debug)]
+pub enum MappingCmd {
+    Mapped(Mapping),
     Fail(String),
 }
 
+/// Transfer statistics reported to trackers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransferStats {
+    /// Total amount of uploaded bytes
+    pub uploaded: u64,
+    /// Total amount of downloaded bytes
+    pub downloaded: u64,
+    /// Number of bytes client still has to download
+    pub left: u64,
+}
+
 #[derive(Debug, Clone)]
 pub enum ExtractorCmd {
     Done,
@@ -26,8 +86,9 @@ pub enum ExtractorCmd {
 
 pub enum ViewCmd {
     Log(String),
+    Warning(String),
     LogPeer {
-        addr: String,
+        addr: SocketAddr,
         peer_id: Option<[u8; PEER_ID_SIZE]>,
         text: String,
     },
@@ -40,61 +101,103 @@ pub enum BroadCmd {
         piece_index: usize,
     },
     SendOwnState {
-        am_choked_map: HashMap<String, bool>,
+        am_choked_map: HashMap<SocketAddr, bool>,
+    },
+    SendUploadOnly,
+    Disconnect {
+        addr: SocketAddr,
+    },
+    RevealPiece {
+        addr: SocketAddr,
+        piece_index: usize,
+    },
+    SendExtended {
+        addr: SocketAddr,
+        name: String,
+        payload: Vec<u8>,
     },
 }
 #[derive(Debug)]
 pub enum PeerCmd {
     Init {
-        addr: String,
+        addr: SocketAddr,
         peer_id: [u8; PEER_ID_SIZE],
+        fast: bool,
+        encrypted: bool,
+        utp: bool,
         resp_ch: oneshot::Sender<InitCmd>,
     },
     RecvChoke {
-        addr: String,
+        addr: SocketAddr,
     },
     RecvUnchoke {
-        addr: String,
+        addr: SocketAddr,
         resp_ch: oneshot::Sender<UnchokeCmd>,
     },
     RecvInterested {
-        addr: String,
+        addr: SocketAddr,
     },
     RecvNotInterested {
-        addr: String,
+        addr: SocketAddr,
         resp_ch: oneshot::Sender<NotInterestedCmd>,
     },
     RecvHave {
-        addr: String,
+        addr: SocketAddr,
         piece_index: usize,
         resp_ch: oneshot::Sender<HaveCmd>,
     },
     RecvBitfield {
-        addr: String,
+        addr: SocketAddr,
         bitfield: Bitfield,
         resp_ch: oneshot::Sender<BitfieldCmd>,
     },
+    RecvAllowedFast {
+        addr: SocketAddr,
+        piece_index: usize,
+        resp_ch: oneshot::Sender<AllowedFastCmd>,
+    },
+    RecvPort {
+        addr: SocketAddr,
+        port: u16,
+    },
+    RecvPex {
+        addr: SocketAddr,
+        added: Vec<(SocketAddr, u8)>,
+        dropped: Vec<SocketAddr>,
+    },
+    RecvExtendedHandshake {
+        addr: SocketAddr,
+        client: Option<String>,
+        your_ip: Option<IpAddr>,
+        extensions: Vec<String>,
+        upload_only: Option<bool>,
+    },
+    RecvHolepunch {
+        addr: SocketAddr,
+        msg: HolepunchMsg,
+    },
     RecvRequest {
-        addr: String,
+        addr: SocketAddr,
         piece_index: usize,
         resp_ch: oneshot::Sender<RequestCmd>,
     },
     PieceDone {
-        addr: String,
+        addr: SocketAddr,
         resp_ch: oneshot::Sender<PieceCmd>,
     },
     PieceCancel {
-        addr: String,
+        addr: SocketAddr,
         resp_ch: oneshot::Sender<PieceCmd>,
     },
     SyncStats {
-        addr: String,
+        addr: SocketAddr,
         downloaded_rate: Option<u32>,
         uploaded_rate: Option<u32>,
+        uploaded: usize,
         unexpected_blocks: usize,
     },
     KillReq {
-        addr: String,
+        addr: SocketAddr,
         reason: String,
     },
 }
@@ -108,7 +211,17 @@ pub struct ReqData {
 
 #[derive(Debug)]
 pub enum InitCmd {
-    SendBitfield { bitfield: Bitfield },
+    SendBitfield {
+        bitfield: Bitfield,
+        allowed_fast: Vec<usize>,
+    },
+    SendHaveAll {
+        allowed_fast: Vec<usize>,
+    },
+    SendHaveNone,
+    SendSuperSeed {
+        piece_index: Option<usize>,
+    },
 }
 
 #[derive(Debug)]
@@ -132,6 +245,13 @@ pub enum HaveCmd {
     Ignore,
 }
 
+#[derive(Debug)]
+pub enum AllowedFastCmd {
+    SendInterestedAndRequest(ReqData),
+    SendRequest(ReqData),
+    Ignore,
+}
+
 #[derive(Debug)]
 pub enum BitfieldCmd {
     SendState {
@@ -146,7 +266,7 @@ pub enum RequestCmd {
         piece_index: usize,
         piece_hash: [u8; HASH_SIZE],
     },
-    Ignore,
+    Reject,
 }
 
 #[derive(Debug)]
@@ -8,33 +8,53 @@
 
 use crate::constants::MAX_FRAME_SIZE;
 use crate::frame::Frame;
+use crate::mse::Cipher;
 use crate::serializer::Serializer;
 use crate::Error;
 use bytes::{Buf, BytesMut};
 use std::io::Cursor;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Transport carrying peer messages (TCP or uTP).
+pub trait PeerStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> PeerStream for T {}
 
 pub struct Connection {
-    pub addr: String,
-    socket: Option<TcpStream>,
+    pub addr: SocketAddr,
+    socket: Option<Box<dyn PeerStream>>,
+    cipher: Option<Cipher>,
     buffer: BytesMut,
 }
 
 impl Connection {
-    pub fn new(addr: String) -> Connection {
+    pub fn new(addr: SocketAddr) -> Connection {
         Connection {
             addr,
             socket: None,
+            cipher: None,
             buffer: BytesMut::with_capacity(MAX_FRAME_SIZE),
         }
     }
 
-    pub fn with_socket(&mut self, socket: TcpStream) -> &mut Self {
+    pub fn with_socket(&mut self, socket: Box<dyn PeerStream>) -> &mut Self {
         self.socket = Some(socket);
         self
     }
 
+    /// Use cipher negotiated in MSE handshake. Data received during handshake (already decrypted)
+    /// is parsed first.
+    pub fn with_encryption(&mut self, cipher: Option<Cipher>, payload: &[u8]) -> &mut Self {
+        self.cipher = cipher;
+        self.buffer.extend_from_slice(payload);
+        self
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
     pub async fn send_frame(&mut self, frame: &Frame) -> Result<(), Box<dyn std::error::Error>> {
         match frame {
             Frame::Handshake(msg) => self.send_msg(msg).await?,
@@ -48,6 +68,13 @@ impl Connection {
             Frame::Request(msg) => self.send_msg(msg).await?,
             Frame::Piece(msg) => self.send_msg(msg).await?,
             Frame::Cancel(msg) => self.send_msg(msg).await?,
+            Frame::Port(msg) => self.send_msg(msg).await?,
+            Frame::Extended(msg) => self.send_msg(msg).await?,
+            Frame::SuggestPiece(msg) => self.send_msg(msg).await?,
+            Frame::HaveAll(msg) => self.send_msg(msg).await?,
+            Frame::HaveNone(msg) => self.send_msg(msg).await?,
+            Frame::RejectRequest(msg) => self.send_msg(msg).await?,
+            Frame::AllowedFast(msg) => self.send_msg(msg).await?,
         }
 
         Ok(())
@@ -58,7 +85,11 @@ impl Connection {
         msg: &T,
     ) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(socket) = self.socket.as_mut() {
-            socket.write_all(msg.data().as_slice()).await?;
+            let mut data = msg.data();
+            if let Some(cipher) = self.cipher.as_mut() {
+                cipher.encrypt(&mut data);
+            }
+            socket.write_all(data.as_slice()).await?;
         }
 
         Ok(())
@@ -72,11 +103,16 @@ impl Connection {
 
             match self.socket.as_mut() {
                 Some(socket) => {
+                    let start = self.buffer.len();
                     let n = match socket.read_buf(&mut self.buffer).await {
                         Err(_) => return Err(Error::CantReadFromSocket),
                         Ok(n) => n,
                     };
 
+                    if let Some(cipher) = self.cipher.as_mut() {
+                        cipher.decrypt(&mut self.buffer[start..]);
+                    }
+
                     if n == 0 {
                         return match self.buffer.is_empty() {
                             // Connection closed by peer
@@ -16,6 +16,10 @@ pub const PIECE_BLOCK_SIZE: usize = 16384;
 pub const PIECE_LENGTH: usize = 262144;
 /// Default port
 pub const PORT: u16 = 6881;
+/// Last port of default listen range (tried when lower ports are taken)
+pub const MAX_PORT: u16 = 6889;
+/// Number of outstanding requests from peer, reported in extension handshake.
+pub const REQQ: u32 = 250;
 
 /// Maximal buffer size for frame
 pub const MAX_FRAME_SIZE: usize = 65536;
@@ -27,3 +31,5 @@ pub const MAX_NOT_INTERESTED: usize = 4;
 pub const MAX_OPTIMISTIC_ROUNDS: usize = 3;
 pub const MAX_OPTIMISTIC: usize = 1;
 pub const MAX_UNCHOKED: usize = 10;
+/// Number of pieces peer can download when choked (Fast Extension)
+pub const ALLOWED_FAST_NUM: usize = 10;
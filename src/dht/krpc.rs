@@ -0,0 +1,290 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::bcodec::bencoder::BEncoder;
+use crate::bcodec::bvalue::BValue;
+use crate::constants::HASH_SIZE;
+use crate::dht::routing_table::NodeId;
+use crate::hashmap;
+use crate::tracker_resp::COMPACT_PEER_SIZE;
+use crate::{BDecoder, Error, TrackerResp};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+/// Size of node entry in compact node info (node ID, IPv4 address and port).
+pub const COMPACT_NODE_SIZE: usize = HASH_SIZE + COMPACT_PEER_SIZE;
+
+/// KRPC error code for malformed packet, invalid arguments or bad token.
+pub const ERROR_PROTOCOL: i64 = 203;
+
+/// DHT query (see [BEP5](https://www.bittorrent.org/beps/bep_0005.html)).
+#[derive(PartialEq, Clone, Debug)]
+pub enum Query {
+    Ping,
+    FindNode {
+        target: NodeId,
+    },
+    GetPeers {
+        info_hash: [u8; HASH_SIZE],
+    },
+    AnnouncePeer {
+        info_hash: [u8; HASH_SIZE],
+        port: u16,
+        implied_port: bool,
+        token: Vec<u8>,
+    },
+}
+
+/// Response to any query. Only querying node ID is always present.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Response {
+    pub id: NodeId,
+    pub nodes: Vec<(NodeId, SocketAddr)>,
+    pub values: Vec<SocketAddr>,
+    pub token: Option<Vec<u8>>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Body {
+    Query { id: NodeId, query: Query },
+    Response(Response),
+    Error { code: i64, msg: String },
+}
+
+/// KRPC message, bencoded dictionary send in single UDP datagram.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Msg {
+    pub tid: Vec<u8>,
+    pub body: Body,
+}
+
+impl Msg {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut dict = hashmap![b"t".to_vec() => BValue::ByteStr(self.tid.clone())];
+
+        match &self.body {
+            Body::Query { id, query } => {
+                let mut args = hashmap![b"id".to_vec() => BValue::ByteStr(id.to_vec())];
+                let name: &[u8] = match query {
+                    Query::Ping => b"ping",
+                    Query::FindNode { target } => {
+                        args.insert(b"target".to_vec(), BValue::ByteStr(target.to_vec()));
+                        b"find_node"
+                    }
+                    Query::GetPeers { info_hash } => {
+                        args.insert(b"info_hash".to_vec(), BValue::ByteStr(info_hash.to_vec()));
+                        b"get_peers"
+                    }
+                    Query::AnnouncePeer {
+                        info_hash,
+                        port,
+                        implied_port,
+                        token,
+                    } => {
+                        args.insert(b"info_hash".to_vec(), BValue::ByteStr(info_hash.to_vec()));
+                        args.insert(b"port".to_vec(), BValue::Int(*port as i64));
+                        args.insert(b"implied_port".to_vec(), BValue::Int(*implied_port as i64));
+                        args.insert(b"token".to_vec(), BValue::ByteStr(token.clone()));
+                        b"announce_peer"
+                    }
+                };
+
+                dict.insert(b"y".to_vec(), BValue::ByteStr(b"q".to_vec()));
+                dict.insert(b"q".to_vec(), BValue::ByteStr(name.to_vec()));
+                dict.insert(b"a".to_vec(), BValue::Dict(args));
+            }
+            Body::Response(resp) => {
+                let mut values = hashmap![b"id".to_vec() => BValue::ByteStr(resp.id.to_vec())];
+                if !resp.nodes.is_empty() {
+                    values.insert(
+                        b"nodes".to_vec(),
+                        BValue::ByteStr(Self::compact_nodes(&resp.nodes)),
+                    );
+                }
+                if !resp.values.is_empty() {
+                    let peers = resp
+                        .values
+                        .iter()
+                        .filter_map(|addr| match addr {
+                            SocketAddr::V4(addr) => Some(BValue::ByteStr(Self::compact_addr(addr))),
+                            SocketAddr::V6(_) => None,
+                        })
+                        .collect();
+                    values.insert(b"values".to_vec(), BValue::List(peers));
+                }
+                if let Some(token) = &resp.token {
+                    values.insert(b"token".to_vec(), BValue::ByteStr(token.clone()));
+                }
+
+                dict.insert(b"y".to_vec(), BValue::ByteStr(b"r".to_vec()));
+                dict.insert(b"r".to_vec(), BValue::Dict(values));
+            }
+            Body::Error { code, msg } => {
+                let error = vec![BValue::Int(*code), BValue::ByteStr(msg.as_bytes().to_vec())];
+                dict.insert(b"y".to_vec(), BValue::ByteStr(b"e".to_vec()));
+                dict.insert(b"e".to_vec(), BValue::List(error));
+            }
+        }
+
+        BEncoder::new().add_dict(&dict).encode().clone()
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Msg, Error> {
+        let dict = match BDecoder::from_array(data)?.into_iter().next() {
+            Some(BValue::Dict(dict)) => dict,
+            _ => return Err(Error::DhtInvalidMsg("dictionary")),
+        };
+
+        let tid = match dict.get(b"t".as_slice()) {
+            Some(BValue::ByteStr(tid)) => tid.clone(),
+            _ => return Err(Error::DhtInvalidMsg("t")),
+        };
+
+        let body = match dict.get(b"y".as_slice()) {
+            Some(BValue::ByteStr(y)) if y == b"q" => Self::decode_query(&dict)?,
+            Some(BValue::ByteStr(y)) if y == b"r" => Self::decode_response(&dict)?,
+            Some(BValue::ByteStr(y)) if y == b"e" => Self::decode_error(&dict)?,
+            _ => return Err(Error::DhtInvalidMsg("y")),
+        };
+
+        Ok(Msg { tid, body })
+    }
+
+    fn decode_query(dict: &HashMap<Vec<u8>, BValue>) -> Result<Body, Error> {
+        let args = match dict.get(&b"a".to_vec()) {
+            Some(BValue::Dict(args)) => args,
+            _ => return Err(Error::DhtInvalidMsg("a")),
+        };
+        let id = Self::find_hash(args, b"id")?;
+
+        let query = match dict.get(&b"q".to_vec()) {
+            Some(BValue::ByteStr(q)) => match q.as_slice() {
+                b"ping" => Query::Ping,
+                b"find_node" => Query::FindNode {
+                    target: Self::find_hash(args, b"target")?,
+                },
+                b"get_peers" => Query::GetPeers {
+                    info_hash: Self::find_hash(args, b"info_hash")?,
+                },
+                b"announce_peer" => Query::AnnouncePeer {
+                    info_hash: Self::find_hash(args, b"info_hash")?,
+                    port: match args.get(&b"port".to_vec()) {
+                        Some(BValue::Int(port)) => {
+                            u16::try_from(*port).or(Err(Error::DhtInvalidMsg("port")))?
+                        }
+                        _ => return Err(Error::DhtInvalidMsg("port")),
+                    },
+                    implied_port: matches!(
+                        args.get(&b"implied_port".to_vec()),
+                        Some(BValue::Int(1))
+                    ),
+                    token: match args.get(&b"token".to_vec()) {
+                        Some(BValue::ByteStr(token)) => token.clone(),
+                        _ => return Err(Error::DhtInvalidMsg("token")),
+                    },
+                },
+                _ => return Err(Error::DhtInvalidMsg("q")),
+            },
+            _ => return Err(Error::DhtInvalidMsg("q")),
+        };
+
+        Ok(Body::Query { id, query })
+    }
+
+    fn decode_response(dict: &HashMap<Vec<u8>, BValue>) -> Result<Body, Error> {
+        let values = match dict.get(&b"r".to_vec()) {
+            Some(BValue::Dict(values)) => values,
+            _ => return Err(Error::DhtInvalidMsg("r")),
+        };
+
+        let nodes = match values.get(&b"nodes".to_vec()) {
+            Some(BValue::ByteStr(nodes)) => Self::parse_compact_nodes(nodes),
+            _ => vec![],
+        };
+
+        let peers = match values.get(&b"values".to_vec()) {
+            Some(BValue::List(peers)) => peers
+                .iter()
+                .filter_map(|peer| match peer {
+                    BValue::ByteStr(peer) if peer.len() == COMPACT_PEER_SIZE => Some(peer),
+                    _ => None,
+                })
+                .flat_map(|peer| TrackerResp::compact_addrs(peer, COMPACT_PEER_SIZE))
+                .collect(),
+            _ => vec![],
+        };
+
+        let token = match values.get(&b"token".to_vec()) {
+            Some(BValue::ByteStr(token)) => Some(token.clone()),
+            _ => None,
+        };
+
+        Ok(Body::Response(Response {
+            id: Self::find_hash(values, b"id")?,
+            nodes,
+            values: peers,
+            token,
+        }))
+    }
+
+    fn decode_error(dict: &HashMap<Vec<u8>, BValue>) -> Result<Body, Error> {
+        match dict.get(&b"e".to_vec()) {
+            Some(BValue::List(error)) => match error.as_slice() {
+                [BValue::Int(code), BValue::ByteStr(msg), ..] => Ok(Body::Error {
+                    code: *code,
+                    msg: String::from_utf8_lossy(msg).to_string(),
+                }),
+                _ => Err(Error::DhtInvalidMsg("e")),
+            },
+            _ => Err(Error::DhtInvalidMsg("e")),
+        }
+    }
+
+    fn find_hash(dict: &HashMap<Vec<u8>, BValue>, key: &'static [u8]) -> Result<NodeId, Error> {
+        match dict.get(key) {
+            Some(BValue::ByteStr(hash)) => hash
+                .as_slice()
+                .try_into()
+                .or(Err(Error::DhtInvalidMsg("hash"))),
+            _ => Err(Error::DhtInvalidMsg("hash")),
+        }
+    }
+
+    fn compact_addr(addr: &SocketAddrV4) -> Vec<u8> {
+        let mut data = addr.ip().octets().to_vec();
+        data.extend_from_slice(&addr.port().to_be_bytes());
+        data
+    }
+
+    /// Convert nodes to compact node info. Only IPv4 nodes are included.
+    pub fn compact_nodes(nodes: &[(NodeId, SocketAddr)]) -> Vec<u8> {
+        let mut data = vec![];
+        for (id, addr) in nodes.iter() {
+            if let SocketAddr::V4(addr) = addr {
+                data.extend_from_slice(id);
+                data.extend_from_slice(&Self::compact_addr(addr));
+            }
+        }
+
+        data
+    }
+
+    /// Parse compact node info. Incomplete entry at the end is ignored.
+    pub fn parse_compact_nodes(data: &[u8]) -> Vec<(NodeId, SocketAddr)> {
+        data.chunks_exact(COMPACT_NODE_SIZE)
+            .map(|entry| {
+                let id = entry[..HASH_SIZE].try_into().unwrap();
+                let ip =
+                    Ipv4Addr::from(<[u8; 4]>::try_from(&entry[HASH_SIZE..HASH_SIZE + 4]).unwrap());
+                let port = u16::from_be_bytes(entry[HASH_SIZE + 4..].try_into().unwrap());
+                (id, SocketAddr::from((ip, port)))
+            })
+            .collect()
+    }
+}
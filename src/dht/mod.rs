@@ -0,0 +1,15 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+pub mod krpc;
+pub mod node;
+pub mod routing_table;
+pub mod source;
+
+pub use node::{Dht, DhtNode};
+pub use source::DhtSource;
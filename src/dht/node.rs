@@ -0,0 +1,531 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::bcodec::bencoder::BEncoder;
+use crate::bcodec::bvalue::BValue;
+use crate::commands::DhtCmd;
+use crate::constants::HASH_SIZE;
+use crate::dht::krpc::{Body, Msg, Query, Response, ERROR_PROTOCOL};
+use crate::dht::routing_table::{distance, NodeId, RoutingTable, K};
+use crate::hashmap;
+use crate::{BDecoder, Error};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryInto;
+use std::fs;
+use std::hash::BuildHasher;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tokio::time;
+use tokio::time::{Duration, Instant};
+
+const CHANNEL_SIZE: usize = 64;
+const MAX_DATAGRAM_SIZE: usize = 2048;
+const QUERY_TIMEOUT_SEC: u64 = 3;
+/// Tokens are valid for current and previous time window (5 to 10 minutes).
+const TOKEN_WINDOW_SEC: u64 = 5 * 60;
+const PEER_TTL_SEC: u64 = 30 * 60;
+const SAVE_INTERVAL_SEC: u64 = 10 * 60;
+/// Maximal number of peers in single get_peers response, to fit in one datagram.
+const MAX_VALUES: usize = 50;
+/// Number of parallel queries during lookup.
+const ALPHA: usize = 3;
+
+/// Mainline DHT node ([BEP5](https://www.bittorrent.org/beps/bep_0005.html)), answering queries
+/// from other nodes and sending queries requested by [`Dht`] handles.
+///
+/// Routing table (own ID and known nodes) may be persisted between runs.
+pub struct DhtNode {
+    socket: UdpSocket,
+    id: NodeId,
+    table: RoutingTable,
+    state_path: Option<PathBuf>,
+    transactions: HashMap<Vec<u8>, Transaction>,
+    next_tid: u16,
+    peers: HashMap<[u8; HASH_SIZE], HashMap<SocketAddr, Instant>>,
+    secret: RandomState,
+    started: Instant,
+    tx_ch: mpsc::Sender<DhtCmd>,
+    rx_ch: mpsc::Receiver<DhtCmd>,
+}
+
+#[derive(Debug)]
+struct Transaction {
+    addr: SocketAddr,
+    sent: Instant,
+    resp_ch: oneshot::Sender<Result<Response, Error>>,
+}
+
+/// Handle to running [`DhtNode`]. Can be cloned and shared between tasks.
+#[derive(Debug, Clone)]
+pub struct Dht {
+    id: NodeId,
+    addr: SocketAddr,
+    tx_ch: mpsc::Sender<DhtCmd>,
+}
+
+/// Nodes closest to lookup target (with tokens if get_peers was used) and found peers.
+type LookupResult = (Vec<(NodeId, SocketAddr, Option<Vec<u8>>)>, Vec<SocketAddr>);
+
+impl DhtNode {
+    /// Bind UDP socket. If `state_path` is set and file exists, node ID and routing table are
+    /// restored from it, otherwise random ID is generated.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use rdest::DhtNode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut node = DhtNode::bind(6881, Some("dht.dat".into())).await.unwrap();
+    /// let dht = node.handle();
+    /// tokio::spawn(async move { node.run().await });
+    ///
+    /// let routers = vec!["67.215.246.10:6881".parse().unwrap()];
+    /// dht.bootstrap(&routers).await;
+    /// # }
+    /// ```
+    pub async fn bind(port: u16, state_path: Option<PathBuf>) -> Result<DhtNode, Error> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))
+            .await
+            .or(Err(Error::DhtBind(port)))?;
+
+        let (id, nodes) = match state_path.as_deref().and_then(Self::load_state) {
+            Some((id, nodes)) => (id, nodes),
+            None => (rand::random(), vec![]),
+        };
+
+        let mut table = RoutingTable::new(id);
+        for (id, addr) in nodes {
+            table.insert(id, addr);
+        }
+
+        let (tx_ch, rx_ch) = mpsc::channel(CHANNEL_SIZE);
+
+        Ok(DhtNode {
+            socket,
+            id,
+            table,
+            state_path,
+            transactions: HashMap::new(),
+            next_tid: 0,
+            peers: HashMap::new(),
+            secret: RandomState::new(),
+            started: Instant::now(),
+            tx_ch,
+            rx_ch,
+        })
+    }
+
+    /// Return address on which node is listening.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.socket.local_addr().or(Err(Error::SocketNotAvailable))
+    }
+
+    /// Create handle, used to send queries through this node.
+    pub fn handle(&self) -> Dht {
+        Dht {
+            id: self.id,
+            addr: self
+                .socket
+                .local_addr()
+                .unwrap_or_else(|_| SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))),
+            tx_ch: self.tx_ch.clone(),
+        }
+    }
+
+    /// Serve queries until task is cancelled.
+    pub async fn run(&mut self) {
+        let mut expire_timer = time::interval(Duration::from_secs(1));
+        let save_interval = Duration::from_secs(SAVE_INTERVAL_SEC);
+        let mut save_timer = time::interval_at(Instant::now() + save_interval, save_interval);
+        let mut buff = vec![0; MAX_DATAGRAM_SIZE];
+
+        loop {
+            tokio::select! {
+                Ok((len, addr)) = self.socket.recv_from(&mut buff) => {
+                    if let Some(resp) = self.handle_datagram(&buff[..len], addr) {
+                        let _ = self.socket.send_to(&resp, addr).await;
+                    }
+                }
+                Some(cmd) = self.rx_ch.recv() => self.handle_cmd(cmd).await,
+                _ = expire_timer.tick() => self.expire(),
+                _ = save_timer.tick() => {
+                    let _ = self.save();
+                }
+            }
+        }
+    }
+
+    fn load_state(path: &Path) -> Option<(NodeId, Vec<(NodeId, SocketAddr)>)> {
+        let data = fs::read(path).ok()?;
+        let dict = match BDecoder::from_array(&data).ok()?.into_iter().next()? {
+            BValue::Dict(dict) => dict,
+            _ => return None,
+        };
+
+        let id = match dict.get(b"id".as_slice())? {
+            BValue::ByteStr(id) => id.as_slice().try_into().ok()?,
+            _ => return None,
+        };
+        let nodes = match dict.get(b"nodes".as_slice()) {
+            Some(BValue::ByteStr(nodes)) => Msg::parse_compact_nodes(nodes),
+            _ => vec![],
+        };
+
+        Some((id, nodes))
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let path = match &self.state_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let dict = hashmap![
+            b"id".to_vec() => BValue::ByteStr(self.id.to_vec()),
+            b"nodes".to_vec() => BValue::ByteStr(Msg::compact_nodes(&self.table.nodes()))
+        ];
+        let data = BEncoder::new().add_dict(&dict).encode().clone();
+
+        fs::write(path, data).or(Err(Error::FileCannotWrite))
+    }
+
+    fn handle_datagram(&mut self, data: &[u8], addr: SocketAddr) -> Option<Vec<u8>> {
+        let msg = Msg::decode(data).ok()?;
+
+        match msg.body {
+            Body::Query { id, query } => {
+                self.table.insert(id, addr);
+                let body = self.handle_query(&query, addr);
+                Some(Msg { tid: msg.tid, body }.encode())
+            }
+            Body::Response(resp) => {
+                if let Some(transaction) = self.take_transaction(&msg.tid, addr) {
+                    self.table.insert(resp.id, addr);
+                    let _ = transaction.resp_ch.send(Ok(resp));
+                }
+                None
+            }
+            Body::Error { code, msg: text } => {
+                if let Some(transaction) = self.take_transaction(&msg.tid, addr) {
+                    let _ = transaction
+                        .resp_ch
+                        .send(Err(Error::DhtErrorResp(code, text)));
+                }
+                None
+            }
+        }
+    }
+
+    /// Remove pending transaction, but only if response came from queried node.
+    fn take_transaction(&mut self, tid: &[u8], addr: SocketAddr) -> Option<Transaction> {
+        match self.transactions.get(tid) {
+            Some(transaction) if transaction.addr == addr => self.transactions.remove(tid),
+            _ => None,
+        }
+    }
+
+    fn handle_query(&mut self, query: &Query, addr: SocketAddr) -> Body {
+        let mut resp = Response {
+            id: self.id,
+            ..Default::default()
+        };
+
+        match query {
+            Query::Ping => (),
+            Query::FindNode { target } => resp.nodes = self.table.closest(target, K),
+            Query::GetPeers { info_hash } => {
+                resp.values = match self.peers.get(info_hash) {
+                    Some(peers) => peers.keys().take(MAX_VALUES).copied().collect(),
+                    None => vec![],
+                };
+                if resp.values.is_empty() {
+                    resp.nodes = self.table.closest(info_hash, K);
+                }
+                resp.token = Some(self.token(addr.ip(), 0));
+            }
+            Query::AnnouncePeer {
+                info_hash,
+                port,
+                implied_port,
+                token,
+            } => {
+                if *token != self.token(addr.ip(), 0) && *token != self.token(addr.ip(), 1) {
+                    return Body::Error {
+                        code: ERROR_PROTOCOL,
+                        msg: "bad token".to_string(),
+                    };
+                }
+
+                let port = if *implied_port { addr.port() } else { *port };
+                self.peers
+                    .entry(*info_hash)
+                    .or_default()
+                    .insert(SocketAddr::new(addr.ip(), port), Instant::now());
+            }
+        }
+
+        Body::Response(resp)
+    }
+
+    /// Token is derived from querying node IP and time, so no state have to be stored.
+    /// `age` select previous time windows.
+    fn token(&self, ip: IpAddr, age: u64) -> Vec<u8> {
+        let window = (self.started.elapsed().as_secs() / TOKEN_WINDOW_SEC).saturating_sub(age);
+        self.secret.hash_one((ip, window)).to_be_bytes().to_vec()
+    }
+
+    async fn handle_cmd(&mut self, cmd: DhtCmd) {
+        match cmd {
+            DhtCmd::Query {
+                addr,
+                query,
+                resp_ch,
+            } => {
+                let tid = self.next_tid.to_be_bytes().to_vec();
+                self.next_tid = self.next_tid.wrapping_add(1);
+                let msg = Msg {
+                    tid: tid.clone(),
+                    body: Body::Query { id: self.id, query },
+                };
+
+                match self.socket.send_to(&msg.encode(), addr).await {
+                    Ok(_) => {
+                        self.transactions.insert(
+                            tid,
+                            Transaction {
+                                addr,
+                                sent: Instant::now(),
+                                resp_ch,
+                            },
+                        );
+                    }
+                    Err(_) => {
+                        let _ = resp_ch.send(Err(Error::SocketNotAvailable));
+                    }
+                }
+            }
+            DhtCmd::Closest {
+                target,
+                count,
+                resp_ch,
+            } => {
+                let _ = resp_ch.send(self.table.closest(&target, count));
+            }
+            DhtCmd::Save { resp_ch } => {
+                let _ = resp_ch.send(self.save());
+            }
+        }
+    }
+
+    /// Fail queries without response, and forget peers that didn't announce for long time.
+    fn expire(&mut self) {
+        let timeout = Duration::from_secs(QUERY_TIMEOUT_SEC);
+        let expired: Vec<_> = self
+            .transactions
+            .iter()
+            .filter(|(_, t)| t.sent.elapsed() >= timeout)
+            .map(|(tid, _)| tid.clone())
+            .collect();
+
+        for tid in expired {
+            if let Some(transaction) = self.transactions.remove(&tid) {
+                self.table.failed(&transaction.addr);
+                let _ = transaction.resp_ch.send(Err(Error::DhtTimeout));
+            }
+        }
+
+        let ttl = Duration::from_secs(PEER_TTL_SEC);
+        for peers in self.peers.values_mut() {
+            peers.retain(|_, last_seen| last_seen.elapsed() < ttl);
+        }
+        self.peers.retain(|_, peers| !peers.is_empty());
+    }
+}
+
+impl Dht {
+    /// Own node ID.
+    pub fn id(&self) -> &[u8; HASH_SIZE] {
+        &self.id
+    }
+
+    /// Address on which node is listening.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    async fn query(&self, addr: SocketAddr, query: Query) -> Result<Response, Error> {
+        let (resp_ch, resp_rx) = oneshot::channel();
+        self.tx_ch
+            .send(DhtCmd::Query {
+                addr,
+                query,
+                resp_ch,
+            })
+            .await
+            .or(Err(Error::DhtNotRunning))?;
+
+        resp_rx.await.or(Err(Error::DhtNotRunning))?
+    }
+
+    /// Send ping query. On success node is added to routing table, and its ID is returned.
+    pub async fn ping(&self, addr: SocketAddr) -> Result<[u8; HASH_SIZE], Error> {
+        Ok(self.query(addr, Query::Ping).await?.id)
+    }
+
+    /// Known nodes, closest to own ID first.
+    pub async fn nodes(&self) -> Result<Vec<([u8; HASH_SIZE], SocketAddr)>, Error> {
+        self.closest(self.id, usize::MAX).await
+    }
+
+    async fn closest(
+        &self,
+        target: NodeId,
+        count: usize,
+    ) -> Result<Vec<(NodeId, SocketAddr)>, Error> {
+        let (resp_ch, resp_rx) = oneshot::channel();
+        self.tx_ch
+            .send(DhtCmd::Closest {
+                target,
+                count,
+                resp_ch,
+            })
+            .await
+            .or(Err(Error::DhtNotRunning))?;
+
+        resp_rx.await.or(Err(Error::DhtNotRunning))
+    }
+
+    /// Join the network through given nodes (routers or nodes from metainfo), by looking up own
+    /// ID. Return number of nodes in routing table.
+    pub async fn bootstrap(&self, addrs: &[SocketAddr]) -> Result<usize, Error> {
+        let mut jobs = JoinSet::new();
+        for addr in addrs.iter().copied() {
+            let dht = self.clone();
+            jobs.spawn(async move { dht.query(addr, Query::FindNode { target: dht.id }).await });
+        }
+        while jobs.join_next().await.is_some() {}
+
+        self.lookup(self.id, false).await?;
+        Ok(self.nodes().await?.len())
+    }
+
+    /// Find peers for torrent.
+    pub async fn get_peers(&self, info_hash: &[u8; HASH_SIZE]) -> Result<Vec<SocketAddr>, Error> {
+        Ok(self.lookup(*info_hash, true).await?.1)
+    }
+
+    /// Find peers for torrent, and announce that this client downloads it on `port`, to the
+    /// closest nodes. Return found peers.
+    pub async fn announce_peer(
+        &self,
+        info_hash: &[u8; HASH_SIZE],
+        port: u16,
+    ) -> Result<Vec<SocketAddr>, Error> {
+        let (nodes, peers) = self.lookup(*info_hash, true).await?;
+
+        let mut jobs = JoinSet::new();
+        for (_, addr, token) in nodes {
+            if let Some(token) = token {
+                let dht = self.clone();
+                let query = Query::AnnouncePeer {
+                    info_hash: *info_hash,
+                    port,
+                    implied_port: false,
+                    token,
+                };
+                jobs.spawn(async move { dht.query(addr, query).await });
+            }
+        }
+        while jobs.join_next().await.is_some() {}
+
+        Ok(peers)
+    }
+
+    /// Write routing table to state file (if node has one).
+    pub async fn save(&self) -> Result<(), Error> {
+        let (resp_ch, resp_rx) = oneshot::channel();
+        self.tx_ch
+            .send(DhtCmd::Save { resp_ch })
+            .await
+            .or(Err(Error::DhtNotRunning))?;
+
+        resp_rx.await.or(Err(Error::DhtNotRunning))?
+    }
+
+    /// Iterative Kademlia lookup. Up to ALPHA queries are in flight, and lookup ends when K
+    /// closest nodes responded or there is no one else to ask.
+    async fn lookup(&self, target: NodeId, get_peers: bool) -> Result<LookupResult, Error> {
+        let mut candidates: BTreeMap<NodeId, (NodeId, SocketAddr)> = self
+            .closest(target, K)
+            .await?
+            .into_iter()
+            .map(|(id, addr)| (distance(&id, &target), (id, addr)))
+            .collect();
+        let mut queried = HashSet::new();
+        let mut found = BTreeMap::new();
+        let mut peers = HashSet::new();
+        let mut jobs = JoinSet::new();
+
+        loop {
+            while jobs.len() < ALPHA {
+                let next = candidates
+                    .iter()
+                    .find(|(dist, (_, addr))| {
+                        !queried.contains(addr)
+                            && (found.len() < K || found.keys().nth(K - 1) > Some(*dist))
+                    })
+                    .map(|(_, (_, addr))| *addr);
+
+                let addr = match next {
+                    Some(addr) => addr,
+                    None => break,
+                };
+                queried.insert(addr);
+
+                let dht = self.clone();
+                let query = match get_peers {
+                    true => Query::GetPeers { info_hash: target },
+                    false => Query::FindNode { target },
+                };
+                jobs.spawn(async move { (addr, dht.query(addr, query).await) });
+            }
+
+            let (addr, resp) = match jobs.join_next().await {
+                Some(Ok(result)) => result,
+                Some(Err(_)) => continue,
+                None => break,
+            };
+
+            match resp {
+                Ok(resp) => {
+                    for (id, addr) in resp.nodes {
+                        if id != self.id {
+                            candidates
+                                .entry(distance(&id, &target))
+                                .or_insert((id, addr));
+                        }
+                    }
+                    peers.extend(resp.values);
+                    found.insert(distance(&resp.id, &target), (resp.id, addr, resp.token));
+                }
+                Err(Error::DhtNotRunning) => return Err(Error::DhtNotRunning),
+                Err(_) => (),
+            }
+        }
+
+        Ok((
+            found.into_values().take(K).collect(),
+            peers.into_iter().collect(),
+        ))
+    }
+}
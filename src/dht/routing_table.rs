@@ -0,0 +1,137 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::HASH_SIZE;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// 160-bit node ID, in the same space as info hashes.
+pub type NodeId = [u8; HASH_SIZE];
+
+/// Bucket size.
+pub const K: usize = 8;
+/// Number of failed queries after which node is replaced by new one.
+const MAX_FAILS: u32 = 2;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    id: NodeId,
+    addr: SocketAddr,
+    fails: u32,
+    last_seen: Instant,
+}
+
+/// Kademlia routing table. Bucket `i` holds nodes, which XOR distance to own ID has `i` leading
+/// zero bits, so buckets close to own ID are never split but are rarely full.
+#[derive(Debug)]
+pub struct RoutingTable {
+    own_id: NodeId,
+    buckets: Vec<Vec<Entry>>,
+}
+
+/// XOR distance between two ID's.
+pub fn distance(a: &NodeId, b: &NodeId) -> NodeId {
+    let mut dist = [0; HASH_SIZE];
+    for (d, (a, b)) in dist.iter_mut().zip(a.iter().zip(b.iter())) {
+        *d = a ^ b;
+    }
+
+    dist
+}
+
+impl RoutingTable {
+    pub fn new(own_id: NodeId) -> RoutingTable {
+        RoutingTable {
+            own_id,
+            buckets: vec![vec![]; HASH_SIZE * 8],
+        }
+    }
+
+    fn bucket_index(&self, id: &NodeId) -> Option<usize> {
+        let dist = distance(&self.own_id, id);
+        let mut zeros = 0;
+        for byte in dist.iter() {
+            if *byte != 0 {
+                return Some(zeros + byte.leading_zeros() as usize);
+            }
+            zeros += 8;
+        }
+
+        None
+    }
+
+    /// Insert node or refresh it, if already known. When bucket is full, node with most failed
+    /// queries is replaced, if any. Return true if node is in table.
+    pub fn insert(&mut self, id: NodeId, addr: SocketAddr) -> bool {
+        let index = match self.bucket_index(&id) {
+            Some(index) => index,
+            None => return false,
+        };
+        let bucket = &mut self.buckets[index];
+
+        if let Some(entry) = bucket.iter_mut().find(|e| e.id == id) {
+            entry.addr = addr;
+            entry.fails = 0;
+            entry.last_seen = Instant::now();
+            return true;
+        }
+
+        let entry = Entry {
+            id,
+            addr,
+            fails: 0,
+            last_seen: Instant::now(),
+        };
+
+        if bucket.len() < K {
+            bucket.push(entry);
+            return true;
+        }
+
+        match bucket.iter().position(|e| e.fails >= MAX_FAILS) {
+            Some(pos) => {
+                bucket[pos] = entry;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Note that node didn't respond.
+    pub fn failed(&mut self, addr: &SocketAddr) {
+        for bucket in self.buckets.iter_mut() {
+            if let Some(entry) = bucket.iter_mut().find(|e| e.addr == *addr) {
+                entry.fails += 1;
+                return;
+            }
+        }
+    }
+
+    /// Up to `count` good nodes closest to target.
+    pub fn closest(&self, target: &NodeId, count: usize) -> Vec<(NodeId, SocketAddr)> {
+        let mut nodes: Vec<_> = self
+            .buckets
+            .iter()
+            .flatten()
+            .filter(|e| e.fails < MAX_FAILS)
+            .map(|e| (e.id, e.addr))
+            .collect();
+        nodes.sort_by_key(|(id, _)| distance(id, target));
+        nodes.truncate(count);
+
+        nodes
+    }
+
+    /// All nodes, most recently seen first.
+    pub fn nodes(&self) -> Vec<(NodeId, SocketAddr)> {
+        let mut entries: Vec<_> = self.buckets.iter().flatten().collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.last_seen));
+
+        entries.iter().map(|e| (e.id, e.addr)).collect()
+    }
+}
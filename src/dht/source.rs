@@ -0,0 +1,90 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::HASH_SIZE;
+use crate::{Dht, Error, PeerSource, PeerSourceEvent, PeerSourceFuture};
+use std::net::SocketAddr;
+use tokio::time;
+use tokio::time::{Duration, Instant};
+
+const ANNOUNCE_INTERVAL_SEC: u64 = 15 * 60;
+const RETRY_INTERVAL_SEC: u64 = 60;
+const MIN_INTERVAL_SEC: u64 = 60;
+
+/// Peer source looking up torrent in DHT, and announcing client in it.
+#[derive(Debug)]
+pub struct DhtSource {
+    dht: Dht,
+    info_hash: [u8; HASH_SIZE],
+    port: u16,
+    next_announce: Instant,
+    last_announce: Option<Instant>,
+}
+
+impl DhtSource {
+    /// Create source for torrent, announcing that client listens on `port`.
+    pub fn new(dht: Dht, info_hash: [u8; HASH_SIZE], port: u16) -> DhtSource {
+        DhtSource {
+            dht,
+            info_hash,
+            port,
+            next_announce: Instant::now(),
+            last_announce: None,
+        }
+    }
+}
+
+impl PeerSource for DhtSource {
+    fn name(&self) -> String {
+        "dht".to_string()
+    }
+
+    fn set_port(&mut self, port: u16) {
+        self.port = port;
+    }
+
+    /// Peers from DHT connect from internet, so mapped port is announced.
+    fn external_addr(&mut self, addr: SocketAddr) {
+        self.port = addr.port();
+    }
+
+    fn next(&mut self) -> PeerSourceFuture<'_, Option<PeerSourceEvent>> {
+        Box::pin(async move {
+            loop {
+                time::sleep_until(self.next_announce).await;
+                let result = self.dht.announce_peer(&self.info_hash, self.port).await;
+
+                let now = Instant::now();
+                self.last_announce = Some(now);
+                match result {
+                    Ok(peers) if !peers.is_empty() => {
+                        self.next_announce = now + Duration::from_secs(ANNOUNCE_INTERVAL_SEC);
+                        return Some(PeerSourceEvent::Peers(
+                            peers.into_iter().map(|addr| (addr, None)).collect(),
+                        ));
+                    }
+                    // Swarm may be empty or network not yet bootstrapped, so try again soon
+                    Ok(_) => self.next_announce = now + Duration::from_secs(RETRY_INTERVAL_SEC),
+                    Err(Error::DhtNotRunning) => return None,
+                    Err(e) => {
+                        self.next_announce = now + Duration::from_secs(RETRY_INTERVAL_SEC);
+                        return Some(PeerSourceEvent::Fail(e.to_string()));
+                    }
+                }
+            }
+        })
+    }
+
+    fn announce(&mut self) {
+        if let Some(last_announce) = self.last_announce {
+            self.next_announce = self
+                .next_announce
+                .min(last_announce + Duration::from_secs(MIN_INTERVAL_SEC));
+        }
+    }
+}
@@ -77,6 +77,62 @@ pub enum Error {
     TrackerIncorrectOrMissing(&'static str),
     /// Tracker replay with error.
     TrackerRespFail(String),
+    /// Tracker URL is incorrect or can't be resolved.
+    TrackerInvalidUrl(String),
+    /// Tracker doesn't respond (all retransmissions failed).
+    TrackerTimeout,
+    /// Scrape URL can't be derived from announce URL.
+    TrackerScrapeUnsupported,
+    /// Tracker server can't listen on requested port.
+    TrackerServerBind(u16),
+    /// DHT node can't listen on requested port.
+    DhtBind(u16),
+    /// DHT node doesn't respond to query.
+    DhtTimeout,
+    /// DHT node replay with error (code and message).
+    DhtErrorResp(i64, String),
+    /// Incorrect or missing fields in KRPC message.
+    DhtInvalidMsg(&'static str),
+    /// DHT node task is not running.
+    DhtNotRunning,
+    /// Incorrect or missing fields in extension message.
+    ExtensionIncorrectOrMissing(&'static str),
+    /// Peer sent Fast Extension message, but extension wasn't negotiated in handshake.
+    FastNotNegotiated(&'static str),
+    /// Encrypted handshake (MSE) failed, or peer doesn't meet encryption policy.
+    MseFail(&'static str),
+    /// Incorrect or missing fields in Local Service Discovery message.
+    LsdInvalidMsg(&'static str),
+    /// Incorrect holepunch extension message.
+    HolepunchInvalidMsg(&'static str),
+    /// uTP socket can't listen on requested port.
+    UtpBind(u16),
+    /// uTP peer doesn't respond (connection can't be established or was lost).
+    UtpTimeout,
+    /// Incorrect uTP packet.
+    UtpInvalidPacket(&'static str),
+    /// uTP socket task is not running.
+    UtpNotRunning,
+    /// No free port in listen range (first and last port).
+    ListenBind(u16, u16),
+    /// Gateway supporting port mapping not found.
+    NatGatewayNotFound,
+    /// Gateway didn't respond.
+    NatTimeout,
+    /// Invalid gateway response.
+    NatInvalidResp(&'static str),
+    /// Gateway refused to map port (result or error code).
+    NatRefused(String),
+    /// Proxy URL is incorrect.
+    ProxyInvalidUrl(String),
+    /// Can't connect to proxy.
+    ProxyUnreachable,
+    /// Proxy rejected credentials (or requires them, but none are set).
+    ProxyAuthFail,
+    /// Proxy refused to open connection (reply code or HTTP status).
+    ProxyRefused(String),
+    /// Invalid proxy response.
+    ProxyInvalidResp(&'static str),
     /// Missing metainfo file.
     MetaFileNotFound,
     /// Missing [bencoded](https://en.wikipedia.org/wiki/Bencode) data in metainfo.
@@ -95,6 +151,12 @@ pub enum Error {
     MetaInvalidU64(&'static str),
     /// Not enough data to extract SHA-1 hashes.
     MetaNotDivisible(&'static str),
+    /// Torrent signature (BEP35) doesn't match info dictionary or embedded certificate.
+    MetaInvalidSignature(String),
+    /// Torrent isn't signed by trusted signer.
+    MetaUntrustedSigner(String),
+    /// Can't load signing key or certificate.
+    SignInvalidKey(&'static str),
 }
 
 impl fmt::Display for Error {
@@ -149,6 +211,50 @@ impl fmt::Display for Error {
                 write!(f, "Tracker, incorrect or missing '{}' value", name)
             }
             Error::TrackerRespFail(reason) => write!(f, "Tracker fail: {}", reason),
+            Error::TrackerInvalidUrl(url) => write!(f, "Tracker, invalid URL '{}'", url),
+            Error::TrackerTimeout => write!(f, "Tracker, timeout"),
+            Error::TrackerScrapeUnsupported => write!(f, "Tracker, scrape not supported"),
+            Error::TrackerServerBind(port) => write!(f, "Tracker server, can't bind port {}", port),
+            Error::DhtBind(port) => write!(f, "DHT, can't bind port {}", port),
+            Error::DhtTimeout => write!(f, "DHT, timeout"),
+            Error::DhtErrorResp(code, msg) => write!(f, "DHT, error {}: {}", code, msg),
+            Error::DhtInvalidMsg(name) => {
+                write!(f, "DHT, incorrect or missing '{}' value in message", name)
+            }
+            Error::DhtNotRunning => write!(f, "DHT, node is not running"),
+            Error::ExtensionIncorrectOrMissing(name) => {
+                write!(f, "Extension, incorrect or missing '{}' value", name)
+            }
+            Error::FastNotNegotiated(name) => {
+                write!(f, "Fast extension not negotiated, but '{}' received", name)
+            }
+            Error::MseFail(reason) => write!(f, "Encryption handshake fail, {}", reason),
+            Error::LsdInvalidMsg(name) => {
+                write!(f, "LSD, incorrect or missing '{}' in message", name)
+            }
+            Error::HolepunchInvalidMsg(name) => {
+                write!(f, "Holepunch, incorrect '{}' in message", name)
+            }
+            Error::UtpBind(port) => write!(f, "uTP, can't bind port {}", port),
+            Error::UtpTimeout => write!(f, "uTP, timeout"),
+            Error::UtpInvalidPacket(reason) => write!(f, "uTP, invalid packet: {}", reason),
+            Error::UtpNotRunning => write!(f, "uTP, socket is not running"),
+            Error::ListenBind(first, last) => {
+                write!(
+                    f,
+                    "Session, can't bind any port in range {}-{}",
+                    first, last
+                )
+            }
+            Error::NatGatewayNotFound => write!(f, "NAT, gateway not found"),
+            Error::NatTimeout => write!(f, "NAT, gateway timeout"),
+            Error::NatInvalidResp(reason) => write!(f, "NAT, invalid response: {}", reason),
+            Error::NatRefused(reason) => write!(f, "NAT, mapping refused: {}", reason),
+            Error::ProxyInvalidUrl(url) => write!(f, "Proxy, invalid URL: {}", url),
+            Error::ProxyUnreachable => write!(f, "Proxy, can't connect"),
+            Error::ProxyAuthFail => write!(f, "Proxy, authentication failed"),
+            Error::ProxyRefused(reason) => write!(f, "Proxy, connection refused: {}", reason),
+            Error::ProxyInvalidResp(reason) => write!(f, "Proxy, invalid response: {}", reason),
             Error::MetaFileNotFound => write!(f, "Metainfo, file not found"),
             Error::MetaBEncodeMissing => write!(f, "Metainfo, bencode is missing"),
             Error::MetaDataMissing => write!(f, "Metainfo, data is missing"),
@@ -165,6 +271,13 @@ impl fmt::Display for Error {
             }
             Error::MetaInvalidU64(name) => write!(f, "Metainfo, can't convert '{}' to u64", name),
             Error::MetaNotDivisible(name) => write!(f, "Metainfo, '{}' not divisible by 20", name),
+            Error::MetaInvalidSignature(identity) => {
+                write!(f, "Metainfo, invalid signature of '{}'", identity)
+            }
+            Error::MetaUntrustedSigner(identity) => {
+                write!(f, "Metainfo, torrent not signed by '{}'", identity)
+            }
+            Error::SignInvalidKey(reason) => write!(f, "Signing, invalid {}", reason),
         }
     }
 }
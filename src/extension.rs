@@ -0,0 +1,334 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::bcodec::bencoder::BEncoder;
+use crate::constants::REQQ;
+use crate::{BDecoder, BValue, Error};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// Extension handshake ([BEP10](https://www.bittorrent.org/beps/bep_0010.html)), send as
+/// extended message with ID 0.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct ExtendedHandshake {
+    /// Supported extensions and message ID's assigned to them by sender (0 means disabled)
+    pub extensions: HashMap<String, u8>,
+    /// Client name and version
+    pub client: Option<String>,
+    /// Sender listen port
+    pub port: Option<u16>,
+    /// Number of outstanding requests sender can handle
+    pub reqq: Option<u32>,
+    /// Receiver IP address, as seen by sender
+    pub your_ip: Option<IpAddr>,
+    /// Size of info dictionary
+    pub metadata_size: Option<u64>,
+    /// Sender only uploads (it's seed or partial seed, see
+    /// [BEP21](https://www.bittorrent.org/beps/bep_0021.html))
+    pub upload_only: Option<bool>,
+}
+
+impl ExtendedHandshake {
+    /// Decode handshake payload. Unknown keys and incorrect optional values are ignored.
+    ///
+    /// # Example
+    /// ```
+    /// use rdest::ExtendedHandshake;
+    ///
+    /// let handshake = ExtendedHandshake::from_bencode(b"d1:md6:ut_pexi1ee1:v5:rdeste").unwrap();
+    /// assert_eq!(handshake.extensions.get("ut_pex"), Some(&1));
+    /// assert_eq!(handshake.client, Some("rdest".to_string()));
+    /// ```
+    pub fn from_bencode(data: &[u8]) -> Result<ExtendedHandshake, Error> {
+        let dict = match BDecoder::from_array(data)?.into_iter().next() {
+            Some(BValue::Dict(dict)) => dict,
+            _ => return Err(Error::ExtensionIncorrectOrMissing("dictionary")),
+        };
+
+        let extensions = match dict.get(b"m".as_slice()) {
+            Some(BValue::Dict(m)) => m
+                .iter()
+                .filter_map(|(name, id)| match id {
+                    BValue::Int(id) => Some((
+                        String::from_utf8(name.clone()).ok()?,
+                        u8::try_from(*id).ok()?,
+                    )),
+                    _ => None,
+                })
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        let client = match dict.get(b"v".as_slice()) {
+            Some(BValue::ByteStr(v)) => Some(String::from_utf8_lossy(v).to_string()),
+            _ => None,
+        };
+
+        let your_ip = match dict.get(b"yourip".as_slice()) {
+            Some(BValue::ByteStr(ip)) => match ip.len() {
+                4 => Some(IpAddr::from(<[u8; 4]>::try_from(ip.as_slice()).unwrap())),
+                16 => Some(IpAddr::from(<[u8; 16]>::try_from(ip.as_slice()).unwrap())),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        Ok(ExtendedHandshake {
+            extensions,
+            client,
+            port: Self::find_int(&dict, b"p").and_then(|p| u16::try_from(p).ok()),
+            reqq: Self::find_int(&dict, b"reqq").and_then(|r| u32::try_from(r).ok()),
+            your_ip,
+            metadata_size: Self::find_int(&dict, b"metadata_size")
+                .and_then(|s| u64::try_from(s).ok()),
+            upload_only: Self::find_int(&dict, b"upload_only").map(|u| u != 0),
+        })
+    }
+
+    fn find_int(dict: &HashMap<Vec<u8>, BValue>, key: &[u8]) -> Option<i64> {
+        match dict.get(key) {
+            Some(BValue::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Encode handshake payload.
+    pub fn encode(&self) -> Vec<u8> {
+        let m = self
+            .extensions
+            .iter()
+            .map(|(name, id)| (name.as_bytes().to_vec(), BValue::Int(*id as i64)))
+            .collect();
+
+        let mut dict = HashMap::new();
+        dict.insert(b"m".to_vec(), BValue::Dict(m));
+        if let Some(client) = &self.client {
+            dict.insert(b"v".to_vec(), BValue::ByteStr(client.as_bytes().to_vec()));
+        }
+        if let Some(port) = self.port {
+            dict.insert(b"p".to_vec(), BValue::Int(port as i64));
+        }
+        if let Some(reqq) = self.reqq {
+            dict.insert(b"reqq".to_vec(), BValue::Int(reqq as i64));
+        }
+        if let Some(ip) = self.your_ip {
+            let ip = match ip {
+                IpAddr::V4(ip) => ip.octets().to_vec(),
+                IpAddr::V6(ip) => ip.octets().to_vec(),
+            };
+            dict.insert(b"yourip".to_vec(), BValue::ByteStr(ip));
+        }
+        if let Some(metadata_size) = self.metadata_size {
+            dict.insert(b"metadata_size".to_vec(), BValue::Int(metadata_size as i64));
+        }
+        if let Some(upload_only) = self.upload_only {
+            dict.insert(b"upload_only".to_vec(), BValue::Int(upload_only as i64));
+        }
+
+        BEncoder::new().add_dict(&dict).encode().clone()
+    }
+}
+
+/// Handler of extension messages, created for every peer connection that supports extension
+/// protocol. Returned payloads are send to peer, with message ID assigned by peer.
+///
+/// # Example
+/// ```
+/// use rdest::{Error, Extension, ExtensionRegistry};
+///
+/// /// Reply with the same payload
+/// struct Echo;
+///
+/// impl Extension for Echo {
+///     fn on_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+///         Ok(vec![payload.to_vec()])
+///     }
+/// }
+///
+/// let mut registry = ExtensionRegistry::new();
+/// registry.register("echo", |_addr| Box::new(Echo));
+/// ```
+pub trait Extension: Send {
+    /// Peer sent extension handshake, and supports this extension.
+    fn on_handshake(&mut self, _handshake: &ExtendedHandshake) -> Vec<Vec<u8>> {
+        vec![]
+    }
+
+    /// Message from peer.
+    fn on_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, Error>;
+
+    /// Called periodically, e.g. to send state updates.
+    fn on_tick(&mut self) -> Vec<Vec<u8>> {
+        vec![]
+    }
+}
+
+/// Creates extension handler for peer with given address.
+pub type ExtensionFactory = Arc<dyn Fn(SocketAddr) -> Box<dyn Extension> + Send + Sync>;
+
+/// Extensions supported by client. Message ID's are assigned in registration order, starting
+/// from 1.
+#[derive(Clone, Default)]
+pub struct ExtensionRegistry {
+    factories: Vec<(String, ExtensionFactory)>,
+}
+
+impl fmt::Debug for ExtensionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.factories.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+impl ExtensionRegistry {
+    /// Create empty registry.
+    pub fn new() -> ExtensionRegistry {
+        ExtensionRegistry { factories: vec![] }
+    }
+
+    /// Register extension under name used in `m` dictionary (e.g. "ut_pex"). Extension with the
+    /// same name is replaced.
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(SocketAddr) -> Box<dyn Extension> + Send + Sync + 'static,
+    {
+        let factory: ExtensionFactory = Arc::new(factory);
+        match self.factories.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = factory,
+            None => self.factories.push((name.to_string(), factory)),
+        }
+    }
+
+    /// Names of registered extensions.
+    pub fn names(&self) -> Vec<String> {
+        self.factories
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Create handlers for new connection.
+    pub fn create(&self, addr: SocketAddr) -> PeerExtensions {
+        PeerExtensions {
+            handlers: self
+                .factories
+                .iter()
+                .map(|(name, factory)| (name.clone(), factory(addr)))
+                .collect(),
+            remote_ids: HashMap::new(),
+            metadata_size: None,
+            upload_only: false,
+        }
+    }
+}
+
+/// Extension handlers for single peer, routing extended messages between them and peer.
+/// Outgoing messages are returned as pairs of peer's message ID and payload.
+pub struct PeerExtensions {
+    handlers: Vec<(String, Box<dyn Extension>)>,
+    remote_ids: HashMap<String, u8>,
+    metadata_size: Option<u64>,
+    upload_only: bool,
+}
+
+impl PeerExtensions {
+    /// Size of info dictionary, reported in own handshake.
+    pub fn set_metadata_size(&mut self, metadata_size: u64) {
+        self.metadata_size = Some(metadata_size);
+    }
+
+    /// Client only uploads, reported in own handshake.
+    pub fn set_upload_only(&mut self, upload_only: bool) {
+        self.upload_only = upload_only;
+    }
+
+    /// Check if client only uploads.
+    pub fn upload_only(&self) -> bool {
+        self.upload_only
+    }
+
+    /// Own handshake, with registered extensions and client details.
+    pub fn handshake(&self) -> ExtendedHandshake {
+        ExtendedHandshake {
+            extensions: self
+                .handlers
+                .iter()
+                .enumerate()
+                .map(|(idx, (name, _))| (name.clone(), idx as u8 + 1))
+                .collect(),
+            client: Some(format!("rdest {}", env!("CARGO_PKG_VERSION"))),
+            reqq: Some(REQQ),
+            metadata_size: self.metadata_size,
+            upload_only: Some(self.upload_only),
+            ..Default::default()
+        }
+    }
+
+    /// Remember ID's assigned by peer, and notify extensions supported by both sides.
+    pub fn on_handshake(&mut self, handshake: &ExtendedHandshake) -> Vec<(u8, Vec<u8>)> {
+        // Subsequent handshakes may update only part of dictionary
+        for (name, id) in handshake.extensions.iter() {
+            self.remote_ids.insert(name.clone(), *id);
+        }
+
+        let mut msgs = vec![];
+        for (name, handler) in self.handlers.iter_mut() {
+            if let Some(id) = self.remote_ids.get(name).filter(|id| **id != 0) {
+                msgs.extend(
+                    handler
+                        .on_handshake(handshake)
+                        .into_iter()
+                        .map(|p| (*id, p)),
+                );
+            }
+        }
+
+        msgs
+    }
+
+    /// Route message to extension, by ID assigned in own handshake. Messages with unknown ID
+    /// are ignored.
+    pub fn on_message(&mut self, ext_id: u8, payload: &[u8]) -> Result<Vec<(u8, Vec<u8>)>, Error> {
+        let (name, handler) = match self.handlers.get_mut((ext_id as usize).wrapping_sub(1)) {
+            Some((name, handler)) => (name, handler),
+            None => return Ok(vec![]),
+        };
+
+        let payloads = handler.on_message(payload)?;
+        Ok(match self.remote_ids.get(name).filter(|id| **id != 0) {
+            Some(id) => payloads.into_iter().map(|p| (*id, p)).collect(),
+            None => vec![],
+        })
+    }
+
+    /// Collect periodic messages from extensions supported by peer.
+    pub fn on_tick(&mut self) -> Vec<(u8, Vec<u8>)> {
+        let mut msgs = vec![];
+        for (name, handler) in self.handlers.iter_mut() {
+            if let Some(id) = self.remote_ids.get(name).filter(|id| **id != 0) {
+                msgs.extend(handler.on_tick().into_iter().map(|p| (*id, p)));
+            }
+        }
+
+        msgs
+    }
+
+    /// Message ID assigned by peer to extension (if peer supports it).
+    pub fn remote_id(&self, name: &str) -> Option<u8> {
+        self.remote_ids.get(name).copied().filter(|id| *id != 0)
+    }
+
+    /// Check if peer supports extension.
+    pub fn peer_supports(&self, name: &str) -> bool {
+        matches!(self.remote_ids.get(name), Some(id) if *id != 0)
+    }
+}
@@ -7,16 +7,23 @@
 // except according to those terms.
 
 use crate::constants::{MAX_FRAME_SIZE, MSG_ID_POS, MSG_ID_SIZE, MSG_LEN_SIZE};
+use crate::messages::allowed_fast::AllowedFast;
 use crate::messages::bitfield::Bitfield;
 use crate::messages::cancel::Cancel;
 use crate::messages::choke::Choke;
+use crate::messages::extended::Extended;
 use crate::messages::handshake::Handshake;
 use crate::messages::have::Have;
+use crate::messages::have_all::HaveAll;
+use crate::messages::have_none::HaveNone;
 use crate::messages::interested::Interested;
 use crate::messages::keep_alive::KeepAlive;
 use crate::messages::not_interested::NotInterested;
 use crate::messages::piece::Piece;
+use crate::messages::port::Port;
+use crate::messages::reject_request::RejectRequest;
 use crate::messages::request::Request;
+use crate::messages::suggest_piece::SuggestPiece;
 use crate::messages::unchoke::Unchoke;
 use crate::Error;
 use num_derive::FromPrimitive;
@@ -36,6 +43,13 @@ pub enum Frame {
     Request(Request),
     Piece(Piece),
     Cancel(Cancel),
+    Port(Port),
+    Extended(Extended),
+    SuggestPiece(SuggestPiece),
+    HaveAll(HaveAll),
+    HaveNone(HaveNone),
+    RejectRequest(RejectRequest),
+    AllowedFast(AllowedFast),
 }
 
 #[derive(PartialEq, FromPrimitive)]
@@ -51,6 +65,13 @@ enum MsgId {
     RequestId = Request::ID,
     PieceId = Piece::ID,
     CancelId = Cancel::ID,
+    PortId = Port::ID,
+    ExtendedId = Extended::ID,
+    SuggestPieceId = SuggestPiece::ID,
+    HaveAllId = HaveAll::ID,
+    HaveNoneId = HaveNone::ID,
+    RejectRequestId = RejectRequest::ID,
+    AllowedFastId = AllowedFast::ID,
 }
 
 impl Frame {
@@ -111,6 +132,34 @@ impl Frame {
                 crs.set_position(Cancel::check(available_data, length)? as u64);
                 Ok(Frame::Cancel(Cancel::from(crs)))
             }
+            Some(MsgId::PortId) => {
+                crs.set_position(Port::check(available_data, length)? as u64);
+                Ok(Frame::Port(Port::from(crs)))
+            }
+            Some(MsgId::ExtendedId) => {
+                crs.set_position(Extended::check(available_data, length)? as u64);
+                Ok(Frame::Extended(Extended::from(crs)))
+            }
+            Some(MsgId::SuggestPieceId) => {
+                crs.set_position(SuggestPiece::check(available_data, length)? as u64);
+                Ok(Frame::SuggestPiece(SuggestPiece::from(crs)))
+            }
+            Some(MsgId::HaveAllId) => {
+                crs.set_position(HaveAll::check(length)? as u64);
+                Ok(Frame::HaveAll(HaveAll {}))
+            }
+            Some(MsgId::HaveNoneId) => {
+                crs.set_position(HaveNone::check(length)? as u64);
+                Ok(Frame::HaveNone(HaveNone {}))
+            }
+            Some(MsgId::RejectRequestId) => {
+                crs.set_position(RejectRequest::check(available_data, length)? as u64);
+                Ok(Frame::RejectRequest(RejectRequest::from(crs)))
+            }
+            Some(MsgId::AllowedFastId) => {
+                crs.set_position(AllowedFast::check(available_data, length)? as u64);
+                Ok(Frame::AllowedFast(AllowedFast::from(crs)))
+            }
             None => {
                 // To skip unknown message
                 crs.set_position((MSG_LEN_SIZE + length) as u64);
@@ -0,0 +1,145 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::commands::PeerCmd;
+use crate::{Error, Extension};
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::sync::mpsc;
+
+const ADDR_V4: u8 = 0x00;
+const ADDR_V6: u8 = 0x01;
+
+/// Holepunch message (ut_holepunch extension, see [BEP55](https://www.bittorrent.org/beps/bep_0055.html)).
+#[derive(PartialEq, Clone, Debug)]
+pub struct HolepunchMsg {
+    /// Message type (rendezvous, connect or error)
+    pub msg_type: u8,
+    /// Rendezvous target, peer to connect to, or target that error refers to
+    pub addr: SocketAddr,
+    /// Error code (0 for other messages)
+    pub err_code: u32,
+}
+
+impl HolepunchMsg {
+    /// Ask relaying peer to introduce client to target peer.
+    pub const RENDEZVOUS: u8 = 0x00;
+    /// Relaying peer asks to connect with the other side.
+    pub const CONNECT: u8 = 0x01;
+    /// Relaying peer can't pass rendezvous.
+    pub const ERROR: u8 = 0x02;
+
+    /// Target endpoint is invalid.
+    pub const NO_SUCH_PEER: u32 = 0x01;
+    /// Relaying peer is not connected to target.
+    pub const NOT_CONNECTED: u32 = 0x02;
+    /// Target doesn't support holepunch extension.
+    pub const NO_SUPPORT: u32 = 0x03;
+    /// Target is the same peer, that sent rendezvous.
+    pub const NO_SELF: u32 = 0x04;
+
+    /// Decode message payload.
+    ///
+    /// # Example
+    /// ```
+    /// use rdest::HolepunchMsg;
+    ///
+    /// let data = b"\x01\x00\x7f\x00\x00\x01\x1a\xe1\x00\x00\x00\x00";
+    /// let msg = HolepunchMsg::from_bytes(data).unwrap();
+    /// assert_eq!(msg.msg_type, HolepunchMsg::CONNECT);
+    /// assert_eq!(msg.addr, "127.0.0.1:6881".parse().unwrap());
+    /// ```
+    pub fn from_bytes(data: &[u8]) -> Result<HolepunchMsg, Error> {
+        let (msg_type, addr_type) = match data {
+            [msg_type, addr_type, ..] => (*msg_type, *addr_type),
+            _ => return Err(Error::HolepunchInvalidMsg("length")),
+        };
+        if msg_type > Self::ERROR {
+            return Err(Error::HolepunchInvalidMsg("msg_type"));
+        }
+
+        let (ip, rest): (IpAddr, &[u8]) = match (addr_type, &data[2..]) {
+            (ADDR_V4, rest) if rest.len() == 4 + 2 + 4 => {
+                let octets: [u8; 4] = rest[..4].try_into().unwrap();
+                (Ipv4Addr::from(octets).into(), &rest[4..])
+            }
+            (ADDR_V6, rest) if rest.len() == 16 + 2 + 4 => {
+                let octets: [u8; 16] = rest[..16].try_into().unwrap();
+                (Ipv6Addr::from(octets).into(), &rest[16..])
+            }
+            (ADDR_V4, _) | (ADDR_V6, _) => return Err(Error::HolepunchInvalidMsg("length")),
+            _ => return Err(Error::HolepunchInvalidMsg("addr_type")),
+        };
+
+        Ok(HolepunchMsg {
+            msg_type,
+            addr: SocketAddr::new(ip, u16::from_be_bytes([rest[0], rest[1]])),
+            err_code: u32::from_be_bytes(rest[2..6].try_into().unwrap()),
+        })
+    }
+
+    /// Encode message payload.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![self.msg_type];
+        match self.addr.ip() {
+            IpAddr::V4(ip) => {
+                data.push(ADDR_V4);
+                data.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                data.push(ADDR_V6);
+                data.extend_from_slice(&ip.octets());
+            }
+        }
+        data.extend_from_slice(&self.addr.port().to_be_bytes());
+        data.extend_from_slice(&self.err_code.to_be_bytes());
+
+        data
+    }
+
+    /// Human readable description of error code.
+    pub fn err_text(&self) -> &'static str {
+        match self.err_code {
+            Self::NO_SUCH_PEER => "no such peer",
+            Self::NOT_CONNECTED => "not connected",
+            Self::NO_SUPPORT => "no support",
+            Self::NO_SELF => "no self",
+            _ => "unknown error",
+        }
+    }
+}
+
+/// ut_holepunch handler for single peer. Received messages are passed to
+/// [`Session`](crate::Session), which relays them or connects with other peer. Messages for peer
+/// are send by session directly (with extended message broadcast).
+pub(crate) struct UtHolepunch {
+    addr: SocketAddr,
+    peer_ch: mpsc::Sender<PeerCmd>,
+}
+
+impl UtHolepunch {
+    pub(crate) const NAME: &'static str = "ut_holepunch";
+
+    pub(crate) fn new(addr: SocketAddr, peer_ch: mpsc::Sender<PeerCmd>) -> UtHolepunch {
+        UtHolepunch { addr, peer_ch }
+    }
+}
+
+impl Extension for UtHolepunch {
+    fn on_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let msg = HolepunchMsg::from_bytes(payload)?;
+
+        // When session is busy, message is dropped. Initiator can retry rendezvous.
+        let _ = self.peer_ch.try_send(PeerCmd::RecvHolepunch {
+            addr: self.addr,
+            msg,
+        });
+
+        Ok(vec![])
+    }
+}
@@ -31,20 +31,34 @@ mod bcodec;
 mod commands;
 mod connection;
 mod constants;
+mod dht;
 mod error;
+mod extension;
 mod extractor;
 mod frame;
+mod holepunch;
+mod lsd;
 mod messages;
 mod metainfo;
+pub mod mse;
+mod nat;
+mod net;
 mod peer;
 mod peer_handler;
 pub mod peer_id;
+mod peer_source;
+mod pex;
 mod progress_view;
+mod scrape_resp;
 mod serializer;
 mod session;
+mod signing;
 mod tracker_client;
 mod tracker_resp;
+mod tracker_server;
+mod udp_tracker_client;
 mod utils;
+mod utp;
 
 pub use crate::error::Error;
 
@@ -55,8 +69,25 @@ pub use crate::bcodec::raw_finder::RawFinder;
 
 pub use crate::metainfo::File;
 pub use crate::metainfo::Metainfo;
+pub use crate::signing::{Certificate, Signature, Signer};
 
-pub use crate::tracker_client::TrackerClient;
+pub use crate::commands::TransferStats;
+pub use crate::dht::{Dht, DhtNode, DhtSource};
+pub use crate::extension::{
+    ExtendedHandshake, Extension, ExtensionFactory, ExtensionRegistry, PeerExtensions,
+};
+pub use crate::holepunch::HolepunchMsg;
+pub use crate::lsd::{LsdMsg, LsdSource};
+pub use crate::nat::{Mapping, NatProtocol, PortMapper, Transport};
+pub use crate::net::{BindAddr, Proxy, ProxyKind};
+pub use crate::peer_source::{PeerSource, PeerSourceEvent, PeerSourceFuture, StaticPeers};
+pub use crate::pex::PexMsg;
+pub use crate::scrape_resp::{ScrapeFile, ScrapeResp};
+pub use crate::tracker_client::{AnnounceEvent, AnnounceParams, TrackerClient};
 pub use crate::tracker_resp::TrackerResp;
+pub use crate::tracker_server::TrackerServer;
+pub use crate::udp_tracker_client::UdpTrackerClient;
+pub use crate::utils::{allowed_fast_set, hash_to_string, peer_priority};
+pub use crate::utp::{Utp, UtpSocket, UtpStream};
 
 pub use crate::session::Session;
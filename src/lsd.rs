@@ -0,0 +1,278 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::HASH_SIZE;
+use crate::{utils, Error, PeerSource, PeerSourceEvent, PeerSourceFuture};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use tokio::net::UdpSocket;
+use tokio::time;
+use tokio::time::{Duration, Instant};
+
+const GROUP_V4: Ipv4Addr = Ipv4Addr::new(239, 192, 152, 143);
+const GROUP_V6: Ipv6Addr = Ipv6Addr::new(0xff15, 0, 0, 0, 0, 0, 0xefc0, 0x988f);
+const LSD_PORT: u16 = 6771;
+const REQUEST_LINE: &str = "BT-SEARCH * HTTP/1.1";
+/// Torrents are re-announced every 5 minutes, but not more often than once per minute (BEP14).
+const ANNOUNCE_INTERVAL_SEC: u64 = 5 * 60;
+const MIN_INTERVAL_SEC: u64 = 60;
+const MAX_DATAGRAM_SIZE: usize = 1400;
+
+/// Local Service Discovery announcement (see [BEP14](https://www.bittorrent.org/beps/bep_0014.html)).
+#[derive(PartialEq, Clone, Debug)]
+pub struct LsdMsg {
+    /// Port the peer listens on
+    pub port: u16,
+    /// Announced torrents
+    pub info_hashes: Vec<[u8; HASH_SIZE]>,
+    /// Random value, used to recognize own announcements
+    pub cookie: Option<String>,
+}
+
+impl LsdMsg {
+    /// Decode BT-SEARCH message. Header names are case-insensitive.
+    ///
+    /// # Example
+    /// ```
+    /// use rdest::LsdMsg;
+    ///
+    /// let data = b"BT-SEARCH * HTTP/1.1\r\n\
+    ///     Host: 239.192.152.143:6771\r\n\
+    ///     Port: 6881\r\n\
+    ///     Infohash: 4141414141424242424243434343434444444444\r\n\
+    ///     \r\n\r\n";
+    /// let msg = LsdMsg::from_bytes(data).unwrap();
+    /// assert_eq!(msg.port, 6881);
+    /// assert_eq!(msg.info_hashes, vec![*b"AAAAABBBBBCCCCCDDDDD"]);
+    /// ```
+    pub fn from_bytes(data: &[u8]) -> Result<LsdMsg, Error> {
+        let text = std::str::from_utf8(data).map_err(|_| Error::LsdInvalidMsg("utf-8"))?;
+        let mut lines = text.split("\r\n");
+        if lines.next() != Some(REQUEST_LINE) {
+            return Err(Error::LsdInvalidMsg("request line"));
+        }
+
+        let mut port = None;
+        let mut info_hashes = vec![];
+        let mut cookie = None;
+        for line in lines.take_while(|line| !line.is_empty()) {
+            let (name, value) = match line.split_once(':') {
+                Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+                None => return Err(Error::LsdInvalidMsg("header")),
+            };
+
+            match name.as_str() {
+                "port" => port = value.parse().ok(),
+                "infohash" => match Self::parse_hash(value) {
+                    Some(info_hash) => info_hashes.push(info_hash),
+                    None => return Err(Error::LsdInvalidMsg("infohash")),
+                },
+                "cookie" => cookie = Some(value.to_string()),
+                _ => (),
+            }
+        }
+
+        match (port, info_hashes.is_empty()) {
+            (None, _) => Err(Error::LsdInvalidMsg("port")),
+            (_, true) => Err(Error::LsdInvalidMsg("infohash")),
+            (Some(port), false) => Ok(LsdMsg {
+                port,
+                info_hashes,
+                cookie,
+            }),
+        }
+    }
+
+    fn parse_hash(value: &str) -> Option<[u8; HASH_SIZE]> {
+        if value.len() != HASH_SIZE * 2 || !value.is_ascii() {
+            return None;
+        }
+
+        let mut hash = [0; HASH_SIZE];
+        for (idx, b) in hash.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&value[idx * 2..idx * 2 + 2], 16).ok()?;
+        }
+
+        Some(hash)
+    }
+
+    /// Encode message for multicast group `host` (e.g. "239.192.152.143:6771").
+    pub fn encode(&self, host: &str) -> Vec<u8> {
+        let mut text = format!(
+            "{}\r\nHost: {}\r\nPort: {}\r\n",
+            REQUEST_LINE, host, self.port
+        );
+        for info_hash in self.info_hashes.iter() {
+            text += &format!("Infohash: {}\r\n", utils::hash_to_string(info_hash));
+        }
+        if let Some(cookie) = &self.cookie {
+            text += &format!("cookie: {}\r\n", cookie);
+        }
+        text += "\r\n\r\n";
+
+        text.into_bytes()
+    }
+}
+
+/// Peer source announcing torrent in local network with multicast (IPv4 and IPv6, if
+/// available), and listening for announcements of other peers.
+#[derive(Debug)]
+pub struct LsdSource {
+    info_hash: [u8; HASH_SIZE],
+    port: u16,
+    cookie: String,
+    socket_v4: Option<UdpSocket>,
+    socket_v6: Option<UdpSocket>,
+    bound: bool,
+    next_announce: Instant,
+    last_announce: Option<Instant>,
+}
+
+impl LsdSource {
+    /// Create source for torrent, announcing that client listens on `port`. Multicast group is
+    /// joined when source is started.
+    pub fn new(info_hash: [u8; HASH_SIZE], port: u16) -> LsdSource {
+        LsdSource {
+            info_hash,
+            port,
+            cookie: format!("{:08x}", rand::random::<u32>()),
+            socket_v4: None,
+            socket_v6: None,
+            bound: false,
+            next_announce: Instant::now(),
+            last_announce: None,
+        }
+    }
+
+    /// Port is shared with other clients on the same host.
+    fn bind_v4() -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, LSD_PORT)).into())?;
+        socket.join_multicast_v4(&GROUP_V4, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_multicast_loop_v4(true)?;
+        UdpSocket::from_std(socket.into())
+    }
+
+    fn bind_v6() -> io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_only_v6(true)?;
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, LSD_PORT)).into())?;
+        socket.join_multicast_v6(&GROUP_V6, 0)?;
+        socket.set_multicast_loop_v6(true)?;
+        UdpSocket::from_std(socket.into())
+    }
+
+    async fn send_announce(&mut self) {
+        let now = Instant::now();
+        self.last_announce = Some(now);
+        self.next_announce = now + Duration::from_secs(ANNOUNCE_INTERVAL_SEC);
+
+        let msg = LsdMsg {
+            port: self.port,
+            info_hashes: vec![self.info_hash],
+            cookie: Some(self.cookie.clone()),
+        };
+        if let Some(socket) = &self.socket_v4 {
+            let group = SocketAddr::from((GROUP_V4, LSD_PORT));
+            let _ = socket.send_to(&msg.encode(&group.to_string()), group).await;
+        }
+        if let Some(socket) = &self.socket_v6 {
+            let group = SocketAddr::from((GROUP_V6, LSD_PORT));
+            let _ = socket.send_to(&msg.encode(&group.to_string()), group).await;
+        }
+    }
+
+    /// Peer address, if announcement is for our torrent and isn't our own.
+    fn handle_msg(&self, data: &[u8], addr: SocketAddr) -> Option<SocketAddr> {
+        let msg = LsdMsg::from_bytes(data).ok()?;
+        if msg.cookie.as_ref() == Some(&self.cookie) || !msg.info_hashes.contains(&self.info_hash) {
+            return None;
+        }
+
+        // Link-local sender is reachable only through interface it was heard on
+        match addr {
+            SocketAddr::V4(addr) => Some(SocketAddr::new((*addr.ip()).into(), msg.port)),
+            SocketAddr::V6(addr) => {
+                Some(SocketAddrV6::new(*addr.ip(), msg.port, 0, addr.scope_id()).into())
+            }
+        }
+    }
+
+    async fn recv_from(
+        socket: &Option<UdpSocket>,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr)> {
+        match socket {
+            Some(socket) => socket.recv_from(buf).await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl PeerSource for LsdSource {
+    fn name(&self) -> String {
+        "lsd".to_string()
+    }
+
+    fn set_port(&mut self, port: u16) {
+        self.port = port;
+    }
+
+    fn next(&mut self) -> PeerSourceFuture<'_, Option<PeerSourceEvent>> {
+        Box::pin(async move {
+            if !self.bound {
+                self.bound = true;
+                self.socket_v4 = Self::bind_v4().ok();
+                self.socket_v6 = Self::bind_v6().ok();
+                if self.socket_v4.is_none() && self.socket_v6.is_none() {
+                    return Some(PeerSourceEvent::Fail(
+                        "can't join multicast group".to_string(),
+                    ));
+                }
+            }
+
+            if self.socket_v4.is_none() && self.socket_v6.is_none() {
+                return None;
+            }
+
+            let mut buf_v4 = vec![0; MAX_DATAGRAM_SIZE];
+            let mut buf_v6 = vec![0; MAX_DATAGRAM_SIZE];
+            loop {
+                let peer = tokio::select! {
+                    _ = time::sleep_until(self.next_announce) => {
+                        self.send_announce().await;
+                        None
+                    }
+                    Ok((n, addr)) = Self::recv_from(&self.socket_v4, &mut buf_v4) => {
+                        self.handle_msg(&buf_v4[..n], addr)
+                    }
+                    Ok((n, addr)) = Self::recv_from(&self.socket_v6, &mut buf_v6) => {
+                        self.handle_msg(&buf_v6[..n], addr)
+                    }
+                };
+
+                if let Some(peer) = peer {
+                    return Some(PeerSourceEvent::Peers(vec![(peer, None)]));
+                }
+            }
+        })
+    }
+
+    fn announce(&mut self) {
+        if let Some(last_announce) = self.last_announce {
+            self.next_announce = self
+                .next_announce
+                .min(last_announce + Duration::from_secs(MIN_INTERVAL_SEC));
+        }
+    }
+}
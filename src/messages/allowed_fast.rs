@@ -0,0 +1,74 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::{MSG_ID_SIZE, MSG_LEN_SIZE};
+use crate::serializer::Serializer;
+use crate::Error;
+use std::io::Cursor;
+
+/// Piece that can be downloaded even when choked, see [BEP6](https://www.bittorrent.org/beps/bep_0006.html).
+#[derive(Debug)]
+pub struct AllowedFast {
+    piece_index: u32,
+}
+
+impl AllowedFast {
+    const LEN: u32 = 5;
+    pub const ID: u8 = 17;
+    const LEN_SIZE: usize = MSG_LEN_SIZE;
+    const ID_SIZE: usize = MSG_ID_SIZE;
+    const INDEX_SIZE: usize = 4;
+    const FULL_SIZE: usize = AllowedFast::LEN_SIZE + AllowedFast::LEN as usize;
+
+    pub fn new(piece_index: usize) -> AllowedFast {
+        AllowedFast {
+            piece_index: piece_index as u32,
+        }
+    }
+
+    pub fn from(crs: &Cursor<&[u8]>) -> AllowedFast {
+        let start = AllowedFast::LEN_SIZE + AllowedFast::ID_SIZE;
+        let mut piece_index = [0; AllowedFast::INDEX_SIZE];
+        piece_index.copy_from_slice(&crs.get_ref()[start..start + AllowedFast::INDEX_SIZE]);
+
+        AllowedFast {
+            piece_index: u32::from_be_bytes(piece_index),
+        }
+    }
+
+    pub fn check(available_data: usize, length: usize) -> Result<usize, Error> {
+        match length == AllowedFast::LEN as usize
+            && available_data >= AllowedFast::LEN_SIZE + length
+        {
+            true => Ok(AllowedFast::FULL_SIZE),
+            false => Err(Error::Incomplete("AllowedFast")),
+        }
+    }
+
+    pub fn piece_index(&self) -> usize {
+        self.piece_index as usize
+    }
+
+    pub fn validate(&self, pieces_num: usize) -> Result<(), Error> {
+        match (self.piece_index as usize) < pieces_num {
+            true => Ok(()),
+            false => Err(Error::InvalidPieceIndex("AllowedFast")),
+        }
+    }
+}
+
+impl Serializer for AllowedFast {
+    fn data(&self) -> Vec<u8> {
+        let mut vec = vec![];
+        vec.extend_from_slice(&AllowedFast::LEN.to_be_bytes());
+        vec.push(AllowedFast::ID);
+        vec.extend_from_slice(&self.piece_index.to_be_bytes());
+
+        vec
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::{MSG_ID_SIZE, MSG_LEN_SIZE};
+use crate::serializer::Serializer;
+use crate::Error;
+use std::io::Cursor;
+
+/// Extension protocol message, see [BEP10](https://www.bittorrent.org/beps/bep_0010.html).
+/// Extended message ID 0 is reserved for extension handshake.
+#[derive(Debug)]
+pub struct Extended {
+    ext_id: u8,
+    payload: Vec<u8>,
+}
+
+impl Extended {
+    const LEN: u32 = 2;
+    pub const ID: u8 = 20;
+    pub const HANDSHAKE_ID: u8 = 0;
+    const LEN_SIZE: usize = MSG_LEN_SIZE;
+    const ID_SIZE: usize = MSG_ID_SIZE;
+    const EXT_ID_SIZE: usize = 1;
+
+    pub fn new(ext_id: u8, payload: Vec<u8>) -> Extended {
+        Extended { ext_id, payload }
+    }
+
+    pub fn from(crs: &Cursor<&[u8]>) -> Extended {
+        let start = Extended::LEN_SIZE + Extended::ID_SIZE;
+        let end = crs.position() as usize;
+
+        Extended {
+            ext_id: crs.get_ref()[start],
+            payload: crs.get_ref()[start + Extended::EXT_ID_SIZE..end].to_vec(),
+        }
+    }
+
+    pub fn check(available_data: usize, length: usize) -> Result<usize, Error> {
+        if length < Extended::LEN as usize {
+            return Err(Error::InvalidLength("Extended"));
+        }
+
+        match available_data >= Extended::LEN_SIZE + length {
+            true => Ok(Extended::LEN_SIZE + length),
+            false => Err(Error::Incomplete("Extended")),
+        }
+    }
+
+    pub fn ext_id(&self) -> u8 {
+        self.ext_id
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+impl Serializer for Extended {
+    fn data(&self) -> Vec<u8> {
+        let length = Extended::LEN + self.payload.len() as u32;
+
+        let mut vec = vec![];
+        vec.extend_from_slice(&length.to_be_bytes());
+        vec.push(Extended::ID);
+        vec.push(self.ext_id);
+        vec.extend_from_slice(&self.payload);
+
+        vec
+    }
+}
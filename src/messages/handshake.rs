@@ -13,6 +13,7 @@ use std::io::Cursor;
 
 #[derive(Debug)]
 pub struct Handshake {
+    reserved: [u8; Handshake::RESERVED_SIZE],
     info_hash: [u8; HASH_SIZE],
     peer_id: [u8; PEER_ID_SIZE],
 }
@@ -29,15 +30,29 @@ impl Handshake {
     const INFO_HASH_SIZE: usize = HASH_SIZE;
     const PEER_ID_SIZE: usize = PEER_ID_SIZE;
     const FULL_SIZE: usize = Handshake::LEN_SIZE + Handshake::LEN as usize;
+    /// Last reserved bit, see [BEP5](https://www.bittorrent.org/beps/bep_0005.html).
+    const DHT_BYTE: usize = 7;
+    const DHT_BIT: u8 = 0x01;
+    /// Third bit from the right, see [BEP6](https://www.bittorrent.org/beps/bep_0006.html).
+    const FAST_BYTE: usize = 7;
+    const FAST_BIT: u8 = 0x04;
+    /// 20th bit from the right, see [BEP10](https://www.bittorrent.org/beps/bep_0010.html).
+    const EXTENSION_BYTE: usize = 5;
+    const EXTENSION_BIT: u8 = 0x10;
 
     pub fn new(info_hash: &[u8; HASH_SIZE], peer_id: &[u8; PEER_ID_SIZE]) -> Handshake {
         Handshake {
+            reserved: [0; Handshake::RESERVED_SIZE],
             info_hash: info_hash.clone(),
             peer_id: peer_id.clone(),
         }
     }
 
     pub fn from(crs: &Cursor<&[u8]>) -> Handshake {
+        let start = Handshake::LEN_SIZE + Handshake::PROTOCOL_ID.len();
+        let mut reserved = [0; Handshake::RESERVED_SIZE];
+        reserved.copy_from_slice(&crs.get_ref()[start..start + Handshake::RESERVED_SIZE]);
+
         let start = Handshake::LEN_SIZE + Handshake::PROTOCOL_ID.len() + Handshake::RESERVED_SIZE;
         let mut info_hash = [0; Handshake::INFO_HASH_SIZE];
         info_hash.copy_from_slice(&crs.get_ref()[start..start + Handshake::INFO_HASH_SIZE]);
@@ -49,7 +64,11 @@ impl Handshake {
         let mut peer_id = [0; Handshake::PEER_ID_SIZE];
         peer_id.copy_from_slice(&crs.get_ref()[start..start + Handshake::PEER_ID_SIZE]);
 
-        Handshake { info_hash, peer_id }
+        Handshake {
+            reserved,
+            info_hash,
+            peer_id,
+        }
     }
 
     pub fn check(
@@ -78,6 +97,33 @@ impl Handshake {
         &self.peer_id
     }
 
+    /// Advertise DHT support.
+    pub fn set_dht(&mut self) {
+        self.reserved[Handshake::DHT_BYTE] |= Handshake::DHT_BIT;
+    }
+
+    pub fn supports_dht(&self) -> bool {
+        self.reserved[Handshake::DHT_BYTE] & Handshake::DHT_BIT != 0
+    }
+
+    /// Advertise Fast Extension support.
+    pub fn set_fast(&mut self) {
+        self.reserved[Handshake::FAST_BYTE] |= Handshake::FAST_BIT;
+    }
+
+    pub fn supports_fast(&self) -> bool {
+        self.reserved[Handshake::FAST_BYTE] & Handshake::FAST_BIT != 0
+    }
+
+    /// Advertise extension protocol support.
+    pub fn set_extension_protocol(&mut self) {
+        self.reserved[Handshake::EXTENSION_BYTE] |= Handshake::EXTENSION_BIT;
+    }
+
+    pub fn supports_extension_protocol(&self) -> bool {
+        self.reserved[Handshake::EXTENSION_BYTE] & Handshake::EXTENSION_BIT != 0
+    }
+
     pub fn validate(
         &self,
         info_hash: &[u8; HASH_SIZE],
@@ -114,7 +160,7 @@ impl Serializer for Handshake {
         let mut vec = vec![];
         vec.push(Handshake::PROTOCOL_ID.len() as u8);
         vec.extend_from_slice(Handshake::PROTOCOL_ID);
-        vec.extend_from_slice(&[0; Handshake::RESERVED_SIZE]);
+        vec.extend_from_slice(&self.reserved);
         vec.extend_from_slice(&self.info_hash);
         vec.extend_from_slice(&self.peer_id);
 
@@ -0,0 +1,43 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::MSG_LEN_SIZE;
+use crate::serializer::Serializer;
+use crate::Error;
+
+/// Sender has all pieces, replaces Bitfield. See [BEP6](https://www.bittorrent.org/beps/bep_0006.html).
+#[derive(Debug)]
+pub struct HaveAll {}
+
+impl HaveAll {
+    const LEN: u32 = 1;
+    pub const ID: u8 = 14;
+    const LEN_SIZE: usize = MSG_LEN_SIZE;
+    const FULL_SIZE: usize = HaveAll::LEN_SIZE + HaveAll::LEN as usize;
+
+    pub fn new() -> HaveAll {
+        HaveAll {}
+    }
+
+    pub fn check(length: usize) -> Result<usize, Error> {
+        match length == HaveAll::LEN as usize {
+            true => Ok(HaveAll::FULL_SIZE),
+            false => Err(Error::Incomplete("HaveAll")),
+        }
+    }
+}
+
+impl Serializer for HaveAll {
+    fn data(&self) -> Vec<u8> {
+        let mut vec = vec![];
+        vec.extend_from_slice(&HaveAll::LEN.to_be_bytes());
+        vec.push(HaveAll::ID);
+
+        vec
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::MSG_LEN_SIZE;
+use crate::serializer::Serializer;
+use crate::Error;
+
+/// Sender has no pieces, replaces Bitfield. See [BEP6](https://www.bittorrent.org/beps/bep_0006.html).
+#[derive(Debug)]
+pub struct HaveNone {}
+
+impl HaveNone {
+    const LEN: u32 = 1;
+    pub const ID: u8 = 15;
+    const LEN_SIZE: usize = MSG_LEN_SIZE;
+    const FULL_SIZE: usize = HaveNone::LEN_SIZE + HaveNone::LEN as usize;
+
+    pub fn new() -> HaveNone {
+        HaveNone {}
+    }
+
+    pub fn check(length: usize) -> Result<usize, Error> {
+        match length == HaveNone::LEN as usize {
+            true => Ok(HaveNone::FULL_SIZE),
+            false => Err(Error::Incomplete("HaveNone")),
+        }
+    }
+}
+
+impl Serializer for HaveNone {
+    fn data(&self) -> Vec<u8> {
+        let mut vec = vec![];
+        vec.extend_from_slice(&HaveNone::LEN.to_be_bytes());
+        vec.push(HaveNone::ID);
+
+        vec
+    }
+}
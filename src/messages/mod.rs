@@ -6,26 +6,40 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+pub mod allowed_fast;
 pub mod bitfield;
 pub mod cancel;
 pub mod choke;
+pub mod extended;
 pub mod handshake;
 pub mod have;
+pub mod have_all;
+pub mod have_none;
 pub mod interested;
 pub mod keep_alive;
 pub mod not_interested;
 pub mod piece;
+pub mod port;
+pub mod reject_request;
 pub mod request;
+pub mod suggest_piece;
 pub mod unchoke;
 
+pub use allowed_fast::AllowedFast;
 pub use bitfield::Bitfield;
 pub use cancel::Cancel;
 pub use choke::Choke;
+pub use extended::Extended;
 pub use handshake::Handshake;
 pub use have::Have;
+pub use have_all::HaveAll;
+pub use have_none::HaveNone;
 pub use interested::Interested;
 pub use keep_alive::KeepAlive;
 pub use not_interested::NotInterested;
 pub use piece::Piece;
+pub use port::Port;
+pub use reject_request::RejectRequest;
 pub use request::Request;
+pub use suggest_piece::SuggestPiece;
 pub use unchoke::Unchoke;
@@ -0,0 +1,63 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::{MSG_ID_SIZE, MSG_LEN_SIZE};
+use crate::serializer::Serializer;
+use crate::Error;
+use std::io::Cursor;
+
+/// DHT port announced by peer, see [BEP5](https://www.bittorrent.org/beps/bep_0005.html).
+#[derive(Debug)]
+pub struct Port {
+    port: u16,
+}
+
+impl Port {
+    const LEN: u32 = 3;
+    pub const ID: u8 = 9;
+    const LEN_SIZE: usize = MSG_LEN_SIZE;
+    const ID_SIZE: usize = MSG_ID_SIZE;
+    const PORT_SIZE: usize = 2;
+    const FULL_SIZE: usize = Port::LEN_SIZE + Port::LEN as usize;
+
+    pub fn new(port: u16) -> Port {
+        Port { port }
+    }
+
+    pub fn from(crs: &Cursor<&[u8]>) -> Port {
+        let start = Port::LEN_SIZE + Port::ID_SIZE;
+        let mut port = [0; Port::PORT_SIZE];
+        port.copy_from_slice(&crs.get_ref()[start..start + Port::PORT_SIZE]);
+
+        Port {
+            port: u16::from_be_bytes(port),
+        }
+    }
+
+    pub fn check(available_data: usize, length: usize) -> Result<usize, Error> {
+        match length == Port::LEN as usize && available_data >= Port::LEN_SIZE + length {
+            true => Ok(Port::FULL_SIZE),
+            false => Err(Error::Incomplete("Port")),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Serializer for Port {
+    fn data(&self) -> Vec<u8> {
+        let mut vec = vec![];
+        vec.extend_from_slice(&Port::LEN.to_be_bytes());
+        vec.push(Port::ID);
+        vec.extend_from_slice(&self.port.to_be_bytes());
+
+        vec
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::{MSG_ID_SIZE, MSG_LEN_SIZE};
+use crate::serializer::Serializer;
+use crate::Error;
+use std::io::Cursor;
+
+/// Request that will not be served, see [BEP6](https://www.bittorrent.org/beps/bep_0006.html).
+#[derive(Debug)]
+pub struct RejectRequest {
+    piece_index: u32,
+    block_begin: u32,
+    block_length: u32,
+}
+
+impl RejectRequest {
+    const LEN: u32 = 13;
+    pub const ID: u8 = 16;
+    const LEN_SIZE: usize = MSG_LEN_SIZE;
+    const ID_SIZE: usize = MSG_ID_SIZE;
+    const INDEX_SIZE: usize = 4;
+    const BEGIN_SIZE: usize = 4;
+    const LENGTH_SIZE: usize = 4;
+    const FULL_SIZE: usize = RejectRequest::LEN_SIZE + RejectRequest::LEN as usize;
+
+    pub fn new(piece_index: usize, block_begin: usize, block_length: usize) -> RejectRequest {
+        RejectRequest {
+            piece_index: piece_index as u32,
+            block_begin: block_begin as u32,
+            block_length: block_length as u32,
+        }
+    }
+
+    pub fn from(crs: &Cursor<&[u8]>) -> RejectRequest {
+        let start = RejectRequest::LEN_SIZE + RejectRequest::ID_SIZE;
+        let mut piece_index = [0; RejectRequest::INDEX_SIZE];
+        piece_index.copy_from_slice(&crs.get_ref()[start..start + RejectRequest::INDEX_SIZE]);
+
+        let start = start + RejectRequest::INDEX_SIZE;
+        let mut block_begin = [0; RejectRequest::BEGIN_SIZE];
+        block_begin.copy_from_slice(&crs.get_ref()[start..start + RejectRequest::BEGIN_SIZE]);
+
+        let start = start + RejectRequest::BEGIN_SIZE;
+        let mut block_length = [0; RejectRequest::LENGTH_SIZE];
+        block_length.copy_from_slice(&crs.get_ref()[start..start + RejectRequest::LENGTH_SIZE]);
+
+        RejectRequest {
+            piece_index: u32::from_be_bytes(piece_index),
+            block_begin: u32::from_be_bytes(block_begin),
+            block_length: u32::from_be_bytes(block_length),
+        }
+    }
+
+    pub fn check(available_data: usize, length: usize) -> Result<usize, Error> {
+        if length == RejectRequest::LEN as usize
+            && available_data >= RejectRequest::LEN_SIZE + length
+        {
+            return Ok(RejectRequest::FULL_SIZE);
+        }
+
+        Err(Error::Incomplete("RejectRequest"))
+    }
+
+    pub fn piece_index(&self) -> usize {
+        self.piece_index as usize
+    }
+
+    pub fn block_begin(&self) -> usize {
+        self.block_begin as usize
+    }
+
+    pub fn block_length(&self) -> usize {
+        self.block_length as usize
+    }
+}
+
+impl Serializer for RejectRequest {
+    fn data(&self) -> Vec<u8> {
+        let mut vec = vec![];
+        vec.extend_from_slice(&RejectRequest::LEN.to_be_bytes());
+        vec.push(RejectRequest::ID);
+        vec.extend_from_slice(&self.piece_index.to_be_bytes());
+        vec.extend_from_slice(&self.block_begin.to_be_bytes());
+        vec.extend_from_slice(&self.block_length.to_be_bytes());
+
+        vec
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::{MSG_ID_SIZE, MSG_LEN_SIZE};
+use crate::serializer::Serializer;
+use crate::Error;
+use std::io::Cursor;
+
+/// Hint which piece peer should download, see [BEP6](https://www.bittorrent.org/beps/bep_0006.html).
+#[derive(Debug)]
+pub struct SuggestPiece {
+    piece_index: u32,
+}
+
+impl SuggestPiece {
+    const LEN: u32 = 5;
+    pub const ID: u8 = 13;
+    const LEN_SIZE: usize = MSG_LEN_SIZE;
+    const ID_SIZE: usize = MSG_ID_SIZE;
+    const INDEX_SIZE: usize = 4;
+    const FULL_SIZE: usize = SuggestPiece::LEN_SIZE + SuggestPiece::LEN as usize;
+
+    pub fn from(crs: &Cursor<&[u8]>) -> SuggestPiece {
+        let start = SuggestPiece::LEN_SIZE + SuggestPiece::ID_SIZE;
+        let mut piece_index = [0; SuggestPiece::INDEX_SIZE];
+        piece_index.copy_from_slice(&crs.get_ref()[start..start + SuggestPiece::INDEX_SIZE]);
+
+        SuggestPiece {
+            piece_index: u32::from_be_bytes(piece_index),
+        }
+    }
+
+    pub fn check(available_data: usize, length: usize) -> Result<usize, Error> {
+        match length == SuggestPiece::LEN as usize
+            && available_data >= SuggestPiece::LEN_SIZE + length
+        {
+            true => Ok(SuggestPiece::FULL_SIZE),
+            false => Err(Error::Incomplete("SuggestPiece")),
+        }
+    }
+
+    pub fn validate(&self, pieces_num: usize) -> Result<(), Error> {
+        match (self.piece_index as usize) < pieces_num {
+            true => Ok(()),
+            false => Err(Error::InvalidPieceIndex("SuggestPiece")),
+        }
+    }
+}
+
+impl Serializer for SuggestPiece {
+    fn data(&self) -> Vec<u8> {
+        let mut vec = vec![];
+        vec.extend_from_slice(&SuggestPiece::LEN.to_be_bytes());
+        vec.push(SuggestPiece::ID);
+        vec.extend_from_slice(&self.piece_index.to_be_bytes());
+
+        vec
+    }
+}
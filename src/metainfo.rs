@@ -11,8 +11,8 @@ use crate::bcodec::bvalue::BValue;
 use crate::bcodec::raw_finder::RawFinder;
 use crate::constants::{HASH_SIZE, PIECE_LENGTH};
 use crate::hashmap;
-use crate::Error;
 use crate::{BDecoder, DeepFinder};
+use crate::{Certificate, Error, Signature, Signer};
 use sha1_smol;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
@@ -24,11 +24,16 @@ use std::path::{Path, PathBuf};
 #[derive(PartialEq, Clone, Debug)]
 pub struct Metainfo {
     announce: String,
+    nodes: Vec<(String, u16)>,
     name: String,
     piece_length: u64,
     pieces: Vec<[u8; HASH_SIZE]>,
     files: Vec<File>,
     info_hash: [u8; HASH_SIZE],
+    metadata_size: u64,
+    private: bool,
+    info: Vec<u8>,
+    signatures: Vec<Signature>,
 }
 
 /// File description in metainfo (.torrent) file.
@@ -56,6 +61,31 @@ impl Metainfo {
     /// Metainfo::create_file(Path::new("my_file.dat"), &"http://127.0.0.1:8000".to_string()).unwrap();
     /// ```
     pub fn create_file(path: &Path, tracker_addr: &String) -> Result<(), Error> {
+        Self::create(path, tracker_addr, None)
+    }
+
+    /// Create new torrent file, signed with signer key (see
+    /// [BEP35](https://www.bittorrent.org/beps/bep_0035.html)). Signer certificate is embedded in
+    /// torrent.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use rdest::{Metainfo, Signer};
+    /// use std::path::Path;
+    ///
+    /// let signer = Signer::from_files(Path::new("key.pem"), Path::new("cert.pem")).unwrap();
+    /// Metainfo::create_signed_file(Path::new("my_file.dat"), "http://127.0.0.1:8000", &signer)
+    ///     .unwrap();
+    /// ```
+    pub fn create_signed_file(
+        path: &Path,
+        tracker_addr: &str,
+        signer: &Signer,
+    ) -> Result<(), Error> {
+        Self::create(path, tracker_addr, Some(signer))
+    }
+
+    fn create(path: &Path, tracker_addr: &str, signer: Option<&Signer>) -> Result<(), Error> {
         let metadata = match fs::metadata(path) {
             Ok(metadata) => {
                 if metadata.is_dir() {
@@ -95,10 +125,24 @@ impl Metainfo {
             b"length".to_vec() => BValue::Int(metadata.len() as i64)
         ];
 
-        let torrent = hashmap![
-            b"announce".to_vec() => BValue::ByteStr(tracker_addr.to_owned().into_bytes()),
-            b"info".to_vec() => BValue::Dict(info)
+        let mut torrent = hashmap![
+            b"announce".to_vec() => BValue::ByteStr(tracker_addr.to_owned().into_bytes())
         ];
+        if let Some(signer) = signer {
+            let signature = signer.sign(BEncoder::new().add_dict(&info).encode())?;
+            let mut entry = hashmap![
+                b"signature".to_vec() => BValue::ByteStr(signature.signature)
+            ];
+            if let Some(certificate) = signature.certificate {
+                entry.insert(b"certificate".to_vec(), BValue::ByteStr(certificate));
+            }
+
+            torrent.insert(
+                b"signatures".to_vec(),
+                BValue::Dict(hashmap![signature.identity.into_bytes() => BValue::Dict(entry)]),
+            );
+        }
+        torrent.insert(b"info".to_vec(), BValue::Dict(info));
 
         let torrent_file = match path.file_name() {
             Some(file_name) => {
@@ -183,28 +227,62 @@ impl Metainfo {
             },
         };
 
+        let info = Self::raw_info(data);
+        let signatures = Self::find_signatures(dict, data)?;
+        Self::verify_embedded_signatures(&info, &signatures)?;
+
         let metainfo = Metainfo {
             announce: Self::find_announce(dict)?,
+            nodes: Self::find_nodes(dict),
             name,
             piece_length: Self::find_piece_length(dict)?,
             pieces: Self::find_pieces(dict)?,
             files,
             info_hash: Self::calculate_hash(data)?,
+            metadata_size: Self::info_size(data),
+            private: Self::find_private(dict),
+            info,
+            signatures,
         };
 
         Ok(metainfo)
     }
 
-    /// Find value for "announce" key in pre-parsed dictionary (converted to HashMap).
+    /// Find value for "announce" key in pre-parsed dictionary (converted to HashMap). Missing
+    /// key is allowed (trackerless torrent), and then empty string is returned.
     pub fn find_announce(dict: &HashMap<Vec<u8>, BValue>) -> Result<String, Error> {
         match dict.get(&b"announce".to_vec()) {
             Some(BValue::ByteStr(val)) => {
                 String::from_utf8(val.to_vec()).or(Err(Error::MetaInvalidUtf8("announce")))
             }
+            None => Ok(String::new()),
             _ => Err(Error::MetaIncorrectOrMissing("announce")),
         }
     }
 
+    /// Find DHT bootstrap nodes in "nodes" key in pre-parsed dictionary (converted to HashMap).
+    /// Incorrect entries are skipped. See [BEP5](https://www.bittorrent.org/beps/bep_0005.html#torrent-file-extensions).
+    pub fn find_nodes(dict: &HashMap<Vec<u8>, BValue>) -> Vec<(String, u16)> {
+        match dict.get(&b"nodes".to_vec()) {
+            Some(BValue::List(nodes)) => nodes
+                .iter()
+                .filter_map(|node| match node {
+                    BValue::List(node) => match node.as_slice() {
+                        [BValue::ByteStr(host), BValue::Int(port)] => {
+                            match (String::from_utf8(host.to_vec()), u16::try_from(*port)) {
+                                (Ok(host), Ok(port)) => Some((host, port)),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
     /// Find value for "info:name" key in pre-parsed dictionary (converted to HashMap).
     pub fn find_name(dict: &HashMap<Vec<u8>, BValue>) -> Result<String, Error> {
         match dict.get(&b"info".to_vec()) {
@@ -250,6 +328,17 @@ impl Metainfo {
         }
     }
 
+    /// Find value for "info:private" key in pre-parsed dictionary (converted to HashMap). See
+    /// [BEP27](https://www.bittorrent.org/beps/bep_0027.html).
+    pub fn find_private(dict: &HashMap<Vec<u8>, BValue>) -> bool {
+        match dict.get(&b"info".to_vec()) {
+            Some(BValue::Dict(info)) => {
+                matches!(info.get(&b"private".to_vec()), Some(BValue::Int(1)))
+            }
+            _ => false,
+        }
+    }
+
     /// Find value for "info:length" key in pre-parsed dictionary (converted to HashMap).
     pub fn find_length(dict: &HashMap<Vec<u8>, BValue>) -> Option<u64> {
         match dict.get(&b"info".to_vec()) {
@@ -272,6 +361,69 @@ impl Metainfo {
         }
     }
 
+    /// Find torrent signatures in "signatures" key in pre-parsed dictionary (converted to
+    /// HashMap). Signed "info" dictionaries are taken in raw format from bencoded `data`.
+    /// See [BEP35](https://www.bittorrent.org/beps/bep_0035.html).
+    pub fn find_signatures(
+        dict: &HashMap<Vec<u8>, BValue>,
+        data: &[u8],
+    ) -> Result<Vec<Signature>, Error> {
+        let signatures = match dict.get(&b"signatures".to_vec()) {
+            Some(BValue::Dict(signatures)) => signatures,
+            None => return Ok(vec![]),
+            _ => return Err(Error::MetaIncorrectOrMissing("signatures")),
+        };
+
+        let mut result = vec![];
+        for (identity, entry) in signatures.iter() {
+            let identity = String::from_utf8(identity.to_vec())
+                .or(Err(Error::MetaInvalidUtf8("signatures")))?;
+            let entry = match entry {
+                BValue::Dict(entry) => entry,
+                _ => return Err(Error::MetaIncorrectOrMissing("signatures")),
+            };
+
+            let signature = match entry.get(&b"signature".to_vec()) {
+                Some(BValue::ByteStr(signature)) => signature.to_vec(),
+                _ => return Err(Error::MetaIncorrectOrMissing("signature")),
+            };
+            let certificate = match entry.get(&b"certificate".to_vec()) {
+                Some(BValue::ByteStr(certificate)) => Some(certificate.to_vec()),
+                None => None,
+                _ => return Err(Error::MetaIncorrectOrMissing("certificate")),
+            };
+            let info = match entry.get(&b"info".to_vec()) {
+                Some(BValue::Dict(_)) => Some(Self::raw_signature_info(data, &identity)?),
+                None => None,
+                _ => return Err(Error::MetaIncorrectOrMissing("info")),
+            };
+
+            result.push(Signature {
+                identity,
+                certificate,
+                info,
+                signature,
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Signatures with embedded certificate can be checked without trusted certificate. Others
+    /// are checked only in [`Metainfo::verify_signer`].
+    fn verify_embedded_signatures(info: &[u8], signatures: &[Signature]) -> Result<(), Error> {
+        for signature in signatures.iter() {
+            if let Some(der) = &signature.certificate {
+                match Certificate::from_bytes(der) {
+                    Ok(certificate) if certificate.verify(signature, info) => (),
+                    _ => return Err(Error::MetaInvalidSignature(signature.identity.clone())),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn file_list(list: &Vec<BValue>) -> Vec<File> {
         list.iter()
             .filter_map(|elem| match elem {
@@ -305,11 +457,56 @@ impl Metainfo {
         Err(Error::InfoMissing)
     }
 
-    /// Return URL of the tracker
+    fn raw_info(data: &[u8]) -> Vec<u8> {
+        match DeepFinder::find_first("4:info", data) {
+            Some(info) => info.to_vec(),
+            None => vec![],
+        }
+    }
+
+    /// Signed data has to be hashed exactly as it was encoded by signer.
+    fn raw_signature_info(data: &[u8], identity: &str) -> Result<Vec<u8>, Error> {
+        DeepFinder::find_first("10:signatures", data)
+            .and_then(|signatures| {
+                let key = format!("{}:{}", identity.len(), identity);
+                DeepFinder::find_first(&key, &signatures)
+            })
+            .and_then(|entry| DeepFinder::find_first("4:info", &entry))
+            .ok_or(Error::MetaIncorrectOrMissing("info"))
+    }
+
+    fn info_size(data: &[u8]) -> u64 {
+        match DeepFinder::find_first("4:info", data) {
+            Some(info) => info.len() as u64,
+            None => 0,
+        }
+    }
+
+    /// Return URL of the tracker (empty for trackerless torrent)
     pub fn tracker_url(&self) -> &String {
         &self.announce
     }
 
+    /// Return DHT nodes (host and port) suggested by torrent author.
+    pub fn nodes(&self) -> &Vec<(String, u16)> {
+        &self.nodes
+    }
+
+    /// Return true if peers should be obtained only from tracker (no DHT or PEX).
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+
+    /// Return size of bencoded info dictionary.
+    pub fn metadata_size(&self) -> u64 {
+        self.metadata_size
+    }
+
+    /// Return name of file or directory described by torrent.
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
     /// Return SHA-1 hash of specific piece.
     pub fn piece(&self, piece_index: usize) -> &[u8; HASH_SIZE] {
         &self.pieces[piece_index]
@@ -339,6 +536,35 @@ impl Metainfo {
         self.files.iter().map(|file| file.length).sum()
     }
 
+    /// Return torrent signatures (see [BEP35](https://www.bittorrent.org/beps/bep_0035.html)).
+    pub fn signatures(&self) -> &Vec<Signature> {
+        &self.signatures
+    }
+
+    /// Check if torrent is signed with trusted certificate key.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use rdest::{Certificate, Metainfo};
+    /// use std::path::Path;
+    ///
+    /// let torrent = Metainfo::from_file(Path::new("my_file.dat.torrent")).unwrap();
+    /// let trusted = Certificate::from_file(Path::new("cert.pem")).unwrap();
+    /// torrent.verify_signer(&trusted).unwrap();
+    /// ```
+    pub fn verify_signer(&self, certificate: &Certificate) -> Result<(), Error> {
+        match self
+            .signatures
+            .iter()
+            .any(|signature| certificate.verify(signature, &self.info))
+        {
+            true => Ok(()),
+            false => Err(Error::MetaUntrustedSigner(
+                certificate.identity().to_string(),
+            )),
+        }
+    }
+
     /// Return SHA-1 hash of info section.
     pub fn info_hash(&self) -> &[u8; HASH_SIZE] {
         &self.info_hash
@@ -0,0 +1,372 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Message Stream Encryption (MSE/PE), obfuscating BitTorrent handshake with Diffie-Hellman key
+//! exchange. Payload is then send as plaintext or encrypted with RC4, see
+//! [specification](https://wiki.vuze.com/w/Message_Stream_Encryption). Handshake works over any
+//! stream (TCP or uTP).
+
+use crate::constants::HASH_SIZE;
+use crate::Error;
+use num_bigint::BigUint;
+use rand::Rng;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::{timeout, Duration};
+
+/// 768-bit safe prime used as Diffie-Hellman modulus.
+const PRIME: &[u8; KEY_SIZE] = b"\
+    \xff\xff\xff\xff\xff\xff\xff\xff\xc9\x0f\xda\xa2\x21\x68\xc2\x34\xc4\xc6\x62\x8b\
+    \x80\xdc\x1c\xd1\x29\x02\x4e\x08\x8a\x67\xcc\x74\x02\x0b\xbe\xa6\x3b\x13\x9b\x22\
+    \x51\x4a\x08\x79\x8e\x34\x04\xdd\xef\x95\x19\xb3\xcd\x3a\x43\x1b\x30\x2b\x0a\x6d\
+    \xf2\x5f\x14\x37\x4f\xe1\x35\x6d\x6d\x51\xc2\x45\xe4\x85\xb5\x76\x62\x5e\x7e\xc6\
+    \xf4\x4c\x42\xe9\xa6\x3a\x36\x21\x00\x00\x00\x00\x00\x09\x05\x63";
+const GENERATOR: u32 = 2;
+const KEY_SIZE: usize = 96;
+const PRIVATE_KEY_SIZE: usize = 20;
+const VC: [u8; 8] = [0; 8];
+const MAX_PAD_SIZE: usize = 512;
+/// RC4 keystream bytes dropped at start, as they leak information about key.
+const RC4_DISCARD: usize = 1024;
+const HANDSHAKE_TIMEOUT_SEC: u64 = 10;
+
+/// Crypto method: payload send as plaintext (only handshake is obfuscated).
+pub const CRYPTO_PLAINTEXT: u32 = 0x01;
+/// Crypto method: payload encrypted with RC4.
+pub const CRYPTO_RC4: u32 = 0x02;
+
+/// Encryption policy for peer connections.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum Encryption {
+    /// Only plain BitTorrent handshake.
+    Disabled,
+    /// Try encrypted connection first, but accept (and fall back to) plain one.
+    #[default]
+    Preferred,
+    /// Only connections with RC4 encrypted payload.
+    Required,
+}
+
+impl Encryption {
+    /// Crypto methods offered or accepted in MSE handshake.
+    pub fn crypto_methods(&self) -> u32 {
+        match self {
+            Encryption::Disabled => 0,
+            Encryption::Preferred => CRYPTO_RC4 | CRYPTO_PLAINTEXT,
+            Encryption::Required => CRYPTO_RC4,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Rc4 {
+    state: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Rc4 {
+    fn new(key: &[u8]) -> Rc4 {
+        let mut state = [0; 256];
+        for (idx, s) in state.iter_mut().enumerate() {
+            *s = idx as u8;
+        }
+
+        let mut j: u8 = 0;
+        for i in 0..256 {
+            j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+            state.swap(i, j as usize);
+        }
+
+        let mut rc4 = Rc4 { state, i: 0, j: 0 };
+        rc4.apply(&mut [0; RC4_DISCARD]);
+        rc4
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        for b in data.iter_mut() {
+            self.i = self.i.wrapping_add(1);
+            self.j = self.j.wrapping_add(self.state[self.i as usize]);
+            self.state.swap(self.i as usize, self.j as usize);
+            let idx = self.state[self.i as usize].wrapping_add(self.state[self.j as usize]);
+            *b ^= self.state[idx as usize];
+        }
+    }
+}
+
+/// RC4 streams for both directions of connection.
+#[derive(Clone)]
+pub struct Cipher {
+    enc: Rc4,
+    dec: Rc4,
+}
+
+impl Cipher {
+    fn new(s: &[u8], skey: &[u8; HASH_SIZE], initiator: bool) -> Cipher {
+        let key_a = Rc4::new(&hash(&[b"keyA", s, skey]));
+        let key_b = Rc4::new(&hash(&[b"keyB", s, skey]));
+        match initiator {
+            true => Cipher {
+                enc: key_a,
+                dec: key_b,
+            },
+            false => Cipher {
+                enc: key_b,
+                dec: key_a,
+            },
+        }
+    }
+
+    /// Encrypt data send to peer.
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        self.enc.apply(data);
+    }
+
+    /// Decrypt data received from peer.
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        self.dec.apply(data);
+    }
+}
+
+/// Check if received data starts like plain BitTorrent handshake.
+///
+/// # Example
+/// ```
+/// use rdest::mse;
+///
+/// assert!(mse::is_plain_handshake(b"\x13BitTorrent protocol"));
+/// ```
+pub fn is_plain_handshake(prefix: &[u8; 20]) -> bool {
+    prefix == b"\x13BitTorrent protocol"
+}
+
+/// Perform MSE handshake as connection initiator, offering given crypto methods. Return cipher if
+/// peer selected RC4, or None for plaintext.
+pub async fn handshake_outgoing<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    skey: &[u8; HASH_SIZE],
+    crypto_provide: u32,
+) -> Result<Option<Cipher>, Error> {
+    match timeout(
+        Duration::from_secs(HANDSHAKE_TIMEOUT_SEC),
+        initiate(socket, skey, crypto_provide),
+    )
+    .await
+    {
+        Ok(res) => res,
+        Err(_) => Err(Error::MseFail("timeout")),
+    }
+}
+
+/// Perform MSE handshake for accepted connection. Prefix contains first bytes already read from
+/// socket (see [`is_plain_handshake`]). Return cipher (None for plaintext) and initial payload
+/// send by initiator.
+pub async fn handshake_incoming<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    prefix: &[u8],
+    skey: &[u8; HASH_SIZE],
+    crypto_accept: u32,
+) -> Result<(Option<Cipher>, Vec<u8>), Error> {
+    match timeout(
+        Duration::from_secs(HANDSHAKE_TIMEOUT_SEC),
+        respond(socket, prefix, skey, crypto_accept),
+    )
+    .await
+    {
+        Ok(res) => res,
+        Err(_) => Err(Error::MseFail("timeout")),
+    }
+}
+
+async fn initiate<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    skey: &[u8; HASH_SIZE],
+    crypto_provide: u32,
+) -> Result<Option<Cipher>, Error> {
+    let (private_key, public_key) = generate_keys();
+    send(socket, &[public_key, random_pad()].concat()).await?;
+
+    let mut remote_key = [0; KEY_SIZE];
+    recv(socket, &mut remote_key).await?;
+    let s = shared_secret(&remote_key, &private_key);
+    let mut cipher = Cipher::new(&s, skey, true);
+
+    // Initial payload is not used, handshake is send after negotiation
+    let mut msg = [
+        &VC[..],
+        &crypto_provide.to_be_bytes(),
+        &0u16.to_be_bytes(),
+        &0u16.to_be_bytes(),
+    ]
+    .concat();
+    cipher.encrypt(&mut msg);
+    let skey_hash = xor(&hash(&[b"req2", skey]), &hash(&[b"req3", &s]));
+    send(
+        socket,
+        &[&hash(&[b"req1", &s])[..], &skey_hash, &msg].concat(),
+    )
+    .await?;
+
+    // Responder padding has unknown length, so look for encrypted VC
+    let mut encrypted_vc = VC;
+    cipher.dec.clone().apply(&mut encrypted_vc);
+    synchronize(socket, &encrypted_vc).await?;
+    cipher.decrypt(&mut [0; VC.len()]);
+
+    let mut msg = [0; 6];
+    recv(socket, &mut msg).await?;
+    cipher.decrypt(&mut msg);
+    let crypto_select = u32::from_be_bytes([msg[0], msg[1], msg[2], msg[3]]);
+    let pad_len = u16::from_be_bytes([msg[4], msg[5]]) as usize;
+    if pad_len > MAX_PAD_SIZE {
+        return Err(Error::MseFail("padding too long"));
+    }
+
+    let mut pad = vec![0; pad_len];
+    recv(socket, &mut pad).await?;
+    cipher.decrypt(&mut pad);
+
+    match crypto_select & crypto_provide {
+        CRYPTO_RC4 => Ok(Some(cipher)),
+        CRYPTO_PLAINTEXT => Ok(None),
+        _ => Err(Error::MseFail("incorrect crypto method selected")),
+    }
+}
+
+async fn respond<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    prefix: &[u8],
+    skey: &[u8; HASH_SIZE],
+    crypto_accept: u32,
+) -> Result<(Option<Cipher>, Vec<u8>), Error> {
+    let mut remote_key = [0; KEY_SIZE];
+    remote_key[..prefix.len()].copy_from_slice(prefix);
+    recv(socket, &mut remote_key[prefix.len()..]).await?;
+
+    let (private_key, public_key) = generate_keys();
+    send(socket, &[public_key, random_pad()].concat()).await?;
+
+    let s = shared_secret(&remote_key, &private_key);
+    synchronize(socket, &hash(&[b"req1", &s])).await?;
+
+    let mut skey_hash = [0; HASH_SIZE];
+    recv(socket, &mut skey_hash).await?;
+    if skey_hash != xor(&hash(&[b"req2", skey]), &hash(&[b"req3", &s])) {
+        return Err(Error::MseFail("unknown info hash"));
+    }
+
+    let mut cipher = Cipher::new(&s, skey, false);
+    let mut msg = [0; 14];
+    recv(socket, &mut msg).await?;
+    cipher.decrypt(&mut msg);
+    if msg[..VC.len()] != VC {
+        return Err(Error::MseFail("incorrect verification constant"));
+    }
+    let crypto_provide = u32::from_be_bytes([msg[8], msg[9], msg[10], msg[11]]);
+    let pad_len = u16::from_be_bytes([msg[12], msg[13]]) as usize;
+    if pad_len > MAX_PAD_SIZE {
+        return Err(Error::MseFail("padding too long"));
+    }
+
+    let mut pad = vec![0; pad_len + 2];
+    recv(socket, &mut pad).await?;
+    cipher.decrypt(&mut pad);
+    let payload_len = u16::from_be_bytes([pad[pad_len], pad[pad_len + 1]]) as usize;
+
+    let mut payload = vec![0; payload_len];
+    recv(socket, &mut payload).await?;
+    cipher.decrypt(&mut payload);
+
+    // RC4 is preferred
+    let crypto_select = match crypto_provide & crypto_accept {
+        methods if methods & CRYPTO_RC4 != 0 => CRYPTO_RC4,
+        methods if methods & CRYPTO_PLAINTEXT != 0 => CRYPTO_PLAINTEXT,
+        _ => return Err(Error::MseFail("no common crypto method")),
+    };
+
+    let mut msg = [&VC[..], &crypto_select.to_be_bytes(), &0u16.to_be_bytes()].concat();
+    cipher.encrypt(&mut msg);
+    send(socket, &msg).await?;
+
+    match crypto_select {
+        CRYPTO_RC4 => Ok((Some(cipher), payload)),
+        _ => Ok((None, payload)),
+    }
+}
+
+fn generate_keys() -> (BigUint, Vec<u8>) {
+    let private_key = BigUint::from_bytes_be(&rand::random::<[u8; PRIVATE_KEY_SIZE]>());
+    let public_key = BigUint::from(GENERATOR).modpow(&private_key, &BigUint::from_bytes_be(PRIME));
+    (private_key, to_key_bytes(&public_key))
+}
+
+fn shared_secret(remote_key: &[u8; KEY_SIZE], private_key: &BigUint) -> Vec<u8> {
+    let s = BigUint::from_bytes_be(remote_key).modpow(private_key, &BigUint::from_bytes_be(PRIME));
+    to_key_bytes(&s)
+}
+
+/// Keys are always send as 96 bytes, big-endian, with leading zeros.
+fn to_key_bytes(value: &BigUint) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    [vec![0; KEY_SIZE - bytes.len()], bytes].concat()
+}
+
+fn random_pad() -> Vec<u8> {
+    let len = rand::thread_rng().gen_range(0..=MAX_PAD_SIZE);
+    (0..len).map(|_| rand::random()).collect()
+}
+
+fn hash(parts: &[&[u8]]) -> [u8; HASH_SIZE] {
+    let mut hasher = sha1_smol::Sha1::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.digest().bytes()
+}
+
+fn xor(a: &[u8; HASH_SIZE], b: &[u8; HASH_SIZE]) -> [u8; HASH_SIZE] {
+    let mut res = [0; HASH_SIZE];
+    for (idx, r) in res.iter_mut().enumerate() {
+        *r = a[idx] ^ b[idx];
+    }
+    res
+}
+
+/// Read stream until pattern is found. Pattern can be preceded by at most MAX_PAD_SIZE bytes.
+async fn synchronize<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    pattern: &[u8],
+) -> Result<(), Error> {
+    let mut window = vec![];
+    let mut b = [0; 1];
+    while window.len() < MAX_PAD_SIZE + pattern.len() {
+        recv(socket, &mut b).await?;
+        window.push(b[0]);
+        if window.ends_with(pattern) {
+            return Ok(());
+        }
+    }
+
+    Err(Error::MseFail("synchronization pattern not found"))
+}
+
+async fn send<S: AsyncRead + AsyncWrite + Unpin>(socket: &mut S, data: &[u8]) -> Result<(), Error> {
+    socket
+        .write_all(data)
+        .await
+        .map_err(|_| Error::ConnectionReset)
+}
+
+async fn recv<S: AsyncRead + AsyncWrite + Unpin>(
+    socket: &mut S,
+    buff: &mut [u8],
+) -> Result<(), Error> {
+    match socket.read_exact(buff).await {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error::ConnectionClosed),
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+mod pmp;
+mod upnp;
+
+use crate::commands::MappingCmd;
+use crate::Error;
+use pmp::PmpClient;
+use std::cmp::max;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
+use tokio::time::Duration;
+use upnp::Igd;
+
+/// Requested mapping lifetime (recommended by RFC 6886).
+const LIFETIME_SEC: u64 = 7200;
+/// Mapping is renewed in half of its lifetime, but not more often than this.
+const MIN_RENEW_SEC: u64 = 60;
+/// Delay before next try, when gateway couldn't map port.
+const RETRY_SEC: u64 = 10 * 60;
+
+/// Transport protocol of mapped port.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Transport {
+    /// TCP
+    Tcp,
+    /// UDP
+    Udp,
+}
+
+/// Protocol used to map port on gateway.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum NatProtocol {
+    /// Port Control Protocol ([RFC 6887](https://www.rfc-editor.org/rfc/rfc6887))
+    Pcp,
+    /// NAT Port Mapping Protocol ([RFC 6886](https://www.rfc-editor.org/rfc/rfc6886))
+    NatPmp,
+    /// UPnP Internet Gateway Device
+    Upnp,
+}
+
+/// Port mapped on gateway (TCP and UDP).
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Mapping {
+    /// Protocol that created mapping
+    pub protocol: NatProtocol,
+    /// Address under which peers from internet reach TCP listener
+    pub external_addr: SocketAddr,
+    /// Time after which gateway removes mapping, unless it's renewed
+    pub lifetime: Duration,
+}
+
+/// Maps listen port on gateway, so peers from internet can connect to session.
+///
+/// PCP is tried first, then NAT-PMP (both use default gateway), and at the end UPnP-IGD (gateway
+/// is discovered with SSDP). Protocol that succeeded is used later for renewal and removal.
+///
+/// # Example
+/// ```no_run
+/// use rdest::PortMapper;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mut mapper = PortMapper::new(6881);
+/// match mapper.map().await {
+///     Ok(mapping) => println!("Reachable at {}", mapping.external_addr),
+///     Err(e) => println!("Port not mapped: {}", e),
+/// }
+/// mapper.unmap().await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PortMapper {
+    port: u16,
+    gateway: Option<SocketAddr>,
+    ssdp_addr: SocketAddr,
+    lifetime: Duration,
+    pmp: Option<PmpClient>,
+    igd: Option<Igd>,
+    mapping: Option<Mapping>,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Tcp => write!(f, "TCP"),
+            Transport::Udp => write!(f, "UDP"),
+        }
+    }
+}
+
+impl fmt::Display for NatProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NatProtocol::Pcp => write!(f, "PCP"),
+            NatProtocol::NatPmp => write!(f, "NAT-PMP"),
+            NatProtocol::Upnp => write!(f, "UPnP"),
+        }
+    }
+}
+
+impl PortMapper {
+    /// Create mapper for TCP and UDP `port` (the same external port is requested).
+    pub fn new(port: u16) -> PortMapper {
+        PortMapper {
+            port,
+            gateway: Self::default_gateway().map(|ip| SocketAddr::new(ip, pmp::SERVER_PORT)),
+            ssdp_addr: upnp::SSDP_ADDR,
+            lifetime: Duration::from_secs(LIFETIME_SEC),
+            pmp: None,
+            igd: None,
+            mapping: None,
+        }
+    }
+
+    /// Address of PCP/NAT-PMP server (by default default gateway, on port 5351).
+    pub fn set_gateway(&mut self, gateway: SocketAddr) {
+        self.gateway = Some(gateway);
+    }
+
+    /// Address to which SSDP search is send (by default multicast group 239.255.255.250:1900).
+    pub fn set_ssdp_addr(&mut self, ssdp_addr: SocketAddr) {
+        self.ssdp_addr = ssdp_addr;
+    }
+
+    /// Requested mapping lifetime (by default 2 hours). Gateway may grant shorter one.
+    pub fn set_lifetime(&mut self, lifetime: Duration) {
+        self.lifetime = lifetime;
+    }
+
+    /// Current mapping.
+    pub fn mapping(&self) -> Option<Mapping> {
+        self.mapping
+    }
+
+    /// Map (or renew) port on gateway. When renewal fails, all protocols are tried again.
+    pub async fn map(&mut self) -> Result<Mapping, Error> {
+        let mut result = Err(Error::NatGatewayNotFound);
+        if self.igd.is_none() {
+            if let Some(gateway) = self.gateway {
+                result = self.map_pmp(gateway).await;
+            }
+        }
+        if result.is_err() {
+            result = self.map_upnp().await;
+        }
+
+        self.mapping = result.as_ref().ok().copied();
+        result
+    }
+
+    /// Remove mapping from gateway (errors are ignored, mapping expires anyway).
+    pub async fn unmap(&mut self) {
+        let mapping = match self.mapping.take() {
+            Some(mapping) => mapping,
+            None => return,
+        };
+
+        for transport in [Transport::Tcp, Transport::Udp] {
+            let _ = match (mapping.protocol, &self.pmp, &self.igd) {
+                (NatProtocol::Upnp, _, Some(igd)) => {
+                    igd.delete_port_mapping(transport, self.port).await
+                }
+                (_, Some(pmp), _) => pmp.unmap(transport, self.port).await,
+                _ => Ok(()),
+            };
+        }
+    }
+
+    /// Keep port mapped (report every change of external address), until stop is requested or
+    /// Session is gone. Mapping is removed at the end.
+    pub(crate) async fn run(
+        &mut self,
+        event_ch: mpsc::Sender<MappingCmd>,
+        mut stop_ch: oneshot::Receiver<()>,
+    ) {
+        let mut external_addr = None;
+        loop {
+            let delay = match self.map().await {
+                Ok(mapping) => {
+                    if external_addr != Some(mapping.external_addr) {
+                        external_addr = Some(mapping.external_addr);
+                        let _ = event_ch.send(MappingCmd::Mapped(mapping)).await;
+                    }
+                    max(mapping.lifetime / 2, Duration::from_secs(MIN_RENEW_SEC))
+                }
+                Err(e) => {
+                    external_addr = None;
+                    let _ = event_ch.send(MappingCmd::Fail(e.to_string())).await;
+                    Duration::from_secs(RETRY_SEC)
+                }
+            };
+
+            tokio::select! {
+                _ = time::sleep(delay) => (),
+                _ = &mut stop_ch => break,
+            }
+        }
+
+        self.unmap().await;
+    }
+
+    async fn map_pmp(&mut self, gateway: SocketAddr) -> Result<Mapping, Error> {
+        if self.pmp.is_none() {
+            self.pmp = Some(PmpClient::connect(gateway).await?);
+        }
+        let pmp = match &mut self.pmp {
+            Some(pmp) => pmp,
+            None => return Err(Error::NatGatewayNotFound),
+        };
+
+        let lifetime = self.lifetime.as_secs() as u32;
+        let result = match pmp.map(Transport::Tcp, self.port, lifetime).await {
+            Ok(tcp) => pmp
+                .map(Transport::Udp, self.port, lifetime)
+                .await
+                .map(|udp| (tcp, udp)),
+            Err(e) => Err(e),
+        };
+
+        // uTP shares external port with TCP, so both have to be the same
+        let result = match result {
+            Ok((tcp, udp)) if tcp.external_addr.port() != udp.external_addr.port() => {
+                for transport in [Transport::Tcp, Transport::Udp] {
+                    let _ = pmp.unmap(transport, self.port).await;
+                }
+                Err(Error::NatRefused(format!(
+                    "TCP port {} and UDP port {} differ",
+                    tcp.external_addr.port(),
+                    udp.external_addr.port()
+                )))
+            }
+            result => result,
+        };
+
+        match result {
+            Ok((tcp, udp)) => Ok(Mapping {
+                protocol: pmp.protocol(),
+                external_addr: tcp.external_addr,
+                lifetime: Duration::from_secs(tcp.lifetime.min(udp.lifetime) as u64),
+            }),
+            Err(e) => {
+                self.pmp = None;
+                Err(e)
+            }
+        }
+    }
+
+    async fn map_upnp(&mut self) -> Result<Mapping, Error> {
+        if self.igd.is_none() {
+            self.igd = Some(Igd::discover(self.ssdp_addr).await?);
+        }
+        let igd = match &self.igd {
+            Some(igd) => igd,
+            None => return Err(Error::NatGatewayNotFound),
+        };
+
+        let lease = self.lifetime.as_secs() as u32;
+        let result = async {
+            igd.add_port_mapping(Transport::Tcp, self.port, lease)
+                .await?;
+            igd.add_port_mapping(Transport::Udp, self.port, lease)
+                .await?;
+            igd.external_ip().await
+        }
+        .await;
+
+        match result {
+            Ok(ip) => Ok(Mapping {
+                protocol: NatProtocol::Upnp,
+                external_addr: SocketAddr::new(ip, self.port),
+                lifetime: self.lifetime,
+            }),
+            Err(e) => {
+                self.igd = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// Default IPv4 gateway from routing table (Linux only).
+    fn default_gateway() -> Option<IpAddr> {
+        let routes = fs::read_to_string("/proc/net/route").ok()?;
+
+        // Columns: interface, destination, gateway (hex, little endian)
+        for line in routes.lines().skip(1) {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            if columns.len() > 2 && columns[1] == "00000000" {
+                let gateway = u32::from_str_radix(columns[2], 16).ok()?;
+                return Some(Ipv4Addr::from(gateway.to_le_bytes()).into());
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::nat::{NatProtocol, Transport};
+use crate::Error;
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::time;
+use tokio::time::Duration;
+
+/// Port on which gateway listens for PCP and NAT-PMP requests.
+pub const SERVER_PORT: u16 = 5351;
+const NATPMP_VERSION: u8 = 0;
+const PCP_VERSION: u8 = 2;
+const OPCODE_EXTERNAL_ADDR: u8 = 0;
+const OPCODE_MAP_UDP: u8 = 1;
+const OPCODE_MAP_TCP: u8 = 2;
+const OPCODE_PCP_MAP: u8 = 1;
+const RESPONSE_FLAG: u8 = 0x80;
+/// The same result code is used by PCP and NAT-PMP, so NAT-PMP gateway reject PCP request with
+/// response, that client understands.
+const RESULT_UNSUPP_VERSION: u16 = 1;
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+/// Retransmission timeout is calculated as 250 * 2 ^ n ms, where n is in range 0..=3 (RFC 6886
+/// allows more tries, but gateway in local network responds quickly or not at all).
+const TIMEOUT_BASE_MS: u64 = 250;
+const MAX_RETRANSMISSIONS: u32 = 3;
+const MAX_DATAGRAM_SIZE: usize = 1100;
+const NONCE_SIZE: usize = 12;
+const PCP_MAP_SIZE: usize = 60;
+const NATPMP_MAP_RESP_SIZE: usize = 16;
+const NATPMP_EXTERNAL_ADDR_RESP_SIZE: usize = 12;
+
+/// Port mapped on gateway.
+#[derive(Debug, Clone, Copy)]
+pub struct MappedPort {
+    pub external_addr: SocketAddr,
+    pub lifetime: u32,
+}
+
+/// PCP client ([RFC 6887](https://www.rfc-editor.org/rfc/rfc6887)), that falls back to NAT-PMP
+/// ([RFC 6886](https://www.rfc-editor.org/rfc/rfc6886)) when gateway doesn't support it.
+#[derive(Debug)]
+pub struct PmpClient {
+    socket: UdpSocket,
+    /// Identify mappings of this client in PCP, reused for renewal and deletion
+    nonce: [u8; NONCE_SIZE],
+    protocol: NatProtocol,
+}
+
+impl PmpClient {
+    pub async fn connect(gateway: SocketAddr) -> Result<PmpClient, Error> {
+        let bind_addr = match gateway {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(bind_addr)
+            .await
+            .map_err(|_| Error::SocketNotAvailable)?;
+        socket
+            .connect(gateway)
+            .await
+            .map_err(|_| Error::NatGatewayNotFound)?;
+
+        Ok(PmpClient {
+            socket,
+            nonce: rand::random(),
+            protocol: NatProtocol::Pcp,
+        })
+    }
+
+    /// Protocol understood by gateway (PCP until gateway reject it).
+    pub fn protocol(&self) -> NatProtocol {
+        self.protocol
+    }
+
+    /// Map (or renew) port on gateway. External port is the same as internal, if gateway allows.
+    pub async fn map(
+        &mut self,
+        transport: Transport,
+        port: u16,
+        lifetime: u32,
+    ) -> Result<MappedPort, Error> {
+        if self.protocol == NatProtocol::Pcp {
+            match self.pcp_map(transport, port, lifetime).await? {
+                Some(mapped) => return Ok(mapped),
+                None => self.protocol = NatProtocol::NatPmp,
+            }
+        }
+
+        let ip = self.natpmp_external_ip().await?;
+        let (external_port, lifetime) = self.natpmp_map(transport, port, lifetime).await?;
+        Ok(MappedPort {
+            external_addr: SocketAddr::new(ip, external_port),
+            lifetime,
+        })
+    }
+
+    /// Remove mapping (request with zero lifetime).
+    pub async fn unmap(&self, transport: Transport, port: u16) -> Result<(), Error> {
+        match self.protocol {
+            NatProtocol::NatPmp => self.natpmp_map(transport, port, 0).await.map(|_| ()),
+            _ => self.pcp_map(transport, port, 0).await.map(|_| ()),
+        }
+    }
+
+    /// Return `None` if gateway doesn't support PCP.
+    async fn pcp_map(
+        &self,
+        transport: Transport,
+        port: u16,
+        lifetime: u32,
+    ) -> Result<Option<MappedPort>, Error> {
+        let protocol = match transport {
+            Transport::Tcp => PROTOCOL_TCP,
+            Transport::Udp => PROTOCOL_UDP,
+        };
+        let client_ip = match self.socket.local_addr() {
+            Ok(addr) => Self::ip_to_bytes(addr.ip()),
+            Err(_) => return Err(Error::SocketNotAvailable),
+        };
+
+        let mut req = vec![PCP_VERSION, OPCODE_PCP_MAP, 0, 0];
+        req.extend_from_slice(&lifetime.to_be_bytes());
+        req.extend_from_slice(&client_ip);
+        req.extend_from_slice(&self.nonce);
+        req.extend_from_slice(&[protocol, 0, 0, 0]);
+        req.extend_from_slice(&port.to_be_bytes());
+        req.extend_from_slice(&port.to_be_bytes());
+        // No preference for external address
+        req.extend_from_slice(&Self::ip_to_bytes(Ipv4Addr::UNSPECIFIED.into()));
+
+        let resp = self.request(&req).await?;
+        match Self::result_code(&resp)? {
+            RESULT_UNSUPP_VERSION => return Ok(None),
+            0 => (),
+            result => return Err(Self::refused(result)),
+        }
+        if resp[0] != PCP_VERSION || resp.len() < PCP_MAP_SIZE {
+            return Err(Error::NatInvalidResp("PCP map size"));
+        } else if resp[24..36] != self.nonce || resp[36] != protocol {
+            return Err(Error::NatInvalidResp("PCP map nonce"));
+        }
+
+        let ip: [u8; 16] = resp[44..60].try_into().unwrap();
+        let ip = Ipv6Addr::from(ip);
+        let ip = match ip.to_ipv4_mapped() {
+            Some(ip) => IpAddr::V4(ip),
+            None => IpAddr::V6(ip),
+        };
+        Ok(Some(MappedPort {
+            external_addr: SocketAddr::new(ip, u16::from_be_bytes([resp[42], resp[43]])),
+            lifetime: u32::from_be_bytes(resp[4..8].try_into().unwrap()),
+        }))
+    }
+
+    async fn natpmp_external_ip(&self) -> Result<IpAddr, Error> {
+        let resp = self
+            .request(&[NATPMP_VERSION, OPCODE_EXTERNAL_ADDR])
+            .await?;
+        match Self::result_code(&resp)? {
+            0 if resp.len() >= NATPMP_EXTERNAL_ADDR_RESP_SIZE => {
+                let ip: [u8; 4] = resp[8..12].try_into().unwrap();
+                Ok(IpAddr::from(ip))
+            }
+            0 => Err(Error::NatInvalidResp("NAT-PMP external address size")),
+            result => Err(Self::refused(result)),
+        }
+    }
+
+    async fn natpmp_map(
+        &self,
+        transport: Transport,
+        port: u16,
+        lifetime: u32,
+    ) -> Result<(u16, u32), Error> {
+        let opcode = match transport {
+            Transport::Tcp => OPCODE_MAP_TCP,
+            Transport::Udp => OPCODE_MAP_UDP,
+        };
+        // Suggested external port has to be 0 when mapping is removed
+        let external_port = match lifetime {
+            0 => 0u16,
+            _ => port,
+        };
+
+        let mut req = vec![NATPMP_VERSION, opcode, 0, 0];
+        req.extend_from_slice(&port.to_be_bytes());
+        req.extend_from_slice(&external_port.to_be_bytes());
+        req.extend_from_slice(&lifetime.to_be_bytes());
+
+        let resp = self.request(&req).await?;
+        match Self::result_code(&resp)? {
+            0 if resp.len() >= NATPMP_MAP_RESP_SIZE => Ok((
+                u16::from_be_bytes([resp[10], resp[11]]),
+                u32::from_be_bytes(resp[12..16].try_into().unwrap()),
+            )),
+            0 => Err(Error::NatInvalidResp("NAT-PMP map size")),
+            result => Err(Self::refused(result)),
+        }
+    }
+
+    /// Send request and wait for response with matching opcode (with retransmissions).
+    async fn request(&self, req: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut buff = vec![0; MAX_DATAGRAM_SIZE];
+        for n in 0..=MAX_RETRANSMISSIONS {
+            // Gateway that is not listening on port may be reported by ICMP
+            if self.socket.send(req).await.is_err() {
+                return Err(Error::NatGatewayNotFound);
+            }
+
+            let timeout = Duration::from_millis(TIMEOUT_BASE_MS * 2u64.pow(n));
+            let deadline = time::Instant::now() + timeout;
+            loop {
+                let len = match time::timeout_at(deadline, self.socket.recv(&mut buff)).await {
+                    Ok(Ok(len)) => len,
+                    Ok(Err(_)) => return Err(Error::NatGatewayNotFound),
+                    Err(_) => break,
+                };
+                if len >= 4 && buff[1] == req[1] | RESPONSE_FLAG {
+                    return Ok(buff[..len].to_vec());
+                }
+            }
+        }
+
+        Err(Error::NatTimeout)
+    }
+
+    /// Result code is at different offset in PCP (1 byte) and NAT-PMP (2 bytes) responses.
+    fn result_code(resp: &[u8]) -> Result<u16, Error> {
+        match resp[0] {
+            PCP_VERSION => Ok(resp[3] as u16),
+            NATPMP_VERSION => Ok(u16::from_be_bytes([resp[2], resp[3]])),
+            _ => Err(Error::NatInvalidResp("version")),
+        }
+    }
+
+    fn refused(result: u16) -> Error {
+        Error::NatRefused(format!("result code {}", result))
+    }
+
+    /// PCP addresses are always 16 bytes long, IPv4 is IPv4-mapped.
+    fn ip_to_bytes(ip: IpAddr) -> [u8; 16] {
+        match ip {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+            IpAddr::V6(ip) => ip.octets(),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::nat::Transport;
+use crate::Error;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::time;
+use tokio::time::{Duration, Instant};
+use url::Url;
+
+/// SSDP multicast group, on which gateways are discovered.
+pub const SSDP_ADDR: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 1900);
+const SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+/// Gateway should respond in MX seconds, one more second is given for network delay.
+const SEARCH_MX_SEC: u64 = 2;
+const MAX_DATAGRAM_SIZE: usize = 2048;
+const HTTP_TIMEOUT_SEC: u64 = 5;
+/// Services able to map ports, in order of preference.
+const SERVICE_TYPES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+const DESCRIPTION: &str = "rdest";
+
+/// Internet Gateway Device, found with SSDP. Mappings are managed with SOAP actions of
+/// WANIPConnection (or WANPPPConnection) service.
+#[derive(Debug)]
+pub struct Igd {
+    control_url: Url,
+    service_type: String,
+    /// Own address in gateway network
+    local_ip: IpAddr,
+    http_client: reqwest::Client,
+}
+
+impl Igd {
+    /// Search for gateway (`ssdp_addr` is multicast group, or address of known gateway), and read
+    /// its description.
+    pub async fn discover(ssdp_addr: SocketAddr) -> Result<Igd, Error> {
+        let location = Self::search(ssdp_addr).await?;
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(HTTP_TIMEOUT_SEC))
+            .build()
+            .map_err(|_| Error::NatGatewayNotFound)?;
+
+        let description = match http_client.get(location.clone()).send().await {
+            Ok(resp) => resp.text().await.map_err(|_| Error::NatGatewayNotFound)?,
+            Err(_) => return Err(Error::NatGatewayNotFound),
+        };
+        let (service_type, control_url) = Self::find_service(&description)?;
+        let base = match Self::tag_value(&description, "URLBase") {
+            Some(base) => Url::parse(base).unwrap_or(location),
+            None => location,
+        };
+        let control_url = base
+            .join(control_url)
+            .map_err(|_| Error::NatInvalidResp("UPnP control URL"))?;
+
+        Ok(Igd {
+            local_ip: Self::local_ip(&control_url).await?,
+            control_url,
+            service_type: service_type.to_string(),
+            http_client,
+        })
+    }
+
+    /// Map the same external port to own port. Lease duration 0 means permanent mapping.
+    pub async fn add_port_mapping(
+        &self,
+        transport: Transport,
+        port: u16,
+        lease: u32,
+    ) -> Result<(), Error> {
+        let args = [
+            ("NewRemoteHost", String::new()),
+            ("NewExternalPort", port.to_string()),
+            ("NewProtocol", transport.to_string()),
+            ("NewInternalPort", port.to_string()),
+            ("NewInternalClient", self.local_ip.to_string()),
+            ("NewEnabled", "1".to_string()),
+            ("NewPortMappingDescription", DESCRIPTION.to_string()),
+            ("NewLeaseDuration", lease.to_string()),
+        ];
+        self.action("AddPortMapping", &args).await.map(|_| ())
+    }
+
+    pub async fn delete_port_mapping(&self, transport: Transport, port: u16) -> Result<(), Error> {
+        let args = [
+            ("NewRemoteHost", String::new()),
+            ("NewExternalPort", port.to_string()),
+            ("NewProtocol", transport.to_string()),
+        ];
+        self.action("DeletePortMapping", &args).await.map(|_| ())
+    }
+
+    pub async fn external_ip(&self) -> Result<IpAddr, Error> {
+        let resp = self.action("GetExternalIPAddress", &[]).await?;
+        Self::tag_value(&resp, "NewExternalIPAddress")
+            .and_then(|ip| ip.parse().ok())
+            .ok_or(Error::NatInvalidResp("UPnP external address"))
+    }
+
+    /// Send M-SEARCH request and return location of first gateway description.
+    async fn search(ssdp_addr: SocketAddr) -> Result<Url, Error> {
+        let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+            .await
+            .map_err(|_| Error::SocketNotAvailable)?;
+        let req = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: {}\r\n\r\n",
+            SSDP_ADDR, SEARCH_MX_SEC, SEARCH_TARGET
+        );
+        if socket.send_to(req.as_bytes(), ssdp_addr).await.is_err() {
+            return Err(Error::NatGatewayNotFound);
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(SEARCH_MX_SEC + 1);
+        let mut buff = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let len = match time::timeout_at(deadline, socket.recv(&mut buff)).await {
+                Ok(Ok(len)) => len,
+                _ => return Err(Error::NatGatewayNotFound),
+            };
+            let resp = String::from_utf8_lossy(&buff[..len]);
+            if let Some(location) = Self::header(&resp, "location") {
+                if let Ok(location) = Url::parse(location) {
+                    return Ok(location);
+                }
+            }
+        }
+    }
+
+    /// Call SOAP action and return response body. Gateway reports errors with UPnP error code and
+    /// description.
+    async fn action(&self, name: &str, args: &[(&str, String)]) -> Result<String, Error> {
+        let args: String = args
+            .iter()
+            .map(|(arg, value)| format!("<{}>{}</{}>", arg, value, arg))
+            .collect();
+        let body = format!(
+            "<?xml version=\"1.0\"?>\r\n\
+            <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+            s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+            <s:Body><u:{} xmlns:u=\"{}\">{}</u:{}></s:Body></s:Envelope>\r\n",
+            name, self.service_type, args, name
+        );
+
+        let resp = self
+            .http_client
+            .post(self.control_url.clone())
+            .header("Content-Type", "text/xml; charset=\"utf-8\"")
+            .header("SOAPAction", format!("\"{}#{}\"", self.service_type, name))
+            .body(body)
+            .send()
+            .await
+            .map_err(|_| Error::NatTimeout)?;
+        let success = resp.status().is_success();
+        let body = resp.text().await.map_err(|_| Error::NatTimeout)?;
+
+        match success {
+            true => Ok(body),
+            false => Err(Error::NatRefused(format!(
+                "{} {}",
+                Self::tag_value(&body, "errorCode").unwrap_or("?"),
+                Self::tag_value(&body, "errorDescription").unwrap_or("")
+            ))),
+        }
+    }
+
+    /// Find service (type and control URL) that can map ports.
+    fn find_service(description: &str) -> Result<(&'static str, &str), Error> {
+        for service_type in SERVICE_TYPES.iter() {
+            for service in description.split("<service>").skip(1) {
+                if Self::tag_value(service, "serviceType") != Some(service_type) {
+                    continue;
+                }
+                if let Some(control_url) = Self::tag_value(service, "controlURL") {
+                    return Ok((service_type, control_url));
+                }
+            }
+        }
+
+        Err(Error::NatInvalidResp("UPnP service not found"))
+    }
+
+    /// Text of first XML element with given name (namespace prefix is ignored).
+    fn tag_value<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+        let mut rest = xml;
+        while let Some(start) = rest.find('<') {
+            rest = &rest[start + 1..];
+            let end = rest.find('>')?;
+            let tag = &rest[..end];
+            let tag = tag.rsplit(':').next().unwrap_or(tag);
+            rest = &rest[end + 1..];
+            if tag == name {
+                return Some(rest[..rest.find('<')?].trim());
+            }
+        }
+
+        None
+    }
+
+    /// Header value from HTTP-like SSDP response (names are case insensitive).
+    fn header<'a>(resp: &'a str, name: &str) -> Option<&'a str> {
+        resp.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            match key.trim().eq_ignore_ascii_case(name) {
+                true => Some(value.trim()),
+                false => None,
+            }
+        })
+    }
+
+    /// Local address used to reach gateway, that will receive forwarded traffic.
+    async fn local_ip(control_url: &Url) -> Result<IpAddr, Error> {
+        let host = control_url
+            .socket_addrs(|| Some(80))
+            .ok()
+            .and_then(|addrs| addrs.into_iter().next())
+            .ok_or(Error::NatInvalidResp("UPnP control URL"))?;
+        let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+            .await
+            .map_err(|_| Error::SocketNotAvailable)?;
+        socket
+            .connect(host)
+            .await
+            .map_err(|_| Error::NatGatewayNotFound)?;
+
+        socket
+            .local_addr()
+            .map(|addr| addr.ip())
+            .map_err(|_| Error::SocketNotAvailable)
+    }
+}
@@ -7,12 +7,14 @@
 // except according to those terms.
 
 use crate::commands::{
-    BitfieldCmd, HaveCmd, InitCmd, NotInterestedCmd, PieceCmd, ReqData, RequestCmd, UnchokeCmd,
+    AllowedFastCmd, BitfieldCmd, HaveCmd, InitCmd, NotInterestedCmd, PieceCmd, ReqData, RequestCmd,
+    UnchokeCmd,
 };
 use crate::constants::{MAX_UNCHOKED, PEER_ID_SIZE};
 use crate::messages::bitfield::Bitfield;
 use crate::session::Status;
 use crate::Metainfo;
+use std::net::SocketAddr;
 use tokio::task::JoinHandle;
 
 #[derive(Debug)]
@@ -28,6 +30,30 @@ pub struct Peer {
     pub optimistic_unchoke: bool,
     pub download_rate: Option<u32>,
     pub uploaded_rate: Option<u32>,
+    /// Session connected to peer, so its address contains listen port
+    pub connectable: bool,
+    /// Both sides support Fast Extension
+    pub fast: bool,
+    /// Pieces that peer can download from client, even when choked
+    pub am_allowed_fast: Vec<usize>,
+    /// Pieces that client can download from peer, even when choked
+    pub allowed_fast: Vec<usize>,
+    /// Connection is encrypted (MSE)
+    pub encrypted: bool,
+    /// Connection goes over uTP
+    pub utp: bool,
+    /// Peer supports ut_holepunch, so it can relay rendezvous
+    pub holepunch: bool,
+    /// Peer (reported by PEX) that could relay rendezvous, when connection fails
+    pub relay: Option<SocketAddr>,
+    /// Connection opened by holepunch target, it gives way to connection from initiator
+    pub holepunch_target: bool,
+    /// Piece revealed to peer in super-seeding mode
+    pub super_seed_piece: Option<usize>,
+    /// All pieces revealed to peer so far, only these can be requested in super-seeding mode
+    pub revealed_pieces: Vec<usize>,
+    /// Peer doesn't download (it's seed or partial seed)
+    pub upload_only: bool,
 }
 
 impl Peer {
@@ -44,6 +70,18 @@ impl Peer {
             optimistic_unchoke: false,
             download_rate: None,
             uploaded_rate: None,
+            connectable: false,
+            fast: false,
+            am_allowed_fast: vec![],
+            allowed_fast: vec![],
+            encrypted: false,
+            utp: false,
+            holepunch: false,
+            relay: None,
+            holepunch_target: false,
+            super_seed_piece: None,
+            revealed_pieces: vec![],
+            upload_only: false,
         }
     }
 
@@ -54,24 +92,61 @@ impl Peer {
     pub fn handle_init(
         &mut self,
         peer_id: [u8; PEER_ID_SIZE],
+        fast: bool,
+        allowed_fast: Vec<usize>,
         pieces_status: &Vec<Status>,
     ) -> InitCmd {
         self.id = Some(peer_id);
+        self.fast = fast;
+        self.am_allowed_fast = allowed_fast.clone();
+
+        let pieces: Vec<bool> = pieces_status
+            .iter()
+            .map(|status| *status == Status::Have)
+            .collect();
+
+        match (
+            fast,
+            pieces.iter().all(|have| *have),
+            pieces.contains(&true),
+        ) {
+            (true, true, _) => InitCmd::SendHaveAll { allowed_fast },
+            (true, _, false) => InitCmd::SendHaveNone,
+            _ => InitCmd::SendBitfield {
+                bitfield: Bitfield::from_vec(&pieces),
+                allowed_fast,
+            },
+        }
+    }
+
+    /// In super-seeding mode (BEP16) client pretends to have no pieces, and reveals them one by
+    /// one.
+    pub fn handle_init_super_seeding(
+        &mut self,
+        peer_id: [u8; PEER_ID_SIZE],
+        fast: bool,
+        piece_index: Option<usize>,
+    ) -> InitCmd {
+        self.id = Some(peer_id);
+        self.fast = fast;
+        self.reveal_piece(piece_index);
 
-        let bitfield = Bitfield::from_vec(
-            &pieces_status
-                .iter()
-                .map(|status| *status == Status::Have)
-                .collect(),
-        );
+        InitCmd::SendSuperSeed { piece_index }
+    }
 
-        InitCmd::SendBitfield { bitfield }
+    pub fn reveal_piece(&mut self, piece_index: Option<usize>) {
+        self.super_seed_piece = piece_index;
+        if let Some(piece_index) = piece_index {
+            self.revealed_pieces.push(piece_index);
+        }
     }
 
     pub fn handle_choke(&mut self, pieces_status: &mut Vec<Status>) {
         self.choked = true;
 
         match self.piece_index {
+            // Download of allowed fast piece is continued
+            Some(piece_index) if self.allowed_fast.contains(&piece_index) => (),
             Some(piece_index) => {
                 pieces_status[piece_index] = match pieces_status[piece_index] {
                     Status::Reserved(peers_count) => match peers_count >= 2 {
@@ -92,6 +167,14 @@ impl Peer {
         pieces_status: &mut Vec<Status>,
         metainfo: &Metainfo,
     ) -> UnchokeCmd {
+        // Allowed fast piece is still downloaded
+        if let Some(piece_index) = self.piece_index {
+            if self.choked && self.allowed_fast.contains(&piece_index) {
+                self.choked = false;
+                return UnchokeCmd::Ignore;
+            }
+        }
+
         let cmd = match chosen_index {
             Some(chosen_index) => {
                 pieces_status[chosen_index] = match pieces_status[chosen_index] {
@@ -154,6 +237,37 @@ impl Peer {
         }
     }
 
+    pub fn handle_allowed_fast(
+        &mut self,
+        piece_index: usize,
+        pieces_status: &mut [Status],
+        metainfo: &Metainfo,
+    ) -> AllowedFastCmd {
+        if !self.allowed_fast.contains(&piece_index) {
+            self.allowed_fast.push(piece_index);
+        }
+
+        // Other piece is already downloaded, or peer doesn't have this one
+        if !self.choked
+            || self.piece_index.is_some()
+            || !self.pieces[piece_index]
+            || pieces_status[piece_index] != Status::Missing
+        {
+            return AllowedFastCmd::Ignore;
+        }
+
+        pieces_status[piece_index] = Status::Reserved(1);
+        self.piece_index = Some(piece_index);
+
+        let cmd = match self.am_interested {
+            true => AllowedFastCmd::SendRequest(req_data(metainfo, piece_index)),
+            false => AllowedFastCmd::SendInterestedAndRequest(req_data(metainfo, piece_index)),
+        };
+        self.am_interested = true;
+
+        cmd
+    }
+
     pub fn handle_bitfield(
         &mut self,
         chosen_index: Option<usize>,
@@ -190,17 +304,23 @@ impl Peer {
         piece_index: usize,
         pieces_status: &Vec<Status>,
         metainfo: &Metainfo,
+        super_seeding: bool,
     ) -> RequestCmd {
-        if self.am_choked {
-            return RequestCmd::Ignore;
+        if self.am_choked && !self.am_allowed_fast.contains(&piece_index) {
+            return RequestCmd::Reject;
+        }
+
+        // In super-seeding mode only revealed pieces are served
+        if super_seeding && !self.revealed_pieces.contains(&piece_index) {
+            return RequestCmd::Reject;
         }
 
         if piece_index >= metainfo.pieces_num() {
-            return RequestCmd::Ignore;
+            return RequestCmd::Reject;
         }
 
         if pieces_status[piece_index] != Status::Have {
-            return RequestCmd::Ignore;
+            return RequestCmd::Reject;
         }
 
         RequestCmd::LoadAndSendPiece {
@@ -224,7 +344,7 @@ impl Peer {
                 };
 
                 self.piece_index = Some(chosen_index);
-                match self.choked {
+                match self.choked && !self.allowed_fast.contains(&chosen_index) {
                     true => PieceCmd::Ignore,
                     false => PieceCmd::SendRequest(req_data(&metainfo, chosen_index)),
                 }
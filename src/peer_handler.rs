@@ -7,19 +7,25 @@
 // except according to those terms.
 
 use crate::commands::{
-    BitfieldCmd, BroadCmd, HaveCmd, InitCmd, NotInterestedCmd, PeerCmd, PieceCmd, ReqData,
-    RequestCmd, UnchokeCmd,
+    AllowedFastCmd, BitfieldCmd, BroadCmd, HaveCmd, InitCmd, NotInterestedCmd, PeerCmd, PieceCmd,
+    ReqData, RequestCmd, UnchokeCmd,
 };
-use crate::connection::Connection;
-use crate::constants::{HASH_SIZE, PEER_ID_SIZE, PIECE_BLOCK_SIZE};
+use crate::connection::{Connection, PeerStream};
+use crate::constants::{HASH_SIZE, PEER_ID_SIZE, PIECE_BLOCK_SIZE, PORT};
 use crate::frame::Frame;
 use crate::messages::{
-    Bitfield, Cancel, Choke, Handshake, Have, Interested, KeepAlive, NotInterested, Piece, Request,
-    Unchoke,
+    AllowedFast, Bitfield, Cancel, Choke, Extended, Handshake, Have, HaveAll, HaveNone, Interested,
+    KeepAlive, NotInterested, Piece, Port, RejectRequest, Request, SuggestPiece, Unchoke,
+};
+use crate::mse::{Cipher, Encryption};
+use crate::net;
+use crate::{
+    mse, utils, BindAddr, Error, ExtendedHandshake, ExtensionRegistry, PeerExtensions, Proxy, Utp,
 };
-use crate::{utils, Error};
 use std::collections::VecDeque;
+use std::net::SocketAddr;
 use tokio::fs;
+use tokio::io::AsyncReadExt;
 use tokio::net::TcpStream;
 use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::time;
@@ -29,13 +35,27 @@ const KEEP_ALIVE_LIMIT: u32 = 2;
 const KEEP_ALIVE_INTERVAL_SEC: u64 = 120;
 const STATS_INTERVAL_SEC: u64 = 10;
 const MAX_STATS_QUEUE_SIZE: usize = 2;
+const EXTENSION_TICK_SEC: u64 = 5;
 
 pub struct PeerHandler {
     connection: Connection,
     own_id: [u8; PEER_ID_SIZE],
     peer_id: Option<[u8; PEER_ID_SIZE]>,
+    initiator: bool,
     info_hash: [u8; HASH_SIZE],
     pieces_num: usize,
+    dht_port: Option<u16>,
+    fast: bool,
+    extended: bool,
+    peer_upload_only: bool,
+    encryption: Encryption,
+    utp: Option<Utp>,
+    /// Connection goes over uTP (not TCP)
+    over_utp: bool,
+    listen_port: u16,
+    bind_addr: Option<BindAddr>,
+    proxy: Option<Proxy>,
+    extensions: PeerExtensions,
     piece_tx: Option<PieceTx>,
     piece_rx: Option<PieceRx>,
     peer_state: State,
@@ -67,6 +87,7 @@ struct State {
 struct Stats {
     downloaded: VecDeque<usize>,
     uploaded: VecDeque<usize>,
+    uploaded_unsynced: usize,
     unexpected_blocks: usize,
 }
 
@@ -100,6 +121,7 @@ impl Stats {
         Stats {
             downloaded: VecDeque::from(vec![0]),
             uploaded: VecDeque::from(vec![0]),
+            uploaded_unsynced: 0,
             unexpected_blocks: 0,
         }
     }
@@ -110,6 +132,7 @@ impl Stats {
 
     fn update_uploaded(&mut self, amount: usize) {
         self.uploaded[0] += amount;
+        self.uploaded_unsynced += amount;
     }
 
     fn increment_unexpected_piece(&mut self) {
@@ -146,11 +169,16 @@ impl Stats {
     fn unexpected_blocks(&mut self) -> usize {
         self.unexpected_blocks
     }
+
+    /// Return amount of uploaded bytes since last call.
+    fn take_uploaded(&mut self) -> usize {
+        std::mem::take(&mut self.uploaded_unsynced)
+    }
 }
 
 impl PeerHandler {
     pub fn new(
-        addr: String,
+        addr: SocketAddr,
         own_id: [u8; PEER_ID_SIZE],
         peer_id: Option<[u8; PEER_ID_SIZE]>,
         info_hash: [u8; HASH_SIZE],
@@ -159,11 +187,23 @@ impl PeerHandler {
         broad_ch: broadcast::Receiver<BroadCmd>,
     ) -> PeerHandler {
         PeerHandler {
+            extensions: ExtensionRegistry::new().create(addr),
             connection: Connection::new(addr),
             own_id,
             peer_id,
+            initiator: false,
             info_hash,
             pieces_num,
+            dht_port: None,
+            fast: false,
+            extended: false,
+            peer_upload_only: false,
+            encryption: Encryption::Disabled,
+            utp: None,
+            over_utp: false,
+            listen_port: PORT,
+            bind_addr: None,
+            proxy: None,
             piece_tx: None,
             piece_rx: None,
             peer_state: State {
@@ -178,26 +218,135 @@ impl PeerHandler {
         }
     }
 
+    /// Advertise DHT support in handshake, and send DHT port to peers that support it too.
+    pub fn enable_dht(&mut self, port: u16) {
+        self.dht_port = Some(port);
+    }
+
+    /// Use extension handlers (by default none is registered, but extension handshake is still
+    /// exchanged).
+    pub fn set_extensions(&mut self, extensions: PeerExtensions) {
+        self.extensions = extensions;
+    }
+
+    /// Set encryption policy (by default only plain handshake is used).
+    pub fn set_encryption(&mut self, encryption: Encryption) {
+        self.encryption = encryption;
+    }
+
+    /// Try uTP before TCP when connecting to peer.
+    pub fn enable_utp(&mut self, utp: Utp) {
+        self.utp = Some(utp);
+    }
+
+    /// Port on which session listens, reported to peer in extension handshake.
+    pub fn set_listen_port(&mut self, port: u16) {
+        self.listen_port = port;
+    }
+
+    /// Open TCP connections from given local address or interface.
+    pub fn set_bind_addr(&mut self, bind_addr: BindAddr) {
+        self.bind_addr = Some(bind_addr);
+    }
+
+    /// Open TCP connections through proxy.
+    pub fn set_proxy(&mut self, proxy: Proxy) {
+        self.proxy = Some(proxy);
+    }
+
     pub async fn run_incoming(&mut self) {
-        match TcpStream::connect(&self.connection.addr).await {
-            Ok(socket) => {
-                self.connection.with_socket(socket);
+        let connected = self.connect().await.map_err(|e| e.to_string());
+        let reason = match connected {
+            Ok(()) => {
+                self.initiator = true;
                 self.run().await;
+                return;
             }
+            Err(e) => format!("Connection fail: {}", e),
+        };
+
+        Self::kill_req(self.connection.addr, &reason, &mut self.peer_ch).await
+    }
+
+    pub async fn run_outgoing(&mut self, mut socket: Box<dyn PeerStream>, over_utp: bool) {
+        self.over_utp = over_utp;
+        match self.accept(&mut socket).await {
+            Ok((cipher, payload)) => {
+                self.connection
+                    .with_socket(socket)
+                    .with_encryption(cipher, &payload);
+                self.run().await;
+            }
+            Err(e) => Self::kill_req(self.connection.addr, &e.to_string(), &mut self.peer_ch).await,
+        }
+    }
+
+    async fn connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut socket = self.open_stream().await?;
+        if self.encryption == Encryption::Disabled {
+            self.connection.with_socket(socket);
+            return Ok(());
+        }
+
+        let crypto_provide = self.encryption.crypto_methods();
+        match mse::handshake_outgoing(&mut socket, &self.info_hash, crypto_provide).await {
+            Ok(cipher) => {
+                self.connection
+                    .with_socket(socket)
+                    .with_encryption(cipher, &[]);
+            }
+            Err(e) if self.encryption == Encryption::Required => return Err(e.into()),
+            // Peer probably doesn't support encryption, so try again with plain handshake
             Err(_) => {
-                Self::kill_req(
-                    &self.connection.addr,
-                    &"Connection fail".to_string(),
-                    &mut self.peer_ch,
-                )
-                .await
+                let socket = self.open_stream().await?;
+                self.connection.with_socket(socket);
             }
         }
+
+        Ok(())
     }
 
-    pub async fn run_outgoing(&mut self, socket: TcpStream) {
-        self.connection.with_socket(socket);
-        self.run().await;
+    /// Connect over uTP if it's enabled (so LEDBAT keeps uplink usable for other traffic), and
+    /// fall back to TCP when peer doesn't respond.
+    async fn open_stream(&mut self) -> Result<Box<dyn PeerStream>, Box<dyn std::error::Error>> {
+        if let Some(utp) = &self.utp {
+            if let Ok(stream) = utp.connect(self.connection.addr).await {
+                self.over_utp = true;
+                return Ok(Box::new(stream));
+            }
+        }
+        self.over_utp = false;
+
+        match (&self.proxy, &self.bind_addr) {
+            (Some(proxy), bind_addr) => Ok(Box::new(
+                net::connect_proxy(self.connection.addr, proxy, bind_addr.as_ref()).await?,
+            )),
+            (None, Some(bind_addr)) => Ok(Box::new(
+                net::connect_tcp(self.connection.addr, bind_addr).await?,
+            )),
+            (None, None) => Ok(Box::new(TcpStream::connect(self.connection.addr).await?)),
+        }
+    }
+
+    /// Detect if peer started with plain or encrypted handshake, and check it against policy.
+    async fn accept(
+        &mut self,
+        socket: &mut Box<dyn PeerStream>,
+    ) -> Result<(Option<Cipher>, Vec<u8>), Error> {
+        let mut prefix = [0; 20];
+        if socket.read_exact(&mut prefix).await.is_err() {
+            return Err(Error::ConnectionClosed);
+        }
+
+        match (mse::is_plain_handshake(&prefix), self.encryption) {
+            (true, Encryption::Required) => Err(Error::MseFail("plain handshake not allowed")),
+            (true, _) => Ok((None, prefix.to_vec())),
+            (false, Encryption::Disabled) => Err(Error::MseFail("encryption disabled")),
+            (false, _) => {
+                let crypto_accept = self.encryption.crypto_methods();
+                mse::handshake_incoming(socket, &prefix, &self.info_hash, crypto_accept).await
+            }
+        }
     }
 
     async fn run(&mut self) {
@@ -206,31 +355,33 @@ impl PeerHandler {
             Err(e) => e.to_string(),
         };
 
-        Self::kill_req(&self.connection.addr, &reason, &mut self.peer_ch).await;
+        Self::kill_req(self.connection.addr, &reason, &mut self.peer_ch).await;
     }
 
-    async fn kill_req(addr: &String, reason: &String, peer_ch: &mut mpsc::Sender<PeerCmd>) {
+    async fn kill_req(addr: SocketAddr, reason: &str, peer_ch: &mut mpsc::Sender<PeerCmd>) {
         peer_ch
             .send(PeerCmd::KillReq {
-                addr: addr.clone(),
-                reason: reason.clone(),
+                addr,
+                reason: reason.to_string(),
             })
             .await
             .expect("Can't inform manager about KillReq");
     }
 
     async fn event_loop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(peer_id) = self.peer_id {
-            self.init_handshake(peer_id).await?;
+        if self.initiator {
+            self.send_handshake().await?;
         }
 
         let mut keep_alive_timer = self.start_keep_alive_timer();
         let mut sync_stats_timer = self.start_sync_stats_timer();
+        let mut extension_timer = time::interval(Duration::from_secs(EXTENSION_TICK_SEC));
 
         loop {
             tokio::select! {
                 _ = keep_alive_timer.tick() => self.timeout_keep_alive().await?,
                 _ = sync_stats_timer.tick() => self.timeout_sync_stats().await?,
+                _ = extension_timer.tick() => self.timeout_extensions().await?,
                 Ok(cmd) = self.broad_ch.recv() => {
                     if self.handle_manager_cmd(cmd).await? == false {
                         break;
@@ -274,6 +425,15 @@ impl PeerHandler {
         Ok(())
     }
 
+    async fn timeout_extensions(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        for (ext_id, payload) in self.extensions.on_tick() {
+            self.connection
+                .send_msg(&Extended::new(ext_id, payload))
+                .await?;
+        }
+        Ok(())
+    }
+
     async fn handle_manager_cmd(
         &mut self,
         cmd: BroadCmd,
@@ -300,11 +460,47 @@ impl PeerHandler {
             }
             BroadCmd::SendOwnState { am_choked_map } => {
                 match am_choked_map.get(&self.connection.addr) {
-                    Some(true) => self.connection.send_msg(&Choke::new()).await?,
+                    Some(true) => {
+                        // Next requests have to be checked again (only allowed fast are served)
+                        self.piece_tx = None;
+                        self.connection.send_msg(&Choke::new()).await?
+                    }
                     Some(false) => self.connection.send_msg(&Unchoke::new()).await?,
                     None => (),
                 }
             }
+            BroadCmd::SendUploadOnly => {
+                self.extensions.set_upload_only(true);
+                if self.extended {
+                    self.send_extended_handshake().await?;
+                }
+                if self.peer_upload_only {
+                    return Ok(false);
+                }
+            }
+            BroadCmd::Disconnect { addr } => {
+                if addr == self.connection.addr {
+                    return Ok(false);
+                }
+            }
+            BroadCmd::RevealPiece { addr, piece_index } => {
+                if addr == self.connection.addr {
+                    self.connection.send_msg(&Have::new(piece_index)).await?
+                }
+            }
+            BroadCmd::SendExtended {
+                addr,
+                name,
+                payload,
+            } => {
+                if addr == self.connection.addr {
+                    if let Some(ext_id) = self.extensions.remote_id(&name) {
+                        self.connection
+                            .send_msg(&Extended::new(ext_id, payload))
+                            .await?
+                    }
+                }
+            }
         }
 
         Ok(true)
@@ -333,6 +529,13 @@ impl PeerHandler {
                     Frame::Request(request) => self.handle_request(request).await?,
                     Frame::Piece(piece) => self.handle_piece(&piece).await?,
                     Frame::Cancel(_) => true,
+                    Frame::Port(port) => self.handle_port(&port).await?,
+                    Frame::Extended(extended) => self.handle_extended(&extended).await?,
+                    Frame::SuggestPiece(suggest) => self.handle_suggest_piece(&suggest)?,
+                    Frame::HaveAll(_) => self.handle_have_all_or_none(true).await?,
+                    Frame::HaveNone(_) => self.handle_have_all_or_none(false).await?,
+                    Frame::RejectRequest(reject) => self.handle_reject_request(&reject).await?,
+                    Frame::AllowedFast(allowed) => self.handle_allowed_fast(&allowed).await?,
                 };
 
                 if handled == false {
@@ -350,14 +553,26 @@ impl PeerHandler {
         handshake: &Handshake,
     ) -> Result<bool, Box<dyn std::error::Error>> {
         handshake.validate(&self.info_hash, &self.peer_id)?;
-
-        let peer_init_handshake = self.peer_id.is_none();
         self.peer_id = Some(*handshake.peer_id());
+        self.fast = handshake.supports_fast();
 
-        if peer_init_handshake {
-            self.init_handshake(*handshake.peer_id()).await?;
+        if !self.initiator {
+            self.send_handshake().await?;
         }
 
+        self.trigger_cmd_init(*handshake.peer_id()).await?;
+
+        // Port message can't precede bitfield, which is send on init
+        if let Some(dht_port) = self.dht_port {
+            if handshake.supports_dht() {
+                self.connection.send_msg(&Port::new(dht_port)).await?;
+            }
+        }
+
+        if handshake.supports_extension_protocol() {
+            self.extended = true;
+            self.send_extended_handshake().await?;
+        }
         Ok(true)
     }
 
@@ -392,6 +607,107 @@ impl PeerHandler {
         self.trigger_cmd_recv_not_interested().await
     }
 
+    async fn handle_port(&mut self, port: &Port) -> Result<bool, Box<dyn std::error::Error>> {
+        self.trigger_cmd_recv_port(port).await?;
+        Ok(true)
+    }
+
+    async fn handle_extended(
+        &mut self,
+        extended: &Extended,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let msgs = match extended.ext_id() {
+            Extended::HANDSHAKE_ID => {
+                let handshake = ExtendedHandshake::from_bencode(extended.payload())?;
+                self.trigger_cmd_recv_extended_handshake(&handshake).await?;
+                if let Some(upload_only) = handshake.upload_only {
+                    self.peer_upload_only = upload_only;
+                }
+                self.extensions.on_handshake(&handshake)
+            }
+            ext_id => self.extensions.on_message(ext_id, extended.payload())?,
+        };
+
+        for (ext_id, payload) in msgs {
+            self.connection
+                .send_msg(&Extended::new(ext_id, payload))
+                .await?;
+        }
+
+        // Neither side wants anything from the other (BEP21)
+        Ok(!(self.peer_upload_only && self.extensions.upload_only()))
+    }
+
+    fn handle_suggest_piece(
+        &mut self,
+        suggest: &SuggestPiece,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if !self.fast {
+            return Err(Error::FastNotNegotiated("SuggestPiece").into());
+        }
+
+        // Suggestion is only advisory, rarest pieces are preferred
+        suggest.validate(self.pieces_num)?;
+        Ok(true)
+    }
+
+    async fn handle_have_all_or_none(
+        &mut self,
+        have_all: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if !self.fast {
+            let name = if have_all { "HaveAll" } else { "HaveNone" };
+            return Err(Error::FastNotNegotiated(name).into());
+        }
+
+        let bitfield = Bitfield::from_vec(&vec![have_all; self.pieces_num]);
+        self.trigger_cmd_recv_bitfield(bitfield).await?;
+        Ok(true)
+    }
+
+    async fn handle_reject_request(
+        &mut self,
+        reject: &RejectRequest,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if !self.fast {
+            return Err(Error::FastNotNegotiated("RejectRequest").into());
+        }
+
+        let piece_rx = match self.piece_rx.as_mut() {
+            Some(piece_rx) if piece_rx.piece_index == reject.piece_index() => piece_rx,
+            _ => return Ok(true),
+        };
+
+        let block = (reject.block_begin(), reject.block_length());
+        if !piece_rx.requested.contains(&block) {
+            return Ok(true);
+        }
+
+        // Block will be requested again later
+        piece_rx.requested.retain(|requested| *requested != block);
+        piece_rx.left.push_front(block);
+
+        // When not choked, peer doesn't want to share this piece, so choose another one
+        if piece_rx.requested.is_empty() && !self.peer_state.choked {
+            self.piece_rx = None;
+            return self.trigger_cmd_piece_finish(false).await;
+        }
+        Ok(true)
+    }
+
+    async fn handle_allowed_fast(
+        &mut self,
+        allowed: &AllowedFast,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if !self.fast {
+            return Err(Error::FastNotNegotiated("AllowedFast").into());
+        }
+
+        allowed.validate(self.pieces_num)?;
+        self.trigger_cmd_recv_allowed_fast(allowed).await?;
+        Ok(true)
+    }
+
     async fn handle_have(&mut self, have: &Have) -> Result<bool, Box<dyn std::error::Error>> {
         have.validate(self.pieces_num)?;
         self.trigger_cmd_recv_have(have).await?;
@@ -425,6 +741,16 @@ impl PeerHandler {
                 request.validate(piece_tx.piece_index, self.pieces_num, piece_tx.buff.len())?;
                 self.send_piece(&request).await?
             }
+            // Without Fast Extension request can be only dropped
+            None if self.fast => {
+                self.connection
+                    .send_msg(&RejectRequest::new(
+                        request.piece_index(),
+                        request.block_begin(),
+                        request.block_length(),
+                    ))
+                    .await?
+            }
             None => (),
         }
 
@@ -481,25 +807,81 @@ impl PeerHandler {
         }
     }
 
-    async fn init_handshake(
-        &mut self,
-        peer_id: [u8; PEER_ID_SIZE],
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn send_handshake(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut handshake = Handshake::new(&self.info_hash, &self.own_id);
+        if self.dht_port.is_some() {
+            handshake.set_dht();
+        }
+        handshake.set_fast();
+        handshake.set_extension_protocol();
+        self.connection.send_msg(&handshake).await?;
+
+        Ok(())
+    }
+
+    async fn send_extended_handshake(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut handshake = self.extensions.handshake();
+        handshake.port = Some(self.listen_port);
+        handshake.your_ip = Some(self.connection.addr.ip());
+
         self.connection
-            .send_msg(&Handshake::new(&self.info_hash, &self.own_id))
+            .send_msg(&Extended::new(Extended::HANDSHAKE_ID, handshake.encode()))
             .await?;
 
+        Ok(())
+    }
+
+    async fn trigger_cmd_init(
+        &mut self,
+        peer_id: [u8; PEER_ID_SIZE],
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.peer_ch
             .send(PeerCmd::Init {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
                 peer_id,
+                fast: self.fast,
+                encrypted: self.connection.is_encrypted(),
+                utp: self.over_utp,
                 resp_ch: resp_tx,
             })
             .await?;
 
-        match resp_rx.await? {
-            InitCmd::SendBitfield { bitfield } => self.connection.send_msg(&bitfield).await?,
+        let allowed_fast = match resp_rx.await? {
+            InitCmd::SendBitfield {
+                bitfield,
+                allowed_fast,
+            } => {
+                self.connection.send_msg(&bitfield).await?;
+                allowed_fast
+            }
+            InitCmd::SendHaveAll { allowed_fast } => {
+                self.connection.send_msg(&HaveAll::new()).await?;
+                allowed_fast
+            }
+            InitCmd::SendHaveNone => {
+                self.connection.send_msg(&HaveNone::new()).await?;
+                vec![]
+            }
+            InitCmd::SendSuperSeed { piece_index } => {
+                match self.fast {
+                    true => self.connection.send_msg(&HaveNone::new()).await?,
+                    false => {
+                        let bitfield = Bitfield::from_vec(&vec![false; self.pieces_num]);
+                        self.connection.send_msg(&bitfield).await?
+                    }
+                }
+                if let Some(piece_index) = piece_index {
+                    self.connection.send_msg(&Have::new(piece_index)).await?;
+                }
+                vec![]
+            }
+        };
+
+        for piece_index in allowed_fast {
+            self.connection
+                .send_msg(&AllowedFast::new(piece_index))
+                .await?;
         }
 
         Ok(())
@@ -508,7 +890,7 @@ impl PeerHandler {
     async fn trigger_cmd_recv_choke(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.peer_ch
             .send(PeerCmd::RecvChoke {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
             })
             .await?;
 
@@ -519,7 +901,7 @@ impl PeerHandler {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.peer_ch
             .send(PeerCmd::RecvUnchoke {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
                 resp_ch: resp_tx,
             })
             .await?;
@@ -541,7 +923,69 @@ impl PeerHandler {
     async fn trigger_cmd_recv_interested(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.peer_ch
             .send(PeerCmd::RecvInterested {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn trigger_cmd_recv_extended_handshake(
+        &mut self,
+        handshake: &ExtendedHandshake,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.peer_ch
+            .send(PeerCmd::RecvExtendedHandshake {
+                addr: self.connection.addr,
+                client: handshake.client.clone(),
+                your_ip: handshake.your_ip,
+                extensions: handshake
+                    .extensions
+                    .iter()
+                    .filter(|(_, id)| **id != 0)
+                    .map(|(name, _)| name.clone())
+                    .collect(),
+                upload_only: handshake.upload_only,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn trigger_cmd_recv_allowed_fast(
+        &mut self,
+        allowed: &AllowedFast,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.peer_ch
+            .send(PeerCmd::RecvAllowedFast {
+                addr: self.connection.addr,
+                piece_index: allowed.piece_index(),
+                resp_ch: resp_tx,
+            })
+            .await?;
+
+        match resp_rx.await? {
+            AllowedFastCmd::SendInterestedAndRequest(req_data) => {
+                self.new_piece_request(true, &req_data).await?
+            }
+            AllowedFastCmd::SendRequest(req_data) => {
+                self.new_piece_request(false, &req_data).await?
+            }
+            AllowedFastCmd::Ignore => (),
+        }
+
+        Ok(())
+    }
+
+    async fn trigger_cmd_recv_port(
+        &mut self,
+        port: &Port,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.peer_ch
+            .send(PeerCmd::RecvPort {
+                addr: self.connection.addr,
+                port: port.port(),
             })
             .await?;
 
@@ -554,7 +998,7 @@ impl PeerHandler {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.peer_ch
             .send(PeerCmd::RecvNotInterested {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
                 resp_ch: resp_tx,
             })
             .await?;
@@ -572,7 +1016,7 @@ impl PeerHandler {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.peer_ch
             .send(PeerCmd::RecvHave {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
                 piece_index: have.piece_index(),
                 resp_ch: resp_tx,
             })
@@ -597,7 +1041,7 @@ impl PeerHandler {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.peer_ch
             .send(PeerCmd::RecvBitfield {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
                 bitfield,
                 resp_ch: resp_tx,
             })
@@ -630,7 +1074,7 @@ impl PeerHandler {
         let (resp_tx, resp_rx) = oneshot::channel();
         self.peer_ch
             .send(PeerCmd::RecvRequest {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
                 piece_index: request.piece_index(),
                 resp_ch: resp_tx,
             })
@@ -641,7 +1085,7 @@ impl PeerHandler {
                 piece_index,
                 piece_hash,
             } => self.load_piece_from_file(piece_index, &piece_hash).await?,
-            RequestCmd::Ignore => self.piece_tx = None,
+            RequestCmd::Reject => self.piece_tx = None,
         };
 
         Ok(())
@@ -654,11 +1098,11 @@ impl PeerHandler {
         let (resp_tx, resp_rx) = oneshot::channel();
         let cmd = match done {
             true => PeerCmd::PieceDone {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
                 resp_ch: resp_tx,
             },
             false => PeerCmd::PieceCancel {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
                 resp_ch: resp_tx,
             },
         };
@@ -678,9 +1122,10 @@ impl PeerHandler {
     async fn trigger_cmd_sync_stats(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.peer_ch
             .send(PeerCmd::SyncStats {
-                addr: self.connection.addr.clone(),
+                addr: self.connection.addr,
                 downloaded_rate: self.stats.downloaded_rate(),
                 uploaded_rate: self.stats.uploaded_rate(),
+                uploaded: self.stats.take_uploaded(),
                 unexpected_blocks: self.stats.unexpected_blocks(),
             })
             .await?;
@@ -12,25 +12,194 @@ use crate::constants::PEER_ID_SIZE;
 use num_traits::AsPrimitive;
 use rand::distributions::Alphanumeric;
 use rand::Rng;
+use std::fmt;
 
-/// Generate random peer ID.
+/// Azureus-style prefix of rdest peer ID (client code "RD", version 0.1.0.0).
+pub const PREFIX: &[u8; 8] = b"-RD0100-";
+
+/// Two letter client codes used in Azureus-style peer IDs.
+const AZUREUS_CLIENTS: &[(&[u8; 2], &str)] = &[
+    (b"AZ", "Vuze"),
+    (b"BC", "BitComet"),
+    (b"BI", "BiglyBT"),
+    (b"BT", "BitTorrent"),
+    (b"DE", "Deluge"),
+    (b"FD", "Free Download Manager"),
+    (b"KT", "KTorrent"),
+    (b"LT", "libtorrent"),
+    (b"lt", "rTorrent"),
+    (b"qB", "qBittorrent"),
+    (b"RD", "rdest"),
+    (b"SD", "Thunder"),
+    (b"TR", "Transmission"),
+    (b"UM", "µTorrent Mac"),
+    (b"UT", "µTorrent"),
+    (b"UW", "µTorrent Web"),
+    (b"WW", "WebTorrent"),
+    (b"XL", "Xunlei"),
+];
+
+/// Single character client codes used in Shadow-style peer IDs.
+const SHADOW_CLIENTS: &[(u8, &str)] = &[
+    (b'A', "ABC"),
+    (b'O', "Osprey Permaculture"),
+    (b'Q', "BTQueue"),
+    (b'R', "Tribler"),
+    (b'S', "Shadow"),
+    (b'T', "BitTornado"),
+    (b'U', "UPnP NAT Bit Torrent"),
+];
+
+/// Client (name and version) recognized from peer ID.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Client {
+    /// Client name, or two letter code, when Azureus-style code is unknown
+    pub name: String,
+    /// Version, e.g. "0.1.0"
+    pub version: String,
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.version)
+    }
+}
+
+/// Generate Azureus-style peer ID: [`PREFIX`] followed by random alphanumeric characters.
 ///
 /// # Example
 /// ```
 /// use rdest::peer_id;
 ///
 /// let id = peer_id::generate();
-/// println!("{:?}", id);
+/// assert!(id.starts_with(b"-RD0100-"));
 /// ```
 pub fn generate() -> [u8; PEER_ID_SIZE] {
     let mut peer_id: [u8; PEER_ID_SIZE] = [0; PEER_ID_SIZE];
+    peer_id[..PREFIX.len()].copy_from_slice(PREFIX);
     for (idx, ch) in rand::thread_rng()
         .sample_iter(&Alphanumeric)
-        .take(PEER_ID_SIZE)
+        .take(PEER_ID_SIZE - PREFIX.len())
         .enumerate()
     {
-        peer_id[idx] = ch.as_();
+        peer_id[PREFIX.len() + idx] = ch.as_();
     }
 
     return peer_id;
 }
+
+/// Recognize client from peer ID. Supported encodings are Azureus-style (`-TR3000-...`),
+/// Mainline (`M4-3-6--...`) and Shadow-style (`T03I-----...`).
+///
+/// # Example
+/// ```
+/// use rdest::peer_id;
+///
+/// let client = peer_id::client(b"-TR4050-abcdefghijkl").unwrap();
+/// assert_eq!(client.to_string(), "Transmission 4.0.5");
+/// ```
+pub fn client(peer_id: &[u8; PEER_ID_SIZE]) -> Option<Client> {
+    azureus_client(peer_id)
+        .or_else(|| mainline_client(peer_id))
+        .or_else(|| shadow_client(peer_id))
+}
+
+/// Format as '-' + client code (2 characters) + version (4 characters) + '-'.
+fn azureus_client(peer_id: &[u8; PEER_ID_SIZE]) -> Option<Client> {
+    if peer_id[0] != b'-' || peer_id[7] != b'-' {
+        return None;
+    }
+
+    let code = &peer_id[1..3];
+    if !code.iter().all(|b| b.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let mut version = peer_id[3..7]
+        .iter()
+        .map(|b| shadow_digit(*b).filter(|num| *num < 36))
+        .collect::<Option<Vec<u8>>>()?;
+    // Fourth number is build (or beta) number, usually zero
+    if version[3] == 0 {
+        version.pop();
+    }
+
+    let name = match AZUREUS_CLIENTS.iter().find(|(c, _)| c.as_ref() == code) {
+        Some((_, name)) => name.to_string(),
+        None => String::from_utf8_lossy(code).to_string(),
+    };
+
+    Some(Client {
+        name,
+        version: join_version(&version),
+    })
+}
+
+/// Format as 'M' + version numbers separated with '-', padded with '-' to 8 characters.
+fn mainline_client(peer_id: &[u8; PEER_ID_SIZE]) -> Option<Client> {
+    if peer_id[0] != b'M' || !peer_id[1].is_ascii_digit() {
+        return None;
+    }
+
+    let prefix = std::str::from_utf8(&peer_id[1..8]).ok()?;
+    let version = prefix
+        .split('-')
+        .filter(|num| !num.is_empty())
+        .collect::<Vec<&str>>();
+    if !prefix.ends_with('-')
+        || version.is_empty()
+        || version
+            .iter()
+            .any(|num| !num.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    Some(Client {
+        name: "Mainline".to_string(),
+        version: version.join("."),
+    })
+}
+
+/// Format as client code (1 character) + version (up to 5 characters, padded with '-') + "---".
+fn shadow_client(peer_id: &[u8; PEER_ID_SIZE]) -> Option<Client> {
+    let name = SHADOW_CLIENTS
+        .iter()
+        .find(|(code, _)| *code == peer_id[0])
+        .map(|(_, name)| name)?;
+    if &peer_id[6..9] != b"---" {
+        return None;
+    }
+
+    let version = peer_id[1..6]
+        .iter()
+        .take_while(|b| **b != b'-')
+        .map(|b| shadow_digit(*b))
+        .collect::<Option<Vec<u8>>>()?;
+    if version.is_empty() {
+        return None;
+    }
+
+    Some(Client {
+        name: name.to_string(),
+        version: join_version(&version),
+    })
+}
+
+/// Decode version character: '0'-'9', 'A'-'Z' (10-35), 'a'-'z' (36-61) and '.' (62).
+fn shadow_digit(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'A'..=b'Z' => Some(b - b'A' + 10),
+        b'a'..=b'z' => Some(b - b'a' + 36),
+        b'.' => Some(62),
+        _ => None,
+    }
+}
+
+fn join_version(version: &[u8]) -> String {
+    version
+        .iter()
+        .map(|num| num.to_string())
+        .collect::<Vec<String>>()
+        .join(".")
+}
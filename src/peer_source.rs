@@ -0,0 +1,172 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::commands::AnnounceCmd;
+use crate::constants::PEER_ID_SIZE;
+use crate::{Proxy, TrackerResp};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tokio::sync::mpsc;
+
+/// Boxed future returned by [`PeerSource`] methods.
+pub type PeerSourceFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Event reported by peer source to [`Session`](crate::Session).
+#[derive(Debug, Clone)]
+pub enum PeerSourceEvent {
+    /// New candidates (addresses and optional peer ID's)
+    Peers(Vec<(SocketAddr, Option<[u8; PEER_ID_SIZE]>)>),
+    /// Full tracker response, with peers and swarm details
+    TrackerResp(TrackerResp),
+    /// Source failed, but may recover later
+    Fail(String),
+}
+
+/// Source of peer candidates consumed by [`Session`](crate::Session), e.g. tracker, static list,
+/// or any other service discovery.
+///
+/// Session drives every source in separate task. `next` is interrupted (dropped) when one of the
+/// hooks has to be called, so it should be cancel safe.
+///
+/// # Example
+/// ```
+/// use rdest::{PeerSource, PeerSourceEvent, PeerSourceFuture};
+///
+/// struct Registry;
+///
+/// impl PeerSource for Registry {
+///     fn name(&self) -> String {
+///         "registry".to_string()
+///     }
+///
+///     fn next(&mut self) -> PeerSourceFuture<'_, Option<PeerSourceEvent>> {
+///         Box::pin(async {
+///             let addr = "10.0.0.1:6881".parse().unwrap();
+///             Some(PeerSourceEvent::Peers(vec![(addr, None)]))
+///         })
+///     }
+/// }
+/// ```
+pub trait PeerSource: Send {
+    /// Name used in logs.
+    fn name(&self) -> String;
+
+    /// Wait for next event. `None` means that source is exhausted, and `next` will not be called
+    /// again until [`announce`](PeerSource::announce).
+    fn next(&mut self) -> PeerSourceFuture<'_, Option<PeerSourceEvent>>;
+
+    /// Session listens for peers on `port`. Called once, before source is started.
+    fn set_port(&mut self, _port: u16) {}
+
+    /// Session connects through `proxy`. Called once, before source is started. Return `true`
+    /// if all source traffic goes through proxy (or source doesn't use network at all). When
+    /// non-proxied traffic is disabled, other sources are not started.
+    fn set_proxy(&mut self, _proxy: &Proxy) -> bool {
+        false
+    }
+
+    /// Session needs more peers.
+    fn announce(&mut self) {}
+
+    /// Download was completed.
+    fn completed(&mut self) {}
+
+    /// Client doesn't download anymore, but has only part of data (partial seed).
+    fn paused(&mut self) {}
+
+    /// Session is reachable from internet under `addr` (e.g. port was mapped on gateway).
+    fn external_addr(&mut self, _addr: SocketAddr) {}
+
+    /// Session is shutting down. Source may inform remote service about it, but Session waits
+    /// only limited time.
+    fn stop(&mut self) -> PeerSourceFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// Fixed list of peers, returned again every time Session needs more peers.
+#[derive(Debug, Clone)]
+pub struct StaticPeers {
+    peers: Vec<SocketAddr>,
+    exhausted: bool,
+}
+
+impl StaticPeers {
+    /// Create source from list of peer addresses.
+    pub fn new(peers: Vec<SocketAddr>) -> StaticPeers {
+        StaticPeers {
+            peers,
+            exhausted: false,
+        }
+    }
+}
+
+impl PeerSource for StaticPeers {
+    fn name(&self) -> String {
+        "static peers".to_string()
+    }
+
+    fn next(&mut self) -> PeerSourceFuture<'_, Option<PeerSourceEvent>> {
+        Box::pin(async move {
+            if self.exhausted {
+                return None;
+            }
+
+            self.exhausted = true;
+            Some(PeerSourceEvent::Peers(
+                self.peers.iter().map(|addr| (*addr, None)).collect(),
+            ))
+        })
+    }
+
+    fn set_proxy(&mut self, _proxy: &Proxy) -> bool {
+        true
+    }
+
+    fn announce(&mut self) {
+        self.exhausted = false;
+    }
+}
+
+/// Drive peer source until `AnnounceCmd::Stop` is received, or channel is closed.
+pub(crate) async fn run(
+    mut source: Box<dyn PeerSource>,
+    event_ch: mpsc::Sender<PeerSourceEvent>,
+    mut announce_ch: mpsc::Receiver<AnnounceCmd>,
+) {
+    let name = source.name();
+    let mut exhausted = false;
+
+    loop {
+        tokio::select! {
+            event = source.next(), if !exhausted => match event {
+                Some(PeerSourceEvent::Fail(e)) => {
+                    let _ = event_ch.send(PeerSourceEvent::Fail(format!("{}: {}", name, e))).await;
+                }
+                Some(event) => {
+                    let _ = event_ch.send(event).await;
+                }
+                None => exhausted = true,
+            },
+            cmd = announce_ch.recv() => match cmd {
+                Some(AnnounceCmd::Announce) => {
+                    source.announce();
+                    exhausted = false;
+                }
+                Some(AnnounceCmd::Completed) => source.completed(),
+                Some(AnnounceCmd::Paused) => source.paused(),
+                Some(AnnounceCmd::ExternalAddr(addr)) => source.external_addr(addr),
+                Some(AnnounceCmd::Stop) | None => {
+                    source.stop().await;
+                    break;
+                }
+            }
+        }
+    }
+}
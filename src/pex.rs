@@ -0,0 +1,246 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::bcodec::bencoder::BEncoder;
+use crate::commands::PeerCmd;
+use crate::tracker_resp::{COMPACT_PEER6_SIZE, COMPACT_PEER_SIZE};
+use crate::{BDecoder, BValue, Error, Extension, TrackerResp};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{Duration, Instant};
+
+/// Minimal interval between messages send to the same peer.
+const SEND_INTERVAL_SEC: u64 = 60;
+/// Messages received more often are ignored (with some tolerance for timers drift).
+const RECV_INTERVAL_SEC: u64 = 45;
+/// Maximal number of added (and dropped) peers in single message.
+const MAX_PEERS: usize = 50;
+
+/// Peer Exchange message (ut_pex extension, see [BEP11](https://www.bittorrent.org/beps/bep_0011.html)).
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct PexMsg {
+    /// Peers connected since last message, with flags
+    pub added: Vec<(SocketAddr, u8)>,
+    /// Peers disconnected since last message
+    pub dropped: Vec<SocketAddr>,
+}
+
+impl PexMsg {
+    /// Peer prefers encryption.
+    pub const ENCRYPTION: u8 = 0x01;
+    /// Peer is seed or partial seed.
+    pub const SEED: u8 = 0x02;
+    /// Peer supports uTP.
+    pub const UTP: u8 = 0x04;
+    /// Peer supports holepunch extension.
+    pub const HOLEPUNCH: u8 = 0x08;
+    /// Peer is reachable (connection was established by sender).
+    pub const OUTGOING: u8 = 0x10;
+
+    /// Decode message payload. Flags missing for some peers are set to 0.
+    ///
+    /// # Example
+    /// ```
+    /// use rdest::PexMsg;
+    ///
+    /// let msg = PexMsg::from_bencode(b"d5:added6:\x7f\x00\x00\x01\x1a\xe17:added.f1:\x02e").unwrap();
+    /// assert_eq!(msg.added, vec![("127.0.0.1:6881".parse().unwrap(), PexMsg::SEED)]);
+    /// ```
+    pub fn from_bencode(data: &[u8]) -> Result<PexMsg, Error> {
+        let dict = match BDecoder::from_array(data)?.into_iter().next() {
+            Some(BValue::Dict(dict)) => dict,
+            _ => return Err(Error::ExtensionIncorrectOrMissing("dictionary")),
+        };
+
+        let mut added = Self::find_peers(&dict, "added", COMPACT_PEER_SIZE)?;
+        added.extend(Self::find_peers(&dict, "added6", COMPACT_PEER6_SIZE)?);
+
+        let mut dropped: Vec<_> = Self::find_peers(&dict, "dropped", COMPACT_PEER_SIZE)?;
+        dropped.extend(Self::find_peers(&dict, "dropped6", COMPACT_PEER6_SIZE)?);
+
+        Ok(PexMsg {
+            added,
+            dropped: dropped.into_iter().map(|(addr, _)| addr).collect(),
+        })
+    }
+
+    fn find_peers(
+        dict: &HashMap<Vec<u8>, BValue>,
+        key: &'static str,
+        entry_size: usize,
+    ) -> Result<Vec<(SocketAddr, u8)>, Error> {
+        let peers = match dict.get(key.as_bytes()) {
+            Some(BValue::ByteStr(peers)) if peers.len() % entry_size == 0 => {
+                TrackerResp::compact_addrs(peers, entry_size)
+            }
+            Some(_) => return Err(Error::ExtensionIncorrectOrMissing(key)),
+            None => vec![],
+        };
+
+        let flags = match dict.get(format!("{}.f", key).as_bytes()) {
+            Some(BValue::ByteStr(flags)) => flags.clone(),
+            _ => vec![],
+        };
+
+        Ok(peers
+            .into_iter()
+            .enumerate()
+            .map(|(idx, addr)| (addr, flags.get(idx).copied().unwrap_or(0)))
+            .collect())
+    }
+
+    /// Encode message payload.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut dict = HashMap::new();
+
+        for (ipv6, key) in [(false, "added"), (true, "added6")] {
+            let peers: Vec<_> = self
+                .added
+                .iter()
+                .filter(|(addr, _)| addr.is_ipv6() == ipv6)
+                .collect();
+            let flags = peers.iter().map(|(_, flags)| *flags).collect();
+            let addrs: Vec<_> = peers.iter().map(|(addr, _)| *addr).collect();
+
+            dict.insert(
+                key.as_bytes().to_vec(),
+                BValue::ByteStr(Self::compact(&addrs)),
+            );
+            dict.insert(format!("{}.f", key).into_bytes(), BValue::ByteStr(flags));
+        }
+
+        for (ipv6, key) in [(false, "dropped"), (true, "dropped6")] {
+            let addrs: Vec<_> = self
+                .dropped
+                .iter()
+                .filter(|addr| addr.is_ipv6() == ipv6)
+                .copied()
+                .collect();
+            dict.insert(
+                key.as_bytes().to_vec(),
+                BValue::ByteStr(Self::compact(&addrs)),
+            );
+        }
+
+        BEncoder::new().add_dict(&dict).encode().clone()
+    }
+
+    fn compact(addrs: &[SocketAddr]) -> Vec<u8> {
+        let mut data = vec![];
+        for addr in addrs.iter() {
+            match addr {
+                SocketAddr::V4(addr) => data.extend_from_slice(&addr.ip().octets()),
+                SocketAddr::V6(addr) => data.extend_from_slice(&addr.ip().octets()),
+            }
+            data.extend_from_slice(&addr.port().to_be_bytes());
+        }
+
+        data
+    }
+}
+
+/// ut_pex handler for single peer. Tells peer about changes in [`Session`](crate::Session)
+/// swarm, and passes received peers to session.
+pub(crate) struct UtPex {
+    addr: SocketAddr,
+    swarm_ch: watch::Receiver<Vec<(SocketAddr, u8)>>,
+    peer_ch: mpsc::Sender<PeerCmd>,
+    /// Peers (and their flags) already reported to peer
+    sent: HashMap<SocketAddr, u8>,
+    last_send: Option<Instant>,
+    last_recv: Option<Instant>,
+}
+
+impl UtPex {
+    pub(crate) fn new(
+        addr: SocketAddr,
+        swarm_ch: watch::Receiver<Vec<(SocketAddr, u8)>>,
+        peer_ch: mpsc::Sender<PeerCmd>,
+    ) -> UtPex {
+        UtPex {
+            addr,
+            swarm_ch,
+            peer_ch,
+            sent: HashMap::new(),
+            last_send: None,
+            last_recv: None,
+        }
+    }
+}
+
+impl Extension for UtPex {
+    fn on_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let now = Instant::now();
+        if let Some(last_recv) = self.last_recv {
+            if now < last_recv + Duration::from_secs(RECV_INTERVAL_SEC) {
+                return Ok(vec![]);
+            }
+        }
+        self.last_recv = Some(now);
+
+        let mut msg = PexMsg::from_bencode(payload)?;
+        msg.added.truncate(MAX_PEERS);
+        msg.dropped.truncate(MAX_PEERS);
+
+        // When session is busy, peers are dropped. They will be probably reported again.
+        let _ = self.peer_ch.try_send(PeerCmd::RecvPex {
+            addr: self.addr,
+            added: msg.added,
+            dropped: msg.dropped,
+        });
+
+        Ok(vec![])
+    }
+
+    fn on_tick(&mut self) -> Vec<Vec<u8>> {
+        let now = Instant::now();
+        if let Some(last_send) = self.last_send {
+            if now < last_send + Duration::from_secs(SEND_INTERVAL_SEC) {
+                return vec![];
+            }
+        }
+
+        let swarm: HashMap<SocketAddr, u8> = self
+            .swarm_ch
+            .borrow()
+            .iter()
+            .filter(|(addr, _)| *addr != self.addr)
+            .copied()
+            .collect();
+
+        let added: Vec<_> = swarm
+            .iter()
+            .filter(|(addr, flags)| self.sent.get(addr) != Some(flags))
+            .map(|(addr, flags)| (*addr, *flags))
+            .take(MAX_PEERS)
+            .collect();
+        let dropped: Vec<_> = self
+            .sent
+            .keys()
+            .filter(|addr| !swarm.contains_key(addr))
+            .copied()
+            .take(MAX_PEERS)
+            .collect();
+
+        if added.is_empty() && dropped.is_empty() {
+            return vec![];
+        }
+
+        // Peers that didn't fit will be send in next message
+        for (addr, flags) in added.iter() {
+            self.sent.insert(*addr, *flags);
+        }
+        for addr in dropped.iter() {
+            self.sent.remove(addr);
+        }
+        self.last_send = Some(now);
+
+        vec![PexMsg { added, dropped }.encode()]
+    }
+}
@@ -8,10 +8,12 @@
 
 use crate::commands::{BroadCmd, ViewCmd};
 use crate::constants::PEER_ID_SIZE;
+use crate::peer_id;
 use crate::utils::hash_to_string;
 use num_traits::abs;
 use std::io;
 use std::io::Write;
+use std::net::SocketAddr;
 use termion::color;
 use termion::color::Color;
 use tokio::sync::{broadcast, mpsc};
@@ -100,6 +102,7 @@ impl ProgressView {
                 text,
             }) => self.log_peer(&addr, &peer_id, &text),
             Some(ViewCmd::Log(text)) => self.log(&text),
+            Some(ViewCmd::Warning(text)) => self.warning(&text),
             Some(ViewCmd::Kill) => return false,
             None => (),
         }
@@ -170,12 +173,15 @@ impl ProgressView {
         pos as usize
     }
 
-    fn log_peer(&self, addr: &String, peer_id: &Option<[u8; PEER_ID_SIZE]>, text: &String) {
+    fn log_peer(&self, addr: &SocketAddr, peer_id: &Option<[u8; PEER_ID_SIZE]>, text: &str) {
         let peer_id = match peer_id {
             None => "".to_string(),
-            Some(peer_id) => match String::from_utf8(peer_id.to_vec()) {
-                Ok(s) => s,
-                Err(_) => hash_to_string(&peer_id),
+            Some(peer_id) => match peer_id::client(peer_id) {
+                Some(client) => client.to_string(),
+                None => match String::from_utf8(peer_id.to_vec()) {
+                    Ok(s) => s,
+                    Err(_) => hash_to_string(peer_id),
+                },
             },
         };
 
@@ -185,4 +191,13 @@ impl ProgressView {
     fn log(&self, text: &String) {
         println!("\r{}", text);
     }
+
+    fn warning(&self, text: &str) {
+        println!(
+            "\r{}Warning: {}{}",
+            color::Fg(color::Yellow),
+            text,
+            color::Fg(color::Reset)
+        );
+    }
 }
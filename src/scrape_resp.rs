@@ -0,0 +1,122 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::bcodec::bvalue::BValue;
+use crate::constants::HASH_SIZE;
+use crate::{BDecoder, Error};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+
+/// Scrape response from the tracker (see [BEP48](https://www.bittorrent.org/beps/bep_0048.html)).
+#[derive(PartialEq, Clone, Debug)]
+pub struct ScrapeResp {
+    files: HashMap<[u8; HASH_SIZE], ScrapeFile>,
+}
+
+/// Swarm statistics for single torrent.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ScrapeFile {
+    /// Number of peers with the entire file (seeders)
+    pub complete: u64,
+    /// Total number of times the tracker registered a completion
+    pub downloaded: u64,
+    /// Number of non-seeder peers (leechers)
+    pub incomplete: u64,
+    /// Torrent name, as specified in metainfo (optional)
+    pub name: Option<String>,
+}
+
+impl ScrapeResp {
+    /// Create response from statistics of many torrents.
+    pub(crate) fn from_files(files: HashMap<[u8; HASH_SIZE], ScrapeFile>) -> ScrapeResp {
+        ScrapeResp { files }
+    }
+
+    /// Parse scrape response from [bencoded](https://en.wikipedia.org/wiki/Bencode) string.
+    ///
+    /// # Example
+    /// ```
+    /// use rdest::ScrapeResp;
+    ///
+    /// let resp = ScrapeResp::from_bencode(b"d5:filesd20:AAAAABBBBBCCCCCDDDDDd8:completei5e10:downloadedi50e10:incompletei10eeee").unwrap();
+    /// assert_eq!(resp.file(b"AAAAABBBBBCCCCCDDDDD").unwrap().complete, 5);
+    /// ```
+    pub fn from_bencode(data: &[u8]) -> Result<ScrapeResp, Error> {
+        let bvalues = BDecoder::from_array(data)?;
+
+        if bvalues.is_empty() {
+            return Err(Error::TrackerBEncodeMissing);
+        }
+
+        let mut err = Err(Error::TrackerDataMissing);
+        for val in bvalues {
+            if let BValue::Dict(dict) = val {
+                match Self::parse(&dict) {
+                    Ok(resp) => return Ok(resp),
+                    Err(e) => err = Err(e),
+                }
+            }
+        }
+
+        err
+    }
+
+    fn parse(dict: &HashMap<Vec<u8>, BValue>) -> Result<ScrapeResp, Error> {
+        if let Some(BValue::ByteStr(reason)) = dict.get(&b"failure reason".to_vec()) {
+            return Err(Error::TrackerRespFail(
+                String::from_utf8_lossy(reason).to_string(),
+            ));
+        }
+
+        match dict.get(&b"files".to_vec()) {
+            Some(BValue::Dict(files)) => Ok(ScrapeResp {
+                files: files
+                    .iter()
+                    .filter_map(
+                        |(info_hash, file)| match (info_hash.as_slice().try_into(), file) {
+                            (Ok(info_hash), BValue::Dict(file)) => {
+                                Some((info_hash, Self::parse_file(file)?))
+                            }
+                            _ => None,
+                        },
+                    )
+                    .collect(),
+            }),
+            _ => Err(Error::TrackerIncorrectOrMissing("files")),
+        }
+    }
+
+    fn parse_file(dict: &HashMap<Vec<u8>, BValue>) -> Option<ScrapeFile> {
+        let find_u64 = |key: &[u8]| match dict.get(key) {
+            Some(BValue::Int(val)) => u64::try_from(*val).ok(),
+            _ => None,
+        };
+
+        let name = match dict.get(&b"name".to_vec()) {
+            Some(BValue::ByteStr(name)) => String::from_utf8(name.to_vec()).ok(),
+            _ => None,
+        };
+
+        Some(ScrapeFile {
+            complete: find_u64(b"complete")?,
+            downloaded: find_u64(b"downloaded")?,
+            incomplete: find_u64(b"incomplete")?,
+            name,
+        })
+    }
+
+    /// Return statistics for specific torrent, if tracker reported them.
+    pub fn file(&self, info_hash: &[u8; HASH_SIZE]) -> Option<&ScrapeFile> {
+        self.files.get(info_hash)
+    }
+
+    /// Return statistics for all torrents in response.
+    pub fn files(&self) -> &HashMap<[u8; HASH_SIZE], ScrapeFile> {
+        &self.files
+    }
+}
@@ -7,24 +7,40 @@
 // except according to those terms.
 
 use crate::commands::{
-    BitfieldCmd, BroadCmd, ExtractorCmd, HaveCmd, InitCmd, NotInterestedCmd, PeerCmd, PieceCmd,
-    RequestCmd, TrackerCmd, UnchokeCmd, ViewCmd,
+    AllowedFastCmd, AnnounceCmd, BitfieldCmd, BroadCmd, ExtractorCmd, HaveCmd, InitCmd, MappingCmd,
+    NotInterestedCmd, PeerCmd, PieceCmd, RequestCmd, TransferStats, UnchokeCmd, ViewCmd,
 };
+use crate::connection::PeerStream;
 use crate::constants::{
-    MAX_NOT_INTERESTED, MAX_OPTIMISTIC, MAX_OPTIMISTIC_ROUNDS, MAX_UNCHOKED, PEER_ID_SIZE, PORT,
+    ALLOWED_FAST_NUM, MAX_NOT_INTERESTED, MAX_OPTIMISTIC, MAX_OPTIMISTIC_ROUNDS, MAX_PORT,
+    MAX_UNCHOKED, PEER_ID_SIZE, PORT,
 };
 use crate::extractor::Extractor;
+use crate::holepunch::UtHolepunch;
 use crate::messages::Bitfield;
+use crate::mse::Encryption;
 use crate::peer::Peer;
 use crate::peer_handler::PeerHandler;
+use crate::peer_id;
+use crate::peer_source;
+use crate::pex::UtPex;
 use crate::progress_view::ProgressView;
-use crate::{Error, Metainfo, TrackerClient};
+use crate::utils;
+use crate::{
+    peer_priority, BindAddr, Dht, DhtSource, Error, Extension, ExtensionRegistry, HolepunchMsg,
+    LsdSource, Metainfo, PeerSource, PeerSourceEvent, PexMsg, PortMapper, Proxy, StaticPeers,
+    TrackerClient, Utp, UtpSocket, UtpStream,
+};
 use rand::seq::SliceRandom;
+use socket2::{Domain, Protocol, Socket, Type};
 use std::cmp::max;
-use std::collections::HashMap;
-use std::net::Ipv4Addr;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::RangeInclusive;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::signal;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
 use tokio::time;
 use tokio::time::{Duration, Instant, Interval};
@@ -33,20 +49,60 @@ const END_GAME_LIMIT: usize = 10;
 const CHANNEL_SIZE: usize = 64;
 const BROADCAST_CHANNEL_SIZE: usize = 32;
 const CHANGE_STATE_INTERVAL_SEC: u64 = 10;
+const KILL_SOURCES_TIMEOUT_SEC: u64 = 10;
+const LISTEN_BACKLOG: i32 = 1024;
 
 /// Session manager.
 pub struct Session {
     own_id: [u8; PEER_ID_SIZE],
     pieces_status: Vec<Status>,
-    peers: HashMap<String, Peer>,
+    peers: HashMap<SocketAddr, Peer>,
     general_channels: GeneralChannels,
     metainfo: Metainfo,
-    candidates: Vec<(String, [u8; PEER_ID_SIZE])>,
+    candidates: Vec<Candidate>,
     view: Option<View>,
-    tracker: Job<TrackerCmd>,
+    sources: PeerSources,
+    stats_ch: watch::Sender<TransferStats>,
     extractor: Job<ExtractorCmd>,
     round: usize,
     files_extracted: bool,
+    uploaded: u64,
+    downloaded: u64,
+    external_ip: Option<IpAddr>,
+    dht: Option<Dht>,
+    extensions: ExtensionRegistry,
+    encryption: Encryption,
+    utp_enabled: bool,
+    utp: Option<UtpSocket>,
+    utp_v6: Option<UtpSocket>,
+    listen_ip: Option<IpAddr>,
+    listen_ports: RangeInclusive<u16>,
+    /// Port that listeners are bound to, reported to peer sources and peers
+    port: u16,
+    listener: Option<TcpListener>,
+    listener_v6: Option<TcpListener>,
+    bind_addr: Option<BindAddr>,
+    proxy: Option<Proxy>,
+    /// Incoming connections and traffic that can't go through proxy are disabled
+    proxy_only: bool,
+    port_mapping: bool,
+    mapper: Job<MappingCmd>,
+    mapper_stop: Option<oneshot::Sender<()>>,
+    super_seeding: bool,
+    upload_only: bool,
+    swarm_ch: watch::Sender<Vec<(SocketAddr, u8)>>,
+    /// Peers for which rendezvous was requested, until session connects with them or fails
+    rendezvous: HashSet<SocketAddr>,
+}
+
+/// Peer that session may connect to. Flags (and relay, if peer supports holepunch) are known
+/// only for peers from PEX.
+#[derive(Debug, Clone)]
+struct Candidate {
+    addr: SocketAddr,
+    peer_id: Option<[u8; PEER_ID_SIZE]>,
+    flags: Option<u8>,
+    relay: Option<SocketAddr>,
 }
 
 #[derive(Debug)]
@@ -62,6 +118,19 @@ struct Job<Cmd> {
     rx_ch: mpsc::Receiver<Cmd>,
 }
 
+struct PeerSources {
+    pending: Vec<Box<dyn PeerSource>>,
+    jobs: Vec<SourceJob>,
+    tx_ch: mpsc::Sender<PeerSourceEvent>,
+    rx_ch: mpsc::Receiver<PeerSourceEvent>,
+}
+
+#[derive(Debug)]
+struct SourceJob {
+    job: JoinHandle<()>,
+    announce_ch: mpsc::Sender<AnnounceCmd>,
+}
+
 #[derive(Debug)]
 struct GeneralChannels {
     tx: mpsc::Sender<PeerCmd>,
@@ -113,9 +182,37 @@ impl Session {
     /// ```
     pub fn new(metainfo: Metainfo, own_id: [u8; PEER_ID_SIZE]) -> Session {
         let (peer_tx, peer_rx) = mpsc::channel(CHANNEL_SIZE);
-        let (tracker_tx, tracker_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (source_tx, source_rx) = mpsc::channel(CHANNEL_SIZE);
         let (extractor_tx, extractor_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (mapper_tx, mapper_rx) = mpsc::channel(CHANNEL_SIZE);
         let (broad, _) = broadcast::channel(BROADCAST_CHANNEL_SIZE);
+        let (stats_ch, _) = watch::channel(TransferStats {
+            uploaded: 0,
+            downloaded: 0,
+            left: metainfo.total_length(),
+        });
+
+        // Trackerless torrent relies only on other peer sources
+        let mut pending: Vec<Box<dyn PeerSource>> = vec![];
+        if !metainfo.tracker_url().is_empty() {
+            let tracker = TrackerClient::new(&own_id, metainfo.clone(), stats_ch.subscribe());
+            pending.push(Box::new(tracker));
+        }
+
+        // Peer exchange (and holepunch, connecting with peers from PEX) is not allowed for
+        // private torrents (BEP27)
+        let mut extensions = ExtensionRegistry::new();
+        let (swarm_ch, swarm_rx) = watch::channel(vec![]);
+        if !metainfo.is_private() {
+            let pex_tx = peer_tx.clone();
+            extensions.register("ut_pex", move |addr| {
+                Box::new(UtPex::new(addr, swarm_rx.clone(), pex_tx.clone()))
+            });
+            let holepunch_tx = peer_tx.clone();
+            extensions.register(UtHolepunch::NAME, move |addr| {
+                Box::new(UtHolepunch::new(addr, holepunch_tx.clone()))
+            });
+        }
 
         Session {
             own_id,
@@ -125,15 +222,159 @@ impl Session {
             metainfo,
             candidates: vec![],
             view: None,
-            tracker: Job::new(tracker_tx, tracker_rx),
+            sources: PeerSources {
+                pending,
+                jobs: vec![],
+                tx_ch: source_tx,
+                rx_ch: source_rx,
+            },
+            stats_ch,
             extractor: Job::new(extractor_tx, extractor_rx),
             round: 0,
             files_extracted: false,
+            uploaded: 0,
+            downloaded: 0,
+            external_ip: None,
+            dht: None,
+            extensions,
+            encryption: Encryption::default(),
+            utp_enabled: true,
+            utp: None,
+            utp_v6: None,
+            listen_ip: None,
+            listen_ports: PORT..=MAX_PORT,
+            port: PORT,
+            listener: None,
+            listener_v6: None,
+            bind_addr: None,
+            proxy: None,
+            proxy_only: false,
+            port_mapping: false,
+            mapper: Job::new(mapper_tx, mapper_rx),
+            mapper_stop: None,
+            super_seeding: false,
+            upload_only: false,
+            swarm_ch,
+            rendezvous: HashSet::new(),
         }
     }
 
-    /// Run Session that will try connect to tracker, get list of available peers, and establish
-    /// connection with them
+    /// Add another source of peer candidates (tracker is used by default). Sources are started
+    /// in [`run`](Session::run).
+    pub fn add_peer_source(&mut self, source: Box<dyn PeerSource>) {
+        self.sources.pending.push(source);
+    }
+
+    /// Add known peers (e.g. from LAN), bypassing the tracker. Peers are connected straight away
+    /// when session is started, and offered again whenever session needs more peers.
+    pub fn add_peers(&mut self, peers: &[SocketAddr]) {
+        let candidates: Vec<_> = peers.iter().map(|addr| (*addr, None)).collect();
+        self.add_candidates(&candidates);
+        self.add_peer_source(Box::new(StaticPeers::new(peers.to_vec())));
+    }
+
+    /// Register extension handler (see [BEP10](https://www.bittorrent.org/beps/bep_0010.html)),
+    /// used for every new peer connection.
+    pub fn register_extension<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(SocketAddr) -> Box<dyn Extension> + Send + Sync + 'static,
+    {
+        self.extensions.register(name, factory);
+    }
+
+    /// Set encryption policy for peer connections (by default encryption is preferred).
+    pub fn set_encryption(&mut self, encryption: Encryption) {
+        self.encryption = encryption;
+    }
+
+    /// Accept and open peer connections over uTP (enabled by default). uTP socket shares port
+    /// with TCP listener. Outgoing connections try uTP first, and fall back to TCP.
+    pub fn set_utp(&mut self, enabled: bool) {
+        self.utp_enabled = enabled;
+    }
+
+    /// Listen for peers only on given local address (by default on all IPv4 and IPv6 addresses).
+    pub fn set_listen_ip(&mut self, ip: IpAddr) {
+        self.listen_ip = Some(ip);
+    }
+
+    /// Ports tried in order, until free one is found (by default 6881-6889). Use single port range
+    /// to disable fallback.
+    pub fn set_listen_ports(&mut self, ports: RangeInclusive<u16>) {
+        self.listen_ports = ports;
+    }
+
+    /// Open outgoing peer connections from given local address or interface, e.g. to keep traffic
+    /// inside VPN. uTP socket is shared with listener, so outgoing connections use uTP only when
+    /// the same address is also set as listen address.
+    pub fn set_bind_addr(&mut self, bind_addr: BindAddr) {
+        self.bind_addr = Some(bind_addr);
+    }
+
+    /// Connect to trackers (HTTP only) and peers through SOCKS5 or HTTP proxy. Outgoing peer
+    /// connections use only TCP, and other traffic (incoming connections, UDP trackers, DHT, LSD)
+    /// is not affected, unless [`set_proxy_only`](Session::set_proxy_only) is enabled.
+    pub fn set_proxy(&mut self, proxy: Proxy) {
+        self.proxy = Some(proxy);
+    }
+
+    /// When proxy is set, don't listen for incoming connections, and don't start peer sources
+    /// that can't use proxy (UDP trackers, DHT, LSD) nor port mapping. DHT node, if used, has to
+    /// be stopped by caller.
+    pub fn set_proxy_only(&mut self, enabled: bool) {
+        self.proxy_only = enabled;
+    }
+
+    /// Map listen port on gateway (with PCP, NAT-PMP or UPnP-IGD), so peers from internet can
+    /// connect. Mapping is renewed before it expires, and removed when session is finished.
+    /// External address is reported to peer sources (e.g. tracker).
+    pub fn enable_port_mapping(&mut self) {
+        self.port_mapping = true;
+    }
+
+    /// Super-seeding mode (see [BEP16](https://www.bittorrent.org/beps/bep_0016.html)), for
+    /// initial seed. Peers see no pieces, and each one get only single piece revealed at once.
+    /// Next piece is revealed when the previous one is passed on to other peers, so the seed
+    /// uploads less redundant data. Used only when all pieces are available.
+    pub fn set_super_seeding(&mut self, enabled: bool) {
+        self.super_seeding = enabled;
+    }
+
+    /// Don't download any pieces, only share those already downloaded (partial seed, see
+    /// [BEP21](https://www.bittorrent.org/beps/bep_0021.html)). Peers are informed in extension
+    /// handshake, and trackers with "paused" event. Seeds are always upload-only.
+    pub fn set_upload_only(&mut self, upload_only: bool) {
+        self.upload_only = upload_only;
+    }
+
+    /// Look for peers in DHT (node has to be already running), and exchange DHT ports with peers
+    /// that support it.
+    pub fn enable_dht(&mut self, dht: Dht) {
+        // Private torrents get peers only from tracker (BEP27)
+        if self.metainfo.is_private() {
+            return;
+        }
+
+        let source = DhtSource::new(dht.clone(), *self.metainfo.info_hash(), PORT);
+        self.add_peer_source(Box::new(source));
+        self.dht = Some(dht);
+    }
+
+    /// Look for peers in local network, with multicast announcements
+    /// ([BEP14](https://www.bittorrent.org/beps/bep_0014.html)).
+    pub fn enable_lsd(&mut self) {
+        // Private torrents get peers only from tracker (BEP27)
+        if self.metainfo.is_private() {
+            return;
+        }
+
+        let source = LsdSource::new(*self.metainfo.info_hash(), PORT);
+        self.add_peer_source(Box::new(source));
+    }
+
+    /// Run Session that will get list of available peers from peer sources (e.g. tracker), and
+    /// establish connection with them. Session is finished on Ctrl-C, and then sources are
+    /// informed that client is stopped.
     ///
     /// # Example
     /// ```no_run
@@ -152,23 +393,55 @@ impl Session {
     /// ```
     pub async fn run(&mut self) {
         self.spawn_view();
-        self.spawn_tracker();
+        if self.is_proxy_only() {
+            self.disable_incoming().await;
+        } else {
+            if self.listener.is_none() && self.listener_v6.is_none() {
+                self.bind().await.expect("Can't listen for peers");
+            }
+            self.log_listen_port().await;
+        }
+        self.spawn_peer_sources().await;
+        if self.port_mapping && !self.is_proxy_only() {
+            self.spawn_port_mapper();
+        }
+        // Partial seed
+        let have_all = self
+            .pieces_status
+            .iter()
+            .all(|status| *status == Status::Have);
+        if self.upload_only && !have_all {
+            self.send_announce_cmd(AnnounceCmd::Paused).await;
+        }
+        self.spawn_peer_handlers();
         self.event_loop().await;
     }
 
     async fn event_loop(&mut self) {
-        let listener = TcpListener::bind((Ipv4Addr::new(0, 0, 0, 0), PORT))
-            .await
-            .expect(&format!("Can't bind to port {}", PORT));
-
         let mut change_state_timer = self.start_change_conn_state_timer();
 
         loop {
             tokio::select! {
+                _ = signal::ctrl_c() => {
+                    self.kill_peer_sources().await;
+                    self.kill_port_mapper().await;
+                    self.kill_view().await;
+                    break;
+                }
                 _ = change_state_timer.tick() => self.timeout_change_conn_state().await.expect("Can't change connection state"),
-                Ok((socket, _)) = listener.accept() => self.spawn_peer_listener(socket).await,
-                Some(cmd) = self.tracker.rx_ch.recv() => self.handle_tracker_cmd(cmd).await,
+                Ok((socket, addr)) = Self::accept_tcp(&self.listener) => self.spawn_peer_listener(Box::new(socket), addr, false).await,
+                Ok((socket, addr)) = Self::accept_tcp(&self.listener_v6) => self.spawn_peer_listener(Box::new(socket), addr, false).await,
+                stream = Self::accept_utp(&mut self.utp) => {
+                    let addr = stream.peer_addr();
+                    self.spawn_peer_listener(Box::new(stream), addr, true).await
+                }
+                stream = Self::accept_utp(&mut self.utp_v6) => {
+                    let addr = stream.peer_addr();
+                    self.spawn_peer_listener(Box::new(stream), addr, true).await
+                }
+                Some(event) = self.sources.rx_ch.recv() => self.handle_peer_source_event(event).await,
                 Some(cmd) = self.extractor.rx_ch.recv() => self.handle_extractor_cmd(cmd).await,
+                Some(cmd) = self.mapper.rx_ch.recv() => self.handle_mapping_cmd(cmd).await,
                 Some(cmd) = self.general_channels.rx.recv() => {
                     if self.handle_peer_cmd(cmd).await.expect("Can't handle command") == false {
                         self.kill_view().await;
@@ -179,6 +452,133 @@ impl Session {
         }
     }
 
+    /// Bind TCP listeners and uTP sockets (if uTP is enabled) to the first free port from listen
+    /// range, and return it (port 0 selects random port). Called by [`run`](Session::run), if not
+    /// done earlier.
+    pub async fn bind(&mut self) -> Result<u16, Error> {
+        for port in self.listen_ports.clone() {
+            if let Some(port) = self.bind_port(port).await {
+                self.port = port;
+                return Ok(port);
+            }
+        }
+
+        Err(Error::ListenBind(
+            *self.listen_ports.start(),
+            *self.listen_ports.end(),
+        ))
+    }
+
+    /// Port is used only when both TCP and uTP can bind to it. Without listen address, IPv6 is
+    /// optional, because host may not support it at all.
+    async fn bind_port(&mut self, port: u16) -> Option<u16> {
+        let addr = match self.listen_ip {
+            Some(ip) => SocketAddr::new(ip, port),
+            None => SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)),
+        };
+        let (bound, port) = self.bind_addr(addr).await?;
+        let bound_v6 = match self.listen_ip {
+            Some(_) => None,
+            None => self
+                .bind_addr(SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)))
+                .await
+                .map(|(bound, _)| bound),
+        };
+
+        for (listener, utp) in [Some(bound), bound_v6].into_iter().flatten() {
+            match listener.local_addr().is_ok_and(|addr| addr.is_ipv6()) {
+                true => (self.listener_v6, self.utp_v6) = (Some(listener), utp),
+                false => (self.listener, self.utp) = (Some(listener), utp),
+            }
+        }
+        Some(port)
+    }
+
+    /// Bind TCP listener, and uTP socket (if enabled) to the same address. Return also bound port.
+    async fn bind_addr(&self, addr: SocketAddr) -> Option<((TcpListener, Option<UtpSocket>), u16)> {
+        let listener = Self::bind_listener(addr).ok()?;
+        let addr = listener.local_addr().ok()?;
+        let utp = match self.utp_enabled {
+            true => Some(UtpSocket::bind_addr(addr).await.ok()?),
+            false => None,
+        };
+
+        Some(((listener, utp), addr.port()))
+    }
+
+    /// Listen on IPv4 or IPv6. IPv6 socket doesn't accept IPv4 connections (as IPv4-mapped
+    /// addresses), so both sockets can share the same port.
+    fn bind_listener(addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(LISTEN_BACKLOG)?;
+        TcpListener::from_std(socket.into())
+    }
+
+    async fn log_listen_port(&mut self) {
+        self.log(format!("Listening on port {}", self.port)).await;
+        if self.listen_ip.is_none() && self.listener_v6.is_none() {
+            self.warning("Can't listen on IPv6".to_string()).await;
+        }
+    }
+
+    fn is_proxy_only(&self) -> bool {
+        self.proxy.is_some() && self.proxy_only
+    }
+
+    /// Close listeners (if they were bound earlier) and uTP sockets. Peers still learn listen
+    /// port from tracker and handshake, but nobody can connect.
+    async fn disable_incoming(&mut self) {
+        (self.listener, self.listener_v6) = (None, None);
+        (self.utp, self.utp_v6) = (None, None);
+        self.dht = None;
+        self.log("Incoming connections disabled, traffic only through proxy".to_string())
+            .await;
+    }
+
+    /// Wait for incoming TCP connection (forever, if listener is not available).
+    async fn accept_tcp(listener: &Option<TcpListener>) -> io::Result<(TcpStream, SocketAddr)> {
+        match listener {
+            Some(listener) => listener.accept().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Handle of uTP socket for peer address family. uTP socket is shared with listener, so it's
+    /// not used when outgoing connections have to be bound elsewhere, or go through proxy.
+    fn utp_handle(&self, addr: SocketAddr) -> Option<Utp> {
+        if self.proxy.is_some() {
+            return None;
+        }
+
+        match (&self.bind_addr, self.listen_ip) {
+            (None, _) => (),
+            (Some(BindAddr::Ip(ip)), Some(listen_ip)) if *ip == listen_ip => (),
+            _ => return None,
+        }
+
+        match addr.is_ipv6() {
+            true => self.utp_v6.as_ref().map(|utp| utp.handle()),
+            false => self.utp.as_ref().map(|utp| utp.handle()),
+        }
+    }
+
+    /// Wait for incoming uTP connection (forever, if uTP is not available).
+    async fn accept_utp(utp: &mut Option<UtpSocket>) -> UtpStream {
+        if let Some(utp) = utp {
+            if let Some(stream) = utp.accept().await {
+                return stream;
+            }
+        }
+
+        std::future::pending().await
+    }
+
     fn start_change_conn_state_timer(&self) -> Interval {
         let start = Instant::now() + Duration::from_secs(CHANGE_STATE_INTERVAL_SEC);
         time::interval_at(start, Duration::from_secs(CHANGE_STATE_INTERVAL_SEC))
@@ -186,6 +586,7 @@ impl Session {
 
     async fn timeout_change_conn_state(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.round = (self.round + 1) % MAX_OPTIMISTIC_ROUNDS;
+        self.update_pex_swarm();
 
         // If not all peers reported their state, do nothing
         if self
@@ -207,23 +608,24 @@ impl Session {
             .all(|status| *status == Status::Have);
 
         let make_pair = match is_seeder {
-            true => |(addr, peer): (&String, &Peer)| (addr.clone(), peer.download_rate.unwrap()),
-            false => |(addr, peer): (&String, &Peer)| (addr.clone(), peer.uploaded_rate.unwrap()),
+            true => |(addr, peer): (&SocketAddr, &Peer)| (*addr, peer.download_rate.unwrap()),
+            false => |(addr, peer): (&SocketAddr, &Peer)| (*addr, peer.uploaded_rate.unwrap()),
         };
 
         let mut rate = self
             .peers
             .iter()
             .map(|param| make_pair(param))
-            .collect::<Vec<(String, u32)>>();
+            .collect::<Vec<(SocketAddr, u32)>>();
 
         let state_before = self.conn_state_text();
         let cmd = self.change_conn_state(&mut rate, &new_optimistic)?;
         let state_after = self.conn_state_text();
 
         self.log(format!(
-            "Peers: {}, connection state {} -> {}",
+            "Peers: {} ({}), connection state {} -> {}",
             self.peers.len(),
+            self.clients_text(),
             state_before,
             state_after
         ))
@@ -232,6 +634,52 @@ impl Session {
         Ok(())
     }
 
+    /// Share connected peers with ut_pex handlers. Only peers to which session connected have
+    /// known listen port.
+    fn update_pex_swarm(&mut self) {
+        let swarm = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.connectable)
+            .map(|(addr, peer)| {
+                let mut flags = PexMsg::OUTGOING;
+                if peer.upload_only || peer.pieces.iter().all(|have| *have) {
+                    flags |= PexMsg::SEED;
+                }
+                if peer.encrypted {
+                    flags |= PexMsg::ENCRYPTION;
+                }
+                if peer.utp {
+                    flags |= PexMsg::UTP;
+                }
+                if peer.holepunch {
+                    flags |= PexMsg::HOLEPUNCH;
+                }
+                (*addr, flags)
+            })
+            .collect();
+
+        self.swarm_ch.send_replace(swarm);
+    }
+
+    /// Number of connected peers per client, e.g. "rdest 0.1.0: 2, unknown: 1".
+    fn clients_text(&self) -> String {
+        let mut clients: BTreeMap<String, usize> = BTreeMap::new();
+        for (_, peer) in self.peers.iter() {
+            let client = match peer.id.as_ref().and_then(peer_id::client) {
+                Some(client) => client.to_string(),
+                None => "unknown".to_string(),
+            };
+            *clients.entry(client).or_default() += 1;
+        }
+
+        clients
+            .iter()
+            .map(|(client, count)| format!("{}: {}", client, count))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
     fn conn_state_text(&self) -> String {
         let text: String = self
             .peers
@@ -245,29 +693,29 @@ impl Session {
         }
     }
 
-    fn new_optimistic_peers(&mut self) -> Vec<String> {
+    fn new_optimistic_peers(&mut self) -> Vec<SocketAddr> {
         let all_am_choked_and_peer_interested = self
             .peers
             .iter()
             .filter(|(_, peer)| peer.am_choked && peer.interested)
-            .map(|(addr, _)| addr.clone())
-            .collect::<Vec<String>>();
+            .map(|(addr, _)| *addr)
+            .collect::<Vec<SocketAddr>>();
 
         match all_am_choked_and_peer_interested.choose(&mut rand::thread_rng()) {
-            Some(addr) => vec![addr.clone()],
+            Some(addr) => vec![*addr],
             None => vec![],
         }
     }
 
     fn change_conn_state(
         &mut self,
-        rates: &mut Vec<(String, u32)>,
-        new_optimistic: &Vec<String>,
+        rates: &mut [(SocketAddr, u32)],
+        new_optimistic: &[SocketAddr],
     ) -> Result<BroadCmd, Box<dyn std::error::Error>> {
         // Downloaded/uploaded rate in descending order
         rates.sort_by(|(_, r1), (_, r2)| r2.cmp(&r1));
 
-        let mut am_choked_map: HashMap<String, bool> = HashMap::new();
+        let mut am_choked_map: HashMap<SocketAddr, bool> = HashMap::new();
         let mut count = 0;
         for (addr, _) in rates.iter() {
             let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
@@ -275,7 +723,7 @@ impl Session {
                 // Choked state changed to Unchoked
                 if peer.am_choked && peer.interested && !new_optimistic.contains(addr) {
                     peer.am_choked = false;
-                    am_choked_map.insert(addr.clone(), false);
+                    am_choked_map.insert(*addr, false);
                     count += 1;
                 // Unchoked state doesn't change
                 } else if !peer.am_choked && peer.interested {
@@ -283,12 +731,12 @@ impl Session {
                 // Choke, because peer is not interested
                 } else if !peer.am_choked && !peer.interested {
                     peer.am_choked = true;
-                    am_choked_map.insert(addr.clone(), true);
+                    am_choked_map.insert(*addr, true);
                 }
             // Limit reached so change all rest peers states from Unchoked to Choked
             } else if !peer.am_choked {
                 peer.am_choked = true;
-                am_choked_map.insert(addr.clone(), true);
+                am_choked_map.insert(*addr, true);
             }
 
             // If some new optimistic then disable "optimistic unchoke"
@@ -301,35 +749,114 @@ impl Session {
         for addr in new_optimistic.iter() {
             let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
             peer.am_choked = false;
-            am_choked_map.insert(addr.clone(), false);
+            am_choked_map.insert(*addr, false);
             peer.optimistic_unchoke = true;
         }
 
         Ok(BroadCmd::SendOwnState { am_choked_map })
     }
 
-    async fn handle_tracker_cmd(&mut self, cmd: TrackerCmd) {
-        match cmd {
-            TrackerCmd::TrackerResp(resp) => {
-                let peers = resp.peers();
-                self.log(format!("Ok, got {} peers from tracker", peers.len()))
-                    .await;
-                self.candidates.extend_from_slice(&peers);
+    async fn handle_peer_source_event(&mut self, event: PeerSourceEvent) {
+        match event {
+            PeerSourceEvent::Peers(peers) => {
+                self.log(format!("Ok, got {} peers", peers.len())).await;
+                self.add_candidates(&peers);
+                self.spawn_peer_handlers();
+            }
+            PeerSourceEvent::TrackerResp(resp) => {
+                if let Some(warning) = resp.warning_message() {
+                    self.warning(format!("Tracker: {}", warning)).await;
+                }
 
-                let all_am_interested = self
-                    .peers
-                    .iter()
-                    .filter(|(_, peer)| peer.am_interested)
-                    .count() as i32;
-                let spawn_num = (MAX_UNCHOKED + MAX_OPTIMISTIC) as i32 - all_am_interested;
+                if let Some(external_ip) = resp.external_ip() {
+                    if self.external_ip != Some(external_ip) {
+                        self.log(format!("External IP reported by tracker: {}", external_ip))
+                            .await;
+                        self.external_ip = Some(external_ip);
+                    }
+                }
 
-                for _ in 0..max(0, spawn_num) {
-                    self.spawn_peer_handler();
+                let peers = resp.peers();
+                match (resp.complete(), resp.incomplete()) {
+                    (Some(complete), Some(incomplete)) => {
+                        self.log(format!(
+                            "Ok, got {} peers from tracker (seeders: {}, leechers: {})",
+                            peers.len(),
+                            complete,
+                            incomplete
+                        ))
+                        .await
+                    }
+                    _ => {
+                        self.log(format!("Ok, got {} peers from tracker", peers.len()))
+                            .await
+                    }
                 }
+                self.add_candidates(&peers);
+                self.spawn_peer_handlers();
             }
-            TrackerCmd::Fail(e) => self.log(format!("Tracker fail: {}", e)).await,
+            PeerSourceEvent::Fail(e) => self.log(format!("Peer source fail: {}", e)).await,
+        }
+    }
+
+    fn spawn_peer_handlers(&mut self) {
+        let all_am_interested = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.am_interested)
+            .count() as i32;
+        let spawn_num = (MAX_UNCHOKED + MAX_OPTIMISTIC) as i32 - all_am_interested;
+
+        for _ in 0..max(0, spawn_num) {
+            self.spawn_peer_handler();
+        }
+    }
+
+    fn add_candidates(&mut self, peers: &[(SocketAddr, Option<[u8; PEER_ID_SIZE]>)]) {
+        for (addr, peer_id) in peers.iter() {
+            self.add_candidate(*addr, *peer_id, None, None);
+        }
+    }
+
+    fn add_candidate(
+        &mut self,
+        addr: SocketAddr,
+        peer_id: Option<[u8; PEER_ID_SIZE]>,
+        flags: Option<u8>,
+        relay: Option<SocketAddr>,
+    ) {
+        // Tracker can return own address
+        let own_addr = self.external_ip.map(|ip| SocketAddr::new(ip, self.port));
+        if Some(addr) == own_addr
+            || peer_id.as_ref() == Some(&self.own_id)
+            || self.peers.contains_key(&addr)
+            || self.candidates.iter().any(|c| c.addr == addr)
+        {
+            return;
+        }
+
+        self.candidates.push(Candidate {
+            addr,
+            peer_id,
+            flags,
+            relay,
+        });
+    }
+
+    async fn handle_mapping_cmd(&mut self, cmd: MappingCmd) {
+        match cmd {
+            MappingCmd::Mapped(mapping) => {
+                self.log(format!(
+                    "Port mapped with {}, external address {}",
+                    mapping.protocol, mapping.external_addr
+                ))
+                .await;
+                self.external_ip = Some(mapping.external_addr.ip());
+                self.send_announce_cmd(AnnounceCmd::ExternalAddr(mapping.external_addr))
+                    .await;
+            }
+            MappingCmd::Fail(e) => self.warning(format!("Port mapping fail: {}", e)).await,
         }
-        self.kill_tracker().await;
     }
 
     async fn handle_extractor_cmd(&mut self, cmd: ExtractorCmd) {
@@ -346,103 +873,363 @@ impl Session {
             PeerCmd::Init {
                 addr,
                 peer_id,
+                fast,
+                encrypted,
+                utp,
                 resp_ch,
-            } => self.handle_init(&addr, peer_id, resp_ch).await,
-            PeerCmd::RecvChoke { addr } => self.handle_choke(&addr).await,
-            PeerCmd::RecvUnchoke { addr, resp_ch } => self.handle_unchoke(&addr, resp_ch).await,
-            PeerCmd::RecvInterested { addr } => self.handle_interested(&addr).await,
+            } => {
+                self.handle_init(addr, peer_id, fast, encrypted, utp, resp_ch)
+                    .await
+            }
+            PeerCmd::RecvChoke { addr } => self.handle_choke(addr).await,
+            PeerCmd::RecvUnchoke { addr, resp_ch } => self.handle_unchoke(addr, resp_ch).await,
+            PeerCmd::RecvInterested { addr } => self.handle_interested(addr).await,
             PeerCmd::RecvNotInterested { addr, resp_ch } => {
-                self.handle_not_interested(&addr, resp_ch).await
+                self.handle_not_interested(addr, resp_ch).await
             }
             PeerCmd::RecvHave {
                 addr,
                 piece_index,
                 resp_ch,
-            } => self.handle_have(&addr, piece_index, resp_ch),
+            } => self.handle_have(addr, piece_index, resp_ch),
             PeerCmd::RecvBitfield {
                 addr,
                 bitfield,
                 resp_ch,
-            } => self.handle_bitfield(&addr, &bitfield, resp_ch).await,
-            PeerCmd::RecvRequest {
+            } => self.handle_bitfield(addr, &bitfield, resp_ch).await,
+            PeerCmd::RecvAllowedFast {
                 addr,
                 piece_index,
                 resp_ch,
-            } => self.handle_request(&addr, piece_index, resp_ch).await,
-            PeerCmd::PieceDone { addr, resp_ch } => self.handle_piece_done(&addr, resp_ch).await,
-            PeerCmd::PieceCancel { addr, resp_ch } => {
-                self.handle_piece_cancel(&addr, resp_ch).await
+            } => self.handle_allowed_fast(addr, piece_index, resp_ch).await,
+            PeerCmd::RecvPort { addr, port } => self.handle_port(addr, port).await,
+            PeerCmd::RecvPex {
+                addr,
+                added,
+                dropped,
+            } => self.handle_pex(addr, &added, &dropped).await,
+            PeerCmd::RecvExtendedHandshake {
+                addr,
+                client,
+                your_ip,
+                extensions,
+                upload_only,
+            } => {
+                self.handle_extended_handshake(addr, client, your_ip, &extensions, upload_only)
+                    .await
             }
+            PeerCmd::RecvHolepunch { addr, msg } => self.handle_holepunch(addr, &msg).await,
+            PeerCmd::RecvRequest {
+                addr,
+                piece_index,
+                resp_ch,
+            } => self.handle_request(addr, piece_index, resp_ch).await,
+            PeerCmd::PieceDone { addr, resp_ch } => self.handle_piece_done(addr, resp_ch).await,
+            PeerCmd::PieceCancel { addr, resp_ch } => self.handle_piece_cancel(addr, resp_ch).await,
             PeerCmd::SyncStats {
                 addr,
                 downloaded_rate,
                 uploaded_rate,
+                uploaded,
                 unexpected_blocks,
             } => {
-                self.handle_sync_stats(&addr, &downloaded_rate, &uploaded_rate, unexpected_blocks)
-                    .await
+                self.handle_sync_stats(
+                    addr,
+                    &downloaded_rate,
+                    &uploaded_rate,
+                    uploaded,
+                    unexpected_blocks,
+                )
+                .await
             }
-            PeerCmd::KillReq { addr, reason } => self.handle_kill_req(&addr, &reason).await,
+            PeerCmd::KillReq { addr, reason } => self.handle_kill_req(addr, &reason).await,
         }
     }
 
     async fn handle_init(
         &mut self,
-        addr: &String,
+        addr: SocketAddr,
         peer_id: [u8; PEER_ID_SIZE],
+        fast: bool,
+        encrypted: bool,
+        utp: bool,
         resp_ch: oneshot::Sender<InitCmd>,
     ) -> Result<bool, Error> {
         self.log_peer(addr, "Handshake with peer".to_string()).await;
+        self.rendezvous.remove(&addr);
+        if let Some(peer) = self.peers.get_mut(&addr) {
+            peer.encrypted = encrypted;
+            peer.utp = utp;
+        }
+        self.drop_duplicate(addr, peer_id);
+
+        // Algorithm from BEP6 is defined only for IPv4
+        let allowed_fast = match (fast, addr) {
+            (true, SocketAddr::V4(addr)) => utils::allowed_fast_set(
+                *addr.ip(),
+                self.metainfo.info_hash(),
+                self.metainfo.pieces_num(),
+                ALLOWED_FAST_NUM,
+            ),
+            _ => vec![],
+        };
 
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
-        let cmd = peer.handle_init(peer_id, &self.pieces_status);
+        let cmd = match self.is_super_seeding() {
+            true => {
+                let piece_index = self.choose_super_seed_piece(addr);
+                let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
+                peer.handle_init_super_seeding(peer_id, fast, piece_index)
+            }
+            false => {
+                let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
+                peer.handle_init(peer_id, fast, allowed_fast, &self.pieces_status)
+            }
+        };
         let _ = resp_ch.send(cmd);
         Ok(true)
     }
 
-    async fn handle_choke(&mut self, addr: &String) -> Result<bool, Error> {
+    /// Both sides of holepunch connect to each other, so there may be two connections with the
+    /// same peer. Both sides keep the one opened by peer with greater ID.
+    fn drop_duplicate(&mut self, addr: SocketAddr, peer_id: [u8; PEER_ID_SIZE]) {
+        let duplicate = self
+            .peers
+            .iter()
+            .find(|(dup_addr, peer)| **dup_addr != addr && peer.id == Some(peer_id))
+            .map(|(dup_addr, peer)| (*dup_addr, peer.connectable));
+        let (dup_addr, dup_connectable) = match duplicate {
+            Some(duplicate) => duplicate,
+            None => return,
+        };
+
+        let keep_connectable = self.own_id > peer_id;
+        let connectable = self.peers.get(&addr).is_some_and(|peer| peer.connectable);
+        let drop_addr = match (connectable, dup_connectable) {
+            (new, old) if new != old && new == keep_connectable => dup_addr,
+            _ => addr,
+        };
+        let _ = self
+            .general_channels
+            .broad
+            .send(BroadCmd::Disconnect { addr: drop_addr });
+    }
+
+    async fn handle_extended_handshake(
+        &mut self,
+        addr: SocketAddr,
+        client: Option<String>,
+        your_ip: Option<IpAddr>,
+        extensions: &[String],
+        upload_only: Option<bool>,
+    ) -> Result<bool, Error> {
+        let client = client.unwrap_or_else(|| "unknown".to_string());
+        self.log_peer(addr, format!("Extension handshake, client: {}", client))
+            .await;
+
+        let holepunch = extensions.iter().any(|name| name == UtHolepunch::NAME)
+            && self
+                .extensions
+                .names()
+                .iter()
+                .any(|name| name == UtHolepunch::NAME);
+        if let Some(peer) = self.peers.get_mut(&addr) {
+            peer.holepunch = holepunch;
+            if let Some(upload_only) = upload_only {
+                peer.upload_only = upload_only;
+            }
+        }
+
+        // Tracker report is preferred, peer may be behind the same NAT
+        if let (None, Some(your_ip)) = (self.external_ip, your_ip) {
+            self.log(format!("External IP reported by peer: {}", your_ip))
+                .await;
+            self.external_ip = Some(your_ip);
+        }
+        Ok(true)
+    }
+
+    async fn handle_pex(
+        &mut self,
+        addr: SocketAddr,
+        added: &[(SocketAddr, u8)],
+        dropped: &[SocketAddr],
+    ) -> Result<bool, Error> {
+        self.log_peer(
+            addr,
+            format!("PEX, added: {}, dropped: {}", added.len(), dropped.len()),
+        )
+        .await;
+
+        // Dropped peers are only informative, they could be still reachable. Peers supporting
+        // holepunch can be introduced by sender, if they are behind NAT.
+        let holepunch = self.peers.get(&addr).is_some_and(|peer| peer.holepunch);
+        for (peer_addr, flags) in added.iter() {
+            let relay = match holepunch && flags & PexMsg::HOLEPUNCH != 0 {
+                true => Some(addr),
+                false => None,
+            };
+            self.add_candidate(*peer_addr, None, Some(*flags), relay);
+        }
+        Ok(true)
+    }
+
+    async fn handle_holepunch(
+        &mut self,
+        addr: SocketAddr,
+        msg: &HolepunchMsg,
+    ) -> Result<bool, Error> {
+        match msg.msg_type {
+            HolepunchMsg::RENDEZVOUS => self.relay_rendezvous(addr, msg.addr).await,
+            HolepunchMsg::CONNECT => self.holepunch_connect(addr, msg.addr).await,
+            _ => {
+                self.rendezvous.remove(&msg.addr);
+                self.log_peer(
+                    addr,
+                    format!("Holepunch with {} failed: {}", msg.addr, msg.err_text()),
+                )
+                .await
+            }
+        }
+        Ok(true)
+    }
+
+    /// Introduce both peers to each other, if relay is connected to both of them (BEP55).
+    async fn relay_rendezvous(&mut self, addr: SocketAddr, target: SocketAddr) {
+        self.log_peer(addr, format!("Holepunch, rendezvous with {}", target))
+            .await;
+
+        let err_code = match self.peers.get(&target) {
+            _ if target == addr => Some(HolepunchMsg::NO_SELF),
+            _ if target.port() == 0 || target.ip().is_unspecified() => {
+                Some(HolepunchMsg::NO_SUCH_PEER)
+            }
+            None => Some(HolepunchMsg::NOT_CONNECTED),
+            Some(peer) if !peer.holepunch => Some(HolepunchMsg::NO_SUPPORT),
+            Some(_) => None,
+        };
+
+        match err_code {
+            Some(err_code) => self.send_holepunch(addr, HolepunchMsg::ERROR, target, err_code),
+            None => {
+                self.send_holepunch(target, HolepunchMsg::CONNECT, addr, 0);
+                self.send_holepunch(addr, HolepunchMsg::CONNECT, target, 0);
+            }
+        }
+    }
+
+    /// Both sides connect to each other, like to any other candidate (uTP first, then TCP).
+    /// Target first opens mapping in its NAT with single uTP packet, and gives up own connection,
+    /// when the one from initiator comes first (from the same address). When both connections
+    /// succeed, duplicate is dropped after handshake.
+    async fn holepunch_connect(&mut self, addr: SocketAddr, peer_addr: SocketAddr) {
+        if self.peers.contains_key(&peer_addr) {
+            return;
+        }
+
+        let initiator = self.rendezvous.contains(&peer_addr);
+        self.log_peer(addr, format!("Holepunch, connecting to {}", peer_addr))
+            .await;
+        if !initiator {
+            if let Some(utp) = self.utp_handle(peer_addr) {
+                let _ = utp.punch(peer_addr).await;
+            }
+        }
+
+        self.candidates.retain(|c| c.addr != peer_addr);
+        self.spawn_candidate(Candidate {
+            addr: peer_addr,
+            peer_id: None,
+            flags: None,
+            relay: None,
+        });
+        if let Some(peer) = self.peers.get_mut(&peer_addr) {
+            peer.holepunch_target = !initiator;
+        }
+    }
+
+    /// Ask relay to introduce peer, that session couldn't connect to.
+    async fn send_rendezvous(&mut self, relay: SocketAddr, target: SocketAddr) {
+        let holepunch = self.peers.get(&relay).is_some_and(|peer| peer.holepunch);
+        if !holepunch || !self.rendezvous.insert(target) {
+            return;
+        }
+
+        self.log_peer(
+            relay,
+            format!("Holepunch, ask for rendezvous with {}", target),
+        )
+        .await;
+        self.send_holepunch(relay, HolepunchMsg::RENDEZVOUS, target, 0);
+    }
+
+    fn send_holepunch(&self, addr: SocketAddr, msg_type: u8, peer_addr: SocketAddr, err_code: u32) {
+        let msg = HolepunchMsg {
+            msg_type,
+            addr: peer_addr,
+            err_code,
+        };
+        let _ = self.general_channels.broad.send(BroadCmd::SendExtended {
+            addr,
+            name: UtHolepunch::NAME.to_string(),
+            payload: msg.encode(),
+        });
+    }
+
+    async fn handle_port(&mut self, addr: SocketAddr, port: u16) -> Result<bool, Error> {
+        self.log_peer(addr, format!("Peer DHT port {}", port)).await;
+
+        // Ping adds node to routing table, if it responds
+        if let Some(dht) = &self.dht {
+            let dht = dht.clone();
+            let node_addr = SocketAddr::new(addr.ip(), port);
+            tokio::spawn(async move { dht.ping(node_addr).await });
+        }
+        Ok(true)
+    }
+
+    async fn handle_choke(&mut self, addr: SocketAddr) -> Result<bool, Error> {
         self.log_peer(addr, "Peer change state to Choke".to_string())
             .await;
 
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         peer.handle_choke(&mut self.pieces_status);
         Ok(true)
     }
 
     async fn handle_unchoke(
         &mut self,
-        addr: &String,
+        addr: SocketAddr,
         resp_ch: oneshot::Sender<UnchokeCmd>,
     ) -> Result<bool, Error> {
         self.log_peer(addr, "Peer change state to Unchoke".to_string())
             .await;
 
         let chosen_index = self.choose_piece_index(addr).await;
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         let cmd = peer.handle_unchoke(chosen_index, &mut self.pieces_status, &self.metainfo);
         let _ = &resp_ch.send(cmd);
         Ok(true)
     }
 
-    async fn handle_interested(&mut self, addr: &String) -> Result<bool, Error> {
+    async fn handle_interested(&mut self, addr: SocketAddr) -> Result<bool, Error> {
         self.log_peer(addr, "Peer change state to Interested".to_string())
             .await;
 
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         peer.handle_interested();
         Ok(true)
     }
 
     async fn handle_not_interested(
         &mut self,
-        addr: &String,
+        addr: SocketAddr,
         resp_ch: oneshot::Sender<NotInterestedCmd>,
     ) -> Result<bool, Error> {
         self.log_peer(addr, "Peer change state to NotInterested".to_string())
             .await;
 
         let chosen_index = self.choose_piece_index(addr).await;
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         let cmd = peer.handle_not_interested(chosen_index);
         let _ = resp_ch.send(cmd);
         Ok(true)
@@ -450,40 +1237,66 @@ impl Session {
 
     fn handle_have(
         &mut self,
-        addr: &String,
+        addr: SocketAddr,
         piece_index: usize,
         resp_ch: oneshot::Sender<HaveCmd>,
     ) -> Result<bool, Error> {
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         let cmd = peer.handle_have(piece_index, &mut self.pieces_status, &self.metainfo);
         let _ = resp_ch.send(cmd);
+
+        if self.is_super_seeding() {
+            self.update_super_seeding(piece_index);
+        }
         Ok(true)
     }
 
     async fn handle_bitfield(
         &mut self,
-        addr: &String,
+        addr: SocketAddr,
         bitfield: &Bitfield,
         resp_ch: oneshot::Sender<BitfieldCmd>,
     ) -> Result<bool, Error> {
         self.log_peer(addr, format!("Received a bitfield")).await;
 
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         peer.update_pieces(&bitfield.to_vec(self.metainfo.pieces_num())?);
 
+        // Peer already has revealed piece (it was chosen before its bitfield was known)
+        if let Some(piece_index) = peer.super_seed_piece {
+            if peer.pieces[piece_index] && self.is_super_seeding() {
+                self.reveal_piece(addr);
+            }
+        }
+
         let chosen_index = self.choose_piece_index(addr).await;
         let unchoked_num = self.unchoked_num();
 
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         let cmd = peer.handle_bitfield(chosen_index, unchoked_num);
         let _ = &resp_ch.send(cmd);
 
         Ok(true)
     }
 
+    async fn handle_allowed_fast(
+        &mut self,
+        addr: SocketAddr,
+        piece_index: usize,
+        resp_ch: oneshot::Sender<AllowedFastCmd>,
+    ) -> Result<bool, Error> {
+        self.log_peer(addr, format!("Peer allowed fast piece: {}", piece_index))
+            .await;
+
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
+        let cmd = peer.handle_allowed_fast(piece_index, &mut self.pieces_status, &self.metainfo);
+        let _ = resp_ch.send(cmd);
+        Ok(true)
+    }
+
     async fn handle_request(
         &mut self,
-        addr: &String,
+        addr: SocketAddr,
         piece_index: usize,
         resp_ch: oneshot::Sender<RequestCmd>,
     ) -> Result<bool, Error> {
@@ -493,30 +1306,54 @@ impl Session {
         )
         .await;
 
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
-        let cmd = peer.handle_request(piece_index, &self.pieces_status, &self.metainfo);
+        let super_seeding = self.is_super_seeding();
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
+        let cmd = peer.handle_request(
+            piece_index,
+            &self.pieces_status,
+            &self.metainfo,
+            super_seeding,
+        );
         let _ = resp_ch.send(cmd);
         Ok(true)
     }
 
     async fn handle_piece_done(
         &mut self,
-        addr: &String,
+        addr: SocketAddr,
         resp_ch: oneshot::Sender<PieceCmd>,
     ) -> Result<bool, Error> {
-        match self.peers.get(addr).ok_or(Error::PeerNotFound)?.piece_index {
+        match self
+            .peers
+            .get(&addr)
+            .ok_or(Error::PeerNotFound)?
+            .piece_index
+        {
             Some(piece_index) => {
                 self.pieces_status[piece_index] = Status::Have;
+                self.downloaded += self.metainfo.piece_length(piece_index) as u64;
+                self.update_transfer_stats();
                 let _ = self
                     .general_channels
                     .broad
                     .send(BroadCmd::SendHave { piece_index });
+
+                if self
+                    .pieces_status
+                    .iter()
+                    .all(|status| *status == Status::Have)
+                {
+                    self.send_announce_cmd(AnnounceCmd::Completed).await;
+                    if !self.upload_only {
+                        let _ = self.general_channels.broad.send(BroadCmd::SendUploadOnly);
+                    }
+                }
             }
             None => panic!("Piece downloaded but not requested"),
         }
 
         let chosen_index = self.choose_piece_index(addr).await;
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         let cmd = peer.handle_piece(chosen_index, &mut self.pieces_status, &self.metainfo);
         let _ = resp_ch.send(cmd);
         Ok(true)
@@ -524,10 +1361,15 @@ impl Session {
 
     async fn handle_piece_cancel(
         &mut self,
-        addr: &String,
+        addr: SocketAddr,
         resp_ch: oneshot::Sender<PieceCmd>,
     ) -> Result<bool, Error> {
-        match self.peers.get(addr).ok_or(Error::PeerNotFound)?.piece_index {
+        match self
+            .peers
+            .get(&addr)
+            .ok_or(Error::PeerNotFound)?
+            .piece_index
+        {
             Some(piece_index) => {
                 self.pieces_status[piece_index] = match self.pieces_status[piece_index] {
                     Status::Reserved(peers_count) => match peers_count >= 2 {
@@ -542,7 +1384,7 @@ impl Session {
         }
 
         let chosen_index = self.choose_piece_index(addr).await;
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         let cmd = peer.handle_piece(chosen_index, &mut self.pieces_status, &self.metainfo);
         let _ = resp_ch.send(cmd);
         Ok(true)
@@ -550,11 +1392,17 @@ impl Session {
 
     async fn handle_sync_stats(
         &mut self,
-        addr: &String,
+        addr: SocketAddr,
         downloaded_rate: &Option<u32>,
         uploaded_rate: &Option<u32>,
+        uploaded: usize,
         unexpected_blocks: usize,
     ) -> Result<bool, Error> {
+        if uploaded > 0 {
+            self.uploaded += uploaded as u64;
+            self.update_transfer_stats();
+        }
+
         if unexpected_blocks > 0 {
             self.log_peer(
                 addr,
@@ -562,15 +1410,25 @@ impl Session {
             )
             .await;
         }
-        let peer = self.peers.get_mut(addr).ok_or(Error::PeerNotFound)?;
+        let peer = self.peers.get_mut(&addr).ok_or(Error::PeerNotFound)?;
         peer.handle_sync_stats(downloaded_rate, uploaded_rate);
         Ok(true)
     }
 
-    async fn handle_kill_req(&mut self, addr: &String, reason: &String) -> Result<bool, Error> {
+    async fn handle_kill_req(&mut self, addr: SocketAddr, reason: &str) -> Result<bool, Error> {
         self.log_peer(addr, "Peer killed, reason: ".to_string() + reason)
             .await;
-        self.kill_peer(&addr).await;
+
+        // Connection (to peer from PEX) failed before handshake, peer may be behind NAT
+        let relay = self
+            .peers
+            .get(&addr)
+            .filter(|peer| peer.id.is_none())
+            .and_then(|peer| peer.relay);
+        self.kill_peer(addr).await;
+        if let Some(relay) = relay {
+            self.send_rendezvous(relay, addr).await;
+        }
 
         let have_all = self
             .pieces_status
@@ -583,7 +1441,7 @@ impl Session {
             }
             self.files_extracted = true;
         } else if self.candidates.is_empty() {
-            self.spawn_tracker();
+            self.send_announce_cmd(AnnounceCmd::Announce).await;
         } else {
             self.spawn_peer_handler();
         }
@@ -591,6 +1449,109 @@ impl Session {
         Ok(true)
     }
 
+    /// Canonical priority (BEP40) of connection with peer, if own external IP is known.
+    fn priority(&self, addr: SocketAddr) -> Option<u32> {
+        let own_addr = SocketAddr::new(self.external_ip?, self.port);
+        Some(peer_priority(own_addr, addr))
+    }
+
+    fn is_upload_only(&self) -> bool {
+        self.upload_only
+            || self
+                .pieces_status
+                .iter()
+                .all(|status| *status == Status::Have)
+    }
+
+    fn is_super_seeding(&self) -> bool {
+        self.super_seeding
+            && self
+                .pieces_status
+                .iter()
+                .all(|status| *status == Status::Have)
+    }
+
+    /// Reveal next piece to peers that passed on their piece (BEP16). Piece is also treated as
+    /// passed on, when there are no other peers that could take it.
+    fn update_super_seeding(&mut self, piece_index: usize) {
+        let waiting: Vec<SocketAddr> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| {
+                peer.super_seed_piece == Some(piece_index) && peer.pieces[piece_index]
+            })
+            .map(|(addr, _)| *addr)
+            .collect();
+
+        for addr in waiting.into_iter() {
+            let mut others = self.peers.iter().filter(|(a, _)| **a != addr).peekable();
+            let passed_on =
+                others.peek().is_none() || others.any(|(_, peer)| peer.pieces[piece_index]);
+            if passed_on {
+                self.reveal_piece(addr);
+            }
+        }
+    }
+
+    fn reveal_piece(&mut self, addr: SocketAddr) {
+        let piece_index = self.choose_super_seed_piece(addr);
+        if let Some(peer) = self.peers.get_mut(&addr) {
+            peer.reveal_piece(piece_index);
+        }
+
+        if let Some(piece_index) = piece_index {
+            let _ = self
+                .general_channels
+                .broad
+                .send(BroadCmd::RevealPiece { addr, piece_index });
+        }
+    }
+
+    /// Choose piece, that peer doesn't have, and that is the least common in swarm (including
+    /// pieces revealed to other peers).
+    fn choose_super_seed_piece(&self, addr: SocketAddr) -> Option<usize> {
+        let peer = self.peers.get(&addr)?;
+
+        let mut counts = vec![0; self.metainfo.pieces_num()];
+        for (other_addr, other) in self.peers.iter() {
+            for (piece_index, have) in other.pieces.iter().enumerate() {
+                if *have {
+                    counts[piece_index] += 1;
+                }
+            }
+            if let Some(piece_index) = other.super_seed_piece.filter(|_| *other_addr != addr) {
+                counts[piece_index] += 1;
+            }
+        }
+
+        // Shuffle to get better distribution of pieces between peers
+        let mut pieces: Vec<usize> = (0..counts.len()).filter(|idx| !peer.pieces[*idx]).collect();
+        pieces.shuffle(&mut rand::thread_rng());
+        pieces.into_iter().min_by_key(|idx| counts[*idx])
+    }
+
+    fn update_transfer_stats(&mut self) {
+        let left = self
+            .pieces_status
+            .iter()
+            .enumerate()
+            .filter(|(_, status)| **status != Status::Have)
+            .map(|(piece_index, _)| self.metainfo.piece_length(piece_index) as u64)
+            .sum();
+
+        self.stats_ch.send_replace(TransferStats {
+            uploaded: self.uploaded,
+            downloaded: self.downloaded,
+            left,
+        });
+    }
+
+    async fn send_announce_cmd(&mut self, cmd: AnnounceCmd) {
+        for source in self.sources.jobs.iter() {
+            let _ = source.announce_ch.send(cmd.clone()).await;
+        }
+    }
+
     fn unchoked_num(&self) -> usize {
         self.peers
             .iter()
@@ -598,8 +1559,12 @@ impl Session {
             .count()
     }
 
-    async fn choose_piece_index(&mut self, addr: &String) -> Option<usize> {
-        let pieces = &self.peers[addr].pieces;
+    async fn choose_piece_index(&mut self, addr: SocketAddr) -> Option<usize> {
+        if self.upload_only {
+            return None;
+        }
+
+        let pieces = &self.peers[&addr].pieces;
 
         // Count how many peers have specific piece
         let mut vec: Vec<u32> = vec![0; self.metainfo.pieces_num()];
@@ -638,6 +1603,12 @@ impl Session {
         // Sort by rarest
         rarest.sort_by(|(_, count1), (_, count2)| count1.cmp(&count2));
 
+        // When choked, only allowed fast pieces can be downloaded straight away
+        let peer = &self.peers[&addr];
+        if peer.choked {
+            rarest.sort_by_key(|(piece_index, _)| !peer.allowed_fast.contains(piece_index));
+        }
+
         for (piece_index, count) in rarest.iter() {
             if count > &0 && pieces[*piece_index] == true {
                 if still_missing < END_GAME_LIMIT {
@@ -660,13 +1631,40 @@ impl Session {
         });
     }
 
-    fn spawn_tracker(&mut self) {
-        let mut tracker = TrackerClient::new(
-            &self.own_id,
-            self.metainfo.clone(),
-            self.tracker.tx_ch.clone(),
-        );
-        self.tracker.job = Some(tokio::spawn(async move { tracker.run().await }));
+    fn spawn_port_mapper(&mut self) {
+        let mut mapper = PortMapper::new(self.port);
+        let event_ch = self.mapper.tx_ch.clone();
+        let (stop_tx, stop_rx) = oneshot::channel();
+        self.mapper_stop = Some(stop_tx);
+        self.mapper.job = Some(tokio::spawn(
+            async move { mapper.run(event_ch, stop_rx).await },
+        ));
+    }
+
+    async fn spawn_peer_sources(&mut self) {
+        let pending: Vec<_> = self.sources.pending.drain(..).collect();
+        for mut source in pending {
+            source.set_port(self.port);
+            let proxied = match &self.proxy {
+                Some(proxy) => source.set_proxy(proxy),
+                None => false,
+            };
+            if self.is_proxy_only() && !proxied {
+                self.log(format!(
+                    "Peer source {} can't use proxy, skipped",
+                    source.name()
+                ))
+                .await;
+                continue;
+            }
+
+            let (announce_tx, announce_rx) = mpsc::channel(CHANNEL_SIZE);
+            let event_ch = self.sources.tx_ch.clone();
+            self.sources.jobs.push(SourceJob {
+                job: tokio::spawn(peer_source::run(source, event_ch, announce_rx)),
+                announce_ch: announce_tx,
+            });
+        }
     }
 
     async fn spawn_extractor(&mut self) {
@@ -676,62 +1674,149 @@ impl Session {
     }
 
     fn spawn_peer_handler(&mut self) {
-        let (addr, peer_id) = match self.candidates.pop() {
-            Some(value) => value,
-            None => return,
-        };
+        // Seeds (and partial seeds) don't need other seeds
+        if self.is_upload_only() {
+            self.candidates
+                .retain(|c| c.flags.is_none_or(|flags| flags & PexMsg::SEED == 0));
+        }
+
+        if let Some(candidate) = self.pop_candidate() {
+            self.spawn_candidate(candidate);
+        }
+    }
+
+    /// Take candidate with the highest priority (BEP40), or the last one added, when own external
+    /// IP is unknown.
+    fn pop_candidate(&mut self) -> Option<Candidate> {
+        if self.external_ip.is_none() {
+            return self.candidates.pop();
+        }
+
+        let (idx, _) = self
+            .candidates
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, candidate)| self.priority(candidate.addr))?;
+        Some(self.candidates.swap_remove(idx))
+    }
 
+    fn spawn_candidate(&mut self, candidate: Candidate) {
+        let addr = candidate.addr;
+        let peer_id = candidate.peer_id;
         if self.peers.contains_key(&addr) {
             return;
         }
 
-        let mut peer_handler = PeerHandler::new(
-            addr.clone(),
-            self.own_id,
-            Some(peer_id),
-            *self.metainfo.info_hash(),
-            self.metainfo.pieces_num(),
-            self.general_channels.tx.clone(),
-            self.general_channels.broad.subscribe(),
-        );
+        let mut peer_handler = self.new_peer_handler(addr, peer_id);
+        if let Some(bind_addr) = &self.bind_addr {
+            peer_handler.set_bind_addr(bind_addr.clone());
+        }
+        if let Some(proxy) = &self.proxy {
+            peer_handler.set_proxy(proxy.clone());
+        }
+
+        // Peers from PEX report if their connections are encrypted and go over uTP, so only
+        // handshake that can succeed is tried
+        let plain = candidate
+            .flags
+            .is_some_and(|flags| flags & PexMsg::ENCRYPTION == 0);
+        if plain && self.encryption == Encryption::Preferred {
+            peer_handler.set_encryption(Encryption::Disabled);
+        }
+        let tcp_only = candidate
+            .flags
+            .is_some_and(|flags| flags & PexMsg::UTP == 0);
+        if let Some(utp) = self.utp_handle(addr).filter(|_| !tcp_only) {
+            peer_handler.enable_utp(utp);
+        }
 
         let job = tokio::spawn(async move { peer_handler.run_incoming().await });
 
-        let peer = Peer::new(Some(peer_id), self.metainfo.pieces_num(), job);
+        let mut peer = Peer::new(peer_id, self.metainfo.pieces_num(), job);
+        peer.connectable = true;
+        peer.relay = candidate.relay;
         self.peers.insert(addr, peer);
     }
 
-    async fn spawn_peer_listener(&mut self, socket: TcpStream) {
+    async fn spawn_peer_listener(
+        &mut self,
+        socket: Box<dyn PeerStream>,
+        addr: SocketAddr,
+        over_utp: bool,
+    ) {
         let am_not_interested = self
             .peers
             .iter()
             .filter(|(_, peer)| !peer.am_interested)
             .count();
         if am_not_interested >= MAX_NOT_INTERESTED {
-            return;
+            // Connection with the lowest priority (BEP40) is replaced, if new one is better
+            let lowest = self
+                .peers
+                .iter()
+                .filter(|(_, peer)| !peer.am_interested)
+                .filter_map(|(addr, _)| Some((*addr, self.priority(*addr)?)))
+                .min_by_key(|(_, priority)| *priority);
+            match (lowest, self.priority(addr)) {
+                (Some((lowest_addr, lowest_priority)), Some(priority))
+                    if priority > lowest_priority =>
+                {
+                    let _ = self
+                        .general_channels
+                        .broad
+                        .send(BroadCmd::Disconnect { addr: lowest_addr });
+                }
+                _ => return,
+            }
         }
 
-        let addr = match socket.peer_addr() {
-            Ok(addr) => addr.to_string(),
-            Err(_) => return,
-        };
+        // Peers using uTP connect from their listen address, which may be already in use by
+        // outgoing connection. Only holepunch target gives way to initiator.
+        if let Some(peer) = self.peers.get_mut(&addr) {
+            if !peer.holepunch_target || peer.id.is_some() {
+                return;
+            }
+            if let Some(job) = peer.job.take() {
+                job.abort();
+            }
+            self.peers.remove(&addr);
+        }
+
+        let mut peer_handler = self.new_peer_handler(addr, None);
+
+        let job = tokio::spawn(async move { peer_handler.run_outgoing(socket, over_utp).await });
+
+        self.log(format!("New peer connect from: {}", addr)).await;
+        let peer = Peer::new(None, self.metainfo.pieces_num(), job);
+        self.peers.insert(addr, peer);
+    }
 
+    /// Handler with settings common for incoming and outgoing connections. Outgoing ones also
+    /// need bind address, proxy and uTP.
+    fn new_peer_handler(
+        &self,
+        addr: SocketAddr,
+        peer_id: Option<[u8; PEER_ID_SIZE]>,
+    ) -> PeerHandler {
         let mut peer_handler = PeerHandler::new(
-            addr.clone(),
+            addr,
             self.own_id,
-            None,
+            peer_id,
             *self.metainfo.info_hash(),
             self.metainfo.pieces_num(),
             self.general_channels.tx.clone(),
             self.general_channels.broad.subscribe(),
         );
-
-        let job = tokio::spawn(async move { peer_handler.run_outgoing(socket).await });
-
-        self.log("New peer connect from: ".to_string() + &addr.as_str())
-            .await;
-        let peer = Peer::new(None, self.metainfo.pieces_num(), job);
-        self.peers.insert(addr, peer);
+        if let Some(dht) = &self.dht {
+            peer_handler.enable_dht(dht.local_addr().port());
+        }
+        let mut extensions = self.extensions.create(addr);
+        extensions.set_metadata_size(self.metainfo.metadata_size());
+        extensions.set_upload_only(self.is_upload_only());
+        peer_handler.set_extensions(extensions);
+        peer_handler.set_encryption(self.encryption);
+        peer_handler.set_listen_port(self.port);
+        peer_handler
     }
 
     async fn kill_view(&mut self) {
@@ -745,32 +1830,51 @@ impl Session {
         }
     }
 
-    async fn kill_peer(&mut self, addr: &String) {
-        match self.peers.get_mut(addr) {
-            Some(peer) => {
-                // Reset piece status
-                if let Some(piece_index) = peer.piece_index {
-                    if self.pieces_status[piece_index] != Status::Have {
-                        self.pieces_status[piece_index] = Status::Missing
-                    }
+    async fn kill_peer(&mut self, addr: SocketAddr) {
+        if let Some(peer) = self.peers.get_mut(&addr) {
+            // Reset piece status
+            if let Some(piece_index) = peer.piece_index {
+                if self.pieces_status[piece_index] != Status::Have {
+                    self.pieces_status[piece_index] = Status::Missing
                 }
+            }
 
-                // Wait for task to finish
-                if let Some(job) = peer.job.take() {
-                    job.await.expect("Can't kill peer job")
-                }
+            // Wait for task to finish
+            if let Some(job) = peer.job.take() {
+                job.await.expect("Can't kill peer job")
             }
-            None => (),
         }
 
         // Remove peer data from map
-        self.peers.remove(addr);
+        self.peers.remove(&addr);
+        self.rendezvous.remove(&addr);
     }
 
-    async fn kill_tracker(&mut self) {
-        match &mut self.tracker.job.take() {
-            Some(job) => job.await.expect("Can't kill tracker"),
-            None => (),
+    async fn kill_peer_sources(&mut self) {
+        self.send_announce_cmd(AnnounceCmd::Stop).await;
+
+        // Sources (e.g. tracker waiting for "stopped" announce response) are stopped in
+        // parallel, but only for limited time
+        let deadline = Instant::now() + Duration::from_secs(KILL_SOURCES_TIMEOUT_SEC);
+        for source in self.sources.jobs.drain(..) {
+            let abort_handle = source.job.abort_handle();
+            if time::timeout_at(deadline, source.job).await.is_err() {
+                abort_handle.abort();
+            }
+        }
+    }
+
+    /// Mapping is removed from gateway, but Session waits only limited time.
+    async fn kill_port_mapper(&mut self) {
+        if let Some(stop_ch) = self.mapper_stop.take() {
+            let _ = stop_ch.send(());
+        }
+        if let Some(job) = self.mapper.job.take() {
+            let abort_handle = job.abort_handle();
+            let timeout = Duration::from_secs(KILL_SOURCES_TIMEOUT_SEC);
+            if time::timeout(timeout, job).await.is_err() {
+                abort_handle.abort();
+            }
         }
     }
 
@@ -787,11 +1891,17 @@ impl Session {
         }
     }
 
-    async fn log_peer(&mut self, addr: &String, text: String) {
+    async fn warning(&mut self, text: String) {
+        if let Some(view) = &mut self.view {
+            let _ = view.channel.send(ViewCmd::Warning(text)).await;
+        }
+    }
+
+    async fn log_peer(&mut self, addr: SocketAddr, text: String) {
         if let Some(view) = &mut self.view {
-            if let Some(peer) = self.peers.get(addr) {
+            if let Some(peer) = self.peers.get(&addr) {
                 let cmd = ViewCmd::LogPeer {
-                    addr: addr.clone(),
+                    addr,
                     peer_id: peer.id,
                     text,
                 };
@@ -0,0 +1,177 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::Error;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use rsa::{Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use x509_cert::der::{Decode, DecodePem, Encode};
+
+/// Torrent signature, entry in "signatures" dictionary (see
+/// [BEP35](https://www.bittorrent.org/beps/bep_0035.html)). Signature covers raw (bencoded) info
+/// dictionary of torrent, followed by signature's own info dictionary (if present).
+#[derive(PartialEq, Clone, Debug)]
+pub struct Signature {
+    /// Signer identity (key in "signatures" dictionary)
+    pub identity: String,
+    /// Signer X.509 certificate (DER), if embedded in torrent
+    pub certificate: Option<Vec<u8>>,
+    /// Bencoded additional info dictionary, covered by signature
+    pub info: Option<Vec<u8>>,
+    /// RSA signature (PKCS#1 v1.5 with SHA-1)
+    pub signature: Vec<u8>,
+}
+
+/// X.509 certificate with RSA public key, used to verify torrent signatures.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Certificate {
+    identity: String,
+    der: Vec<u8>,
+    public_key: RsaPublicKey,
+}
+
+/// RSA private key and matching certificate, used to sign torrents.
+#[derive(Clone)]
+pub struct Signer {
+    key: RsaPrivateKey,
+    certificate: Certificate,
+}
+
+impl Signature {
+    fn digest(&self, info: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha1::new();
+        hasher.update(info);
+        if let Some(sig_info) = &self.info {
+            hasher.update(sig_info);
+        }
+        hasher.finalize().to_vec()
+    }
+}
+
+impl Certificate {
+    /// Load certificate in PEM or DER format.
+    pub fn from_bytes(data: &[u8]) -> Result<Certificate, Error> {
+        let cert = match data.starts_with(b"-----BEGIN") {
+            true => x509_cert::Certificate::from_pem(data),
+            false => x509_cert::Certificate::from_der(data),
+        }
+        .map_err(|_| Error::SignInvalidKey("certificate"))?;
+
+        let spki = cert
+            .tbs_certificate
+            .subject_public_key_info
+            .to_der()
+            .map_err(|_| Error::SignInvalidKey("certificate"))?;
+        let public_key = RsaPublicKey::from_public_key_der(&spki)
+            .map_err(|_| Error::SignInvalidKey("public key"))?;
+
+        // Identity is certificate common name, or whole subject when CN is missing
+        let subject = cert.tbs_certificate.subject.to_string();
+        let identity = match subject.split(',').find_map(|attr| attr.strip_prefix("CN=")) {
+            Some(cn) => cn.to_string(),
+            None => subject.clone(),
+        };
+
+        Ok(Certificate {
+            identity,
+            der: cert
+                .to_der()
+                .map_err(|_| Error::SignInvalidKey("certificate"))?,
+            public_key,
+        })
+    }
+
+    /// Load certificate (PEM or DER) from file.
+    pub fn from_file(path: &Path) -> Result<Certificate, Error> {
+        match fs::read(path) {
+            Ok(data) => Self::from_bytes(&data),
+            Err(_) => Err(Error::FileNotFound),
+        }
+    }
+
+    /// Signer identity (certificate common name).
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Certificate in DER format.
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// Check if signature of raw (bencoded) info dictionary was made with certificate key.
+    pub fn verify(&self, signature: &Signature, info: &[u8]) -> bool {
+        self.public_key
+            .verify(
+                Pkcs1v15Sign::new::<Sha1>(),
+                &signature.digest(info),
+                &signature.signature,
+            )
+            .is_ok()
+    }
+}
+
+impl Signer {
+    /// Create signer from PEM encoded private key (PKCS#8 or PKCS#1) and certificate.
+    pub fn from_pem(key: &str, certificate: &str) -> Result<Signer, Error> {
+        let key = RsaPrivateKey::from_pkcs8_pem(key)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(key))
+            .map_err(|_| Error::SignInvalidKey("private key"))?;
+        let certificate = Certificate::from_bytes(certificate.as_bytes())?;
+        if RsaPublicKey::from(&key) != certificate.public_key {
+            return Err(Error::SignInvalidKey(
+                "private key doesn't match certificate",
+            ));
+        }
+
+        Ok(Signer { key, certificate })
+    }
+
+    /// Load private key and certificate (both PEM) from files.
+    pub fn from_files(key: &Path, certificate: &Path) -> Result<Signer, Error> {
+        match (fs::read_to_string(key), fs::read_to_string(certificate)) {
+            (Ok(key), Ok(certificate)) => Self::from_pem(&key, &certificate),
+            _ => Err(Error::FileNotFound),
+        }
+    }
+
+    /// Signer certificate.
+    pub fn certificate(&self) -> &Certificate {
+        &self.certificate
+    }
+
+    /// Sign raw (bencoded) info dictionary. Certificate is embedded in signature, so clients can
+    /// check torrent integrity without having it.
+    pub fn sign(&self, info: &[u8]) -> Result<Signature, Error> {
+        let mut signature = Signature {
+            identity: self.certificate.identity.clone(),
+            certificate: Some(self.certificate.der.clone()),
+            info: None,
+            signature: vec![],
+        };
+        signature.signature = self
+            .key
+            .sign(Pkcs1v15Sign::new::<Sha1>(), &signature.digest(info))
+            .map_err(|_| Error::SignInvalidKey("private key"))?;
+
+        Ok(signature)
+    }
+}
+
+/// Private key is never printed.
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signer")
+            .field("certificate", &self.certificate)
+            .finish_non_exhaustive()
+    }
+}
@@ -6,99 +6,456 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::commands::TrackerCmd;
-use crate::constants::{PEER_ID_SIZE, PORT};
-use crate::{Metainfo, TrackerResp};
+use crate::commands::TransferStats;
+use crate::constants::{HASH_SIZE, PEER_ID_SIZE, PORT};
+use crate::{
+    utils, Error, Metainfo, PeerSource, PeerSourceEvent, PeerSourceFuture, Proxy, ScrapeResp,
+    TrackerResp, UdpTrackerClient,
+};
+use rand::Rng;
 use reqwest::Response;
-use tokio::sync::mpsc;
+use std::cmp::{max, min};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::sync::watch;
 use tokio::time;
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 use url::form_urlencoded;
 
 const DELAY_MS: u64 = 1000;
+const MAX_DELAY_MS: u64 = 30 * 60 * 1000;
+const MIN_INTERVAL_SEC: u64 = 30;
+const STOP_TIMEOUT_SEC: u64 = 5;
+const NUM_WANT: i32 = 20;
+/// Tracker that never responded is abandoned after this many failures.
+const MAX_FAILURES: u32 = 5;
 
-/// Tracker client.
-#[derive(Clone, Debug)]
+/// Tracker client, working as [`PeerSource`] for [`Session`](crate::Session).
+///
+/// Announce is repeated in intervals requested by tracker, or earlier (but not more often than
+/// "min interval") when session need more peers. If tracker respond with failure, new request
+/// is made after exponentially growing delay.
+#[derive(Debug)]
 pub struct TrackerClient {
     own_id: [u8; PEER_ID_SIZE],
     metainfo: Metainfo,
-    tracker_ch: mpsc::Sender<TrackerCmd>,
+    port: u16,
+    /// Address reachable from internet (e.g. port mapped on gateway)
+    external_addr: Option<SocketAddr>,
+    http_client: reqwest::Client,
+    /// HTTP announces go through proxy, so local addresses are not revealed to tracker
+    proxied: bool,
+    /// Local addresses reachable from internet, found once on creation
+    global_ips: (Option<Ipv4Addr>, Option<Ipv6Addr>),
+    udp_client: Option<UdpTrackerClient>,
+    key: u32,
+    tracker_id: Option<String>,
+    stats_ch: watch::Receiver<TransferStats>,
+    event: AnnounceEvent,
+    /// Regular announces are send with "paused" event
+    paused: bool,
+    next_announce: Instant,
+    last_announce: Option<Instant>,
+    min_interval: Duration,
+    failures: u32,
+    abandoned: bool,
+}
+
+/// Parameters of announce request, common for HTTP and UDP trackers.
+#[derive(PartialEq, Clone, Debug)]
+pub struct AnnounceParams {
+    /// SHA-1 hash of info section from metainfo file
+    pub info_hash: [u8; HASH_SIZE],
+    /// Own peer ID
+    pub peer_id: [u8; PEER_ID_SIZE],
+    /// Port on which client is listening
+    pub port: u16,
+    /// Total amount of uploaded bytes
+    pub uploaded: u64,
+    /// Total amount of downloaded bytes
+    pub downloaded: u64,
+    /// Number of bytes client still has to download
+    pub left: u64,
+    /// Announce event
+    pub event: AnnounceEvent,
+    /// Number of peers that client would like to receive
+    pub num_want: i32,
+    /// Random value, that allow tracker to identify client if its IP address change
+    pub key: u32,
+    /// Tracker ID received in previous announce
+    pub tracker_id: Option<String>,
+    /// Own IPv4 address, announced so tracker can pass it to peers when announce is send over IPv6
+    pub ipv4: Option<Ipv4Addr>,
+    /// Own IPv6 address, announced so tracker can pass it to peers when announce is send over IPv4
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+/// Announce event. Values are the same as used by UDP tracker protocol (except `Paused`, which
+/// is send to UDP trackers as regular announce).
+#[derive(PartialEq, Clone, Copy, Debug)]
+#[repr(u32)]
+pub enum AnnounceEvent {
+    /// Regular announce, performed in intervals
+    None = 0,
+    /// Download was completed
+    Completed = 1,
+    /// First request to tracker
+    Started = 2,
+    /// Client is shutting down gracefully
+    Stopped = 3,
+    /// Client is partial seed, it doesn't download anymore
+    /// ([BEP21](https://www.bittorrent.org/beps/bep_0021.html))
+    Paused = 4,
+}
+
+impl AnnounceEvent {
+    /// Return event name as used by HTTP trackers (empty for regular announce).
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnnounceEvent::None => "",
+            AnnounceEvent::Completed => "completed",
+            AnnounceEvent::Started => "started",
+            AnnounceEvent::Stopped => "stopped",
+            AnnounceEvent::Paused => "paused",
+        }
+    }
 }
 
 impl TrackerClient {
-    /// Create new tracker client
+    /// Create new tracker client. Transfer statistics for announces are taken from `stats_ch`.
     pub fn new(
         own_id: &[u8; PEER_ID_SIZE],
         metainfo: Metainfo,
-        tracker_ch: mpsc::Sender<TrackerCmd>,
+        stats_ch: watch::Receiver<TransferStats>,
     ) -> TrackerClient {
         TrackerClient {
             own_id: *own_id,
             metainfo,
-            tracker_ch,
+            port: PORT,
+            external_addr: None,
+            http_client: reqwest::Client::new(),
+            proxied: false,
+            global_ips: Self::global_ips(),
+            udp_client: None,
+            key: rand::thread_rng().gen(),
+            tracker_id: None,
+            stats_ch,
+            event: AnnounceEvent::Started,
+            paused: false,
+            next_announce: Instant::now(),
+            last_announce: None,
+            min_interval: Duration::from_secs(MIN_INTERVAL_SEC),
+            failures: 0,
+            abandoned: false,
         }
     }
 
-    /// Connect to tracker and wait for response.
+    /// Wait for next announce time and send request. State is updated only when request is
+    /// finished, so interrupted announce is simply repeated.
+    async fn next_announce(&mut self) -> PeerSourceEvent {
+        time::sleep_until(self.next_announce).await;
+
+        match self.send_announce(self.event).await {
+            Ok(resp) => {
+                let interval = max(resp.interval(), MIN_INTERVAL_SEC);
+                self.min_interval = Duration::from_secs(min(
+                    max(
+                        resp.min_interval().unwrap_or(MIN_INTERVAL_SEC),
+                        MIN_INTERVAL_SEC,
+                    ),
+                    interval,
+                ));
+                if let Some(tracker_id) = resp.tracker_id() {
+                    self.tracker_id = Some(tracker_id.clone());
+                }
+
+                // Session paused before "started" was answered, so "paused" follows at once
+                let paused_now = self.paused && self.event == AnnounceEvent::Started;
+                self.event = match self.paused {
+                    true => AnnounceEvent::Paused,
+                    false => AnnounceEvent::None,
+                };
+                self.failures = 0;
+                self.last_announce = Some(Instant::now());
+                self.next_announce = match paused_now {
+                    true => Instant::now(),
+                    false => Instant::now() + Duration::from_secs(interval),
+                };
+                PeerSourceEvent::TrackerResp(resp)
+            }
+            Err(e) => {
+                self.failures += 1;
+                self.next_announce = Instant::now() + Self::retry_delay(self.failures);
+
+                // Unreachable tracker shouldn't be retried for the whole session, when peers
+                // can be found by other means
+                if self.last_announce.is_none() && self.failures >= MAX_FAILURES {
+                    self.abandoned = true;
+                    return PeerSourceEvent::Fail(format!("{} (giving up)", e));
+                }
+
+                PeerSourceEvent::Fail(e.to_string())
+            }
+        }
+    }
+
+    /// Return scrape URL derived from announce URL (see
+    /// [BEP48](https://www.bittorrent.org/beps/bep_0048.html)), or `None` if tracker doesn't
+    /// support scrape convention. UDP trackers use the same URL for both requests.
     ///
-    /// If tracker respond with failure caller is informed and new connection is made after
-    /// DELAY_MS ms.
-    pub async fn run(&mut self) {
-        let params = [
-            ("peer_id", String::from_utf8(self.own_id.to_vec()).unwrap()),
-            ("port", PORT.to_string()),
-            ("uploaded", "0".to_string()),
-            ("downloaded", "0".to_string()),
-            ("left", self.metainfo.total_length().to_string()),
-            ("event", "started".to_string()),
-            ("numwant", "20".to_string()),
-        ];
+    /// # Example
+    /// ```
+    /// use rdest::TrackerClient;
+    ///
+    /// assert_eq!(
+    ///     TrackerClient::scrape_url("http://example.com/x/announce.php?key=1"),
+    ///     Some("http://example.com/x/scrape.php?key=1".to_string())
+    /// );
+    /// assert_eq!(TrackerClient::scrape_url("http://example.com/a"), None);
+    /// ```
+    pub fn scrape_url(announce: &str) -> Option<String> {
+        if announce.starts_with("udp://") {
+            return Some(announce.to_string());
+        }
 
-        let client = reqwest::Client::new();
-        let url = &Self::create_url(&self.metainfo);
+        let pos = announce.rfind('/')? + 1;
+        match announce[pos..].starts_with("announce") {
+            true => {
+                Some(announce[..pos].to_string() + "scrape" + &announce[pos + "announce".len()..])
+            }
+            false => None,
+        }
+    }
 
-        loop {
-            match Self::parse_resp(client.get(url).query(&params).send().await).await {
-                Ok(resp) => {
-                    self.send_cmd(TrackerCmd::TrackerResp(resp)).await;
-                    break;
+    /// Request swarm statistics for many torrents, announced on the same tracker.
+    pub async fn scrape(
+        announce: &str,
+        info_hashes: &[[u8; HASH_SIZE]],
+    ) -> Result<ScrapeResp, Error> {
+        let url = Self::scrape_url(announce).ok_or(Error::TrackerScrapeUnsupported)?;
+        if url.starts_with("udp://") {
+            return UdpTrackerClient::new(&url)?.scrape(info_hashes).await;
+        }
+
+        let mut url = url;
+        for info_hash in info_hashes.iter() {
+            url = Self::create_url(&url, info_hash);
+        }
+
+        match reqwest::get(url).await {
+            Ok(resp) => {
+                if !resp.status().is_success() {
+                    return Err(Error::TrackerRespFail(resp.status().to_string()));
                 }
-                Err(e) => {
-                    self.send_cmd(TrackerCmd::Fail(e)).await;
-                    time::sleep(Duration::from_millis(DELAY_MS)).await;
+
+                match resp.bytes().await {
+                    Ok(body) => ScrapeResp::from_bencode(body.as_ref()),
+                    Err(e) => Err(Error::TrackerRespFail(e.to_string())),
                 }
             }
+            Err(e) => Err(Error::TrackerRespFail(e.to_string())),
+        }
+    }
+
+    fn retry_delay(failures: u32) -> Duration {
+        let delay = DELAY_MS.saturating_mul(2u64.saturating_pow(failures - 1));
+        Duration::from_millis(min(delay, MAX_DELAY_MS))
+    }
+
+    async fn send_announce(&mut self, event: AnnounceEvent) -> Result<TrackerResp, Error> {
+        let stats = *self.stats_ch.borrow();
+        let (ipv4, ipv6) = self.own_ips();
+        let params = AnnounceParams {
+            info_hash: *self.metainfo.info_hash(),
+            peer_id: self.own_id,
+            port: self.external_addr.map_or(self.port, |addr| addr.port()),
+            uploaded: stats.uploaded,
+            downloaded: stats.downloaded,
+            left: stats.left,
+            event,
+            num_want: NUM_WANT,
+            key: self.key,
+            tracker_id: self.tracker_id.clone(),
+            ipv4,
+            ipv6,
+        };
+
+        let url = self.metainfo.tracker_url();
+        if url.starts_with("udp://") {
+            if self.udp_client.is_none() {
+                self.udp_client = Some(UdpTrackerClient::new(url)?);
+            }
+
+            match &mut self.udp_client {
+                Some(udp_client) => udp_client.announce(&params).await,
+                None => Err(Error::TrackerInvalidUrl(url.clone())),
+            }
+        } else {
+            Self::announce_http(&self.http_client, url, &params).await
         }
     }
 
-    async fn parse_resp(resp: Result<Response, reqwest::Error>) -> Result<TrackerResp, String> {
+    /// Own addresses announced to tracker. External address (e.g. from port mapping) is preferred
+    /// over local one, and local ones are not announced through proxy.
+    fn own_ips(&self) -> (Option<Ipv4Addr>, Option<Ipv6Addr>) {
+        if self.proxied {
+            return (None, None);
+        }
+
+        let (mut ipv4, mut ipv6) = self.global_ips;
+        match self.external_addr.map(|addr| addr.ip()) {
+            Some(IpAddr::V4(ip)) => ipv4 = Some(ip),
+            Some(IpAddr::V6(ip)) => ipv6 = Some(ip),
+            None => (),
+        }
+
+        (ipv4, ipv6)
+    }
+
+    /// Local addresses are announced only if they are reachable from internet. Private ones
+    /// (e.g. behind NAT) are useless for peers outside of local network.
+    fn global_ips() -> (Option<Ipv4Addr>, Option<Ipv6Addr>) {
+        let ipv4 = match utils::local_ip(false) {
+            Some(IpAddr::V4(ip)) if utils::is_global(IpAddr::V4(ip)) => Some(ip),
+            _ => None,
+        };
+        let ipv6 = match utils::local_ip(true) {
+            Some(IpAddr::V6(ip)) if utils::is_global(IpAddr::V6(ip)) => Some(ip),
+            _ => None,
+        };
+
+        (ipv4, ipv6)
+    }
+
+    async fn announce_http(
+        client: &reqwest::Client,
+        url: &str,
+        params: &AnnounceParams,
+    ) -> Result<TrackerResp, Error> {
+        let mut query = vec![
+            (
+                "peer_id",
+                String::from_utf8(params.peer_id.to_vec()).unwrap(),
+            ),
+            ("port", params.port.to_string()),
+            ("uploaded", params.uploaded.to_string()),
+            ("downloaded", params.downloaded.to_string()),
+            ("left", params.left.to_string()),
+            ("numwant", params.num_want.to_string()),
+            ("compact", "1".to_string()),
+            ("key", format!("{:08x}", params.key)),
+        ];
+        if params.event != AnnounceEvent::None {
+            query.push(("event", params.event.name().to_string()));
+        }
+        if let Some(tracker_id) = &params.tracker_id {
+            query.push(("trackerid", tracker_id.clone()));
+        }
+        if let Some(ipv4) = params.ipv4 {
+            query.push(("ipv4", ipv4.to_string()));
+        }
+        if let Some(ipv6) = params.ipv6 {
+            query.push(("ipv6", ipv6.to_string()));
+        }
+
+        let url = Self::create_url(url, &params.info_hash);
+        Self::parse_resp(client.get(url).query(&query).send().await).await
+    }
+
+    async fn parse_resp(resp: Result<Response, reqwest::Error>) -> Result<TrackerResp, Error> {
         match resp {
             Ok(resp) => {
                 if !resp.status().is_success() {
-                    return Err(resp.status().to_string());
+                    return Err(Error::TrackerRespFail(resp.status().to_string()));
                 }
 
                 match resp.bytes().await {
-                    Ok(body) => match TrackerResp::from_bencode(body.as_ref()) {
-                        Ok(data) => Ok(data),
-                        Err(e) => Err(e.to_string()),
-                    },
-                    Err(e) => Err(e.to_string()),
+                    Ok(body) => TrackerResp::from_bencode(body.as_ref()),
+                    Err(e) => Err(Error::TrackerRespFail(e.to_string())),
                 }
             }
-            Err(e) => Err(e.to_string()),
+            Err(e) => Err(Error::TrackerRespFail(e.to_string())),
+        }
+    }
+
+    fn create_url(url: &str, info_hash: &[u8; HASH_SIZE]) -> String {
+        let info_hash: String = form_urlencoded::byte_serialize(info_hash).collect();
+        let separator = match url.contains('?') {
+            true => "&",
+            false => "?",
+        };
+        url.to_string() + separator + "info_hash=" + info_hash.as_str()
+    }
+}
+
+impl PeerSource for TrackerClient {
+    fn name(&self) -> String {
+        "tracker".to_string()
+    }
+
+    fn set_port(&mut self, port: u16) {
+        self.port = port;
+    }
+
+    fn set_proxy(&mut self, proxy: &Proxy) -> bool {
+        let client = proxy
+            .reqwest_proxy()
+            .and_then(|proxy| reqwest::Client::builder().proxy(proxy).build().ok());
+        if let Some(client) = client {
+            self.http_client = client;
+            self.proxied = true;
+        }
+
+        // UDP trackers are always contacted directly
+        self.proxied && !self.metainfo.tracker_url().starts_with("udp://")
+    }
+
+    fn external_addr(&mut self, addr: SocketAddr) {
+        self.external_addr = Some(addr);
+    }
+
+    fn next(&mut self) -> PeerSourceFuture<'_, Option<PeerSourceEvent>> {
+        Box::pin(async move {
+            match self.abandoned {
+                true => None,
+                false => Some(self.next_announce().await),
+            }
+        })
+    }
+
+    fn announce(&mut self) {
+        if let Some(last_announce) = self.last_announce {
+            self.next_announce = min(self.next_announce, last_announce + self.min_interval);
         }
     }
 
-    async fn send_cmd(&mut self, cmd: TrackerCmd) {
-        self.tracker_ch
-            .send(cmd)
-            .await
-            .expect("Can't communicate to manager");
+    fn completed(&mut self) {
+        // If "started" wasn't delivered yet, "completed" shouldn't be send
+        self.paused = false;
+        if matches!(self.event, AnnounceEvent::None | AnnounceEvent::Paused) {
+            self.event = AnnounceEvent::Completed;
+            self.next_announce = Instant::now();
+        }
     }
 
-    fn create_url(metainfo: &Metainfo) -> String {
-        let info_hash: String = form_urlencoded::byte_serialize(metainfo.info_hash()).collect();
-        metainfo.tracker_url().clone() + "?info_hash=" + info_hash.as_str()
+    fn paused(&mut self) {
+        // Like "completed", it's send after "started"
+        self.paused = true;
+        if self.event == AnnounceEvent::None {
+            self.event = AnnounceEvent::Paused;
+            self.next_announce = Instant::now();
+        }
+    }
+
+    fn stop(&mut self) -> PeerSourceFuture<'_, ()> {
+        Box::pin(async move {
+            // Tracker should be informed only if he knows about this client
+            if self.event != AnnounceEvent::Started {
+                let _ = time::timeout(
+                    Duration::from_secs(STOP_TIMEOUT_SEC),
+                    self.send_announce(AnnounceEvent::Stopped),
+                )
+                .await;
+            }
+        })
     }
 }
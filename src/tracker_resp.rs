@@ -7,26 +7,62 @@
 // except according to those terms.
 
 use crate::bcodec::bvalue::BValue;
-use crate::constants::HASH_SIZE;
+use crate::constants::PEER_ID_SIZE;
 use crate::{BDecoder, Error};
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Size of peer entry in compact IPv4 peer list (see [BEP23](https://www.bittorrent.org/beps/bep_0023.html)).
+pub const COMPACT_PEER_SIZE: usize = 6;
+/// Size of peer entry in compact IPv6 peer list (see [BEP7](https://www.bittorrent.org/beps/bep_0007.html)).
+pub const COMPACT_PEER6_SIZE: usize = 18;
 
 /// Response from the tracker.
 #[derive(PartialEq, Clone, Debug)]
 pub struct TrackerResp {
     interval: u64,
+    min_interval: Option<u64>,
+    tracker_id: Option<String>,
+    warning_message: Option<String>,
+    complete: Option<u64>,
+    incomplete: Option<u64>,
+    external_ip: Option<IpAddr>,
     peers: Vec<PeerAddr>,
 }
 
 #[derive(PartialEq, Clone, Debug)]
 pub struct PeerAddr {
-    ip: String,
-    peer_id: [u8; HASH_SIZE],
-    port: u64,
+    addr: SocketAddr,
+    peer_id: Option<[u8; PEER_ID_SIZE]>,
 }
 
 impl TrackerResp {
+    /// Create response from swarm statistics and addresses of peers, which IDs are unknown.
+    pub(crate) fn from_addrs(
+        interval: u64,
+        complete: u64,
+        incomplete: u64,
+        addrs: Vec<SocketAddr>,
+    ) -> TrackerResp {
+        TrackerResp {
+            interval,
+            min_interval: None,
+            tracker_id: None,
+            warning_message: None,
+            complete: Some(complete),
+            incomplete: Some(incomplete),
+            external_ip: None,
+            peers: addrs
+                .into_iter()
+                .map(|addr| PeerAddr {
+                    addr,
+                    peer_id: None,
+                })
+                .collect(),
+        }
+    }
+
     /// Parse tracker response from [bencoded](https://en.wikipedia.org/wiki/Bencode) string.
     pub fn from_bencode(data: &[u8]) -> Result<TrackerResp, Error> {
         let bvalues = BDecoder::from_array(data)?;
@@ -56,6 +92,12 @@ impl TrackerResp {
 
         let response = TrackerResp {
             interval: Self::find_interval(dict)?,
+            min_interval: Self::find_min_interval(dict),
+            tracker_id: Self::find_string(dict, b"tracker id"),
+            warning_message: Self::find_string(dict, b"warning message"),
+            complete: Self::find_count(dict, b"complete"),
+            incomplete: Self::find_count(dict, b"incomplete"),
+            external_ip: Self::find_external_ip(dict),
             peers: Self::find_peers(dict)?,
         };
 
@@ -78,11 +120,59 @@ impl TrackerResp {
         }
     }
 
+    fn find_min_interval(dict: &HashMap<Vec<u8>, BValue>) -> Option<u64> {
+        match dict.get(&b"min interval".to_vec()) {
+            Some(BValue::Int(min_interval)) => u64::try_from(*min_interval).ok(),
+            _ => None,
+        }
+    }
+
+    fn find_string(dict: &HashMap<Vec<u8>, BValue>, key: &[u8]) -> Option<String> {
+        match dict.get(key) {
+            Some(BValue::ByteStr(value)) => String::from_utf8(value.to_vec()).ok(),
+            _ => None,
+        }
+    }
+
+    fn find_count(dict: &HashMap<Vec<u8>, BValue>, key: &[u8]) -> Option<u64> {
+        match dict.get(key) {
+            Some(BValue::Int(count)) => u64::try_from(*count).ok(),
+            _ => None,
+        }
+    }
+
+    /// External IP is send as 4 or 16 bytes in network byte order (see
+    /// [BEP24](https://www.bittorrent.org/beps/bep_0024.html)).
+    fn find_external_ip(dict: &HashMap<Vec<u8>, BValue>) -> Option<IpAddr> {
+        match dict.get(&b"external ip".to_vec()) {
+            Some(BValue::ByteStr(ip)) => match ip.len() {
+                4 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(ip.as_slice()).ok()?).into()),
+                16 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(ip.as_slice()).ok()?).into()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Peers can be send in dictionary model (list of dictionaries), or in compact model (binary
+    /// string). IPv6 peers are send only in compact model as "peers6" value.
     fn find_peers(dict: &HashMap<Vec<u8>, BValue>) -> Result<Vec<PeerAddr>, Error> {
-        match dict.get(&b"peers".to_vec()) {
-            Some(BValue::List(peers)) => Ok(Self::peer_list(peers)),
-            _ => Err(Error::TrackerIncorrectOrMissing("peers")),
+        let mut peers = match dict.get(&b"peers".to_vec()) {
+            Some(BValue::List(peers)) => Self::peer_list(peers),
+            Some(BValue::ByteStr(peers)) => Self::compact_peer_list(peers, COMPACT_PEER_SIZE)?,
+            None => vec![],
+            _ => return Err(Error::TrackerIncorrectOrMissing("peers")),
+        };
+
+        match dict.get(&b"peers6".to_vec()) {
+            Some(BValue::ByteStr(peers6)) => {
+                peers.append(&mut Self::compact_peer_list(peers6, COMPACT_PEER6_SIZE)?)
+            }
+            None if dict.contains_key(&b"peers".to_vec()) => (),
+            _ => return Err(Error::TrackerIncorrectOrMissing("peers")),
         }
+
+        Ok(peers)
     }
 
     fn peer_list(list: &Vec<BValue>) -> Vec<PeerAddr> {
@@ -91,38 +181,102 @@ impl TrackerResp {
                 BValue::Dict(dict) => Some(dict),
                 _ => None,
             })
-            .filter_map(|dict| {
-                match (
-                    dict.get(&b"ip".to_vec()),
-                    dict.get(&b"peer id".to_vec()),
-                    dict.get(&b"port".to_vec()),
-                ) {
-                    (
-                        Some(BValue::ByteStr(ip)),
-                        Some(BValue::ByteStr(peer_id)),
-                        Some(BValue::Int(port)),
-                    ) => Some((ip, peer_id, port)),
+            .filter_map(
+                |dict| match (dict.get(&b"ip".to_vec()), dict.get(&b"port".to_vec())) {
+                    (Some(BValue::ByteStr(ip)), Some(BValue::Int(port))) => {
+                        Some((ip, dict.get(&b"peer id".to_vec()), port))
+                    }
                     _ => None,
-                }
-            })
+                },
+            )
             .filter_map(|(ip, peer_id, port)| {
+                let peer_id = match peer_id {
+                    Some(BValue::ByteStr(peer_id)) => Some(peer_id.as_slice().try_into().ok()?),
+                    Some(_) => return None,
+                    None => None,
+                };
+
                 match (
-                    String::from_utf8(ip.to_vec()),
-                    peer_id.as_slice().try_into(),
-                    u64::try_from(*port),
+                    String::from_utf8(ip.to_vec()).map(|ip| ip.parse::<IpAddr>()),
+                    u16::try_from(*port),
                 ) {
-                    (Ok(ip), Ok(peer_id), Ok(port)) => Some(PeerAddr { ip, peer_id, port }),
+                    (Ok(Ok(ip)), Ok(port)) => Some(PeerAddr {
+                        addr: SocketAddr::new(ip, port),
+                        peer_id,
+                    }),
                     _ => None,
                 }
             })
             .collect()
     }
 
-    /// Return addresses and peer ID's.
-    pub fn peers(&self) -> Vec<(String, [u8; HASH_SIZE])> {
-        self.peers
-            .iter()
-            .map(|p| (p.ip.clone() + ":" + p.port.to_string().as_str(), p.peer_id))
+    fn compact_peer_list(data: &[u8], entry_size: usize) -> Result<Vec<PeerAddr>, Error> {
+        if !data.len().is_multiple_of(entry_size) {
+            return Err(Error::TrackerIncorrectOrMissing("peers"));
+        }
+
+        Ok(Self::compact_addrs(data, entry_size)
+            .into_iter()
+            .map(|addr| PeerAddr {
+                addr,
+                peer_id: None,
+            })
+            .collect())
+    }
+
+    /// Convert compact peer list (4 or 16 bytes of IP address, followed by 2 bytes of port, both
+    /// in network byte order) to addresses. Incomplete entry at the end is ignored.
+    pub(crate) fn compact_addrs(data: &[u8], entry_size: usize) -> Vec<SocketAddr> {
+        data.chunks_exact(entry_size)
+            .map(|entry| {
+                let port_pos = entry_size - 2;
+                let port = u16::from_be_bytes(entry[port_pos..].try_into().unwrap());
+                let ip: IpAddr = match port_pos {
+                    4 => Ipv4Addr::from(<[u8; 4]>::try_from(&entry[..port_pos]).unwrap()).into(),
+                    _ => Ipv6Addr::from(<[u8; 16]>::try_from(&entry[..port_pos]).unwrap()).into(),
+                };
+                SocketAddr::new(ip, port)
+            })
             .collect()
     }
+
+    /// Return interval (in seconds) that client should wait between regular announces.
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Return minimal announce interval (in seconds), if tracker defined it.
+    pub fn min_interval(&self) -> Option<u64> {
+        self.min_interval
+    }
+
+    /// Return tracker ID, that should be send back in next announces.
+    pub fn tracker_id(&self) -> Option<&String> {
+        self.tracker_id.as_ref()
+    }
+
+    /// Return warning message. Unlike failure reason, response is still valid.
+    pub fn warning_message(&self) -> Option<&String> {
+        self.warning_message.as_ref()
+    }
+
+    /// Return number of peers with the entire file (seeders), if tracker reported it.
+    pub fn complete(&self) -> Option<u64> {
+        self.complete
+    }
+
+    /// Return number of non-seeder peers (leechers), if tracker reported it.
+    pub fn incomplete(&self) -> Option<u64> {
+        self.incomplete
+    }
+
+    /// Return client IP address as seen by the tracker.
+    pub fn external_ip(&self) -> Option<IpAddr> {
+        self.external_ip
+    }
+
+    /// Return addresses and peer ID's (if tracker provided them).
+    pub fn peers(&self) -> Vec<(SocketAddr, Option<[u8; PEER_ID_SIZE]>)> {
+        self.peers.iter().map(|p| (p.addr, p.peer_id)).collect()
+    }
 }
@@ -0,0 +1,755 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::bcodec::bencoder::BEncoder;
+use crate::bcodec::bvalue::BValue;
+use crate::commands::TrackerServerCmd;
+use crate::constants::{HASH_SIZE, PEER_ID_SIZE};
+use crate::hashmap;
+use crate::{AnnounceEvent, Error, ScrapeFile};
+use rand::seq::SliceRandom;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::hash::BuildHasher;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
+use tokio::time::{Duration, Instant};
+
+const CHANNEL_SIZE: usize = 64;
+const INTERVAL_SEC: u64 = 30 * 60;
+const DEFAULT_NUM_WANT: usize = 50;
+const MAX_NUM_WANT: usize = 200;
+const MAX_REQUEST_SIZE: usize = 8192;
+const REQUEST_TIMEOUT_SEC: u64 = 10;
+const LISTEN_BACKLOG: i32 = 1024;
+/// Magic constant identifying UDP connect request, see [BEP15](https://www.bittorrent.org/beps/bep_0015.html).
+const PROTOCOL_ID: u64 = 0x41727101980;
+/// Connection ID is valid for at least one minute (and at most two).
+const CONNECTION_ID_TTL_SEC: u64 = 60;
+const MAX_DATAGRAM_SIZE: usize = 2048;
+const UDP_HEADER_SIZE: usize = 16;
+const UDP_ANNOUNCE_SIZE: usize = 98;
+const MAX_SCRAPE_HASHES: usize = 74;
+const ACTION_CONNECT: u32 = 0;
+const ACTION_ANNOUNCE: u32 = 1;
+const ACTION_SCRAPE: u32 = 2;
+const ACTION_ERROR: u32 = 3;
+
+/// Simple BitTorrent tracker, serving HTTP and UDP ([BEP15](https://www.bittorrent.org/beps/bep_0015.html))
+/// clients on the same port, over IPv4 and IPv6.
+///
+/// Peers are tracked separately for every info hash. Peer that doesn't announce for two intervals
+/// is removed from swarm. IPv6 peers are returned in "peers6" (see
+/// [BEP7](https://www.bittorrent.org/beps/bep_0007.html)).
+pub struct TrackerServer {
+    listener: TcpListener,
+    socket: UdpSocket,
+    listener_v6: Option<TcpListener>,
+    socket_v6: Option<UdpSocket>,
+    interval: Duration,
+    whitelist: Option<HashSet<[u8; HASH_SIZE]>>,
+    swarms: HashMap<[u8; HASH_SIZE], Swarm>,
+    secret: RandomState,
+    started: Instant,
+    tx_ch: mpsc::Sender<TrackerServerCmd>,
+    rx_ch: mpsc::Receiver<TrackerServerCmd>,
+}
+
+#[derive(Debug, Default)]
+struct Swarm {
+    peers: HashMap<[u8; PEER_ID_SIZE], SwarmPeer>,
+    downloaded: u64,
+}
+
+#[derive(Debug)]
+struct SwarmPeer {
+    addr: SocketAddr,
+    /// Address from other IP family, announced with "ipv4" or "ipv6" parameter
+    alt_addr: Option<SocketAddr>,
+    left: u64,
+    /// Partial seed (BEP21)
+    paused: bool,
+    last_seen: Instant,
+}
+
+impl SwarmPeer {
+    fn upload_only(&self) -> bool {
+        self.left == 0 || self.paused
+    }
+}
+
+#[derive(Debug)]
+struct AnnounceReq {
+    info_hash: [u8; HASH_SIZE],
+    peer_id: [u8; PEER_ID_SIZE],
+    addr: SocketAddr,
+    alt_addr: Option<SocketAddr>,
+    left: u64,
+    event: AnnounceEvent,
+    num_want: usize,
+}
+
+#[derive(Debug)]
+struct AnnounceReply {
+    complete: u64,
+    incomplete: u64,
+    peers: Vec<([u8; PEER_ID_SIZE], SocketAddr)>,
+}
+
+impl Swarm {
+    fn complete(&self) -> u64 {
+        self.peers.values().filter(|peer| peer.left == 0).count() as u64
+    }
+
+    fn incomplete(&self) -> u64 {
+        self.peers.values().filter(|peer| peer.left != 0).count() as u64
+    }
+
+    fn scrape_file(&self) -> ScrapeFile {
+        ScrapeFile {
+            complete: self.complete(),
+            downloaded: self.downloaded,
+            incomplete: self.incomplete(),
+            name: None,
+        }
+    }
+}
+
+impl TrackerServer {
+    /// Bind TCP and UDP sockets to the same port, on all IPv4 and IPv6 addresses (IPv6 is
+    /// skipped, if host doesn't support it). If `whitelist` is set, only listed torrents are
+    /// tracked.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use rdest::TrackerServer;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let mut tracker = TrackerServer::bind(8000, None).await.unwrap();
+    /// tracker.run().await;
+    /// # }
+    /// ```
+    pub async fn bind(
+        port: u16,
+        whitelist: Option<HashSet<[u8; HASH_SIZE]>>,
+    ) -> Result<TrackerServer, Error> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))
+            .await
+            .or(Err(Error::TrackerServerBind(port)))?;
+        // If port was chosen by OS, UDP socket should use the same one
+        let port = listener
+            .local_addr()
+            .or(Err(Error::TrackerServerBind(port)))?
+            .port();
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))
+            .await
+            .or(Err(Error::TrackerServerBind(port)))?;
+        let (listener_v6, socket_v6) = match Self::bind_v6(port) {
+            Ok((listener, socket)) => (Some(listener), Some(socket)),
+            Err(_) => (None, None),
+        };
+        let (tx_ch, rx_ch) = mpsc::channel(CHANNEL_SIZE);
+
+        Ok(TrackerServer {
+            listener,
+            socket,
+            listener_v6,
+            socket_v6,
+            interval: Duration::from_secs(INTERVAL_SEC),
+            whitelist,
+            swarms: HashMap::new(),
+            secret: RandomState::new(),
+            started: Instant::now(),
+            tx_ch,
+            rx_ch,
+        })
+    }
+
+    /// IPv6 sockets don't accept IPv4 traffic (as IPv4-mapped addresses), so they can share port
+    /// with IPv4 ones.
+    fn bind_v6(port: u16) -> io::Result<(TcpListener, UdpSocket)> {
+        let tcp = Self::socket_v6(port, Type::STREAM, Protocol::TCP)?;
+        tcp.listen(LISTEN_BACKLOG)?;
+        let udp = Self::socket_v6(port, Type::DGRAM, Protocol::UDP)?;
+
+        Ok((
+            TcpListener::from_std(tcp.into())?,
+            UdpSocket::from_std(udp.into())?,
+        ))
+    }
+
+    fn socket_v6(port: u16, kind: Type, protocol: Protocol) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::IPV6, kind, Some(protocol))?;
+        socket.set_only_v6(true)?;
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+        Ok(socket)
+    }
+
+    /// Change announce interval requested from clients (default is 30 minutes).
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Return address on which tracker is listening.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener
+            .local_addr()
+            .or(Err(Error::SocketNotAvailable))
+    }
+
+    /// Return `true` if tracker listens also on IPv6.
+    pub fn is_dual_stack(&self) -> bool {
+        self.listener_v6.is_some()
+    }
+
+    /// Serve requests until task is cancelled.
+    pub async fn run(&mut self) {
+        let mut purge_timer = time::interval(self.interval);
+        let mut buff = vec![0; MAX_DATAGRAM_SIZE];
+        let mut buff_v6 = vec![0; MAX_DATAGRAM_SIZE];
+
+        loop {
+            tokio::select! {
+                Ok((stream, addr)) = self.listener.accept() => self.spawn_http_handler(stream, addr),
+                Ok((stream, addr)) = Self::accept_v6(&self.listener_v6) => self.spawn_http_handler(stream, addr),
+                Ok((len, addr)) = self.socket.recv_from(&mut buff) => {
+                    if let Some(resp) = self.handle_datagram(&buff[..len], addr) {
+                        let _ = self.socket.send_to(&resp, addr).await;
+                    }
+                }
+                Ok((len, addr)) = Self::recv_v6(&self.socket_v6, &mut buff_v6) => {
+                    if let Some(resp) = self.handle_datagram(&buff_v6[..len], addr) {
+                        if let Some(socket) = &self.socket_v6 {
+                            let _ = socket.send_to(&resp, addr).await;
+                        }
+                    }
+                }
+                Some(cmd) = self.rx_ch.recv() => self.handle_cmd(cmd),
+                _ = purge_timer.tick() => self.purge_peers(),
+            }
+        }
+    }
+
+    /// Wait for IPv6 HTTP connection (forever, if IPv6 is not available).
+    async fn accept_v6(listener: &Option<TcpListener>) -> io::Result<(TcpStream, SocketAddr)> {
+        match listener {
+            Some(listener) => listener.accept().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Wait for IPv6 datagram (forever, if IPv6 is not available).
+    async fn recv_v6(
+        socket: &Option<UdpSocket>,
+        buff: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr)> {
+        match socket {
+            Some(socket) => socket.recv_from(buff).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn spawn_http_handler(&self, stream: TcpStream, addr: SocketAddr) {
+        let tx_ch = self.tx_ch.clone();
+        tokio::spawn(async move {
+            let _ = time::timeout(
+                Duration::from_secs(REQUEST_TIMEOUT_SEC),
+                Self::handle_http(stream, addr, tx_ch),
+            )
+            .await;
+        });
+    }
+
+    /// Read single HTTP request, pass it to tracker loop, and write back the response.
+    async fn handle_http(
+        mut stream: TcpStream,
+        addr: SocketAddr,
+        tx_ch: mpsc::Sender<TrackerServerCmd>,
+    ) -> Result<(), Error> {
+        let mut buff = vec![];
+        while !buff.windows(4).any(|w| w == b"\r\n\r\n") {
+            if buff.len() > MAX_REQUEST_SIZE {
+                return Err(Error::MsgToLarge);
+            }
+
+            let mut chunk = [0; 1024];
+            match stream.read(&mut chunk).await {
+                Ok(0) => return Err(Error::ConnectionClosed),
+                Ok(n) => buff.extend_from_slice(&chunk[..n]),
+                Err(_) => return Err(Error::CantReadFromSocket),
+            }
+        }
+
+        let request = String::from_utf8_lossy(&buff).to_string();
+        let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+        let resp = match (request_line.next(), request_line.next()) {
+            (Some("GET"), Some(target)) => {
+                let (resp_tx, resp_rx) = oneshot::channel();
+                let cmd = TrackerServerCmd::HttpRequest {
+                    target: target.to_string(),
+                    addr,
+                    resp_ch: resp_tx,
+                };
+                tx_ch.send(cmd).await.or(Err(Error::ConnectionClosed))?;
+                match resp_rx.await {
+                    Ok(Some(body)) => Self::http_resp("200 OK", &body),
+                    _ => Self::http_resp("404 Not Found", b""),
+                }
+            }
+            _ => Self::http_resp("400 Bad Request", b""),
+        };
+
+        stream
+            .write_all(&resp)
+            .await
+            .or(Err(Error::SocketNotAvailable))?;
+        let _ = stream.shutdown().await;
+        Ok(())
+    }
+
+    fn http_resp(status: &str, body: &[u8]) -> Vec<u8> {
+        let mut resp = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        )
+        .into_bytes();
+        resp.extend_from_slice(body);
+        resp
+    }
+
+    fn handle_cmd(&mut self, cmd: TrackerServerCmd) {
+        match cmd {
+            TrackerServerCmd::HttpRequest {
+                target,
+                addr,
+                resp_ch,
+            } => {
+                let _ = resp_ch.send(self.handle_http_request(&target, addr));
+            }
+        }
+    }
+
+    fn handle_http_request(&mut self, target: &str, addr: SocketAddr) -> Option<Vec<u8>> {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let params = Self::parse_query(query);
+
+        if path.ends_with("/announce") {
+            Some(self.http_announce(&params, addr))
+        } else if path.ends_with("/scrape") {
+            Some(self.http_scrape(&params))
+        } else {
+            None
+        }
+    }
+
+    fn http_announce(&mut self, params: &[(String, Vec<u8>)], addr: SocketAddr) -> Vec<u8> {
+        let req = match Self::announce_req_from_query(params, addr) {
+            Ok(req) => req,
+            Err(reason) => return Self::failure(reason),
+        };
+
+        let reply = match self.announce(&req) {
+            Ok(reply) => reply,
+            Err(reason) => return Self::failure(reason),
+        };
+
+        let mut dict = hashmap![
+            b"interval".to_vec() => BValue::Int(self.interval.as_secs() as i64),
+            b"complete".to_vec() => BValue::Int(reply.complete as i64),
+            b"incomplete".to_vec() => BValue::Int(reply.incomplete as i64)
+        ];
+
+        // Compact model is used by default (see BEP23)
+        match Self::param(params, "compact") {
+            Some(b"0") => {
+                let peers = reply
+                    .peers
+                    .iter()
+                    .map(|(peer_id, addr)| {
+                        BValue::Dict(hashmap![
+                            b"peer id".to_vec() => BValue::ByteStr(peer_id.to_vec()),
+                            b"ip".to_vec() => BValue::ByteStr(addr.ip().to_string().into_bytes()),
+                            b"port".to_vec() => BValue::Int(addr.port() as i64)
+                        ])
+                    })
+                    .collect();
+                dict.insert(b"peers".to_vec(), BValue::List(peers));
+            }
+            _ => {
+                let peers6 = Self::compact_peers(&reply.peers, true);
+                if !peers6.is_empty() {
+                    dict.insert(b"peers6".to_vec(), BValue::ByteStr(peers6));
+                }
+                dict.insert(
+                    b"peers".to_vec(),
+                    BValue::ByteStr(Self::compact_peers(&reply.peers, false)),
+                );
+            }
+        }
+
+        BEncoder::new().add_dict(&dict).encode().clone()
+    }
+
+    fn http_scrape(&mut self, params: &[(String, Vec<u8>)]) -> Vec<u8> {
+        let info_hashes: Vec<[u8; HASH_SIZE]> = params
+            .iter()
+            .filter(|(key, _)| key == "info_hash")
+            .filter_map(|(_, value)| value.as_slice().try_into().ok())
+            .collect();
+
+        let files = self
+            .scrape(&info_hashes)
+            .into_iter()
+            .map(|(info_hash, file)| {
+                let file = hashmap![
+                    b"complete".to_vec() => BValue::Int(file.complete as i64),
+                    b"downloaded".to_vec() => BValue::Int(file.downloaded as i64),
+                    b"incomplete".to_vec() => BValue::Int(file.incomplete as i64)
+                ];
+                (info_hash.to_vec(), BValue::Dict(file))
+            })
+            .collect();
+
+        let dict = hashmap![b"files".to_vec() => BValue::Dict(files)];
+        BEncoder::new().add_dict(&dict).encode().clone()
+    }
+
+    fn failure(reason: &str) -> Vec<u8> {
+        let dict =
+            hashmap![b"failure reason".to_vec() => BValue::ByteStr(reason.as_bytes().to_vec())];
+        BEncoder::new().add_dict(&dict).encode().clone()
+    }
+
+    fn announce_req_from_query(
+        params: &[(String, Vec<u8>)],
+        addr: SocketAddr,
+    ) -> Result<AnnounceReq, &'static str> {
+        let info_hash = Self::param(params, "info_hash")
+            .and_then(|value| value.try_into().ok())
+            .ok_or("invalid info_hash")?;
+        let peer_id = Self::param(params, "peer_id")
+            .and_then(|value| value.try_into().ok())
+            .ok_or("invalid peer_id")?;
+        let port = Self::param_num::<u16>(params, "port").ok_or("invalid port")?;
+        let left = Self::param_num::<u64>(params, "left").ok_or("invalid left")?;
+        let event = match Self::param(params, "event") {
+            None | Some(b"") => AnnounceEvent::None,
+            Some(b"started") => AnnounceEvent::Started,
+            Some(b"completed") => AnnounceEvent::Completed,
+            Some(b"stopped") => AnnounceEvent::Stopped,
+            Some(b"paused") => AnnounceEvent::Paused,
+            Some(_) => return Err("invalid event"),
+        };
+        let num_want = Self::param_num::<usize>(params, "numwant").unwrap_or(DEFAULT_NUM_WANT);
+        let addr = SocketAddr::new(addr.ip().to_canonical(), port);
+
+        Ok(AnnounceReq {
+            info_hash,
+            peer_id,
+            addr,
+            alt_addr: Self::alt_addr(params, addr),
+            left,
+            event,
+            num_want,
+        })
+    }
+
+    /// Address from other IP family (see BEP7). Value is IP (announced port is used) or IP with
+    /// port.
+    fn alt_addr(params: &[(String, Vec<u8>)], addr: SocketAddr) -> Option<SocketAddr> {
+        let value = match addr.is_ipv4() {
+            true => Self::param(params, "ipv6")?,
+            false => Self::param(params, "ipv4")?,
+        };
+        let value = std::str::from_utf8(value).ok()?;
+        let alt_addr = match value.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, addr.port()),
+            Err(_) => value.parse::<SocketAddr>().ok()?,
+        };
+
+        match alt_addr.is_ipv4() != addr.is_ipv4() && !alt_addr.ip().is_unspecified() {
+            true => Some(alt_addr),
+            false => None,
+        }
+    }
+
+    fn param<'a>(params: &'a [(String, Vec<u8>)], key: &str) -> Option<&'a [u8]> {
+        params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    fn param_num<T: std::str::FromStr>(params: &[(String, Vec<u8>)], key: &str) -> Option<T> {
+        std::str::from_utf8(Self::param(params, key)?)
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// Split query into key-value pairs. Values are percent-decoded to raw bytes, because
+    /// info hash and peer ID don't have to be valid UTF-8.
+    fn parse_query(query: &str) -> Vec<(String, Vec<u8>)> {
+        query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (
+                    String::from_utf8_lossy(&Self::percent_decode(key)).to_string(),
+                    Self::percent_decode(value),
+                )
+            })
+            .collect()
+    }
+
+    fn percent_decode(data: &str) -> Vec<u8> {
+        let data = data.as_bytes();
+        let mut out = vec![];
+        let mut i = 0;
+        while i < data.len() {
+            let hex = data
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match (data[i], hex) {
+                (b'%', Some(byte)) => {
+                    out.push(byte);
+                    i += 3;
+                }
+                (b'+', _) => {
+                    out.push(b' ');
+                    i += 1;
+                }
+                (byte, _) => {
+                    out.push(byte);
+                    i += 1;
+                }
+            }
+        }
+
+        out
+    }
+
+    fn compact_peers(peers: &[([u8; PEER_ID_SIZE], SocketAddr)], ipv6: bool) -> Vec<u8> {
+        let mut data = vec![];
+        for (_, addr) in peers.iter() {
+            match addr.ip() {
+                IpAddr::V4(ip) if !ipv6 => data.extend_from_slice(&ip.octets()),
+                IpAddr::V6(ip) if ipv6 => data.extend_from_slice(&ip.octets()),
+                _ => continue,
+            }
+            data.extend_from_slice(&addr.port().to_be_bytes());
+        }
+
+        data
+    }
+
+    /// Handle UDP request (see [BEP15](https://www.bittorrent.org/beps/bep_0015.html)). Invalid
+    /// datagrams are silently dropped.
+    fn handle_datagram(&mut self, data: &[u8], addr: SocketAddr) -> Option<Vec<u8>> {
+        if data.len() < UDP_HEADER_SIZE {
+            return None;
+        }
+
+        let connection_id = u64::from_be_bytes(data[0..8].try_into().unwrap());
+        let action = u32::from_be_bytes(data[8..12].try_into().unwrap());
+        let transaction_id = &data[12..16];
+
+        let mut resp = vec![];
+        resp.extend_from_slice(&action.to_be_bytes());
+        resp.extend_from_slice(transaction_id);
+
+        match action {
+            ACTION_CONNECT if connection_id == PROTOCOL_ID => {
+                resp.extend_from_slice(&self.connection_id(addr, 0).to_be_bytes());
+            }
+            ACTION_ANNOUNCE | ACTION_SCRAPE
+                if !self.is_valid_connection_id(connection_id, addr) =>
+            {
+                return Some(Self::udp_error(transaction_id, "invalid connection id"));
+            }
+            ACTION_ANNOUNCE if data.len() >= UDP_ANNOUNCE_SIZE => {
+                let req = Self::announce_req_from_datagram(data, addr);
+                match self.announce(&req) {
+                    Ok(reply) => {
+                        resp.extend_from_slice(&(self.interval.as_secs() as u32).to_be_bytes());
+                        resp.extend_from_slice(&(reply.incomplete as u32).to_be_bytes());
+                        resp.extend_from_slice(&(reply.complete as u32).to_be_bytes());
+                        resp.extend_from_slice(&Self::compact_peers(&reply.peers, addr.is_ipv6()));
+                    }
+                    Err(reason) => return Some(Self::udp_error(transaction_id, reason)),
+                }
+            }
+            ACTION_SCRAPE => {
+                let info_hashes: Vec<[u8; HASH_SIZE]> = data[UDP_HEADER_SIZE..]
+                    .chunks_exact(HASH_SIZE)
+                    .take(MAX_SCRAPE_HASHES)
+                    .map(|info_hash| info_hash.try_into().unwrap())
+                    .collect();
+                let files = self.scrape(&info_hashes);
+
+                // Every requested info hash should be answered, in the same order
+                for info_hash in info_hashes.iter() {
+                    let (complete, downloaded, incomplete) = match files.get(info_hash) {
+                        Some(file) => (file.complete, file.downloaded, file.incomplete),
+                        None => (0, 0, 0),
+                    };
+                    resp.extend_from_slice(&(complete as u32).to_be_bytes());
+                    resp.extend_from_slice(&(downloaded as u32).to_be_bytes());
+                    resp.extend_from_slice(&(incomplete as u32).to_be_bytes());
+                }
+            }
+            _ => return None,
+        }
+
+        Some(resp)
+    }
+
+    fn udp_error(transaction_id: &[u8], reason: &str) -> Vec<u8> {
+        let mut resp = vec![];
+        resp.extend_from_slice(&ACTION_ERROR.to_be_bytes());
+        resp.extend_from_slice(transaction_id);
+        resp.extend_from_slice(reason.as_bytes());
+        resp
+    }
+
+    fn announce_req_from_datagram(data: &[u8], addr: SocketAddr) -> AnnounceReq {
+        let event = match u32::from_be_bytes(data[80..84].try_into().unwrap()) {
+            1 => AnnounceEvent::Completed,
+            2 => AnnounceEvent::Started,
+            3 => AnnounceEvent::Stopped,
+            _ => AnnounceEvent::None,
+        };
+        let num_want = match i32::from_be_bytes(data[92..96].try_into().unwrap()) {
+            num_want if num_want < 0 => DEFAULT_NUM_WANT,
+            num_want => num_want as usize,
+        };
+        let port = u16::from_be_bytes(data[96..98].try_into().unwrap());
+
+        AnnounceReq {
+            info_hash: data[16..36].try_into().unwrap(),
+            peer_id: data[36..56].try_into().unwrap(),
+            addr: SocketAddr::new(addr.ip().to_canonical(), port),
+            alt_addr: None,
+            left: u64::from_be_bytes(data[64..72].try_into().unwrap()),
+            event,
+            num_want,
+        }
+    }
+
+    /// Connection ID is derived from client address and time, so no state have to be stored.
+    /// `age` select previous time windows.
+    fn connection_id(&self, addr: SocketAddr, age: u64) -> u64 {
+        let window = (self.started.elapsed().as_secs() / CONNECTION_ID_TTL_SEC).saturating_sub(age);
+        self.secret.hash_one((addr, window))
+    }
+
+    fn is_valid_connection_id(&self, connection_id: u64, addr: SocketAddr) -> bool {
+        connection_id == self.connection_id(addr, 0) || connection_id == self.connection_id(addr, 1)
+    }
+
+    /// Update swarm and return peers for client. Seeders (and partial seeds) don't receive other
+    /// seeders.
+    fn announce(&mut self, req: &AnnounceReq) -> Result<AnnounceReply, &'static str> {
+        if let Some(whitelist) = &self.whitelist {
+            if !whitelist.contains(&req.info_hash) {
+                return Err("torrent not registered");
+            }
+        }
+
+        let timeout = self.peer_timeout();
+        let swarm = self.swarms.entry(req.info_hash).or_default();
+        swarm
+            .peers
+            .retain(|_, peer| peer.last_seen.elapsed() < timeout);
+
+        match req.event {
+            AnnounceEvent::Stopped => {
+                swarm.peers.remove(&req.peer_id);
+                return Ok(AnnounceReply {
+                    complete: swarm.complete(),
+                    incomplete: swarm.incomplete(),
+                    peers: vec![],
+                });
+            }
+            AnnounceEvent::Completed => swarm.downloaded += 1,
+            _ => (),
+        }
+
+        let peer = SwarmPeer {
+            addr: req.addr,
+            alt_addr: req.alt_addr,
+            left: req.left,
+            paused: req.event == AnnounceEvent::Paused,
+            last_seen: Instant::now(),
+        };
+        let upload_only = peer.upload_only();
+        swarm.peers.insert(req.peer_id, peer);
+
+        let mut peers: Vec<_> = swarm
+            .peers
+            .iter()
+            .filter(|(peer_id, peer)| {
+                **peer_id != req.peer_id && !(upload_only && peer.upload_only())
+            })
+            .flat_map(|(peer_id, peer)| {
+                [Some(peer.addr), peer.alt_addr]
+                    .into_iter()
+                    .flatten()
+                    .map(|addr| (*peer_id, addr))
+            })
+            .collect();
+        peers.shuffle(&mut rand::thread_rng());
+        peers.truncate(req.num_want.min(MAX_NUM_WANT));
+
+        Ok(AnnounceReply {
+            complete: swarm.complete(),
+            incomplete: swarm.incomplete(),
+            peers,
+        })
+    }
+
+    /// Return statistics of requested torrents, or all tracked torrents if list is empty.
+    fn scrape(&mut self, info_hashes: &[[u8; HASH_SIZE]]) -> HashMap<[u8; HASH_SIZE], ScrapeFile> {
+        self.purge_peers();
+
+        self.swarms
+            .iter()
+            .filter(|(info_hash, _)| info_hashes.is_empty() || info_hashes.contains(info_hash))
+            .map(|(info_hash, swarm)| (*info_hash, swarm.scrape_file()))
+            .collect()
+    }
+
+    fn purge_peers(&mut self) {
+        let timeout = self.peer_timeout();
+        for swarm in self.swarms.values_mut() {
+            swarm
+                .peers
+                .retain(|_, peer| peer.last_seen.elapsed() < timeout);
+        }
+
+        // Abandoned torrents are forgotten, unless someone completed them (still scraped)
+        self.swarms
+            .retain(|_, swarm| !swarm.peers.is_empty() || swarm.downloaded > 0);
+    }
+
+    fn peer_timeout(&self) -> Duration {
+        self.interval * 2
+    }
+}
@@ -0,0 +1,323 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::constants::HASH_SIZE;
+use crate::tracker_client::{AnnounceEvent, AnnounceParams};
+use crate::tracker_resp::{COMPACT_PEER6_SIZE, COMPACT_PEER_SIZE};
+use crate::{Error, ScrapeFile, ScrapeResp, TrackerResp};
+use rand::Rng;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time;
+use tokio::time::{Duration, Instant};
+use url::Url;
+
+/// Magic constant identifying connect request, see [BEP15](https://www.bittorrent.org/beps/bep_0015.html).
+const PROTOCOL_ID: u64 = 0x41727101980;
+/// Client can use connection ID until one minute after it has received it.
+const CONNECTION_ID_TTL_SEC: u64 = 60;
+/// Retransmission timeout is calculated as 15 * 2 ^ n, where n is in range 0..=8.
+const TIMEOUT_BASE_SEC: u64 = 15;
+const MAX_RETRANSMISSIONS: u32 = 8;
+const MAX_DATAGRAM_SIZE: usize = 65536;
+const HEADER_SIZE: usize = 8;
+const CONNECT_RESP_SIZE: usize = 16;
+const ANNOUNCE_RESP_MIN_SIZE: usize = 20;
+const SCRAPE_ENTRY_SIZE: usize = 12;
+/// Up to about 74 torrents can be scraped at once, see [BEP15](https://www.bittorrent.org/beps/bep_0015.html).
+const MAX_SCRAPE_HASHES: usize = 74;
+/// BEP41 option types.
+const OPTION_END_OF_OPTIONS: u8 = 0x0;
+const OPTION_URL_DATA: u8 = 0x2;
+const MAX_OPTION_LEN: usize = 255;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+#[repr(u32)]
+enum Action {
+    Connect = 0,
+    Announce = 1,
+    Scrape = 2,
+    Error = 3,
+}
+
+/// UDP tracker client (see [BEP15](https://www.bittorrent.org/beps/bep_0015.html)).
+///
+/// Connection ID received from tracker is cached and reused, until it expire.
+#[derive(Debug)]
+pub struct UdpTrackerClient {
+    host: String,
+    url_data: Vec<u8>,
+    socket: Option<UdpSocket>,
+    connection: Option<(u64, Instant)>,
+}
+
+impl UdpTrackerClient {
+    /// Create new UDP tracker client for `udp://` URL. Path and query part of URL is send to
+    /// tracker as URLData option (see [BEP41](https://www.bittorrent.org/beps/bep_0041.html)).
+    ///
+    /// # Example
+    /// ```
+    /// use rdest::UdpTrackerClient;
+    ///
+    /// let client = UdpTrackerClient::new("udp://tracker.example.com:6969/announce").unwrap();
+    /// ```
+    pub fn new(url: &str) -> Result<UdpTrackerClient, Error> {
+        let parsed = Url::parse(url).or(Err(Error::TrackerInvalidUrl(url.to_string())))?;
+        if parsed.scheme() != "udp" {
+            return Err(Error::TrackerInvalidUrl(url.to_string()));
+        }
+
+        let host = match (parsed.host_str(), parsed.port()) {
+            (Some(host), Some(port)) => host.to_string() + ":" + port.to_string().as_str(),
+            _ => return Err(Error::TrackerInvalidUrl(url.to_string())),
+        };
+
+        let mut url_data = parsed.path().to_string();
+        if let Some(query) = parsed.query() {
+            url_data += "?";
+            url_data += query;
+        }
+        if url_data == "/" {
+            url_data.clear();
+        }
+
+        Ok(UdpTrackerClient {
+            host,
+            url_data: url_data.into_bytes(),
+            socket: None,
+            connection: None,
+        })
+    }
+
+    /// Send announce request and wait for response.
+    pub async fn announce(&mut self, params: &AnnounceParams) -> Result<TrackerResp, Error> {
+        let body = self.announce_body(params);
+        let resp = self.request(Action::Announce, &body).await?;
+
+        if resp.len() < ANNOUNCE_RESP_MIN_SIZE {
+            return Err(Error::TrackerIncorrectOrMissing("announce"));
+        }
+
+        let interval = u32::from_be_bytes(resp[8..12].try_into().unwrap());
+        let leechers = u32::from_be_bytes(resp[12..16].try_into().unwrap());
+        let seeders = u32::from_be_bytes(resp[16..20].try_into().unwrap());
+        let entry_size = match self.is_ipv6() {
+            true => COMPACT_PEER6_SIZE,
+            false => COMPACT_PEER_SIZE,
+        };
+        let peers = TrackerResp::compact_addrs(&resp[ANNOUNCE_RESP_MIN_SIZE..], entry_size);
+
+        Ok(TrackerResp::from_addrs(
+            interval as u64,
+            seeders as u64,
+            leechers as u64,
+            peers,
+        ))
+    }
+
+    /// Send scrape request for many info hashes. Hashes are split into many requests if they
+    /// don't fit in one datagram.
+    pub async fn scrape(&mut self, info_hashes: &[[u8; HASH_SIZE]]) -> Result<ScrapeResp, Error> {
+        let mut files = HashMap::new();
+
+        for chunk in info_hashes.chunks(MAX_SCRAPE_HASHES) {
+            let body: Vec<u8> = chunk.iter().flatten().copied().collect();
+            let resp = self.request(Action::Scrape, &body).await?;
+
+            let entries = resp[HEADER_SIZE..].chunks_exact(SCRAPE_ENTRY_SIZE);
+            if entries.len() != chunk.len() {
+                return Err(Error::TrackerIncorrectOrMissing("scrape"));
+            }
+
+            for (info_hash, entry) in chunk.iter().zip(entries) {
+                let file = ScrapeFile {
+                    complete: u32::from_be_bytes(entry[0..4].try_into().unwrap()) as u64,
+                    downloaded: u32::from_be_bytes(entry[4..8].try_into().unwrap()) as u64,
+                    incomplete: u32::from_be_bytes(entry[8..12].try_into().unwrap()) as u64,
+                    name: None,
+                };
+                files.insert(*info_hash, file);
+            }
+        }
+
+        Ok(ScrapeResp::from_files(files))
+    }
+
+    fn announce_body(&self, params: &AnnounceParams) -> Vec<u8> {
+        let mut body = vec![];
+        body.extend_from_slice(&params.info_hash);
+        body.extend_from_slice(&params.peer_id);
+        body.extend_from_slice(&params.downloaded.to_be_bytes());
+        body.extend_from_slice(&params.left.to_be_bytes());
+        body.extend_from_slice(&params.uploaded.to_be_bytes());
+        // UDP protocol has no "paused" event
+        let event = match params.event {
+            AnnounceEvent::Paused => AnnounceEvent::None,
+            event => event,
+        };
+        body.extend_from_slice(&(event as u32).to_be_bytes());
+        // IP address, 0 means that tracker should use sender address
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend_from_slice(&params.key.to_be_bytes());
+        body.extend_from_slice(&params.num_want.to_be_bytes());
+        body.extend_from_slice(&params.port.to_be_bytes());
+        body.extend_from_slice(&self.url_data_option());
+
+        body
+    }
+
+    fn url_data_option(&self) -> Vec<u8> {
+        if self.url_data.is_empty() {
+            return vec![];
+        }
+
+        // Longer data is split into many URLData options, which are concatenated by tracker
+        let mut option = vec![];
+        for chunk in self.url_data.chunks(MAX_OPTION_LEN) {
+            option.push(OPTION_URL_DATA);
+            option.push(chunk.len() as u8);
+            option.extend_from_slice(chunk);
+        }
+        option.push(OPTION_END_OF_OPTIONS);
+
+        option
+    }
+
+    fn is_ipv6(&self) -> bool {
+        match &self.socket {
+            Some(socket) => matches!(socket.peer_addr(), Ok(SocketAddr::V6(_))),
+            None => false,
+        }
+    }
+
+    /// Send request and wait for response (with retransmissions). Connection ID is refreshed
+    /// when needed, also between retransmissions.
+    async fn request(&mut self, action: Action, body: &[u8]) -> Result<Vec<u8>, Error> {
+        for n in 0..=MAX_RETRANSMISSIONS {
+            let connection_id = match self.connection_id(n).await? {
+                Some(connection_id) => connection_id,
+                None => continue,
+            };
+
+            let mut req = vec![];
+            req.extend_from_slice(&connection_id.to_be_bytes());
+            req.extend_from_slice(&(action as u32).to_be_bytes());
+            let transaction_id = rand::thread_rng().gen::<u32>();
+            req.extend_from_slice(&transaction_id.to_be_bytes());
+            req.extend_from_slice(body);
+
+            if let Some(resp) = self.transact(&req, action, transaction_id, n).await? {
+                return Ok(resp);
+            }
+        }
+
+        Err(Error::TrackerTimeout)
+    }
+
+    /// Return cached connection ID, or connect to tracker to get new one. `None` is returned if
+    /// tracker doesn't respond in time.
+    async fn connection_id(&mut self, n: u32) -> Result<Option<u64>, Error> {
+        if let Some((connection_id, received)) = self.connection {
+            if received.elapsed() < Duration::from_secs(CONNECTION_ID_TTL_SEC) {
+                return Ok(Some(connection_id));
+            }
+        }
+
+        let mut req = vec![];
+        req.extend_from_slice(&PROTOCOL_ID.to_be_bytes());
+        req.extend_from_slice(&(Action::Connect as u32).to_be_bytes());
+        let transaction_id = rand::thread_rng().gen::<u32>();
+        req.extend_from_slice(&transaction_id.to_be_bytes());
+
+        match self
+            .transact(&req, Action::Connect, transaction_id, n)
+            .await?
+        {
+            Some(resp) => {
+                if resp.len() < CONNECT_RESP_SIZE {
+                    return Err(Error::TrackerIncorrectOrMissing("connection id"));
+                }
+
+                let connection_id = u64::from_be_bytes(resp[8..16].try_into().unwrap());
+                self.connection = Some((connection_id, Instant::now()));
+                Ok(Some(connection_id))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Send single datagram and wait 15 * 2 ^ n seconds for matching response.
+    async fn transact(
+        &mut self,
+        req: &[u8],
+        action: Action,
+        transaction_id: u32,
+        n: u32,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let socket = self.socket().await?;
+        socket.send(req).await.or(Err(Error::SocketNotAvailable))?;
+
+        let timeout = Duration::from_secs(TIMEOUT_BASE_SEC * 2u64.pow(n));
+        let deadline = Instant::now() + timeout;
+        let mut buff = vec![0; MAX_DATAGRAM_SIZE];
+
+        loop {
+            let len = match time::timeout_at(deadline, socket.recv(&mut buff)).await {
+                Ok(Ok(len)) => len,
+                Ok(Err(_)) => return Err(Error::CantReadFromSocket),
+                Err(_) => return Ok(None),
+            };
+
+            // Ignore datagrams not related to this transaction
+            if len < HEADER_SIZE
+                || u32::from_be_bytes(buff[4..8].try_into().unwrap()) != transaction_id
+            {
+                continue;
+            }
+
+            let resp_action = u32::from_be_bytes(buff[0..4].try_into().unwrap());
+            if resp_action == Action::Error as u32 {
+                let reason = String::from_utf8_lossy(&buff[HEADER_SIZE..len]).to_string();
+                return Err(Error::TrackerRespFail(reason));
+            } else if resp_action != action as u32 {
+                return Err(Error::TrackerIncorrectOrMissing("action"));
+            }
+
+            buff.truncate(len);
+            return Ok(Some(buff));
+        }
+    }
+
+    async fn socket(&mut self) -> Result<&UdpSocket, Error> {
+        if self.socket.is_none() {
+            let addr = lookup_host(&self.host)
+                .await
+                .or(Err(Error::TrackerInvalidUrl(self.host.clone())))?
+                .next()
+                .ok_or(Error::TrackerInvalidUrl(self.host.clone()))?;
+
+            let local: SocketAddr = match addr {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+
+            let socket = UdpSocket::bind(local)
+                .await
+                .or(Err(Error::SocketNotAvailable))?;
+            socket
+                .connect(addr)
+                .await
+                .or(Err(Error::SocketNotAvailable))?;
+            self.socket = Some(socket);
+        }
+
+        self.socket.as_ref().ok_or(Error::SocketNotAvailable)
+    }
+}
@@ -7,6 +7,7 @@
 // except according to those terms.
 
 use crate::constants::HASH_SIZE;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
 
 /// Create new HashMap with emplaced elements.
 ///
@@ -29,8 +30,182 @@ macro_rules! hashmap {
     }}
 }
 
+/// Hash as uppercase hex string, e.g. for info hash or piece file name.
 pub fn hash_to_string(hash: &[u8; HASH_SIZE]) -> String {
     hash.iter()
         .map(|b| format!("{:02X}", b))
         .collect::<String>()
 }
+
+/// Generate set of pieces that peer with given IP can download even when choked, with algorithm
+/// described in [BEP6](https://www.bittorrent.org/beps/bep_0006.html).
+///
+/// # Example
+/// ```
+/// use rdest::allowed_fast_set;
+///
+/// let set = allowed_fast_set("80.4.4.200".parse().unwrap(), &[0xaa; 20], 1313, 7);
+/// assert_eq!(set, vec![1059, 431, 808, 1217, 287, 376, 1188]);
+/// ```
+pub fn allowed_fast_set(
+    ip: Ipv4Addr,
+    info_hash: &[u8; HASH_SIZE],
+    pieces_num: usize,
+    count: usize,
+) -> Vec<usize> {
+    let count = count.min(pieces_num);
+    let mut set = vec![];
+
+    // Peers from the same /24 network get the same set
+    let mut x = (u32::from(ip) & 0xffffff00).to_be_bytes().to_vec();
+    x.extend_from_slice(info_hash);
+
+    while set.len() < count {
+        x = sha1_smol::Sha1::from(&x).digest().bytes().to_vec();
+        for chunk in x.chunks(4) {
+            if set.len() == count {
+                break;
+            }
+
+            let y = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            let index = y as usize % pieces_num;
+            if !set.contains(&index) {
+                set.push(index);
+            }
+        }
+    }
+
+    set
+}
+
+/// Canonical priority of connection between two peers, as described in
+/// [BEP40](https://www.bittorrent.org/beps/bep_0040.html). Both sides compute the same value, so
+/// peers prefer the same connections and swarm is well mixed.
+///
+/// # Example
+/// ```
+/// use rdest::peer_priority;
+///
+/// let priority = peer_priority(
+///     "123.213.32.10:6881".parse().unwrap(),
+///     "98.76.54.32:6881".parse().unwrap(),
+/// );
+/// assert_eq!(priority, 0xec2d7224);
+/// ```
+pub fn peer_priority(addr1: SocketAddr, addr2: SocketAddr) -> u32 {
+    // Peers behind the same IP are distinguished by ports
+    if addr1.ip() == addr2.ip() {
+        let mut ports = [addr1.port(), addr2.port()];
+        ports.sort();
+        let data = [ports[0].to_be_bytes(), ports[1].to_be_bytes()].concat();
+        return crc32c::crc32c(&data);
+    }
+
+    const V4_MASKS: [&[u8]; 3] = [
+        &[0xff, 0xff, 0x55, 0x55],
+        &[0xff, 0xff, 0xff, 0x55],
+        &[0xff; 4],
+    ];
+    const V6_MASKS: [&[u8]; 3] = [
+        &[0xff, 0xff, 0xff, 0xff, 0x55, 0x55, 0x55, 0x55],
+        &[0xff, 0xff, 0xff, 0xff, 0xff, 0x55, 0x55, 0x55],
+        &[0xff; 8],
+    ];
+
+    let (mut ip1, mut ip2, masks) = match (addr1.ip(), addr2.ip()) {
+        (IpAddr::V4(ip1), IpAddr::V4(ip2)) => {
+            (ip1.octets().to_vec(), ip2.octets().to_vec(), V4_MASKS)
+        }
+        (ip1, ip2) => (ipv6_octets(ip1), ipv6_octets(ip2), V6_MASKS),
+    };
+
+    // Mask depends on common prefix (IPv4 /16 and /24, IPv6 /32 and /40)
+    let prefix = masks[0].iter().take_while(|m| **m == 0xff).count();
+    let mask = match (
+        ip1[..prefix] == ip2[..prefix],
+        ip1[..=prefix] == ip2[..=prefix],
+    ) {
+        (false, _) => masks[0],
+        (true, false) => masks[1],
+        (true, true) => masks[2],
+    };
+    for (idx, m) in mask.iter().enumerate() {
+        ip1[idx] &= m;
+        ip2[idx] &= m;
+    }
+
+    let data = match ip1 < ip2 {
+        true => [ip1, ip2].concat(),
+        false => [ip2, ip1].concat(),
+    };
+    crc32c::crc32c(&data)
+}
+
+fn ipv6_octets(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
+
+/// Local address from which traffic to internet is send, for IPv4 or IPv6. No packet is send,
+/// connecting UDP socket only selects route. Loopback and link-local addresses are skipped.
+pub(crate) fn local_ip(ipv6: bool) -> Option<IpAddr> {
+    let (bind, target) = match ipv6 {
+        false => (
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::from((Ipv4Addr::new(8, 8, 8, 8), 53)),
+        ),
+        true => (
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+            SocketAddr::from((
+                Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888),
+                53,
+            )),
+        ),
+    };
+
+    let socket = UdpSocket::bind(bind).ok()?;
+    socket.connect(target).ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    let routable = match ip {
+        IpAddr::V4(ip) => !ip.is_loopback() && !ip.is_unspecified() && !ip.is_link_local(),
+        IpAddr::V6(ip) => {
+            !ip.is_loopback() && !ip.is_unspecified() && ip.segments()[0] & 0xffc0 != 0xfe80
+        }
+    };
+
+    routable.then_some(ip)
+}
+
+/// Address is reachable from internet, i.e. it's not private, shared (CGNAT), unique local,
+/// link-local, loopback nor reserved for documentation.
+pub(crate) fn is_global(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || (a == 100 && b & 0xc0 == 64)
+                || (a, b, c) == (192, 0, 0)
+                || (a, b, c) == (192, 0, 2)
+                || (a, b, c) == (198, 51, 100)
+                || (a, b, c) == (203, 0, 113)
+                || (a == 198 && b & 0xfe == 18)
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || segments[0] & 0xfe00 == 0xfc00
+                || segments[0] & 0xffc0 == 0xfe80
+                || (segments[0], segments[1]) == (0x2001, 0x0db8))
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::utp::MAX_PAYLOAD_SIZE;
+use std::collections::VecDeque;
+use tokio::time::{Duration, Instant};
+
+/// Queuing delay that LEDBAT tries to keep (BEP29 recommends 100ms).
+const TARGET_DELAY_US: i64 = 100_000;
+/// Maximal window growth (in packets) per round trip, when there is no queuing delay.
+const GAIN: f64 = 1.0;
+const MIN_WINDOW: usize = MAX_PAYLOAD_SIZE;
+const INIT_WINDOW: usize = 2 * MAX_PAYLOAD_SIZE;
+const MAX_WINDOW: usize = 1024 * 1024;
+/// Base delay is minimum of delays observed during last minutes.
+const BASE_DELAY_HISTORY: usize = 2;
+const BASE_DELAY_INTERVAL_SEC: u64 = 60;
+
+/// LEDBAT congestion controller ([RFC6817](https://www.rfc-editor.org/rfc/rfc6817)). Window
+/// grows only while one-way delay is below target, so uTP yields to other traffic on the same
+/// uplink.
+pub struct Ledbat {
+    window: usize,
+    /// Minimal delays, one per interval (current first)
+    base_delays: VecDeque<u32>,
+    interval_start: Instant,
+}
+
+impl Ledbat {
+    pub fn new() -> Ledbat {
+        Ledbat {
+            window: INIT_WINDOW,
+            base_delays: VecDeque::new(),
+            interval_start: Instant::now(),
+        }
+    }
+
+    /// Congestion window in bytes.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Update window, after `bytes_acked` were acknowledged. `delay_us` is one-way delay reported
+    /// by peer (0 if peer doesn't have measurement yet).
+    pub fn on_ack(&mut self, bytes_acked: usize, delay_us: u32) {
+        let off_target = match delay_us {
+            0 => 1.0,
+            _ => {
+                let queuing_delay = delay_us.wrapping_sub(self.update_base_delay(delay_us)) as i64;
+                (TARGET_DELAY_US - queuing_delay) as f64 / TARGET_DELAY_US as f64
+            }
+        };
+
+        let change =
+            GAIN * off_target * bytes_acked as f64 * MAX_PAYLOAD_SIZE as f64 / self.window as f64;
+        let window = (self.window as f64 + change).clamp(MIN_WINDOW as f64, MAX_WINDOW as f64);
+        self.window = window as usize;
+    }
+
+    /// Packet loss detected (duplicated acknowledgments).
+    pub fn on_loss(&mut self) {
+        self.window = (self.window / 2).max(MIN_WINDOW);
+    }
+
+    /// Retransmission timeout, start from minimal window.
+    pub fn on_timeout(&mut self) {
+        self.window = MIN_WINDOW;
+    }
+
+    fn update_base_delay(&mut self, delay_us: u32) -> u32 {
+        let now = Instant::now();
+        if self.base_delays.is_empty()
+            || now >= self.interval_start + Duration::from_secs(BASE_DELAY_INTERVAL_SEC)
+        {
+            self.interval_start = now;
+            self.base_delays.push_front(delay_us);
+            self.base_delays.truncate(BASE_DELAY_HISTORY);
+        } else if delay_us < self.base_delays[0] {
+            self.base_delays[0] = delay_us;
+        }
+
+        *self.base_delays.iter().min().unwrap()
+    }
+}
@@ -0,0 +1,18 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+mod ledbat;
+mod packet;
+mod socket;
+mod stream;
+
+pub use socket::{Utp, UtpSocket};
+pub use stream::UtpStream;
+
+/// Maximal payload in single packet, so datagram fits in typical MTU (also for IPv6).
+const MAX_PAYLOAD_SIZE: usize = 1400;
@@ -0,0 +1,119 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::Error;
+use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 20;
+const EXTENSION_HEADER_SIZE: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Data = 0,
+    Fin = 1,
+    State = 2,
+    Reset = 3,
+    Syn = 4,
+}
+
+/// uTP packet ([BEP29](https://www.bittorrent.org/beps/bep_0029.html)). Extensions (e.g.
+/// selective ACK) are skipped when parsing and never send.
+#[derive(Debug, Clone)]
+pub struct Packet {
+    pub kind: Type,
+    pub conn_id: u16,
+    pub timestamp: u32,
+    pub timestamp_diff: u32,
+    pub wnd_size: u32,
+    pub seq_nr: u16,
+    pub ack_nr: u16,
+    pub payload: Vec<u8>,
+}
+
+impl Packet {
+    pub fn new(kind: Type, conn_id: u16, seq_nr: u16, ack_nr: u16) -> Packet {
+        Packet {
+            kind,
+            conn_id,
+            timestamp: 0,
+            timestamp_diff: 0,
+            wnd_size: 0,
+            seq_nr,
+            ack_nr,
+            payload: vec![],
+        }
+    }
+
+    pub fn from(data: &[u8]) -> Result<Packet, Error> {
+        if data.len() < HEADER_SIZE {
+            return Err(Error::UtpInvalidPacket("header too short"));
+        }
+
+        if data[0] & 0x0f != VERSION {
+            return Err(Error::UtpInvalidPacket("unknown version"));
+        }
+        let kind = match data[0] >> 4 {
+            0 => Type::Data,
+            1 => Type::Fin,
+            2 => Type::State,
+            3 => Type::Reset,
+            4 => Type::Syn,
+            _ => return Err(Error::UtpInvalidPacket("unknown type")),
+        };
+
+        // Skip extensions chain
+        let mut extension = data[1];
+        let mut pos = HEADER_SIZE;
+        while extension != 0 {
+            if pos + EXTENSION_HEADER_SIZE > data.len() {
+                return Err(Error::UtpInvalidPacket("extension too short"));
+            }
+            extension = data[pos];
+            pos += EXTENSION_HEADER_SIZE + data[pos + 1] as usize;
+        }
+        if pos > data.len() {
+            return Err(Error::UtpInvalidPacket("extension too short"));
+        }
+
+        Ok(Packet {
+            kind,
+            conn_id: u16::from_be_bytes(data[2..4].try_into().unwrap()),
+            timestamp: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            timestamp_diff: u32::from_be_bytes(data[8..12].try_into().unwrap()),
+            wnd_size: u32::from_be_bytes(data[12..16].try_into().unwrap()),
+            seq_nr: u16::from_be_bytes(data[16..18].try_into().unwrap()),
+            ack_nr: u16::from_be_bytes(data[18..20].try_into().unwrap()),
+            payload: data[pos..].to_vec(),
+        })
+    }
+
+    pub fn data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        data.push((self.kind as u8) << 4 | VERSION);
+        data.push(0);
+        data.extend_from_slice(&self.conn_id.to_be_bytes());
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&self.timestamp_diff.to_be_bytes());
+        data.extend_from_slice(&self.wnd_size.to_be_bytes());
+        data.extend_from_slice(&self.seq_nr.to_be_bytes());
+        data.extend_from_slice(&self.ack_nr.to_be_bytes());
+        data.extend_from_slice(&self.payload);
+
+        data
+    }
+}
+
+/// Current time in microseconds (truncated), as used in packet timestamps.
+pub fn timestamp_us() -> u32 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_micros() as u32,
+        Err(_) => 0,
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::commands::UtpCmd;
+use crate::utp::packet::{Packet, Type};
+use crate::utp::stream::{Conn, UtpStream};
+use crate::Error;
+use socket2::{Domain, Protocol, Socket};
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+
+const CHANNEL_SIZE: usize = 64;
+const ACCEPT_QUEUE_SIZE: usize = 16;
+const PACKET_QUEUE_SIZE: usize = 512;
+const MAX_DATAGRAM_SIZE: usize = 2048;
+
+/// UDP socket carrying uTP ([BEP29](https://www.bittorrent.org/beps/bep_0029.html))
+/// connections. Incoming and outgoing connections share the same socket, so peers see one
+/// address for both. Socket task runs until socket and all its [`Utp`] handles are dropped, and
+/// all connections are closed (like TCP streams, they outlive the socket).
+///
+/// # Example
+/// ```no_run
+/// use rdest::UtpSocket;
+/// use tokio::io::AsyncWriteExt;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mut socket = UtpSocket::bind(6881).await.unwrap();
+/// while let Some(mut stream) = socket.accept().await {
+///     stream.write_all(b"hello").await.unwrap();
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct UtpSocket {
+    local_addr: SocketAddr,
+    tx_ch: mpsc::Sender<UtpCmd>,
+    accept_ch: mpsc::Receiver<UtpStream>,
+}
+
+/// Handle to [`UtpSocket`], used to open outgoing connections. Can be cloned and shared between
+/// tasks.
+#[derive(Debug, Clone)]
+pub struct Utp {
+    local_addr: SocketAddr,
+    tx_ch: mpsc::Sender<UtpCmd>,
+}
+
+/// Dispatches received packets to connections (by address and connection ID).
+struct Mux {
+    socket: Arc<UdpSocket>,
+    conns: HashMap<(SocketAddr, u16), mpsc::Sender<Packet>>,
+    rx_ch: mpsc::Receiver<UtpCmd>,
+    accept_ch: mpsc::Sender<UtpStream>,
+    close_tx: mpsc::Sender<(SocketAddr, u16)>,
+    close_rx: mpsc::Receiver<(SocketAddr, u16)>,
+}
+
+impl UtpSocket {
+    /// Bind UDP socket (on all IPv4 interfaces) and start socket task. Port 0 selects random
+    /// port.
+    pub async fn bind(port: u16) -> Result<UtpSocket, Error> {
+        Self::bind_addr(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))).await
+    }
+
+    /// Bind UDP socket to given address and start socket task. IPv6 socket doesn't accept IPv4
+    /// traffic, so separate IPv4 socket can share the same port.
+    pub async fn bind_addr(addr: SocketAddr) -> Result<UtpSocket, Error> {
+        let port = addr.port();
+        let socket = Self::udp_socket(addr).map_err(|_| Error::UtpBind(port))?;
+        let local_addr = socket.local_addr().map_err(|_| Error::UtpBind(port))?;
+
+        let (tx_ch, rx_ch) = mpsc::channel(CHANNEL_SIZE);
+        let (accept_tx, accept_rx) = mpsc::channel(ACCEPT_QUEUE_SIZE);
+        let mut mux = Mux::new(socket, rx_ch, accept_tx);
+        tokio::spawn(async move { mux.run().await });
+
+        Ok(UtpSocket {
+            local_addr,
+            tx_ch,
+            accept_ch: accept_rx,
+        })
+    }
+
+    fn udp_socket(addr: SocketAddr) -> io::Result<UdpSocket> {
+        let socket = Socket::new(
+            Domain::for_address(addr),
+            socket2::Type::DGRAM,
+            Some(Protocol::UDP),
+        )?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        UdpSocket::from_std(socket.into())
+    }
+
+    /// Address the socket is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Handle for opening outgoing connections.
+    pub fn handle(&self) -> Utp {
+        Utp {
+            local_addr: self.local_addr,
+            tx_ch: self.tx_ch.clone(),
+        }
+    }
+
+    /// Wait for incoming connection. Returns `None` when socket task is not running.
+    pub async fn accept(&mut self) -> Option<UtpStream> {
+        self.accept_ch.recv().await
+    }
+}
+
+impl Utp {
+    /// Address of underlying socket.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Connect to peer. Fails with [`Error::UtpTimeout`] when peer doesn't respond (e.g. it
+    /// supports only TCP).
+    pub async fn connect(&self, addr: SocketAddr) -> Result<UtpStream, Error> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.tx_ch
+            .send(UtpCmd::Connect {
+                addr,
+                resp_ch: resp_tx,
+            })
+            .await
+            .map_err(|_| Error::UtpNotRunning)?;
+
+        resp_rx.await.map_err(|_| Error::UtpNotRunning)?
+    }
+
+    /// Send single packet (RESET for unknown connection, ignored by peer) to open mapping in NAT,
+    /// so peer can connect from given address (see
+    /// [BEP55](https://www.bittorrent.org/beps/bep_0055.html)).
+    pub async fn punch(&self, addr: SocketAddr) -> Result<(), Error> {
+        self.tx_ch
+            .send(UtpCmd::Punch { addr })
+            .await
+            .map_err(|_| Error::UtpNotRunning)
+    }
+}
+
+impl Mux {
+    fn new(
+        socket: UdpSocket,
+        rx_ch: mpsc::Receiver<UtpCmd>,
+        accept_ch: mpsc::Sender<UtpStream>,
+    ) -> Mux {
+        let (close_tx, close_rx) = mpsc::channel(CHANNEL_SIZE);
+        Mux {
+            socket: Arc::new(socket),
+            conns: HashMap::new(),
+            rx_ch,
+            accept_ch,
+            close_tx,
+            close_rx,
+        }
+    }
+
+    async fn run(&mut self) {
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        let mut handles = true;
+        while handles || !self.conns.is_empty() {
+            tokio::select! {
+                cmd = self.rx_ch.recv(), if handles => match cmd {
+                    Some(UtpCmd::Connect { addr, resp_ch }) => self.connect(addr, resp_ch),
+                    Some(UtpCmd::Punch { addr }) => self.punch(addr).await,
+                    None => handles = false,
+                },
+                Some(key) = self.close_rx.recv() => {
+                    self.conns.remove(&key);
+                }
+                Ok((n, addr)) = self.socket.recv_from(&mut buf) => self.handle_datagram(&buf[..n], addr),
+            }
+        }
+    }
+
+    fn handle_datagram(&mut self, data: &[u8], addr: SocketAddr) {
+        let packet = match Packet::from(data) {
+            Ok(packet) => packet,
+            Err(_) => return,
+        };
+
+        // When connection is busy packet is dropped, and it will be retransmitted
+        if let Some(conn_ch) = self.conns.get(&(addr, packet.conn_id)) {
+            let _ = conn_ch.try_send(packet);
+            return;
+        }
+
+        if packet.kind != Type::Syn {
+            return;
+        }
+
+        // Retransmitted SYN for already accepted connection
+        if let Some(conn_ch) = self.conns.get(&(addr, packet.conn_id.wrapping_add(1))) {
+            let _ = conn_ch.try_send(packet);
+            return;
+        }
+
+        self.accept(addr, &packet);
+    }
+
+    async fn punch(&mut self, addr: SocketAddr) {
+        let packet = Packet::new(Type::Reset, rand::random(), rand::random(), 0);
+        let _ = self.socket.send_to(&packet.data(), addr).await;
+    }
+
+    fn accept(&mut self, addr: SocketAddr, syn: &Packet) {
+        let (packet_tx, packet_rx) = mpsc::channel(PACKET_QUEUE_SIZE);
+        let (stream, mut conn) = Conn::incoming(
+            self.socket.clone(),
+            addr,
+            syn,
+            packet_rx,
+            self.close_tx.clone(),
+        );
+
+        // Nobody listens or too many pending connections
+        if self.accept_ch.try_send(stream).is_err() {
+            return;
+        }
+
+        self.conns
+            .insert((addr, syn.conn_id.wrapping_add(1)), packet_tx);
+        tokio::spawn(async move { conn.run().await });
+    }
+
+    fn connect(&mut self, addr: SocketAddr, resp_ch: oneshot::Sender<Result<UtpStream, Error>>) {
+        // Both IDs (own and peer) shouldn't collide with existing connections
+        let recv_id = loop {
+            let recv_id: u16 = rand::random();
+            if !self.conns.contains_key(&(addr, recv_id))
+                && !self.conns.contains_key(&(addr, recv_id.wrapping_add(1)))
+            {
+                break recv_id;
+            }
+        };
+
+        let (packet_tx, packet_rx) = mpsc::channel(PACKET_QUEUE_SIZE);
+        let mut conn = Conn::outgoing(
+            self.socket.clone(),
+            addr,
+            recv_id,
+            packet_rx,
+            self.close_tx.clone(),
+            resp_ch,
+        );
+
+        self.conns.insert((addr, recv_id), packet_tx);
+        tokio::spawn(async move { conn.run().await });
+    }
+}
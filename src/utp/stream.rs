@@ -0,0 +1,587 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::utp::ledbat::Ledbat;
+use crate::utp::packet::{timestamp_us, Packet, Type};
+use crate::utp::MAX_PAYLOAD_SIZE;
+use crate::Error;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{
+    AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf, ReadHalf, WriteHalf,
+};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
+use tokio::time::{Duration, Instant};
+
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+/// Receive window advertised to peer.
+const RECV_WINDOW: usize = 1024 * 1024;
+/// Maximal number of packets in flight, and how far ahead received packets are buffered.
+const MAX_PACKETS_AHEAD: u16 = 1024;
+const DUP_ACK_LIMIT: usize = 3;
+const INIT_TIMEOUT_MS: u64 = 1000;
+const MIN_TIMEOUT_MS: u64 = 500;
+const MAX_TIMEOUT_MS: u64 = 60_000;
+/// Sequence number of SYN packet (connection initiator).
+const SYN_NR: u16 = 1;
+const SYN_ATTEMPTS: u32 = 3;
+const MAX_TIMEOUTS: u32 = 6;
+/// How long to wait for peer FIN, after own FIN was acknowledged.
+const LINGER_SEC: u64 = 30;
+
+/// Reliable, ordered byte stream over uTP connection (see [`Utp::connect`](crate::Utp::connect)
+/// and [`UtpSocket::accept`](crate::UtpSocket::accept)). Dropping or shutting down the stream
+/// closes the connection.
+#[derive(Debug)]
+pub struct UtpStream {
+    inner: DuplexStream,
+    peer_addr: SocketAddr,
+}
+
+impl UtpStream {
+    /// Address of remote peer.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+}
+
+impl AsyncRead for UtpStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UtpStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[derive(PartialEq)]
+enum State {
+    SynSent,
+    /// SYN was accepted, but initiator didn't confirm it yet
+    SynRecv,
+    Connected,
+    Closed,
+}
+
+struct Sent {
+    packet: Packet,
+    sent: Instant,
+    retransmitted: bool,
+}
+
+/// Single uTP connection. Packets are received from [`UtpSocket`](crate::UtpSocket) task, and
+/// data is exchanged with application through [`UtpStream`].
+pub struct Conn {
+    socket: Arc<UdpSocket>,
+    addr: SocketAddr,
+    recv_id: u16,
+    send_id: u16,
+    state: State,
+    /// Sequence number of next packet
+    seq_nr: u16,
+    /// Last sequence number received in order
+    ack_nr: u16,
+    peer_wnd: usize,
+    /// Delay of last received packet, reported back to peer
+    reply_micro: u32,
+    in_flight: VecDeque<Sent>,
+    in_flight_bytes: usize,
+    out_of_order: HashMap<u16, Packet>,
+    /// Data received in order, but not yet passed to application
+    pending: Vec<u8>,
+    eof_nr: Option<u16>,
+    fin_nr: Option<u16>,
+    app_reader: ReadHalf<DuplexStream>,
+    app_writer: Option<WriteHalf<DuplexStream>>,
+    app_eof: bool,
+    ledbat: Ledbat,
+    /// Smoothed round trip time and its variance (in microseconds)
+    rtt: Option<(i64, i64)>,
+    timeout: Duration,
+    timeouts: u32,
+    dup_acks: usize,
+    last_recv: Instant,
+    connect: Option<(UtpStream, oneshot::Sender<Result<UtpStream, Error>>)>,
+    packet_ch: mpsc::Receiver<Packet>,
+    close_ch: mpsc::Sender<(SocketAddr, u16)>,
+}
+
+impl Conn {
+    /// Connection initiated by us. Stream is passed to `resp_ch` when peer accepts it.
+    pub fn outgoing(
+        socket: Arc<UdpSocket>,
+        addr: SocketAddr,
+        recv_id: u16,
+        packet_ch: mpsc::Receiver<Packet>,
+        close_ch: mpsc::Sender<(SocketAddr, u16)>,
+        resp_ch: oneshot::Sender<Result<UtpStream, Error>>,
+    ) -> Conn {
+        let (stream, mut conn) = Self::new(socket, addr, recv_id, packet_ch, close_ch);
+        conn.send_id = recv_id.wrapping_add(1);
+        conn.state = State::SynSent;
+        conn.seq_nr = SYN_NR;
+        conn.connect = Some((stream, resp_ch));
+
+        conn
+    }
+
+    /// Connection initiated by peer with `syn` packet.
+    pub fn incoming(
+        socket: Arc<UdpSocket>,
+        addr: SocketAddr,
+        syn: &Packet,
+        packet_ch: mpsc::Receiver<Packet>,
+        close_ch: mpsc::Sender<(SocketAddr, u16)>,
+    ) -> (UtpStream, Conn) {
+        let recv_id = syn.conn_id.wrapping_add(1);
+        let (stream, mut conn) = Self::new(socket, addr, recv_id, packet_ch, close_ch);
+        conn.send_id = syn.conn_id;
+        conn.state = State::SynRecv;
+        conn.seq_nr = rand::random();
+        conn.ack_nr = syn.seq_nr;
+        conn.reply_micro = timestamp_us().wrapping_sub(syn.timestamp);
+        conn.peer_wnd = syn.wnd_size as usize;
+
+        (stream, conn)
+    }
+
+    fn new(
+        socket: Arc<UdpSocket>,
+        addr: SocketAddr,
+        recv_id: u16,
+        packet_ch: mpsc::Receiver<Packet>,
+        close_ch: mpsc::Sender<(SocketAddr, u16)>,
+    ) -> (UtpStream, Conn) {
+        let (inner, app) = tokio::io::duplex(STREAM_BUFFER_SIZE);
+        let (app_reader, app_writer) = tokio::io::split(app);
+
+        let stream = UtpStream {
+            inner,
+            peer_addr: addr,
+        };
+        let conn = Conn {
+            socket,
+            addr,
+            recv_id,
+            send_id: 0,
+            state: State::Closed,
+            seq_nr: 0,
+            ack_nr: 0,
+            peer_wnd: MAX_PAYLOAD_SIZE,
+            reply_micro: 0,
+            in_flight: VecDeque::new(),
+            in_flight_bytes: 0,
+            out_of_order: HashMap::new(),
+            pending: vec![],
+            eof_nr: None,
+            fin_nr: None,
+            app_reader,
+            app_writer: Some(app_writer),
+            app_eof: false,
+            ledbat: Ledbat::new(),
+            rtt: None,
+            timeout: Duration::from_millis(INIT_TIMEOUT_MS),
+            timeouts: 0,
+            dup_acks: 0,
+            last_recv: Instant::now(),
+            connect: None,
+            packet_ch,
+            close_ch,
+        };
+
+        (stream, conn)
+    }
+
+    pub async fn run(&mut self) {
+        match self.state {
+            State::SynSent => self.send_new(Type::Syn, vec![]).await,
+            _ => self.send_state().await,
+        }
+
+        let mut buf = vec![0; MAX_PAYLOAD_SIZE];
+        while self.state != State::Closed {
+            let read_len = self.send_quota();
+            let deadline = self.deadline();
+
+            tokio::select! {
+                packet = self.packet_ch.recv() => match packet {
+                    Some(packet) => self.handle_packet(packet).await,
+                    None => self.state = State::Closed,
+                },
+                n = self.app_reader.read(&mut buf[..read_len]), if read_len > 0 => {
+                    self.handle_app_data(&buf[..n.unwrap_or(0)]).await
+                }
+                n = Self::write_app(&mut self.app_writer, &self.pending), if !self.pending.is_empty() => {
+                    self.handle_app_written(n)
+                }
+                _ = time::sleep_until(deadline) => self.handle_timeout().await,
+            }
+
+            self.check_finished().await;
+        }
+
+        if let Some((_, resp_ch)) = self.connect.take() {
+            let _ = resp_ch.send(Err(Error::UtpNotRunning));
+        }
+        let _ = self.close_ch.send((self.addr, self.recv_id)).await;
+    }
+
+    async fn write_app(
+        writer: &mut Option<WriteHalf<DuplexStream>>,
+        data: &[u8],
+    ) -> io::Result<usize> {
+        match writer {
+            Some(writer) => writer.write(data).await,
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    /// Amount of data that can be send in next packet. Window (own and peer) is respected, but
+    /// at least one packet is always allowed in flight.
+    fn send_quota(&self) -> usize {
+        if self.state != State::Connected || self.app_eof || self.connect.is_some() {
+            return 0;
+        }
+
+        let window = self.ledbat.window().min(self.peer_wnd);
+        match self.in_flight.is_empty()
+            || (self.in_flight_bytes + MAX_PAYLOAD_SIZE <= window
+                && self.in_flight.len() < MAX_PACKETS_AHEAD as usize)
+        {
+            true => MAX_PAYLOAD_SIZE,
+            false => 0,
+        }
+    }
+
+    fn deadline(&self) -> Instant {
+        match self.in_flight.front() {
+            Some(front) => front.sent + self.timeout,
+            None if self.state == State::SynRecv => {
+                self.last_recv + self.timeout * (self.timeouts + 1)
+            }
+            None if self.fin_nr.is_some() => self.last_recv + Duration::from_secs(LINGER_SEC),
+            None => Instant::now() + Duration::from_secs(LINGER_SEC),
+        }
+    }
+
+    fn recv_window(&self) -> usize {
+        RECV_WINDOW
+            .saturating_sub(self.pending.len())
+            .saturating_sub(self.out_of_order.len() * MAX_PAYLOAD_SIZE)
+    }
+
+    async fn handle_packet(&mut self, packet: Packet) {
+        self.last_recv = Instant::now();
+        self.reply_micro = timestamp_us().wrapping_sub(packet.timestamp);
+
+        match packet.kind {
+            Type::Reset => {
+                self.state = State::Closed;
+                return;
+            }
+            // Our reply to SYN was lost
+            Type::Syn => {
+                if self.connect.is_none() {
+                    self.send_state().await;
+                }
+                return;
+            }
+            _ => (),
+        }
+
+        match self.state {
+            // Only reply to SYN carries sequence number of peer
+            State::SynSent => {
+                if packet.kind != Type::State || packet.ack_nr != SYN_NR {
+                    return;
+                }
+
+                self.state = State::Connected;
+                self.ack_nr = packet.seq_nr.wrapping_sub(1);
+                self.send_state().await;
+                if let Some((stream, resp_ch)) = self.connect.take() {
+                    // When nobody waits for stream, it's dropped and connection is closed
+                    let _ = resp_ch.send(Ok(stream));
+                }
+            }
+            State::SynRecv => {
+                self.state = State::Connected;
+                self.timeouts = 0;
+            }
+            // Reply to SYN was retransmitted, because our confirmation was lost
+            State::Connected
+                if packet.kind == Type::State
+                    && packet.ack_nr == SYN_NR
+                    && self.seq_nr == SYN_NR.wrapping_add(1) =>
+            {
+                self.send_state().await;
+            }
+            _ => (),
+        }
+
+        self.peer_wnd = packet.wnd_size as usize;
+        self.handle_ack(&packet).await;
+
+        if packet.kind == Type::Data || packet.kind == Type::Fin {
+            self.handle_data(packet).await;
+        }
+    }
+
+    async fn handle_ack(&mut self, packet: &Packet) {
+        let now = Instant::now();
+        let mut acked = 0;
+        let mut popped = 0;
+        let mut rtt_sample = None;
+        while let Some(front) = self.in_flight.front() {
+            if packet.ack_nr.wrapping_sub(front.packet.seq_nr) >= 0x8000 {
+                break;
+            }
+
+            let sent = self.in_flight.pop_front().unwrap();
+            acked += sent.packet.payload.len();
+            popped += 1;
+            // Karn's algorithm, retransmitted packets are ambiguous
+            if !sent.retransmitted {
+                rtt_sample = Some(now - sent.sent);
+            }
+        }
+
+        if popped > 0 {
+            self.in_flight_bytes -= acked;
+            self.timeouts = 0;
+            self.dup_acks = 0;
+            if let Some(sample) = rtt_sample {
+                self.update_rtt(sample);
+            }
+            self.ledbat.on_ack(acked, packet.timestamp_diff);
+            return;
+        }
+
+        let front_nr = match self.in_flight.front() {
+            Some(front) => front.packet.seq_nr,
+            None => return,
+        };
+        if packet.kind == Type::State && packet.ack_nr == front_nr.wrapping_sub(1) {
+            self.dup_acks += 1;
+            if self.dup_acks == DUP_ACK_LIMIT {
+                self.ledbat.on_loss();
+                self.retransmit_front().await;
+            }
+        }
+    }
+
+    fn update_rtt(&mut self, sample: Duration) {
+        let sample = sample.as_micros() as i64;
+        let (rtt, rtt_var) = match self.rtt {
+            None => (sample, sample / 2),
+            Some((rtt, rtt_var)) => (
+                rtt + (sample - rtt) / 8,
+                rtt_var + ((rtt - sample).abs() - rtt_var) / 4,
+            ),
+        };
+
+        self.rtt = Some((rtt, rtt_var));
+        self.timeout = Duration::from_micros((rtt + 4 * rtt_var) as u64).clamp(
+            Duration::from_millis(MIN_TIMEOUT_MS),
+            Duration::from_millis(MAX_TIMEOUT_MS),
+        );
+    }
+
+    async fn handle_data(&mut self, packet: Packet) {
+        let ahead = packet.seq_nr.wrapping_sub(self.ack_nr.wrapping_add(1));
+        match ahead {
+            0 => {
+                self.accept_packet(packet);
+                while let Some(next) = self.out_of_order.remove(&self.ack_nr.wrapping_add(1)) {
+                    self.accept_packet(next);
+                }
+            }
+            ahead if ahead < MAX_PACKETS_AHEAD && self.eof_nr.is_none() => {
+                self.out_of_order.insert(packet.seq_nr, packet);
+            }
+            // Duplicate (our ACK was lost) or too far ahead
+            _ => (),
+        }
+
+        self.send_state().await;
+    }
+
+    fn accept_packet(&mut self, packet: Packet) {
+        self.ack_nr = packet.seq_nr;
+        match packet.kind {
+            Type::Fin => {
+                self.eof_nr = Some(packet.seq_nr);
+                self.out_of_order.clear();
+            }
+            _ if self.app_writer.is_some() => self.pending.extend_from_slice(&packet.payload),
+            _ => (),
+        }
+    }
+
+    async fn handle_app_data(&mut self, data: &[u8]) {
+        match data.is_empty() {
+            true => {
+                self.app_eof = true;
+                self.fin_nr = Some(self.seq_nr);
+                self.send_new(Type::Fin, vec![]).await;
+            }
+            false => self.send_new(Type::Data, data.to_vec()).await,
+        }
+    }
+
+    fn handle_app_written(&mut self, result: io::Result<usize>) {
+        match result {
+            Ok(n) => {
+                self.pending.drain(..n);
+            }
+            // Application dropped the stream, so received data is discarded
+            Err(_) => {
+                self.app_writer = None;
+                self.pending.clear();
+            }
+        }
+    }
+
+    async fn handle_timeout(&mut self) {
+        let now = Instant::now();
+        match self.in_flight.front() {
+            Some(front) if now >= front.sent + self.timeout => (),
+            Some(_) => return,
+            None if self.state == State::SynRecv => {
+                if now < self.deadline() {
+                    return;
+                }
+
+                self.timeouts += 1;
+                match self.timeouts >= SYN_ATTEMPTS {
+                    true => self.state = State::Closed,
+                    false => self.send_state().await,
+                }
+                return;
+            }
+            None => {
+                if self.fin_nr.is_some() && now >= self.last_recv + Duration::from_secs(LINGER_SEC)
+                {
+                    self.state = State::Closed;
+                }
+                return;
+            }
+        }
+
+        self.timeouts += 1;
+        let max_timeouts = match self.state {
+            State::SynSent => SYN_ATTEMPTS,
+            _ => MAX_TIMEOUTS,
+        };
+        if self.timeouts >= max_timeouts {
+            if let Some((_, resp_ch)) = self.connect.take() {
+                let _ = resp_ch.send(Err(Error::UtpTimeout));
+            }
+            self.state = State::Closed;
+            return;
+        }
+
+        // SYN is resend quickly, so fallback to TCP doesn't take long
+        if self.state == State::Connected {
+            self.timeout = (self.timeout * 2).min(Duration::from_millis(MAX_TIMEOUT_MS));
+        }
+        self.ledbat.on_timeout();
+        self.retransmit_front().await;
+    }
+
+    async fn check_finished(&mut self) {
+        // Peer finished sending, and everything was passed to application
+        if self.eof_nr == Some(self.ack_nr) && self.pending.is_empty() {
+            if let Some(mut writer) = self.app_writer.take() {
+                let _ = writer.shutdown().await;
+            }
+        }
+
+        if self.eof_nr.is_some()
+            && self.app_writer.is_none()
+            && self.fin_nr.is_some()
+            && self.in_flight.is_empty()
+        {
+            self.state = State::Closed;
+        }
+    }
+
+    /// Send packet with next sequence number, and keep it until it's acknowledged.
+    async fn send_new(&mut self, kind: Type, payload: Vec<u8>) {
+        let conn_id = match kind {
+            Type::Syn => self.recv_id,
+            _ => self.send_id,
+        };
+        let mut packet = Packet::new(kind, conn_id, self.seq_nr, self.ack_nr);
+        packet.payload = payload;
+        self.seq_nr = self.seq_nr.wrapping_add(1);
+
+        self.in_flight_bytes += packet.payload.len();
+        self.in_flight.push_back(Sent {
+            packet: packet.clone(),
+            sent: Instant::now(),
+            retransmitted: false,
+        });
+        self.send(&mut packet).await;
+    }
+
+    async fn retransmit_front(&mut self) {
+        let mut packet = match self.in_flight.front_mut() {
+            Some(front) => {
+                front.sent = Instant::now();
+                front.retransmitted = true;
+                front.packet.clone()
+            }
+            None => return,
+        };
+
+        self.send(&mut packet).await;
+    }
+
+    async fn send_state(&mut self) {
+        let mut packet = Packet::new(Type::State, self.send_id, self.seq_nr, self.ack_nr);
+        self.send(&mut packet).await;
+    }
+
+    async fn send(&mut self, packet: &mut Packet) {
+        packet.timestamp = timestamp_us();
+        packet.timestamp_diff = self.reply_micro;
+        packet.wnd_size = self.recv_window() as u32;
+        if packet.kind != Type::Syn {
+            packet.ack_nr = self.ack_nr;
+        }
+
+        // Lost datagrams are retransmitted, so errors can be ignored
+        let _ = self.socket.send_to(&packet.data(), self.addr).await;
+    }
+}
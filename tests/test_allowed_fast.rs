@@ -0,0 +1,34 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::allowed_fast_set;
+
+const INFO_HASH: [u8; 20] = [0xaa; 20];
+
+#[test]
+fn bep6_reference_set() {
+    assert_eq!(
+        allowed_fast_set("80.4.4.200".parse().unwrap(), &INFO_HASH, 1313, 9),
+        vec![1059, 431, 808, 1217, 287, 376, 1188, 353, 508]
+    );
+}
+
+#[test]
+fn same_set_for_the_same_network() {
+    assert_eq!(
+        allowed_fast_set("80.4.4.1".parse().unwrap(), &INFO_HASH, 1313, 7),
+        allowed_fast_set("80.4.4.200".parse().unwrap(), &INFO_HASH, 1313, 7)
+    );
+}
+
+#[test]
+fn set_limited_by_pieces_num() {
+    let mut set = allowed_fast_set("80.4.4.200".parse().unwrap(), &INFO_HASH, 3, 10);
+    set.sort();
+    assert_eq!(set, vec![0, 1, 2]);
+}
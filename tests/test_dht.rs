@@ -0,0 +1,122 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Dht, DhtNode, DhtSource, Error, PeerSource, PeerSourceEvent};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+const INFO_HASH: [u8; 20] = *b"AAAAABBBBBCCCCCDDDDD";
+
+async fn spawn_node(state_path: Option<PathBuf>) -> (Dht, SocketAddr) {
+    let mut node = DhtNode::bind(0, state_path).await.unwrap();
+    let addr = SocketAddr::from(([127, 0, 0, 1], node.local_addr().unwrap().port()));
+    let dht = node.handle();
+    tokio::spawn(async move { node.run().await });
+    (dht, addr)
+}
+
+/// First node acts as router, and the rest bootstrap from it.
+async fn spawn_network(size: usize) -> Vec<(Dht, SocketAddr)> {
+    let mut nodes = vec![spawn_node(None).await];
+    let router = nodes[0].1;
+    for _ in 1..size {
+        let node = spawn_node(None).await;
+        node.0.bootstrap(&[router]).await.unwrap();
+        nodes.push(node);
+    }
+
+    nodes
+}
+
+#[tokio::test]
+async fn ping() {
+    let (dht1, _) = spawn_node(None).await;
+    let (dht2, addr2) = spawn_node(None).await;
+
+    assert_eq!(dht1.ping(addr2).await, Ok(*dht2.id()));
+    assert_eq!(dht1.nodes().await.unwrap(), vec![(*dht2.id(), addr2)]);
+}
+
+#[tokio::test]
+async fn ping_timeout() {
+    let (dht, _) = spawn_node(None).await;
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    assert_eq!(
+        dht.ping(socket.local_addr().unwrap()).await,
+        Err(Error::DhtTimeout)
+    );
+}
+
+#[tokio::test]
+async fn bootstrap() {
+    let nodes = spawn_network(5).await;
+
+    // Last node learns about others from router
+    let (dht, _) = &nodes[4];
+    let known = dht.nodes().await.unwrap();
+    assert_eq!(known.len(), 4);
+    for (other, addr) in nodes[..4].iter() {
+        assert!(known.contains(&(*other.id(), *addr)));
+    }
+}
+
+#[tokio::test]
+async fn announce_and_get_peers() {
+    let nodes = spawn_network(6).await;
+
+    let peers = nodes[2].0.announce_peer(&INFO_HASH, 6881).await.unwrap();
+    assert!(peers.is_empty());
+
+    let peers = nodes[5].0.get_peers(&INFO_HASH).await.unwrap();
+    assert_eq!(peers, vec!["127.0.0.1:6881".parse().unwrap()]);
+}
+
+#[tokio::test]
+async fn dht_source() {
+    let nodes = spawn_network(4).await;
+    nodes[1].0.announce_peer(&INFO_HASH, 6882).await.unwrap();
+
+    let mut source = DhtSource::new(nodes[3].0.clone(), INFO_HASH, 6883);
+    match source.next().await {
+        Some(PeerSourceEvent::Peers(peers)) => {
+            assert_eq!(peers, vec![("127.0.0.1:6882".parse().unwrap(), None)])
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+
+    // Source announced itself too
+    let mut peers = nodes[0].0.get_peers(&INFO_HASH).await.unwrap();
+    peers.sort();
+    assert_eq!(
+        peers,
+        vec![
+            "127.0.0.1:6882".parse().unwrap(),
+            "127.0.0.1:6883".parse().unwrap()
+        ]
+    );
+}
+
+#[tokio::test]
+async fn routing_table_persisted() {
+    let path = std::env::temp_dir().join(format!("rdest-dht-{}.dat", std::process::id()));
+    let (other, other_addr) = spawn_node(None).await;
+
+    let (dht, _) = spawn_node(Some(path.clone())).await;
+    dht.ping(other_addr).await.unwrap();
+    dht.save().await.unwrap();
+
+    let (restored, _) = spawn_node(Some(path.clone())).await;
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(restored.id(), dht.id());
+    assert_eq!(
+        restored.nodes().await.unwrap(),
+        vec![(*other.id(), other_addr)]
+    );
+}
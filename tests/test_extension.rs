@@ -0,0 +1,125 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Error, ExtendedHandshake, Extension, ExtensionRegistry};
+use std::collections::HashMap;
+
+/// Reply with the same payload, and greet peer after handshake.
+struct Echo;
+
+impl Extension for Echo {
+    fn on_handshake(&mut self, _handshake: &ExtendedHandshake) -> Vec<Vec<u8>> {
+        vec![b"hello".to_vec()]
+    }
+
+    fn on_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        Ok(vec![payload.to_vec()])
+    }
+}
+
+struct Silent;
+
+impl Extension for Silent {
+    fn on_message(&mut self, _payload: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        Ok(vec![])
+    }
+}
+
+fn peer_handshake(extensions: &[(&str, u8)]) -> ExtendedHandshake {
+    ExtendedHandshake {
+        extensions: extensions
+            .iter()
+            .map(|(name, id)| (name.to_string(), *id))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn handshake_encode_decode() {
+    let handshake = ExtendedHandshake {
+        extensions: HashMap::from([("ut_pex".to_string(), 1), ("lt_donthave".to_string(), 0)]),
+        client: Some("rdest 0.1.0".to_string()),
+        port: Some(6881),
+        reqq: Some(250),
+        your_ip: Some("10.0.0.1".parse().unwrap()),
+        metadata_size: Some(31235),
+        upload_only: Some(true),
+    };
+
+    assert_eq!(
+        ExtendedHandshake::from_bencode(&handshake.encode()),
+        Ok(handshake)
+    );
+}
+
+#[test]
+fn handshake_incorrect_optional_values_ignored() {
+    let handshake =
+        ExtendedHandshake::from_bencode(b"d1:md6:ut_pexi300e5:ut_xx1:1e1:pi-1e6:yourip3:abce")
+            .unwrap();
+
+    assert_eq!(handshake, ExtendedHandshake::default());
+}
+
+#[test]
+fn handshake_not_dictionary() {
+    assert_eq!(
+        ExtendedHandshake::from_bencode(b"i5e"),
+        Err(Error::ExtensionIncorrectOrMissing("dictionary"))
+    );
+}
+
+#[test]
+fn own_handshake_ids_in_registration_order() {
+    let mut registry = ExtensionRegistry::new();
+    registry.register("silent", |_| Box::new(Silent));
+    registry.register("echo", |_| Box::new(Echo));
+    registry.register("silent", |_| Box::new(Echo));
+
+    let handshake = registry
+        .create("127.0.0.1:6881".parse().unwrap())
+        .handshake();
+    assert_eq!(registry.names(), vec!["silent", "echo"]);
+    assert_eq!(handshake.extensions.get("silent"), Some(&1));
+    assert_eq!(handshake.extensions.get("echo"), Some(&2));
+    assert_eq!(handshake.reqq, Some(250));
+}
+
+#[test]
+fn route_messages() {
+    let mut registry = ExtensionRegistry::new();
+    registry.register("silent", |_| Box::new(Silent));
+    registry.register("echo", |_| Box::new(Echo));
+    let mut extensions = registry.create("127.0.0.1:6881".parse().unwrap());
+
+    // Peer assigned own ID's
+    assert_eq!(
+        extensions.on_handshake(&peer_handshake(&[("echo", 7), ("silent", 0)])),
+        vec![(7, b"hello".to_vec())]
+    );
+    assert!(extensions.peer_supports("echo"));
+    assert!(!extensions.peer_supports("silent"));
+
+    assert_eq!(
+        extensions.on_message(2, b"ping"),
+        Ok(vec![(7, b"ping".to_vec())])
+    );
+    assert_eq!(extensions.on_message(1, b"ping"), Ok(vec![]));
+    assert_eq!(extensions.on_message(9, b"ping"), Ok(vec![]));
+}
+
+#[test]
+fn no_messages_before_handshake() {
+    let mut registry = ExtensionRegistry::new();
+    registry.register("echo", |_| Box::new(Echo));
+    let mut extensions = registry.create("127.0.0.1:6881".parse().unwrap());
+
+    assert_eq!(extensions.on_message(1, b"ping"), Ok(vec![]));
+    assert!(extensions.on_tick().is_empty());
+}
@@ -0,0 +1,269 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::mse::Encryption;
+use rdest::{
+    Error, ExtendedHandshake, Extension, HolepunchMsg, Metainfo, PexMsg, Session, UtpSocket,
+};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
+
+const PEX_ID: u8 = 1;
+const HOLEPUNCH_ID: u8 = 2;
+
+/// Reports listen port of every peer, that finished extension handshake.
+struct Probe {
+    tx: mpsc::UnboundedSender<Option<u16>>,
+}
+
+impl Extension for Probe {
+    fn on_handshake(&mut self, handshake: &ExtendedHandshake) -> Vec<Vec<u8>> {
+        let _ = self.tx.send(handshake.port);
+        vec![]
+    }
+
+    fn on_message(&mut self, _payload: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        Ok(vec![])
+    }
+}
+
+#[test]
+fn encode_and_decode_v4() {
+    let msg = HolepunchMsg {
+        msg_type: HolepunchMsg::ERROR,
+        addr: "10.0.0.1:6881".parse().unwrap(),
+        err_code: HolepunchMsg::NO_SUPPORT,
+    };
+
+    let data = msg.encode();
+    assert_eq!(data, b"\x02\x00\x0a\x00\x00\x01\x1a\xe1\x00\x00\x00\x03");
+    assert_eq!(HolepunchMsg::from_bytes(&data), Ok(msg));
+}
+
+#[test]
+fn encode_and_decode_v6() {
+    let msg = HolepunchMsg {
+        msg_type: HolepunchMsg::RENDEZVOUS,
+        addr: "[2001:db8::1]:51413".parse().unwrap(),
+        err_code: 0,
+    };
+
+    let data = msg.encode();
+    assert_eq!(data.len(), 1 + 1 + 16 + 2 + 4);
+    assert_eq!(data[1], 0x01);
+    assert_eq!(HolepunchMsg::from_bytes(&data), Ok(msg));
+}
+
+#[test]
+fn from_bytes_invalid() {
+    assert_eq!(
+        HolepunchMsg::from_bytes(b"\x00"),
+        Err(Error::HolepunchInvalidMsg("length"))
+    );
+    assert_eq!(
+        HolepunchMsg::from_bytes(b"\x03\x00\x0a\x00\x00\x01\x1a\xe1\x00\x00\x00\x00"),
+        Err(Error::HolepunchInvalidMsg("msg_type"))
+    );
+    assert_eq!(
+        HolepunchMsg::from_bytes(b"\x01\x02\x0a\x00\x00\x01\x1a\xe1\x00\x00\x00\x00"),
+        Err(Error::HolepunchInvalidMsg("addr_type"))
+    );
+    assert_eq!(
+        HolepunchMsg::from_bytes(b"\x01\x01\x0a\x00\x00\x01\x1a\xe1\x00\x00\x00\x00"),
+        Err(Error::HolepunchInvalidMsg("length"))
+    );
+}
+
+#[tokio::test]
+async fn punch_is_ignored_by_peer() {
+    let mut target = UtpSocket::bind(0).await.unwrap();
+    let initiator = UtpSocket::bind(0).await.unwrap();
+    let target_addr = ([127, 0, 0, 1], target.local_addr().port()).into();
+    let initiator_addr = ([127, 0, 0, 1], initiator.local_addr().port()).into();
+
+    // Target opens NAT mapping, and initiator connects through it
+    target.handle().punch(initiator_addr).await.unwrap();
+    let mut stream = initiator.handle().connect(target_addr).await.unwrap();
+    stream.write_all(b"hello").await.unwrap();
+
+    let mut accepted = target.accept().await.unwrap();
+    let mut buf = [0; 5];
+    accepted.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+}
+
+async fn new_session(own_id: &[u8; 20]) -> (Session, u16, mpsc::UnboundedReceiver<Option<u16>>) {
+    let torrent = b"d8:announce17:udp://127.0.0.1:14:infod4:name4:NAME12:piece lengthi10e6:pieces20:AAAAABBBBBCCCCCDDDDD6:lengthi10eee";
+    let mut session = Session::new(Metainfo::from_bencode(torrent).unwrap(), *own_id);
+    session.set_listen_ip("127.0.0.1".parse().unwrap());
+    session.set_listen_ports(0..=0);
+    session.set_encryption(Encryption::Disabled);
+    session.set_utp(false);
+    let (tx, rx) = mpsc::unbounded_channel();
+    session.register_extension("test_probe", move |_| Box::new(Probe { tx: tx.clone() }));
+    let port = session.bind().await.unwrap();
+
+    (session, port, rx)
+}
+
+/// Connection with session, after BitTorrent and extension handshakes.
+struct RelayedPeer {
+    stream: TcpStream,
+    peer_id: [u8; 20],
+    handshake: ExtendedHandshake,
+}
+
+impl RelayedPeer {
+    async fn accept(listener: &TcpListener) -> RelayedPeer {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut handshake = [0; 68];
+        stream.read_exact(&mut handshake).await.unwrap();
+        let mut peer_id = [0; 20];
+        peer_id.copy_from_slice(&handshake[48..]);
+
+        // The same info hash, and only extension protocol bit set
+        handshake[20..28].copy_from_slice(&[0, 0, 0, 0, 0, 0x10, 0, 0]);
+        handshake[48..].copy_from_slice(b"RRRRRRRRRRRRRRRRRRRR");
+        stream.write_all(&handshake).await.unwrap();
+
+        let own = ExtendedHandshake {
+            extensions: HashMap::from([
+                ("ut_pex".to_string(), PEX_ID),
+                ("ut_holepunch".to_string(), HOLEPUNCH_ID),
+            ]),
+            ..Default::default()
+        };
+        let mut peer = RelayedPeer {
+            stream,
+            peer_id,
+            handshake: ExtendedHandshake::default(),
+        };
+        peer.send_extended(0, &own.encode()).await;
+        peer.handshake = ExtendedHandshake::from_bencode(&peer.recv_extended(0).await).unwrap();
+        peer
+    }
+
+    fn listen_addr(&self) -> SocketAddr {
+        ([127, 0, 0, 1], self.handshake.port.unwrap()).into()
+    }
+
+    async fn send_extended(&mut self, ext_id: u8, payload: &[u8]) {
+        let mut frame = ((payload.len() + 2) as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&[20, ext_id]);
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame).await.unwrap();
+    }
+
+    async fn send_holepunch(&mut self, msg: HolepunchMsg) {
+        let ext_id = self.handshake.extensions["ut_holepunch"];
+        self.send_extended(ext_id, &msg.encode()).await;
+    }
+
+    /// Skip other messages, until extended one with given ID comes.
+    async fn recv_extended(&mut self, ext_id: u8) -> Vec<u8> {
+        loop {
+            let len = self.stream.read_u32().await.unwrap() as usize;
+            let mut frame = vec![0; len];
+            self.stream.read_exact(&mut frame).await.unwrap();
+            if frame.len() >= 2 && frame[0] == 20 && frame[1] == ext_id {
+                return frame[2..].to_vec();
+            }
+        }
+    }
+}
+
+/// Relay stand-in. It introduces initiator (through PEX) to target, with address that initiator
+/// can't connect to, and arranges rendezvous when asked for it.
+async fn run_relay(listener: TcpListener, unreachable: SocketAddr, pex_sent: oneshot::Sender<()>) {
+    let mut initiator = RelayedPeer::accept(&listener).await;
+    let mut target = RelayedPeer::accept(&listener).await;
+    if initiator.peer_id[0] != b'I' {
+        std::mem::swap(&mut initiator, &mut target);
+    }
+
+    let pex = PexMsg {
+        added: vec![(unreachable, PexMsg::OUTGOING | PexMsg::HOLEPUNCH)],
+        dropped: vec![],
+    };
+    let ext_id = initiator.handshake.extensions["ut_pex"];
+    initiator.send_extended(ext_id, &pex.encode()).await;
+    let _ = pex_sent.send(());
+
+    let msg = HolepunchMsg::from_bytes(&initiator.recv_extended(HOLEPUNCH_ID).await).unwrap();
+    assert_eq!(msg.msg_type, HolepunchMsg::RENDEZVOUS);
+    assert_eq!(msg.addr, unreachable);
+
+    let initiator_addr = initiator.listen_addr();
+    target
+        .send_holepunch(HolepunchMsg {
+            msg_type: HolepunchMsg::CONNECT,
+            addr: initiator_addr,
+            err_code: 0,
+        })
+        .await;
+    initiator
+        .send_holepunch(HolepunchMsg {
+            msg_type: HolepunchMsg::CONNECT,
+            addr: unreachable,
+            err_code: 0,
+        })
+        .await;
+
+    // Both connections are kept open
+    let _ = tokio::join!(
+        initiator.recv_extended(u8::MAX),
+        target.recv_extended(u8::MAX)
+    );
+}
+
+#[tokio::test]
+async fn rendezvous_through_relay() {
+    let (mut initiator, _, mut initiator_rx) = new_session(b"IIIIIIIIIIIIIIIIIIII").await;
+    let (mut target, target_port, _) = new_session(b"TTTTTTTTTTTTTTTTTTTT").await;
+
+    // Target is behind NAT, nothing listens on its address known to relay
+    let unreachable = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let relay = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let relay_addr = relay.local_addr().unwrap();
+    let (pex_tx, pex_rx) = oneshot::channel();
+    tokio::spawn(run_relay(relay, unreachable, pex_tx));
+
+    // Initiator connects to peers from PEX only when it needs more of them, e.g. when this one
+    // disconnects
+    let other = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let other_addr = other.local_addr().unwrap();
+    tokio::spawn(async move {
+        let _stream = other.accept().await.unwrap();
+        let _ = pex_rx.await;
+        time::sleep(Duration::from_millis(500)).await;
+    });
+
+    initiator.add_peers(&[relay_addr, other_addr]);
+    target.add_peers(&[relay_addr]);
+
+    time::timeout(Duration::from_secs(30), async {
+        tokio::select! {
+            _ = initiator.run() => panic!("Initiator finished"),
+            _ = target.run() => panic!("Target finished"),
+            _ = async {
+                while initiator_rx.recv().await != Some(Some(target_port)) {}
+            } => (),
+        }
+    })
+    .await
+    .expect("Initiator not connected with target");
+}
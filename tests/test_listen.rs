@@ -0,0 +1,58 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Error, Metainfo, Session};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+
+fn new_session() -> Session {
+    let torrent =
+        b"d8:announce3:URL4:infod6:lengthi222e4:name4:NAME12:piece lengthi111e6:pieces40:AAAAABBBBBCCCCCDDDDDEEEEEFFFFFGGGGGHHHHHee";
+    let metainfo = Metainfo::from_bencode(torrent).unwrap();
+    let mut session = Session::new(metainfo, *b"AAAAABBBBBCCCCCDDDDD");
+    session.set_listen_ip("127.0.0.1".parse().unwrap());
+    session
+}
+
+#[tokio::test]
+async fn bind_falls_back_to_next_port() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = taken.local_addr().unwrap().port();
+
+    let mut session = new_session();
+    session.set_listen_ports(port..=port.saturating_add(8));
+    let bound = session.bind().await.unwrap();
+
+    assert!(bound > port);
+    assert!(TcpStream::connect(("127.0.0.1", bound)).is_ok());
+}
+
+#[tokio::test]
+async fn bind_all_ports_taken() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = taken.local_addr().unwrap().port();
+
+    let mut session = new_session();
+    session.set_listen_ports(port..=port);
+
+    assert_eq!(session.bind().await, Err(Error::ListenBind(port, port)));
+}
+
+#[tokio::test]
+async fn bind_port_shared_with_utp() {
+    let taken = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let port = taken.local_addr().unwrap().port();
+
+    let mut session = new_session();
+    session.set_listen_ports(port..=port);
+    assert_eq!(session.bind().await, Err(Error::ListenBind(port, port)));
+
+    let mut session = new_session();
+    session.set_listen_ports(port..=port);
+    session.set_utp(false);
+    assert_eq!(session.bind().await, Ok(port));
+}
@@ -0,0 +1,67 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Error, LsdMsg};
+
+#[test]
+fn from_bytes_many_hashes() {
+    let data = b"BT-SEARCH * HTTP/1.1\r\n\
+        host: [ff15::efc0:988f]:6771\r\n\
+        port: 51413\r\n\
+        infohash: 4141414141424242424243434343434444444444\r\n\
+        INFOHASH: 0123456789abcdef0123456789ABCDEF01234567\r\n\
+        cookie: xyz\r\n\
+        \r\n\r\n";
+
+    assert_eq!(
+        LsdMsg::from_bytes(data),
+        Ok(LsdMsg {
+            port: 51413,
+            info_hashes: vec![
+                *b"AAAAABBBBBCCCCCDDDDD",
+                [
+                    0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89,
+                    0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67
+                ]
+            ],
+            cookie: Some("xyz".to_string()),
+        })
+    );
+}
+
+#[test]
+fn encode_and_decode() {
+    let msg = LsdMsg {
+        port: 6881,
+        info_hashes: vec![*b"AAAAABBBBBCCCCCDDDDD"],
+        cookie: Some("abc".to_string()),
+    };
+
+    let data = msg.encode("239.192.152.143:6771");
+    assert!(data.starts_with(b"BT-SEARCH * HTTP/1.1\r\nHost: 239.192.152.143:6771\r\n"));
+    assert!(data.ends_with(b"\r\n\r\n\r\n"));
+    assert_eq!(LsdMsg::from_bytes(&data), Ok(msg));
+}
+
+#[test]
+fn from_bytes_invalid() {
+    assert_eq!(
+        LsdMsg::from_bytes(b"M-SEARCH * HTTP/1.1\r\nPort: 6881\r\n\r\n\r\n"),
+        Err(Error::LsdInvalidMsg("request line"))
+    );
+    assert_eq!(
+        LsdMsg::from_bytes(
+            b"BT-SEARCH * HTTP/1.1\r\nInfohash: 4141414141424242424243434343434444444444\r\n\r\n\r\n"
+        ),
+        Err(Error::LsdInvalidMsg("port"))
+    );
+    assert_eq!(
+        LsdMsg::from_bytes(b"BT-SEARCH * HTTP/1.1\r\nPort: 6881\r\nInfohash: 41\r\n\r\n\r\n"),
+        Err(Error::LsdInvalidMsg("infohash"))
+    );
+}
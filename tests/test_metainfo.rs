@@ -17,6 +17,44 @@ fn find_announce_incorrect() {
     );
 }
 
+#[test]
+fn find_announce_missing_trackerless() {
+    assert_eq!(
+        Metainfo::find_announce(&hashmap![b"info".to_vec() => BValue::Int(5)]),
+        Ok(String::new())
+    );
+}
+
+#[test]
+fn find_nodes_skip_incorrect() {
+    let nodes = vec![
+        BValue::List(vec![
+            BValue::ByteStr(b"127.0.0.1".to_vec()),
+            BValue::Int(6881),
+        ]),
+        BValue::List(vec![BValue::ByteStr(b"router".to_vec()), BValue::Int(-1)]),
+        BValue::Int(5),
+    ];
+
+    assert_eq!(
+        Metainfo::find_nodes(&hashmap![b"nodes".to_vec() => BValue::List(nodes)]),
+        vec![("127.0.0.1".to_string(), 6881)]
+    );
+}
+
+#[test]
+fn find_private() {
+    let private =
+        |value| hashmap![b"info".to_vec() => BValue::Dict(hashmap![b"private".to_vec() => value])];
+
+    assert!(Metainfo::find_private(&private(BValue::Int(1))));
+    assert!(!Metainfo::find_private(&private(BValue::Int(0))));
+    assert!(!Metainfo::find_private(&private(BValue::ByteStr(
+        b"1".to_vec()
+    ))));
+    assert!(!Metainfo::find_private(&hashmap![]));
+}
+
 #[test]
 fn find_announce_ok() {
     assert_eq!(
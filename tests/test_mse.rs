@@ -0,0 +1,89 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::mse::{self, Cipher, CRYPTO_PLAINTEXT, CRYPTO_RC4};
+use rdest::Error;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+
+const INFO_HASH: [u8; 20] = *b"AAAAABBBBBCCCCCDDDDD";
+
+type Outgoing = Result<Option<Cipher>, Error>;
+type Incoming = Result<(Option<Cipher>, Vec<u8>), Error>;
+
+async fn handshake(
+    outgoing_hash: [u8; 20],
+    crypto_provide: u32,
+    crypto_accept: u32,
+) -> (Outgoing, Incoming) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let responder = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut prefix = [0; 20];
+        socket.read_exact(&mut prefix).await.unwrap();
+        assert!(!mse::is_plain_handshake(&prefix));
+        mse::handshake_incoming(&mut socket, &prefix, &INFO_HASH, crypto_accept).await
+    });
+
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+    let outgoing = mse::handshake_outgoing(&mut socket, &outgoing_hash, crypto_provide).await;
+    (outgoing, responder.await.unwrap())
+}
+
+#[tokio::test]
+async fn rc4_selected() {
+    let (outgoing, incoming) = handshake(
+        INFO_HASH,
+        CRYPTO_RC4 | CRYPTO_PLAINTEXT,
+        CRYPTO_RC4 | CRYPTO_PLAINTEXT,
+    )
+    .await;
+    let mut initiator = outgoing.unwrap().unwrap();
+    let (responder, payload) = incoming.unwrap();
+    let mut responder = responder.unwrap();
+    assert!(payload.is_empty());
+
+    let mut data = b"hello".to_vec();
+    initiator.encrypt(&mut data);
+    assert_ne!(data, b"hello");
+    responder.decrypt(&mut data);
+    assert_eq!(data, b"hello");
+
+    let mut data = b"world".to_vec();
+    responder.encrypt(&mut data);
+    initiator.decrypt(&mut data);
+    assert_eq!(data, b"world");
+}
+
+#[tokio::test]
+async fn plaintext_selected() {
+    let (outgoing, incoming) =
+        handshake(INFO_HASH, CRYPTO_PLAINTEXT, CRYPTO_RC4 | CRYPTO_PLAINTEXT).await;
+
+    assert!(outgoing.unwrap().is_none());
+    assert!(incoming.unwrap().0.is_none());
+}
+
+#[tokio::test]
+async fn no_common_crypto_method() {
+    let (_, incoming) = handshake(INFO_HASH, CRYPTO_PLAINTEXT, CRYPTO_RC4).await;
+
+    assert_eq!(
+        incoming.err(),
+        Some(Error::MseFail("no common crypto method"))
+    );
+}
+
+#[tokio::test]
+async fn unknown_info_hash() {
+    let (_, incoming) = handshake(*b"XXXXXBBBBBCCCCCDDDDD", CRYPTO_RC4, CRYPTO_RC4).await;
+
+    assert_eq!(incoming.err(), Some(Error::MseFail("unknown info hash")));
+}
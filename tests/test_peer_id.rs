@@ -0,0 +1,61 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::peer_id;
+use rdest::peer_id::Client;
+
+fn client(name: &str, version: &str) -> Option<Client> {
+    Some(Client {
+        name: name.to_string(),
+        version: version.to_string(),
+    })
+}
+
+#[test]
+fn generate_own_client() {
+    let id = peer_id::generate();
+
+    assert!(id.starts_with(peer_id::PREFIX));
+    assert!(id[peer_id::PREFIX.len()..]
+        .iter()
+        .all(|b| b.is_ascii_alphanumeric()));
+    assert_eq!(peer_id::client(&id), client("rdest", "0.1.0"));
+}
+
+#[test]
+fn client_azureus_style() {
+    assert_eq!(
+        peer_id::client(b"-qB4250-abcdefghijkl"),
+        client("qBittorrent", "4.2.5")
+    );
+    assert_eq!(
+        peer_id::client(b"-UT355W-\x01\x02abcdefghij"),
+        client("µTorrent", "3.5.5.32")
+    );
+    assert_eq!(
+        peer_id::client(b"-XX1200-abcdefghijkl"),
+        client("XX", "1.2.0")
+    );
+}
+
+#[test]
+fn client_mainline_and_shadow_style() {
+    assert_eq!(
+        peer_id::client(b"M4-3-6--abcdefghijkl"),
+        client("Mainline", "4.3.6")
+    );
+    assert_eq!(
+        peer_id::client(b"M7-10-2-abcdefghijkl"),
+        client("Mainline", "7.10.2")
+    );
+    assert_eq!(
+        peer_id::client(b"T03I-----abcdefghijk"),
+        client("BitTornado", "0.3.18")
+    );
+    assert_eq!(peer_id::client(b"AAAAABBBBBCCCCCDDDDD"), None);
+}
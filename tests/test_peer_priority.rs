@@ -0,0 +1,61 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::peer_priority;
+
+#[test]
+fn bep40_reference_priorities() {
+    assert_eq!(
+        peer_priority(
+            "123.213.32.10:0".parse().unwrap(),
+            "98.76.54.32:0".parse().unwrap()
+        ),
+        0xec2d7224
+    );
+    assert_eq!(
+        peer_priority(
+            "123.213.32.10:0".parse().unwrap(),
+            "123.213.32.234:0".parse().unwrap()
+        ),
+        0x99568189
+    );
+}
+
+#[test]
+fn symmetric() {
+    let addrs = [
+        "123.213.32.10:6881",
+        "123.213.48.1:6881",
+        "123.213.32.10:6882",
+        "[2001:db8::1]:6881",
+        "[2001:db8:1::1]:6881",
+    ];
+
+    for addr1 in addrs.iter() {
+        for addr2 in addrs.iter() {
+            assert_eq!(
+                peer_priority(addr1.parse().unwrap(), addr2.parse().unwrap()),
+                peer_priority(addr2.parse().unwrap(), addr1.parse().unwrap())
+            );
+        }
+    }
+}
+
+#[test]
+fn same_ip_uses_ports() {
+    assert_ne!(
+        peer_priority(
+            "10.0.0.1:6881".parse().unwrap(),
+            "10.0.0.1:6882".parse().unwrap()
+        ),
+        peer_priority(
+            "10.0.0.1:6881".parse().unwrap(),
+            "10.0.0.1:6883".parse().unwrap()
+        )
+    );
+}
@@ -0,0 +1,180 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{
+    Metainfo, PeerSource, PeerSourceEvent, PeerSourceFuture, Session, StaticPeers, TrackerClient,
+    TrackerServer, TransferStats,
+};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::time;
+
+/// Mock source, that return new peer on every call, and start from the beginning on announce.
+struct MockSource {
+    port: u16,
+}
+
+impl PeerSource for MockSource {
+    fn name(&self) -> String {
+        "mock".to_string()
+    }
+
+    fn next(&mut self) -> PeerSourceFuture<'_, Option<PeerSourceEvent>> {
+        Box::pin(async move {
+            self.port += 1;
+            let addr = SocketAddr::from(([127, 0, 0, 1], self.port));
+            Some(PeerSourceEvent::Peers(vec![(addr, None)]))
+        })
+    }
+
+    fn announce(&mut self) {
+        self.port = 6880;
+    }
+}
+
+fn peers(event: Option<PeerSourceEvent>) -> Vec<SocketAddr> {
+    match event {
+        Some(PeerSourceEvent::Peers(peers)) => peers.iter().map(|(addr, _)| *addr).collect(),
+        _ => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn static_peers_repeated_on_announce() {
+    let addr: SocketAddr = "127.0.0.1:6881".parse().unwrap();
+    let mut source = StaticPeers::new(vec![addr]);
+
+    assert_eq!(peers(source.next().await), vec![addr]);
+    assert!(source.next().await.is_none());
+
+    source.announce();
+    assert_eq!(peers(source.next().await), vec![addr]);
+}
+
+#[tokio::test]
+async fn mock_source_as_trait_object() {
+    let mut source: Box<dyn PeerSource> = Box::new(MockSource { port: 6880 });
+
+    source.next().await;
+    assert_eq!(
+        peers(source.next().await),
+        vec!["127.0.0.1:6882".parse().unwrap()]
+    );
+
+    // Default hooks are no-op
+    source.completed();
+    source.stop().await;
+
+    source.announce();
+    assert_eq!(
+        peers(source.next().await),
+        vec!["127.0.0.1:6881".parse().unwrap()]
+    );
+}
+
+#[tokio::test]
+async fn tracker_client_hooks() {
+    let mut tracker = TrackerServer::bind(0, None).await.unwrap();
+    let url = format!(
+        "http://127.0.0.1:{}/announce",
+        tracker.local_addr().unwrap().port()
+    );
+    tokio::spawn(async move { tracker.run().await });
+
+    let torrent = format!(
+        "d8:announce{}:{}4:infod4:name4:NAME12:piece lengthi10e6:pieces20:AAAAABBBBBCCCCCDDDDD6:lengthi10eee",
+        url.len(),
+        url
+    );
+    let metainfo = Metainfo::from_bencode(torrent.as_bytes()).unwrap();
+    let info_hash = *metainfo.info_hash();
+    let (stats_tx, stats_rx) = watch::channel(TransferStats {
+        uploaded: 0,
+        downloaded: 0,
+        left: 10,
+    });
+    let mut source = TrackerClient::new(b"EEEEEFFFFFGGGGGHHHHH", metainfo, stats_rx);
+
+    assert!(matches!(
+        source.next().await,
+        Some(PeerSourceEvent::TrackerResp(_))
+    ));
+
+    stats_tx.send_replace(TransferStats {
+        uploaded: 0,
+        downloaded: 10,
+        left: 0,
+    });
+    source.completed();
+    source.next().await;
+    let resp = TrackerClient::scrape(&url, &[info_hash]).await.unwrap();
+    let file = resp.file(&info_hash).unwrap();
+    assert_eq!((file.complete, file.downloaded, file.incomplete), (1, 1, 0));
+
+    source.stop().await;
+    let resp = TrackerClient::scrape(&url, &[info_hash]).await.unwrap();
+    assert_eq!(resp.file(&info_hash).unwrap().complete, 0);
+}
+
+/// HTTP tracker stand-in, that returns request line of the next announce.
+async fn next_announce(listener: &TcpListener) -> String {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let mut req = vec![];
+    while !req.ends_with(b"\r\n\r\n") {
+        req.push(stream.read_u8().await.unwrap());
+    }
+    let body = b"d8:intervali1800e5:peers0:e";
+    let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+    stream.write_all(head.as_bytes()).await.unwrap();
+    stream.write_all(body).await.unwrap();
+
+    let req = String::from_utf8(req).unwrap();
+    req.lines().next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn partial_seed_announces_paused() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/announce", listener.local_addr().unwrap());
+    let torrent = format!(
+        "d8:announce{}:{}4:infod4:name4:NAME12:piece lengthi10e6:pieces40:AAAAABBBBBCCCCCDDDDDEEEEEFFFFFGGGGGHHHHH6:lengthi20eee",
+        url.len(),
+        url
+    );
+    let mut session = Session::new(
+        Metainfo::from_bencode(torrent.as_bytes()).unwrap(),
+        *b"EEEEEFFFFFGGGGGHHHHH",
+    );
+    session.set_listen_ip("127.0.0.1".parse().unwrap());
+    session.set_listen_ports(0..=0);
+    session.set_upload_only(true);
+
+    // Session doesn't have all pieces, and won't download missing ones
+    let announces = time::timeout(Duration::from_secs(10), async {
+        tokio::select! {
+            _ = session.run() => panic!("Session finished"),
+            announces = async {
+                let mut announces = vec![];
+                while !announces.iter().any(|line: &String| line.contains("event=paused")) {
+                    announces.push(next_announce(&listener).await);
+                }
+                announces
+            } => announces,
+        }
+    })
+    .await
+    .unwrap();
+
+    assert!(announces[0].contains("event=started"));
+    assert!(!announces
+        .iter()
+        .any(|line| line.contains("event=completed")));
+}
@@ -0,0 +1,51 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Error, PexMsg};
+
+#[test]
+fn encode_decode() {
+    let msg = PexMsg {
+        added: vec![
+            ("10.0.0.1:6881".parse().unwrap(), PexMsg::SEED | PexMsg::UTP),
+            ("10.0.0.2:6882".parse().unwrap(), PexMsg::OUTGOING),
+            ("[::1]:6883".parse().unwrap(), PexMsg::ENCRYPTION),
+        ],
+        dropped: vec![
+            "10.0.0.3:6884".parse().unwrap(),
+            "[::2]:6885".parse().unwrap(),
+        ],
+    };
+
+    assert_eq!(PexMsg::from_bencode(&msg.encode()), Ok(msg));
+}
+
+#[test]
+fn missing_flags() {
+    let msg = PexMsg::from_bencode(
+        b"d5:added12:\x0a\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x1a\xe27:added.f1:\x12e",
+    )
+    .unwrap();
+
+    assert_eq!(
+        msg.added,
+        vec![
+            ("10.0.0.1:6881".parse().unwrap(), 0x12),
+            ("10.0.0.2:6882".parse().unwrap(), 0),
+        ]
+    );
+    assert!(msg.dropped.is_empty());
+}
+
+#[test]
+fn incorrect_peers() {
+    assert_eq!(
+        PexMsg::from_bencode(b"d7:dropped5:AAAAAe"),
+        Err(Error::ExtensionIncorrectOrMissing("dropped"))
+    );
+}
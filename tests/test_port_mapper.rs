@@ -0,0 +1,271 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Mapping, NatProtocol, PortMapper};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::mpsc;
+
+const PORT: u16 = 6881;
+
+/// Address on which nothing listens, so requests are rejected straight away.
+async fn closed_addr() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.local_addr().unwrap()
+}
+
+/// PCP/NAT-PMP gateway, responding with `handler` and reporting every request.
+async fn spawn_gateway<F>(handler: F) -> (SocketAddr, mpsc::UnboundedReceiver<Vec<u8>>)
+where
+    F: Fn(&[u8]) -> Vec<u8> + Send + 'static,
+{
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut buff = [0; 1100];
+        while let Ok((len, from)) = socket.recv_from(&mut buff).await {
+            let _ = tx.send(buff[..len].to_vec());
+            let _ = socket.send_to(&handler(&buff[..len]), from).await;
+        }
+    });
+
+    (addr, rx)
+}
+
+/// NAT-PMP gateway, that doesn't understand PCP.
+fn natpmp_resp(req: &[u8]) -> Vec<u8> {
+    let epoch = 1000u32.to_be_bytes();
+    match (req[0], req[1]) {
+        // Unsupported version
+        (2, op) => [&[0, 0x80 | op, 0, 1], &epoch[..]].concat(),
+        (0, 0) => [&[0, 0x80, 0, 0], &epoch[..], &[203, 0, 113, 7]].concat(),
+        (0, op) => {
+            let lifetime = u32::from_be_bytes(req[8..12].try_into().unwrap()).min(3600);
+            let external_port = match lifetime {
+                0 => [0, 0],
+                _ => [0x1a, 0xe1],
+            };
+            [
+                &[0, 0x80 | op, 0, 0],
+                &epoch[..],
+                &req[4..6],
+                &external_port,
+                &lifetime.to_be_bytes(),
+            ]
+            .concat()
+        }
+        _ => vec![],
+    }
+}
+
+/// NAT-PMP gateway, that maps UDP on different external port than TCP.
+fn natpmp_udp_resp(req: &[u8]) -> Vec<u8> {
+    let mut resp = natpmp_resp(req);
+    if req[0] == 0 && req[1] == 1 && resp[10..12] != [0, 0] {
+        resp[10..12].copy_from_slice(&6882u16.to_be_bytes());
+    }
+    resp
+}
+
+/// PCP gateway, mapping the same port on external address 198.51.100.1.
+fn pcp_resp(req: &[u8]) -> Vec<u8> {
+    let mut resp = req.to_vec();
+    resp[1] |= 0x80;
+    resp[3] = 0;
+    if resp[4..8] != [0; 4] {
+        resp[4..8].copy_from_slice(&600u32.to_be_bytes());
+    }
+    resp[8..24].copy_from_slice(&[0; 16]);
+    resp[44..60].copy_from_slice(&Ipv4Addr::new(198, 51, 100, 1).to_ipv6_mapped().octets());
+    resp
+}
+
+/// UPnP gateway (SSDP responder and HTTP server with description and control URL). Reports
+/// SOAP actions with their protocol argument.
+async fn spawn_igd() -> (SocketAddr, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let http_addr = listener.local_addr().unwrap();
+    let ssdp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let ssdp_addr = ssdp.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut buff = [0; 2048];
+        while let Ok((len, from)) = ssdp.recv_from(&mut buff).await {
+            assert!(buff[..len].starts_with(b"M-SEARCH * HTTP/1.1\r\n"));
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\
+                Location: http://{}/rootDesc.xml\r\n\r\n",
+                http_addr
+            );
+            let _ = ssdp.send_to(resp.as_bytes(), from).await;
+        }
+    });
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut req = vec![];
+            let mut buff = [0; 4096];
+            // Whole request is sent before response is expected
+            loop {
+                let get_done = req.starts_with(b"GET") && req.ends_with(b"\r\n\r\n");
+                if get_done || String::from_utf8_lossy(&req).contains("</s:Envelope>") {
+                    break;
+                }
+                match stream.read(&mut buff).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(len) => req.extend_from_slice(&buff[..len]),
+                }
+            }
+            let req = String::from_utf8_lossy(&req).to_string();
+
+            let body = match req.split_once('#') {
+                None => "<root><device><serviceList>\
+                    <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+                    <controlURL>/ctl/L3F</controlURL></service>\
+                    <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+                    <controlURL>/ctl/IPConn</controlURL></service>\
+                    </serviceList></device></root>"
+                    .to_string(),
+                Some((_, action)) => {
+                    let action = &action[..action.find('"').unwrap()];
+                    let protocol = match req.find("<NewProtocol>") {
+                        Some(pos) => &req[pos + 13..pos + 16],
+                        None => "",
+                    };
+                    let _ = tx.send(format!("{} {}", action, protocol).trim().to_string());
+                    "<s:Envelope><s:Body><u:Response>\
+                    <NewExternalIPAddress>192.0.2.55</NewExternalIPAddress>\
+                    </u:Response></s:Body></s:Envelope>"
+                        .to_string()
+                }
+            };
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\
+                Connection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(resp.as_bytes()).await;
+        }
+    });
+
+    (ssdp_addr, rx)
+}
+
+#[tokio::test]
+async fn natpmp_map_and_unmap() {
+    let (gateway, mut requests) = spawn_gateway(natpmp_resp).await;
+    let mut mapper = PortMapper::new(PORT);
+    mapper.set_gateway(gateway);
+    mapper.set_ssdp_addr(closed_addr().await);
+
+    let mapping = Mapping {
+        protocol: NatProtocol::NatPmp,
+        external_addr: "203.0.113.7:6881".parse().unwrap(),
+        lifetime: Duration::from_secs(3600),
+    };
+    assert_eq!(mapper.map().await, Ok(mapping));
+    assert_eq!(mapper.mapping(), Some(mapping));
+
+    mapper.unmap().await;
+    assert_eq!(mapper.mapping(), None);
+
+    let mut maps = vec![];
+    while let Ok(req) = requests.try_recv() {
+        if req[0] == 0 && req[1] != 0 {
+            maps.push((req[1], u32::from_be_bytes(req[8..12].try_into().unwrap())));
+        }
+    }
+    // TCP and UDP mapped, then removed (zero lifetime)
+    assert_eq!(maps, vec![(2, 7200), (1, 7200), (2, 0), (1, 0)]);
+}
+
+#[tokio::test]
+async fn natpmp_different_udp_port() {
+    let (gateway, mut requests) = spawn_gateway(natpmp_udp_resp).await;
+    let mut mapper = PortMapper::new(PORT);
+    mapper.set_gateway(gateway);
+    mapper.set_ssdp_addr(closed_addr().await);
+
+    assert!(mapper.map().await.is_err());
+    assert_eq!(mapper.mapping(), None);
+
+    let mut maps = vec![];
+    while let Ok(req) = requests.try_recv() {
+        if req[0] == 0 && req[1] != 0 {
+            maps.push((req[1], u32::from_be_bytes(req[8..12].try_into().unwrap())));
+        }
+    }
+    // Both mappings are removed straight away
+    assert_eq!(maps, vec![(2, 7200), (1, 7200), (2, 0), (1, 0)]);
+}
+
+#[tokio::test]
+async fn pcp_map() {
+    let (gateway, mut requests) = spawn_gateway(pcp_resp).await;
+    let mut mapper = PortMapper::new(PORT);
+    mapper.set_gateway(gateway);
+    mapper.set_lifetime(Duration::from_secs(1800));
+
+    assert_eq!(
+        mapper.map().await,
+        Ok(Mapping {
+            protocol: NatProtocol::Pcp,
+            external_addr: "198.51.100.1:6881".parse().unwrap(),
+            lifetime: Duration::from_secs(600),
+        })
+    );
+
+    // Version, MAP opcode, lifetime, protocol (TCP, then UDP) and internal port
+    let tcp = requests.recv().await.unwrap();
+    let udp = requests.recv().await.unwrap();
+    assert_eq!(tcp.len(), 60);
+    assert_eq!(&tcp[..2], &[2, 1]);
+    assert_eq!(&tcp[4..8], &1800u32.to_be_bytes());
+    assert_eq!((tcp[36], udp[36]), (6, 17));
+    assert_eq!(&tcp[40..42], &PORT.to_be_bytes());
+    // The same nonce identify all mappings of client
+    assert_eq!(tcp[24..36], udp[24..36]);
+}
+
+#[tokio::test]
+async fn upnp_map_and_unmap() {
+    let (ssdp_addr, mut actions) = spawn_igd().await;
+    let mut mapper = PortMapper::new(PORT);
+    mapper.set_gateway(closed_addr().await);
+    mapper.set_ssdp_addr(ssdp_addr);
+
+    assert_eq!(
+        mapper.map().await,
+        Ok(Mapping {
+            protocol: NatProtocol::Upnp,
+            external_addr: "192.0.2.55:6881".parse().unwrap(),
+            lifetime: Duration::from_secs(7200),
+        })
+    );
+    mapper.unmap().await;
+
+    let mut received = vec![];
+    while let Ok(action) = actions.try_recv() {
+        received.push(action);
+    }
+    assert_eq!(
+        received,
+        vec![
+            "AddPortMapping TCP",
+            "AddPortMapping UDP",
+            "GetExternalIPAddress",
+            "DeletePortMapping TCP",
+            "DeletePortMapping UDP",
+        ]
+    );
+}
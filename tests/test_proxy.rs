@@ -0,0 +1,249 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::mse::Encryption;
+use rdest::{
+    Error, Metainfo, PeerSource, PeerSourceEvent, Proxy, ProxyKind, Session, TrackerClient,
+    TrackerServer, TransferStats,
+};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::time;
+
+const USER: &str = "user";
+const PASSWORD: &str = "secret";
+
+/// Proxy stand-in, that requires credentials USER and PASSWORD, and reports target of every
+/// opened tunnel.
+async fn spawn_proxy(kind: ProxyKind) -> (SocketAddr, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let target = match kind {
+                    ProxyKind::Socks5 => socks5_handshake(&mut stream).await,
+                    ProxyKind::Http => http_handshake(&mut stream).await,
+                };
+                if let Some((target, mut remote)) = target {
+                    let _ = tx.send(target);
+                    let _ = tokio::io::copy_bidirectional(&mut stream, &mut remote).await;
+                }
+            });
+        }
+    });
+
+    (addr, rx)
+}
+
+async fn socks5_handshake(stream: &mut TcpStream) -> Option<(String, TcpStream)> {
+    let mut head = [0; 2];
+    stream.read_exact(&mut head).await.ok()?;
+    let mut methods = vec![0; head[1] as usize];
+    stream.read_exact(&mut methods).await.ok()?;
+    if !methods.contains(&2) {
+        let _ = stream.write_all(&[5, 0xff]).await;
+        return None;
+    }
+    stream.write_all(&[5, 2]).await.ok()?;
+
+    // Subnegotiation version, then user name and password (both prefixed with length)
+    stream.read_u8().await.ok()?;
+    let mut credentials = vec![];
+    for _ in 0..2 {
+        let mut field = vec![0; stream.read_u8().await.ok()? as usize];
+        stream.read_exact(&mut field).await.ok()?;
+        credentials.push(String::from_utf8(field).ok()?);
+    }
+    if credentials != [USER, PASSWORD] {
+        let _ = stream.write_all(&[1, 1]).await;
+        return None;
+    }
+    stream.write_all(&[1, 0]).await.ok()?;
+
+    let mut req = [0; 4];
+    stream.read_exact(&mut req).await.ok()?;
+    let host = match req[3] {
+        1 => {
+            let mut ip = [0; 4];
+            stream.read_exact(&mut ip).await.ok()?;
+            std::net::Ipv4Addr::from(ip).to_string()
+        }
+        3 => {
+            let mut name = vec![0; stream.read_u8().await.ok()? as usize];
+            stream.read_exact(&mut name).await.ok()?;
+            String::from_utf8(name).ok()?
+        }
+        _ => {
+            let mut ip = [0; 16];
+            stream.read_exact(&mut ip).await.ok()?;
+            format!("[{}]", std::net::Ipv6Addr::from(ip))
+        }
+    };
+    let target = format!("{}:{}", host, stream.read_u16().await.ok()?);
+
+    let remote = TcpStream::connect(&target).await.ok()?;
+    stream
+        .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+        .await
+        .ok()?;
+    Some((target, remote))
+}
+
+async fn http_handshake(stream: &mut TcpStream) -> Option<(String, TcpStream)> {
+    let mut req = vec![];
+    while !req.ends_with(b"\r\n\r\n") {
+        req.push(stream.read_u8().await.ok()?);
+    }
+    let req = String::from_utf8(req).ok()?;
+    let target = req.strip_prefix("CONNECT ")?.split(' ').next()?.to_string();
+
+    // Base64 of "user:secret"
+    if !req.contains("\r\nProxy-Authorization: Basic dXNlcjpzZWNyZXQ=\r\n") {
+        let _ = stream
+            .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+            .await;
+        return None;
+    }
+
+    let remote = TcpStream::connect(&target).await.ok()?;
+    stream
+        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+        .await
+        .ok()?;
+    Some((target, remote))
+}
+
+fn proxy(kind: ProxyKind, addr: SocketAddr, password: &str) -> Proxy {
+    Proxy {
+        kind,
+        host: addr.ip().to_string(),
+        port: addr.port(),
+        auth: Some((USER.to_string(), password.to_string())),
+    }
+}
+
+fn metainfo(url: &str) -> Metainfo {
+    let torrent = format!(
+        "d8:announce{}:{}4:infod4:name4:NAME12:piece lengthi10e6:pieces20:AAAAABBBBBCCCCCDDDDD6:lengthi10eee",
+        url.len(),
+        url
+    );
+    Metainfo::from_bencode(torrent.as_bytes()).unwrap()
+}
+
+#[test]
+fn proxy_from_url() {
+    let proxy: Proxy = "http://[fd00::1]".parse().unwrap();
+    assert_eq!(
+        proxy,
+        Proxy {
+            kind: ProxyKind::Http,
+            host: "fd00::1".to_string(),
+            port: 8080,
+            auth: None,
+        }
+    );
+    assert_eq!(proxy.to_string(), "http://[fd00::1]:8080");
+
+    // Password is not shown
+    let proxy: Proxy = "socks5h://user:p@ss:w0rd@proxy.example.com:1081/"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        proxy.auth,
+        Some(("user".to_string(), "p@ss:w0rd".to_string()))
+    );
+    assert_eq!(proxy.to_string(), "socks5://user@proxy.example.com:1081");
+
+    assert_eq!(
+        "ftp://proxy.example.com".parse::<Proxy>(),
+        Err(Error::ProxyInvalidUrl(
+            "ftp://proxy.example.com".to_string()
+        ))
+    );
+    assert!("socks5://proxy.example.com:port".parse::<Proxy>().is_err());
+}
+
+#[tokio::test]
+async fn tracker_announce_through_socks5() {
+    let mut tracker = TrackerServer::bind(0, None).await.unwrap();
+    let tracker_addr = SocketAddr::from(([127, 0, 0, 1], tracker.local_addr().unwrap().port()));
+    tokio::spawn(async move { tracker.run().await });
+    let (proxy_addr, mut targets) = spawn_proxy(ProxyKind::Socks5).await;
+
+    let url = format!("http://{}/announce", tracker_addr);
+    let (_stats_tx, stats_rx) = watch::channel(TransferStats {
+        uploaded: 0,
+        downloaded: 0,
+        left: 10,
+    });
+    let mut source = TrackerClient::new(b"EEEEEFFFFFGGGGGHHHHH", metainfo(&url), stats_rx.clone());
+    assert!(source.set_proxy(&proxy(ProxyKind::Socks5, proxy_addr, PASSWORD)));
+
+    assert!(matches!(
+        source.next().await,
+        Some(PeerSourceEvent::TrackerResp(_))
+    ));
+    assert_eq!(targets.recv().await, Some(tracker_addr.to_string()));
+
+    // Proxy rejects credentials, and announce is not send directly
+    let mut source = TrackerClient::new(b"EEEEEFFFFFGGGGGHHHHH", metainfo(&url), stats_rx);
+    source.set_proxy(&proxy(ProxyKind::Socks5, proxy_addr, "wrong"));
+    assert!(matches!(
+        source.next().await,
+        Some(PeerSourceEvent::Fail(_))
+    ));
+    assert!(targets.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn peers_through_proxy_only() {
+    for kind in [ProxyKind::Socks5, ProxyKind::Http] {
+        let peer = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer_addr = peer.local_addr().unwrap();
+        let (proxy_addr, mut targets) = spawn_proxy(kind).await;
+        let port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let mut session = Session::new(metainfo("udp://127.0.0.1:1"), *b"AAAAABBBBBCCCCCDDDDD");
+        session.set_listen_ip("127.0.0.1".parse().unwrap());
+        session.set_listen_ports(port..=port);
+        session.set_encryption(Encryption::Disabled);
+        session.set_proxy(proxy(kind, proxy_addr, PASSWORD));
+        session.set_proxy_only(true);
+        session.add_peers(&[peer_addr]);
+
+        let handshake = time::timeout(Duration::from_secs(10), async {
+            tokio::select! {
+                _ = session.run() => panic!("Session finished"),
+                Ok((mut stream, _)) = peer.accept() => {
+                    let mut prefix = [0; 20];
+                    stream.read_exact(&mut prefix).await.unwrap();
+                    prefix
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(&handshake, b"\x13BitTorrent protocol");
+        assert_eq!(targets.recv().await, Some(peer_addr.to_string()));
+        // Session doesn't listen for incoming connections
+        assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Error, ScrapeFile, ScrapeResp, TrackerClient};
+
+#[test]
+fn failure_reason() {
+    assert_eq!(
+        ScrapeResp::from_bencode(b"d14:failure reason11:not allowede"),
+        Err(Error::TrackerRespFail("not allowed".to_string()))
+    );
+}
+
+#[test]
+fn missing_files() {
+    assert_eq!(
+        ScrapeResp::from_bencode(b"d5:otheri1ee"),
+        Err(Error::TrackerIncorrectOrMissing("files"))
+    );
+}
+
+#[test]
+fn many_files() {
+    let resp = ScrapeResp::from_bencode(
+        b"d5:filesd\
+        20:AAAAABBBBBCCCCCDDDDDd8:completei5e10:downloadedi50e10:incompletei10e4:name3:abce\
+        20:EEEEEFFFFFGGGGGHHHHHd8:completei0e10:downloadedi1e10:incompletei2ee\
+        ee",
+    )
+    .unwrap();
+
+    assert_eq!(
+        resp.file(b"AAAAABBBBBCCCCCDDDDD"),
+        Some(&ScrapeFile {
+            complete: 5,
+            downloaded: 50,
+            incomplete: 10,
+            name: Some("abc".to_string())
+        })
+    );
+    assert_eq!(
+        resp.file(b"EEEEEFFFFFGGGGGHHHHH"),
+        Some(&ScrapeFile {
+            complete: 0,
+            downloaded: 1,
+            incomplete: 2,
+            name: None
+        })
+    );
+}
+
+#[test]
+fn invalid_entries_skipped() {
+    let resp = ScrapeResp::from_bencode(
+        b"d5:filesd\
+        3:abcd8:completei5e10:downloadedi50e10:incompletei10ee\
+        20:EEEEEFFFFFGGGGGHHHHHd8:completei-1e10:downloadedi1e10:incompletei2ee\
+        ee",
+    )
+    .unwrap();
+
+    assert!(resp.files().is_empty());
+}
+
+#[test]
+fn scrape_url_derived() {
+    assert_eq!(
+        TrackerClient::scrape_url("http://example.com/announce"),
+        Some("http://example.com/scrape".to_string())
+    );
+    assert_eq!(
+        TrackerClient::scrape_url("http://example.com/x/announce?x2%0644"),
+        Some("http://example.com/x/scrape?x2%0644".to_string())
+    );
+    assert_eq!(
+        TrackerClient::scrape_url("udp://example.com:6969"),
+        Some("udp://example.com:6969".to_string())
+    );
+}
+
+#[test]
+fn scrape_url_unsupported() {
+    assert_eq!(TrackerClient::scrape_url("http://example.com/a"), None);
+    assert_eq!(
+        TrackerClient::scrape_url("http://example.com/announce?x=2/4"),
+        None
+    );
+    assert_eq!(
+        TrackerClient::scrape_url("http://example.com/x%064announce"),
+        None
+    );
+}
@@ -0,0 +1,113 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Certificate, Error, Metainfo, Signature, Signer};
+
+const INFO: &[u8] =
+    b"d6:lengthi222e4:name4:NAME12:piece lengthi111e6:pieces20:AAAAABBBBBCCCCCDDDDDe";
+
+fn publisher() -> Signer {
+    Signer::from_pem(
+        include_str!("data/publisher.key"),
+        include_str!("data/publisher.crt"),
+    )
+    .unwrap()
+}
+
+fn byte_str(data: &[u8]) -> Vec<u8> {
+    [format!("{}:", data.len()).as_bytes(), data].concat()
+}
+
+fn torrent(info: &[u8], signature: &Signature) -> Vec<u8> {
+    let mut entry = b"d".to_vec();
+    if let Some(certificate) = &signature.certificate {
+        entry.extend(byte_str(b"certificate"));
+        entry.extend(byte_str(certificate));
+    }
+    if let Some(info) = &signature.info {
+        entry.extend(byte_str(b"info"));
+        entry.extend(info);
+    }
+    entry.extend(byte_str(b"signature"));
+    entry.extend(byte_str(&signature.signature));
+    entry.push(b'e');
+
+    [
+        b"d8:announce3:URL4:info".as_ref(),
+        info,
+        b"10:signaturesd",
+        &byte_str(signature.identity.as_bytes()),
+        &entry,
+        b"ee",
+    ]
+    .concat()
+}
+
+#[test]
+fn signed_torrent_verify_signer() {
+    let signer = publisher();
+    let signature = signer.sign(INFO).unwrap();
+    assert_eq!(signature.identity, "publisher.rdest.test");
+
+    let metainfo = Metainfo::from_bencode(&torrent(INFO, &signature)).unwrap();
+    assert_eq!(metainfo.signatures(), &vec![signature]);
+
+    let trusted = Certificate::from_bytes(include_bytes!("data/publisher.crt")).unwrap();
+    assert_eq!(metainfo.verify_signer(&trusted), Ok(()));
+
+    let stranger = Certificate::from_bytes(include_bytes!("data/stranger.crt")).unwrap();
+    assert_eq!(
+        metainfo.verify_signer(&stranger),
+        Err(Error::MetaUntrustedSigner(
+            "stranger.rdest.test".to_string()
+        ))
+    );
+}
+
+#[test]
+fn tampered_info_invalid_signature() {
+    let signature = publisher().sign(INFO).unwrap();
+    let tampered =
+        b"d6:lengthi222e4:name4:EVIL12:piece lengthi111e6:pieces20:AAAAABBBBBCCCCCDDDDDe";
+
+    assert_eq!(
+        Metainfo::from_bencode(&torrent(tampered, &signature)),
+        Err(Error::MetaInvalidSignature(
+            "publisher.rdest.test".to_string()
+        ))
+    );
+}
+
+#[test]
+fn signature_info_kept_as_encoded() {
+    // Keys aren't sorted, so re-encoded dictionary would differ
+    let mut signature = publisher().sign(INFO).unwrap();
+    signature.certificate = None;
+    signature.info = Some(b"d4:site8:rdest.io3:agei1ee".to_vec());
+    let metainfo = Metainfo::from_bencode(&torrent(INFO, &signature)).unwrap();
+
+    assert_eq!(metainfo.signatures(), &vec![signature]);
+}
+
+#[test]
+fn signature_without_certificate_needs_trusted_signer() {
+    let mut signature = publisher().sign(INFO).unwrap();
+    signature.certificate = None;
+    let metainfo = Metainfo::from_bencode(&torrent(INFO, &signature)).unwrap();
+
+    let trusted = Certificate::from_bytes(include_bytes!("data/publisher.crt")).unwrap();
+    assert_eq!(metainfo.verify_signer(&trusted), Ok(()));
+    assert_eq!(
+        Signer::from_pem(
+            include_str!("data/stranger.key"),
+            include_str!("data/publisher.crt")
+        )
+        .unwrap_err(),
+        Error::SignInvalidKey("private key doesn't match certificate")
+    );
+}
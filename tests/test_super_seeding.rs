@@ -0,0 +1,183 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::mse::Encryption;
+use rdest::{Metainfo, Session};
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::{env, fs};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+const PIECES: [&[u8; 10]; 3] = [b"AAAAAAAAAA", b"BBBBBBBBBB", b"CCCCCCCCCC"];
+
+const UNCHOKE: u8 = 1;
+const HAVE: u8 = 4;
+const BITFIELD: u8 = 5;
+const REQUEST: u8 = 6;
+const PIECE: u8 = 7;
+const HAVE_NONE: u8 = 15;
+
+fn metainfo() -> Metainfo {
+    let mut torrent = b"d8:announce17:udp://127.0.0.1:14:infod4:name13:super_seeding12:piece lengthi10e6:pieces60:".to_vec();
+    for piece in PIECES {
+        torrent.extend_from_slice(&sha1_smol::Sha1::from(piece).digest().bytes());
+    }
+    torrent.extend_from_slice(b"6:lengthi30eee");
+    Metainfo::from_bencode(&torrent).unwrap()
+}
+
+/// Peer stand-in, speaking plain BitTorrent protocol (with Fast Extension, if enabled).
+struct TestPeer {
+    stream: TcpStream,
+}
+
+impl TestPeer {
+    async fn handshake(mut stream: TcpStream, peer_id: &[u8; 20], fast: bool) -> TestPeer {
+        let mut handshake = b"\x13BitTorrent protocol".to_vec();
+        handshake.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, if fast { 0x04 } else { 0 }]);
+        handshake.extend_from_slice(metainfo().info_hash());
+        handshake.extend_from_slice(peer_id);
+        stream.write_all(&handshake).await.unwrap();
+
+        let mut handshake = [0; 68];
+        stream.read_exact(&mut handshake).await.unwrap();
+        TestPeer { stream }
+    }
+
+    async fn connect(addr: SocketAddr, peer_id: &[u8; 20], fast: bool) -> TestPeer {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        TestPeer::handshake(stream, peer_id, fast).await
+    }
+
+    async fn send(&mut self, id: u8, payload: &[u8]) {
+        let mut frame = ((payload.len() + 1) as u32).to_be_bytes().to_vec();
+        frame.push(id);
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame).await.unwrap();
+    }
+
+    /// Next message (other than keep-alive), with its ID.
+    async fn recv(&mut self) -> (u8, Vec<u8>) {
+        loop {
+            let len = self.stream.read_u32().await.unwrap() as usize;
+            if len > 0 {
+                let mut frame = vec![0; len];
+                self.stream.read_exact(&mut frame).await.unwrap();
+                return (frame[0], frame[1..].to_vec());
+            }
+        }
+    }
+
+    /// Pieces announced with Have, until session stays silent for a while.
+    async fn recv_haves(&mut self) -> Vec<u32> {
+        let mut haves = vec![];
+        while let Ok((id, payload)) = time::timeout(Duration::from_millis(500), self.recv()).await {
+            if id == HAVE {
+                haves.push(u32::from_be_bytes(payload[..4].try_into().unwrap()));
+            }
+        }
+        haves
+    }
+}
+
+/// Seed that session downloads all pieces from, before it starts super-seeding.
+async fn serve_all(listener: TcpListener) {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut seeder = TestPeer::handshake(stream, b"EEEEEEEEEEEEEEEEEEEE", false).await;
+    seeder.send(BITFIELD, &[0xe0]).await;
+    seeder.send(UNCHOKE, &[]).await;
+
+    let mut served = 0;
+    while served < PIECES.len() {
+        let (id, payload) = seeder.recv().await;
+        if id == REQUEST {
+            let index = u32::from_be_bytes(payload[..4].try_into().unwrap()) as usize;
+            let begin = u32::from_be_bytes(payload[4..8].try_into().unwrap()) as usize;
+            let length = u32::from_be_bytes(payload[8..].try_into().unwrap()) as usize;
+            let mut piece = payload[..8].to_vec();
+            piece.extend_from_slice(&PIECES[index][begin..begin + length]);
+            seeder.send(PIECE, &piece).await;
+            served += 1;
+        }
+    }
+
+    // Let session verify last piece
+    time::sleep(Duration::from_secs(1)).await;
+}
+
+async fn check_super_seeding(addr: SocketAddr) {
+    // Peer with Fast Extension gets HaveNone, other one empty bitfield, and then exactly one
+    // piece each (different ones, as revealed piece is counted as already in swarm)
+    let mut first = TestPeer::connect(addr, b"11111111111111111111", true).await;
+    assert_eq!(first.recv().await, (HAVE_NONE, vec![]));
+    let first_haves = first.recv_haves().await;
+    assert_eq!(first_haves.len(), 1);
+
+    let mut second = TestPeer::connect(addr, b"22222222222222222222", false).await;
+    assert_eq!(second.recv().await, (BITFIELD, vec![0]));
+    let second_haves = second.recv_haves().await;
+    assert_eq!(second_haves.len(), 1);
+    assert_ne!(first_haves, second_haves);
+
+    // Next piece is revealed, only when other peer reports the previous one
+    let revealed = first_haves[0];
+    first.send(HAVE_NONE, &[]).await;
+    first.send(HAVE, &revealed.to_be_bytes()).await;
+    assert!(first.recv_haves().await.is_empty());
+
+    second.send(BITFIELD, &[0]).await;
+    second.send(HAVE, &revealed.to_be_bytes()).await;
+    let next_haves = first.recv_haves().await;
+    assert_eq!(next_haves.len(), 1);
+    assert_ne!(next_haves[0], revealed);
+
+    // Third peer has all pieces but the most common one (revealed first). Whichever piece is
+    // chosen for it before its bitfield comes, it's replaced by the missing one.
+    let mut third = TestPeer::connect(addr, b"33333333333333333333", false).await;
+    assert_eq!(third.recv().await, (BITFIELD, vec![0]));
+    let bitfield = (0..PIECES.len() as u32)
+        .filter(|index| *index != revealed)
+        .fold(0, |bitfield, index| bitfield | 0x80 >> index);
+    third.send(BITFIELD, &[bitfield]).await;
+    assert_eq!(third.recv_haves().await.last(), Some(&revealed));
+}
+
+#[tokio::test]
+async fn reveal_pieces_one_by_one() {
+    // Session stores pieces in working directory
+    let dir = env::temp_dir().join(format!("rdest_super_seeding_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    env::set_current_dir(&dir).unwrap();
+
+    let mut session = Session::new(metainfo(), *b"SSSSSSSSSSSSSSSSSSSS");
+    session.set_listen_ip("127.0.0.1".parse().unwrap());
+    session.set_listen_ports(0..=0);
+    session.set_encryption(Encryption::Disabled);
+    session.set_utp(false);
+    session.set_super_seeding(true);
+    let port = session.bind().await.unwrap();
+
+    let seeder = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    session.add_peers(&[seeder.local_addr().unwrap()]);
+
+    time::timeout(Duration::from_secs(30), async {
+        tokio::select! {
+            _ = session.run() => panic!("Session finished"),
+            _ = async {
+                serve_all(seeder).await;
+                check_super_seeding(([127, 0, 0, 1], port).into()).await;
+            } => (),
+        }
+    })
+    .await
+    .unwrap();
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,143 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Error, TrackerResp};
+
+#[test]
+fn empty_input_incorrect() {
+    assert_eq!(
+        TrackerResp::from_bencode(b""),
+        Err(Error::TrackerBEncodeMissing)
+    );
+}
+
+#[test]
+fn failure_reason() {
+    assert_eq!(
+        TrackerResp::from_bencode(b"d14:failure reason4:FAILe"),
+        Err(Error::TrackerRespFail("FAIL".to_string()))
+    );
+}
+
+#[test]
+fn missing_interval() {
+    assert_eq!(
+        TrackerResp::from_bencode(b"d5:peerslee"),
+        Err(Error::TrackerIncorrectOrMissing("interval"))
+    );
+}
+
+#[test]
+fn missing_peers() {
+    assert_eq!(
+        TrackerResp::from_bencode(b"d8:intervali900ee"),
+        Err(Error::TrackerIncorrectOrMissing("peers"))
+    );
+}
+
+#[test]
+fn dict_peers() {
+    let resp = TrackerResp::from_bencode(
+        b"d8:intervali900e5:peersld2:ip9:127.0.0.17:peer id20:AAAAABBBBBCCCCCDDDDD4:porti6881eeee",
+    )
+    .unwrap();
+    assert_eq!(
+        resp.peers(),
+        vec![(
+            "127.0.0.1:6881".parse().unwrap(),
+            Some(*b"AAAAABBBBBCCCCCDDDDD")
+        )]
+    );
+}
+
+#[test]
+fn dict_peers_without_peer_id() {
+    let resp =
+        TrackerResp::from_bencode(b"d8:intervali900e5:peersld2:ip3:::14:porti6881eeee").unwrap();
+    assert_eq!(resp.peers(), vec![("[::1]:6881".parse().unwrap(), None)]);
+}
+
+#[test]
+fn dict_peers_invalid_entries_skipped() {
+    let resp = TrackerResp::from_bencode(
+        b"d8:intervali900e5:peersld2:ip4:host4:porti1eed2:ip9:127.0.0.14:porti-1eeee",
+    )
+    .unwrap();
+    assert_eq!(resp.peers(), vec![]);
+}
+
+#[test]
+fn compact_peers() {
+    let resp = TrackerResp::from_bencode(
+        b"d8:intervali900e5:peers12:\x7f\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x1a\xe2e",
+    )
+    .unwrap();
+    assert_eq!(
+        resp.peers(),
+        vec![
+            ("127.0.0.1:6881".parse().unwrap(), None),
+            ("10.0.0.2:6882".parse().unwrap(), None)
+        ]
+    );
+}
+
+#[test]
+fn compact_peers_invalid_length() {
+    assert_eq!(
+        TrackerResp::from_bencode(b"d8:intervali900e5:peers5:\x7f\x00\x00\x01\x1ae"),
+        Err(Error::TrackerIncorrectOrMissing("peers"))
+    );
+}
+
+#[test]
+fn compact_peers6() {
+    let resp = TrackerResp::from_bencode(
+        b"d8:intervali900e5:peers0:6:peers618:\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x1a\xe1e",
+    )
+    .unwrap();
+    assert_eq!(resp.peers(), vec![("[::1]:6881".parse().unwrap(), None)]);
+}
+
+#[test]
+fn compact_peers6_only() {
+    let resp = TrackerResp::from_bencode(
+        b"d8:intervali900e6:peers618:\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x1a\xe1e",
+    )
+    .unwrap();
+    assert_eq!(resp.peers(), vec![("[::1]:6881".parse().unwrap(), None)]);
+}
+
+#[test]
+fn intervals() {
+    let resp = TrackerResp::from_bencode(b"d8:intervali900e12:min intervali60e5:peers0:e").unwrap();
+    assert_eq!(resp.interval(), 900);
+    assert_eq!(resp.min_interval(), Some(60));
+}
+
+#[test]
+fn optional_fields() {
+    let resp = TrackerResp::from_bencode(
+        b"d8:completei12e11:external ip4:\x0a\x00\x00\x0510:incompletei3e8:intervali900e5:peers0:10:tracker id3:abc15:warning message4:busye",
+    )
+    .unwrap();
+    assert_eq!(resp.tracker_id(), Some(&"abc".to_string()));
+    assert_eq!(resp.warning_message(), Some(&"busy".to_string()));
+    assert_eq!(resp.complete(), Some(12));
+    assert_eq!(resp.incomplete(), Some(3));
+    assert_eq!(resp.external_ip(), Some("10.0.0.5".parse().unwrap()));
+}
+
+#[test]
+fn optional_fields_missing() {
+    let resp = TrackerResp::from_bencode(b"d8:intervali900e5:peers0:11:external ip3:abce").unwrap();
+    assert_eq!(resp.tracker_id(), None);
+    assert_eq!(resp.warning_message(), None);
+    assert_eq!(resp.complete(), None);
+    assert_eq!(resp.incomplete(), None);
+    assert_eq!(resp.external_ip(), None);
+}
@@ -0,0 +1,272 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{
+    AnnounceEvent, AnnounceParams, Error, TrackerClient, TrackerResp, TrackerServer,
+    UdpTrackerClient,
+};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+const INFO_HASH: [u8; 20] = *b"AAAAABBBBBCCCCCDDDDD";
+
+async fn spawn_tracker(whitelist: Option<HashSet<[u8; 20]>>, interval_sec: u64) -> u16 {
+    let mut tracker = TrackerServer::bind(0, whitelist).await.unwrap();
+    tracker.set_interval(Duration::from_secs(interval_sec));
+    let port = tracker.local_addr().unwrap().port();
+    tokio::spawn(async move { tracker.run().await });
+    port
+}
+
+async fn http_announce(
+    port: u16,
+    peer_id: &[u8; 20],
+    peer_port: u16,
+    left: u64,
+    extra: &str,
+) -> Result<TrackerResp, Error> {
+    let url = format!(
+        "http://127.0.0.1:{}/announce?info_hash=AAAAABBBBBCCCCCDDDDD&peer_id={}&port={}&uploaded=0&downloaded=0&left={}{}",
+        port,
+        String::from_utf8(peer_id.to_vec()).unwrap(),
+        peer_port,
+        left,
+        extra
+    );
+    let body = reqwest::get(url).await.unwrap().bytes().await.unwrap();
+    TrackerResp::from_bencode(&body)
+}
+
+fn udp_params(peer_id: &[u8; 20], port: u16, left: u64) -> AnnounceParams {
+    AnnounceParams {
+        info_hash: INFO_HASH,
+        peer_id: *peer_id,
+        port,
+        uploaded: 0,
+        downloaded: 0,
+        left,
+        event: AnnounceEvent::Started,
+        num_want: -1,
+        key: 0,
+        tracker_id: None,
+        ipv4: None,
+        ipv6: None,
+    }
+}
+
+#[tokio::test]
+async fn http_swarm() {
+    let port = spawn_tracker(None, 1800).await;
+
+    let resp = http_announce(port, b"EEEEEFFFFFGGGGGHHHHH", 6881, 0, "&event=started")
+        .await
+        .unwrap();
+    assert_eq!(resp.peers(), vec![]);
+    assert_eq!(resp.interval(), 1800);
+
+    let resp = http_announce(port, b"IIIIIJJJJJKKKKKLLLLL", 6882, 100, "")
+        .await
+        .unwrap();
+    assert_eq!(
+        resp.peers(),
+        vec![("127.0.0.1:6881".parse().unwrap(), None)]
+    );
+    assert_eq!(resp.complete(), Some(1));
+    assert_eq!(resp.incomplete(), Some(1));
+}
+
+#[tokio::test]
+async fn http_alternate_ip_family() {
+    let port = spawn_tracker(None, 1800).await;
+
+    http_announce(
+        port,
+        b"EEEEEFFFFFGGGGGHHHHH",
+        6881,
+        0,
+        "&ipv6=2001%3Adb8%3A%3A1",
+    )
+    .await
+    .unwrap();
+    http_announce(
+        port,
+        b"IIIIIJJJJJKKKKKLLLLL",
+        6882,
+        0,
+        "&ipv6=%5B%3A%3A%5D%3A6882",
+    )
+    .await
+    .unwrap();
+    let resp = http_announce(port, b"MMMMMNNNNNOOOOOPPPPP", 6883, 100, "")
+        .await
+        .unwrap();
+
+    let mut peers: Vec<SocketAddr> = resp.peers().into_iter().map(|(addr, _)| addr).collect();
+    peers.sort();
+    assert_eq!(
+        peers,
+        vec![
+            "127.0.0.1:6881".parse().unwrap(),
+            "127.0.0.1:6882".parse().unwrap(),
+            "[2001:db8::1]:6881".parse().unwrap(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn http_dict_model() {
+    let port = spawn_tracker(None, 1800).await;
+
+    http_announce(port, b"EEEEEFFFFFGGGGGHHHHH", 6881, 0, "")
+        .await
+        .unwrap();
+    let resp = http_announce(port, b"IIIIIJJJJJKKKKKLLLLL", 6882, 100, "&compact=0")
+        .await
+        .unwrap();
+    assert_eq!(
+        resp.peers(),
+        vec![(
+            "127.0.0.1:6881".parse().unwrap(),
+            Some(*b"EEEEEFFFFFGGGGGHHHHH")
+        )]
+    );
+}
+
+#[tokio::test]
+async fn http_stopped_peer_removed() {
+    let port = spawn_tracker(None, 1800).await;
+
+    http_announce(port, b"EEEEEFFFFFGGGGGHHHHH", 6881, 0, "")
+        .await
+        .unwrap();
+    http_announce(port, b"EEEEEFFFFFGGGGGHHHHH", 6881, 0, "&event=stopped")
+        .await
+        .unwrap();
+    let resp = http_announce(port, b"IIIIIJJJJJKKKKKLLLLL", 6882, 100, "")
+        .await
+        .unwrap();
+    assert_eq!(resp.peers(), vec![]);
+}
+
+#[tokio::test]
+async fn http_paused_peer_gets_no_seeders() {
+    let port = spawn_tracker(None, 1800).await;
+
+    http_announce(port, b"EEEEEFFFFFGGGGGHHHHH", 6881, 0, "")
+        .await
+        .unwrap();
+    let resp = http_announce(port, b"IIIIIJJJJJKKKKKLLLLL", 6882, 100, "&event=paused")
+        .await
+        .unwrap();
+    assert_eq!(resp.peers(), vec![]);
+    assert_eq!(resp.incomplete(), Some(1));
+
+    let resp = http_announce(port, b"MMMMMNNNNNOOOOOPPPPP", 6883, 100, "")
+        .await
+        .unwrap();
+    assert_eq!(resp.peers().len(), 2);
+}
+
+#[tokio::test]
+async fn peer_expired() {
+    let port = spawn_tracker(None, 1).await;
+
+    http_announce(port, b"EEEEEFFFFFGGGGGHHHHH", 6881, 0, "")
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    let resp = http_announce(port, b"IIIIIJJJJJKKKKKLLLLL", 6882, 100, "")
+        .await
+        .unwrap();
+    assert_eq!(resp.peers(), vec![]);
+}
+
+#[tokio::test]
+async fn whitelist() {
+    let whitelist = Some(HashSet::from([*b"00000000000000000000"]));
+    let port = spawn_tracker(whitelist, 1800).await;
+
+    assert_eq!(
+        http_announce(port, b"EEEEEFFFFFGGGGGHHHHH", 6881, 0, "").await,
+        Err(Error::TrackerRespFail("torrent not registered".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn udp_swarm() {
+    let port = spawn_tracker(None, 1800).await;
+    let mut client = UdpTrackerClient::new(&format!("udp://127.0.0.1:{}", port)).unwrap();
+
+    client
+        .announce(&udp_params(b"EEEEEFFFFFGGGGGHHHHH", 6881, 0))
+        .await
+        .unwrap();
+    let resp = client
+        .announce(&udp_params(b"IIIIIJJJJJKKKKKLLLLL", 6882, 100))
+        .await
+        .unwrap();
+    assert_eq!(
+        resp.peers(),
+        vec![("127.0.0.1:6881".parse().unwrap(), None)]
+    );
+    assert_eq!(resp.interval(), 1800);
+    assert_eq!(resp.complete(), Some(1));
+    assert_eq!(resp.incomplete(), Some(1));
+}
+
+#[tokio::test]
+async fn ipv6_clients() {
+    let port = spawn_tracker(None, 1800).await;
+    let url = format!(
+        "http://[::1]:{}/announce?info_hash=AAAAABBBBBCCCCCDDDDD&peer_id=EEEEEFFFFFGGGGGHHHHH&port=6881&uploaded=0&downloaded=0&left=0",
+        port
+    );
+    let body = reqwest::get(url).await.unwrap().bytes().await.unwrap();
+    TrackerResp::from_bencode(&body).unwrap();
+
+    // UDP clients get peers from the same IP family
+    let mut client = UdpTrackerClient::new(&format!("udp://[::1]:{}", port)).unwrap();
+    let resp = client
+        .announce(&udp_params(b"IIIIIJJJJJKKKKKLLLLL", 6882, 100))
+        .await
+        .unwrap();
+    assert_eq!(resp.peers(), vec![("[::1]:6881".parse().unwrap(), None)]);
+
+    // HTTP clients get IPv6 peers in "peers6"
+    let resp = http_announce(port, b"MMMMMNNNNNOOOOOPPPPP", 6883, 100, "")
+        .await
+        .unwrap();
+    let mut peers: Vec<SocketAddr> = resp.peers().into_iter().map(|(addr, _)| addr).collect();
+    peers.sort();
+    assert_eq!(
+        peers,
+        vec!["[::1]:6881".parse().unwrap(), "[::1]:6882".parse().unwrap()]
+    );
+}
+
+#[tokio::test]
+async fn scrape() {
+    let port = spawn_tracker(None, 1800).await;
+
+    http_announce(port, b"EEEEEFFFFFGGGGGHHHHH", 6881, 0, "&event=completed")
+        .await
+        .unwrap();
+    http_announce(port, b"IIIIIJJJJJKKKKKLLLLL", 6882, 100, "")
+        .await
+        .unwrap();
+
+    for url in [
+        format!("http://127.0.0.1:{}/announce", port),
+        format!("udp://127.0.0.1:{}", port),
+    ] {
+        let resp = TrackerClient::scrape(&url, &[INFO_HASH]).await.unwrap();
+        let file = resp.file(&INFO_HASH).unwrap();
+        assert_eq!((file.complete, file.downloaded, file.incomplete), (1, 1, 1));
+    }
+}
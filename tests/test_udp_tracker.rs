@@ -0,0 +1,177 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{AnnounceEvent, AnnounceParams, Error, ScrapeFile, UdpTrackerClient};
+use std::convert::TryInto;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+const CONNECTION_ID: u64 = 0x1122334455667788;
+
+/// Local stand-in for UDP tracker. Respond to every request, and return all received
+/// requests when finished.
+async fn spawn_tracker(requests_num: usize, error: bool) -> (String, JoinHandle<Vec<Vec<u8>>>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("udp://{}/announce?key=abc", socket.local_addr().unwrap());
+
+    let job = tokio::spawn(async move {
+        let mut requests = vec![];
+        let mut buff = vec![0; 2048];
+        for _ in 0..requests_num {
+            let (len, addr) = socket.recv_from(&mut buff).await.unwrap();
+            let req = buff[..len].to_vec();
+            let action = u32::from_be_bytes(req[8..12].try_into().unwrap());
+            let transaction_id = &req[12..16];
+
+            let mut resp = vec![];
+            match (action, error) {
+                (0, _) => {
+                    resp.extend_from_slice(&0u32.to_be_bytes());
+                    resp.extend_from_slice(transaction_id);
+                    resp.extend_from_slice(&CONNECTION_ID.to_be_bytes());
+                }
+                (_, true) => {
+                    resp.extend_from_slice(&3u32.to_be_bytes());
+                    resp.extend_from_slice(transaction_id);
+                    resp.extend_from_slice(b"not registered");
+                }
+                (1, false) => {
+                    resp.extend_from_slice(&1u32.to_be_bytes());
+                    resp.extend_from_slice(transaction_id);
+                    resp.extend_from_slice(&1800u32.to_be_bytes());
+                    resp.extend_from_slice(&5u32.to_be_bytes());
+                    resp.extend_from_slice(&7u32.to_be_bytes());
+                    resp.extend_from_slice(&[127, 0, 0, 1, 0x1a, 0xe1]);
+                    resp.extend_from_slice(&[10, 0, 0, 2, 0x1a, 0xe2]);
+                }
+                (_, false) => {
+                    resp.extend_from_slice(&2u32.to_be_bytes());
+                    resp.extend_from_slice(transaction_id);
+                    for _ in 0..(len - 16) / 20 {
+                        resp.extend_from_slice(&7u32.to_be_bytes());
+                        resp.extend_from_slice(&9u32.to_be_bytes());
+                        resp.extend_from_slice(&5u32.to_be_bytes());
+                    }
+                }
+            }
+
+            socket.send_to(&resp, addr).await.unwrap();
+            requests.push(req);
+        }
+
+        requests
+    });
+
+    (url, job)
+}
+
+fn params() -> AnnounceParams {
+    AnnounceParams {
+        info_hash: *b"AAAAABBBBBCCCCCDDDDD",
+        peer_id: *b"EEEEEFFFFFGGGGGHHHHH",
+        port: 6881,
+        uploaded: 0,
+        downloaded: 0,
+        left: 1000,
+        event: AnnounceEvent::Started,
+        num_want: 20,
+        key: 0xdeadbeef,
+        tracker_id: None,
+        ipv4: None,
+        ipv6: None,
+    }
+}
+
+#[test]
+fn invalid_scheme() {
+    assert_eq!(
+        UdpTrackerClient::new("http://127.0.0.1:8000").unwrap_err(),
+        Error::TrackerInvalidUrl("http://127.0.0.1:8000".to_string())
+    );
+}
+
+#[test]
+fn missing_port() {
+    assert!(UdpTrackerClient::new("udp://127.0.0.1/announce").is_err());
+}
+
+#[tokio::test]
+async fn announce_ok() {
+    let (url, job) = spawn_tracker(2, false).await;
+    let mut client = UdpTrackerClient::new(&url).unwrap();
+
+    let resp = client.announce(&params()).await.unwrap();
+    assert_eq!(
+        resp.peers(),
+        vec![
+            ("127.0.0.1:6881".parse().unwrap(), None),
+            ("10.0.0.2:6882".parse().unwrap(), None)
+        ]
+    );
+    assert_eq!(resp.complete(), Some(7));
+    assert_eq!(resp.incomplete(), Some(5));
+
+    let requests = job.await.unwrap();
+    let announce = &requests[1];
+    assert_eq!(announce[0..8], CONNECTION_ID.to_be_bytes());
+    assert_eq!(announce[16..36], *b"AAAAABBBBBCCCCCDDDDD");
+    assert_eq!(announce[36..56], *b"EEEEEFFFFFGGGGGHHHHH");
+    assert_eq!(announce[80..84], 2u32.to_be_bytes());
+    assert_eq!(announce[88..92], 0xdeadbeefu32.to_be_bytes());
+    assert_eq!(announce[96..98], 6881u16.to_be_bytes());
+    // BEP41 URLData option
+    assert_eq!(announce[98..], *b"\x02\x11/announce?key=abc\x00");
+}
+
+#[tokio::test]
+async fn connection_id_reused() {
+    let (url, job) = spawn_tracker(3, false).await;
+    let mut client = UdpTrackerClient::new(&url).unwrap();
+
+    client.announce(&params()).await.unwrap();
+    client.announce(&params()).await.unwrap();
+
+    let requests = job.await.unwrap();
+    let actions: Vec<u32> = requests
+        .iter()
+        .map(|req| u32::from_be_bytes(req[8..12].try_into().unwrap()))
+        .collect();
+    assert_eq!(actions, vec![0, 1, 1]);
+}
+
+#[tokio::test]
+async fn announce_error() {
+    let (url, _job) = spawn_tracker(2, true).await;
+    let mut client = UdpTrackerClient::new(&url).unwrap();
+
+    assert_eq!(
+        client.announce(&params()).await,
+        Err(Error::TrackerRespFail("not registered".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn scrape_ok() {
+    let (url, _job) = spawn_tracker(2, false).await;
+    let mut client = UdpTrackerClient::new(&url).unwrap();
+
+    let resp = client
+        .scrape(&[*b"AAAAABBBBBCCCCCDDDDD", *b"EEEEEFFFFFGGGGGHHHHH"])
+        .await
+        .unwrap();
+    assert_eq!(resp.files().len(), 2);
+    assert_eq!(
+        resp.file(b"EEEEEFFFFFGGGGGHHHHH"),
+        Some(&ScrapeFile {
+            complete: 7,
+            downloaded: 9,
+            incomplete: 5,
+            name: None
+        })
+    );
+}
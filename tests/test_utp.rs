@@ -0,0 +1,133 @@
+// Copyright 2020 Mateusz Janda.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use rdest::{Error, UtpSocket};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UdpSocket;
+
+fn local(socket: &UtpSocket) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], socket.local_addr().port()))
+}
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Send data in both directions between two sockets, client connects to `target`.
+async fn transfer(mut server: UtpSocket, target: SocketAddr, up_len: usize, down_len: usize) {
+    let client = UtpSocket::bind(0).await.unwrap();
+
+    let responder = tokio::spawn(async move {
+        let stream = server.accept().await.unwrap();
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let write = async {
+            writer.write_all(&data(down_len)).await.unwrap();
+            writer.shutdown().await.unwrap();
+        };
+        let read = async {
+            let mut buf = vec![];
+            reader.read_to_end(&mut buf).await.unwrap();
+            buf
+        };
+        tokio::join!(write, read).1
+    });
+
+    let stream = client.handle().connect(target).await.unwrap();
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let write = async {
+        writer.write_all(&data(up_len)).await.unwrap();
+        writer.shutdown().await.unwrap();
+    };
+    let read = async {
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf).await.unwrap();
+        buf
+    };
+    let received = tokio::join!(write, read).1;
+
+    assert_eq!(received.len(), down_len);
+    assert_eq!(received, data(down_len));
+    assert_eq!(responder.await.unwrap(), data(up_len));
+}
+
+#[tokio::test]
+async fn transfer_both_directions() {
+    let server = UtpSocket::bind(0).await.unwrap();
+    let target = local(&server);
+    transfer(server, target, 1024 * 1024, 100 * 1024).await;
+}
+
+#[tokio::test]
+async fn transfer_over_ipv6() {
+    let mut server = UtpSocket::bind_addr("[::1]:0".parse().unwrap())
+        .await
+        .unwrap();
+    let client = UtpSocket::bind_addr("[::1]:0".parse().unwrap())
+        .await
+        .unwrap();
+    let target = server.local_addr();
+    assert!(target.is_ipv6());
+
+    let responder = tokio::spawn(async move {
+        let mut stream = server.accept().await.unwrap();
+        let mut buf = vec![];
+        stream.read_to_end(&mut buf).await.unwrap();
+        buf
+    });
+
+    let mut stream = client.handle().connect(target).await.unwrap();
+    stream.write_all(&data(10 * 1024)).await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    assert_eq!(responder.await.unwrap(), data(10 * 1024));
+}
+
+#[tokio::test]
+async fn transfer_with_packet_loss() {
+    let server = UtpSocket::bind(0).await.unwrap();
+    let server_addr = local(&server);
+
+    // Forward datagrams between client and server, dropping every 10th
+    let proxy = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 2048];
+        let mut client_addr = None;
+        let mut count = 0;
+        while let Ok((n, addr)) = proxy.recv_from(&mut buf).await {
+            count += 1;
+            if count % 10 == 0 {
+                continue;
+            }
+
+            let target = match addr == server_addr {
+                true => client_addr,
+                false => {
+                    client_addr = Some(addr);
+                    Some(server_addr)
+                }
+            };
+            if let Some(target) = target {
+                let _ = proxy.send_to(&buf[..n], target).await;
+            }
+        }
+    });
+
+    transfer(server, proxy_addr, 200 * 1024, 50 * 1024).await;
+}
+
+#[tokio::test]
+async fn connect_timeout() {
+    // Socket that never answers
+    let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let client = UtpSocket::bind(0).await.unwrap();
+
+    let res = client.handle().connect(silent.local_addr().unwrap()).await;
+    assert!(matches!(res, Err(Error::UtpTimeout)));
+}